@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // WinFsp's DLL is loaded from where WinFsp is installed, so it's only linked to be loaded once
+    // it's first called. Only the binaries being linked can ask for that, not winfsp-sys
+    let windows = env::var("CARGO_CFG_TARGET_OS").is_ok_and(|os| os == "windows");
+    if windows && env::var_os("CARGO_FEATURE_WINFSP").is_some() {
+        let arch = match env::var("CARGO_CFG_TARGET_ARCH").as_deref() {
+            Ok("x86") => "x86",
+            Ok("aarch64") => "a64",
+            _ => "x64",
+        };
+        println!("cargo:rustc-link-arg=/DELAYLOAD:winfsp-{arch}.dll");
+    }
+}
@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::raw::c_int;
+use std::path::Path;
+
+/// Reads in a row that continue from where the last one ended, or that don't, after which the
+/// kernel is told the file is read sequentially, or randomly.
+const STREAK: u32 = 16;
+
+/// The pattern reads of a file follow, which the kernel is told about so it reads ahead of them
+/// as far as is useful.
+#[derive(Debug)]
+pub struct Access {
+    /// Where the last read ended.
+    end: u64,
+    /// Whether the last read continued from the one before.
+    sequential: bool,
+    /// How many reads in a row did as the last did.
+    streak: u32,
+    /// What the kernel was last told.
+    advice: c_int,
+}
+
+impl Access {
+    pub const fn new() -> Self {
+        Self {
+            end: 0,
+            sequential: false,
+            streak: 0,
+            advice: libc::POSIX_FADV_NORMAL,
+        }
+    }
+
+    /// Notes a read of `file` from `start` to `end`, telling the kernel once reads do something
+    /// else than it was last told.
+    pub fn record(&mut self, file: &File, start: u64, end: u64) {
+        let sequential = start == self.end;
+        self.end = end;
+        if sequential == self.sequential {
+            self.streak = self.streak.saturating_add(1);
+        } else {
+            self.sequential = sequential;
+            self.streak = 1;
+        }
+        let advice = if sequential {
+            libc::POSIX_FADV_SEQUENTIAL
+        } else {
+            libc::POSIX_FADV_RANDOM
+        };
+        if self.streak >= STREAK && advice != self.advice {
+            self.advice = advice;
+            // Only a hint, reads work the same if it's not taken
+            // SAFETY: posix_fadvise only takes a file descriptor, which is open
+            unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice) };
+        }
+    }
+}
+
+/// Drops the pages of the file at `path` from the page cache, for files that won't be read again
+/// soon, such as images just extracted whole, so they don't push out pages that will.
+///
+/// # Errors
+///
+/// [`io::Error`] if the file can't be opened or the kernel refuses.
+pub fn drop_cached(path: &Path) -> io::Result<()> {
+    let file = File::open(path)?;
+    // SAFETY: posix_fadvise only takes a file descriptor, which is open
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        err => Err(io::Error::from_raw_os_error(err)),
+    }
+}
@@ -0,0 +1,439 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::fst;
+use encoding_rs::SHIFT_JIS;
+use std::collections::HashSet;
+
+const RARC_MAGIC: &[u8] = b"RARC";
+const U8_MAGIC: &[u8] = &[0x55, 0xAA, 0x38, 0x2D];
+
+/// How deeply directories can be nested in an archive, far more than is ever needed, so
+/// archives crafted to nest endlessly are taken as corrupt rather than expanded.
+const MAX_DEPTH: usize = 64;
+
+/// Number of bytes needed to tell whether a file is an archive with [`is_archive`].
+pub const MAGIC_SIZE: usize = 4;
+
+/// An entry in an archive.
+#[derive(Clone, Debug)]
+pub enum Member {
+    /// A file, stored at `offset` from the start of the archive.
+    File {
+        name: String,
+        offset: u64,
+        size: u64,
+    },
+    /// A directory, holding its members in archive order.
+    Directory { name: String, members: Vec<Self> },
+}
+
+/// Returns whether `data` starts like a RARC or U8 archive.
+#[must_use]
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(RARC_MAGIC) || data.starts_with(U8_MAGIC)
+}
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(*data.get(offset..)?.first_chunk()?))
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(*data.get(offset..)?.first_chunk()?))
+}
+
+fn be32_usize(data: &[u8], offset: usize) -> Option<usize> {
+    usize::try_from(be32(data, offset)?).ok()
+}
+
+/// Reads the NUL terminated name at `offset` in the string table `strings`.
+fn name(strings: &[u8], offset: usize) -> Option<String> {
+    let bytes = strings.get(offset..)?;
+    let end = bytes.iter().position(|&byte| byte == 0)?;
+    let (name, _, _) = SHIFT_JIS.decode(&bytes[..end]);
+    Some(name.into_owned())
+}
+
+/// Parses the contents of a RARC or U8 archive. Names that can't be listed, such as empty ones
+/// or ones with a `/`, are replaced with `entry-N`, `N` being the index of their entry.
+///
+/// Returns `None` if the archive is truncated or corrupt.
+#[must_use]
+pub fn parse(data: &[u8]) -> Option<Vec<Member>> {
+    if data.starts_with(RARC_MAGIC) {
+        parse_rarc(data)
+    } else if data.starts_with(U8_MAGIC) {
+        parse_u8(data)
+    } else {
+        None
+    }
+}
+
+/// Locations of the tables of a RARC archive.
+struct Rarc<'a> {
+    data: &'a [u8],
+    data_offset: u64,
+    node_count: usize,
+    nodes: usize,
+    entry_count: usize,
+    entries: usize,
+    strings: &'a [u8],
+}
+
+/// A directory being read, with the members found in it so far.
+struct Open {
+    name: String,
+    /// Index just past its last entry, of the RARC entry table or the U8 node table.
+    end: usize,
+    /// Index of the next entry to read, for RARC archives.
+    next: usize,
+    members: Vec<Member>,
+}
+
+/// Returns `name`, or `entry-N` in its place if it can't be listed as it is, `N` being the
+/// index of its entry, like names in the FST.
+fn listable(name: String, index: usize) -> String {
+    if fst::name_problem(&name).is_some() {
+        format!("entry-{index}")
+    } else {
+        name
+    }
+}
+
+/// Adds the directory `open` to the members of the one it's in, the last of `stack`, or returns
+/// its members if it's the root.
+fn close(stack: &mut [Open], open: Open) -> Option<Vec<Member>> {
+    match stack.last_mut() {
+        Some(parent) => {
+            parent.members.push(Member::Directory {
+                name: open.name,
+                members: open.members,
+            });
+            None
+        }
+        None => Some(open.members),
+    }
+}
+
+impl Rarc<'_> {
+    /// Returns the index of the first entry of the directory node `node` and one past its last.
+    fn entries_of(&self, node: usize) -> Option<(usize, usize)> {
+        if node >= self.node_count {
+            return None;
+        }
+        let node = self.nodes.checked_add(node.checked_mul(0x10)?)?;
+        let count = usize::from(be16(self.data, node + 0x0A)?);
+        let first = be32_usize(self.data, node + 0x0C)?;
+        let end = first.checked_add(count)?;
+        (end <= self.entry_count).then_some((first, end))
+    }
+
+    /// Returns the members of the root directory node. Directory nodes list their entries, and
+    /// directory entries point back at nodes, which are each only read once, so nodes listed
+    /// twice or in a loop make the archive corrupt.
+    fn members(&self) -> Option<Vec<Member>> {
+        let (first, end) = self.entries_of(0)?;
+        let mut visited = HashSet::from([0]);
+        let mut stack = vec![Open {
+            name: String::new(),
+            end,
+            next: first,
+            members: vec![],
+        }];
+        while let Some(open) = stack.last_mut() {
+            if open.next == open.end {
+                let open = stack.pop()?;
+                if let Some(members) = close(&mut stack, open) {
+                    return Some(members);
+                }
+                continue;
+            }
+            let index = open.next;
+            open.next += 1;
+            let entry = self.entries.checked_add(index.checked_mul(0x14)?)?;
+            let flags = *self.data.get(entry + 0x04)?;
+            let name = name(self.strings, usize::from(be16(self.data, entry + 0x06)?))?;
+            let value = be32(self.data, entry + 0x08)?;
+            if flags & 0x02 != 0 {
+                if name == "." || name == ".." {
+                    continue;
+                }
+                let node = usize::try_from(value).ok()?;
+                if stack.len() > MAX_DEPTH || !visited.insert(node) {
+                    return None;
+                }
+                let (first, end) = self.entries_of(node)?;
+                stack.push(Open {
+                    name: listable(name, index),
+                    end,
+                    next: first,
+                    members: vec![],
+                });
+            } else {
+                let size = be32(self.data, entry + 0x0C)?;
+                let offset = self.data_offset + u64::from(value);
+                if offset + u64::from(size) > self.data.len() as u64 {
+                    return None;
+                }
+                open.members.push(Member::File {
+                    name: listable(name, index),
+                    offset,
+                    size: size.into(),
+                });
+            }
+        }
+        None
+    }
+}
+
+fn parse_rarc(data: &[u8]) -> Option<Vec<Member>> {
+    // Offsets in the info block are relative to the end of the 0x20 byte header
+    const INFO: usize = 0x20;
+    let strings_size = be32_usize(data, INFO + 0x10)?;
+    let strings_offset = INFO.checked_add(be32_usize(data, INFO + 0x14)?)?;
+    let rarc = Rarc {
+        data,
+        data_offset: u64::from(be32(data, 0x0C)?) + INFO as u64,
+        node_count: be32_usize(data, INFO)?,
+        nodes: INFO.checked_add(be32_usize(data, INFO + 0x04)?)?,
+        entry_count: be32_usize(data, INFO + 0x08)?,
+        entries: INFO.checked_add(be32_usize(data, INFO + 0x0C)?)?,
+        strings: data.get(strings_offset..strings_offset.checked_add(strings_size)?)?,
+    };
+    // The root node's name is usually just the archive's name, so its contents are shown as the
+    // contents of the archive itself
+    rarc.members()
+}
+
+/// Locations of the tables of a U8 archive.
+struct U8<'a> {
+    data: &'a [u8],
+    nodes: usize,
+    strings: &'a [u8],
+}
+
+impl U8<'_> {
+    /// Returns the members described by the nodes from `start` to `end`. Like in the FST,
+    /// directory nodes hold the index one past their last descendant.
+    fn members(&self, start: usize, end: usize) -> Option<Vec<Member>> {
+        let mut stack = vec![Open {
+            name: String::new(),
+            end,
+            next: 0,
+            members: vec![],
+        }];
+        let mut index = start;
+        loop {
+            let open = stack.last_mut()?;
+            if index == open.end {
+                let open = stack.pop()?;
+                if let Some(members) = close(&mut stack, open) {
+                    return Some(members);
+                }
+                continue;
+            }
+            let node = self.nodes.checked_add(index.checked_mul(12)?)?;
+            let kind = *self.data.get(node)?;
+            let name_offset = usize::try_from(be32(self.data, node)? & 0x00FF_FFFF).ok()?;
+            let name = listable(name(self.strings, name_offset)?, index);
+            let value = be32(self.data, node + 0x04)?;
+            let size = be32(self.data, node + 0x08)?;
+            if kind == 1 {
+                let next = usize::try_from(size).ok()?;
+                // Directories end after they start and within the one they're in
+                if next <= index || next > open.end || stack.len() > MAX_DEPTH {
+                    return None;
+                }
+                stack.push(Open {
+                    name,
+                    end: next,
+                    next: 0,
+                    members: vec![],
+                });
+            } else {
+                if u64::from(value) + u64::from(size) > self.data.len() as u64 {
+                    return None;
+                }
+                open.members.push(Member::File {
+                    name,
+                    offset: value.into(),
+                    size: size.into(),
+                });
+            }
+            index += 1;
+        }
+    }
+}
+
+fn parse_u8(data: &[u8]) -> Option<Vec<Member>> {
+    let nodes = be32_usize(data, 0x04)?;
+    let count = be32_usize(data, nodes + 0x08)?;
+    let u8 = U8 {
+        data,
+        nodes,
+        strings: data.get(nodes.checked_add(count.checked_mul(12)?)?..)?,
+    };
+    // The root node holds everything else
+    u8.members(1, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lists `members` as paths, with the offset and size of files.
+    fn paths(members: &[Member], prefix: &str) -> Vec<String> {
+        let mut paths = vec![];
+        for member in members {
+            match member {
+                Member::File { name, offset, size } => {
+                    paths.push(format!("{prefix}{name} {offset} {size}"));
+                }
+                Member::Directory { name, members } => {
+                    paths.push(format!("{prefix}{name}/"));
+                    paths.extend(self::paths(members, &format!("{prefix}{name}/")));
+                }
+            }
+        }
+        paths
+    }
+
+    /// Returns a RARC archive with the given `(entry count, first entry)` nodes, `(flags, name,
+    /// value, size)` entries and file data.
+    fn rarc(nodes: &[(u16, u32)], entries: &[(u8, &str, u32, u32)], data: &[u8]) -> Vec<u8> {
+        let mut strings = vec![];
+        let mut table = vec![];
+        for &(flags, name, value, size) in entries {
+            table.extend([0, 0, 0, 0, flags, 0]);
+            table.extend(u16::try_from(strings.len()).unwrap().to_be_bytes());
+            table.extend(value.to_be_bytes());
+            table.extend(size.to_be_bytes());
+            table.extend([0; 4]);
+            strings.extend(name.as_bytes());
+            strings.push(0);
+        }
+        let be = |value: usize| u32::try_from(value).unwrap().to_be_bytes();
+        let nodes_offset = 0x20;
+        let entries_offset = nodes_offset + nodes.len() * 0x10;
+        let strings_offset = entries_offset + table.len();
+        let data_offset = strings_offset + strings.len();
+        let mut archive = RARC_MAGIC.to_vec();
+        archive.extend([0; 8]);
+        archive.extend(be(data_offset));
+        archive.extend([0; 0x10]);
+        archive.extend(be(nodes.len()));
+        archive.extend(be(nodes_offset));
+        archive.extend(be(entries.len()));
+        archive.extend(be(entries_offset));
+        archive.extend(be(strings.len()));
+        archive.extend(be(strings_offset));
+        archive.extend([0; 8]);
+        for &(count, first) in nodes {
+            archive.extend([0; 0x0A]);
+            archive.extend(count.to_be_bytes());
+            archive.extend(first.to_be_bytes());
+        }
+        archive.extend(table);
+        archive.extend(strings);
+        archive.extend(data);
+        archive
+    }
+
+    /// Returns a U8 archive with the given `(kind, name, value, size)` nodes after the root.
+    fn u8(nodes: &[(u8, &str, u32, u32)]) -> Vec<u8> {
+        let count = u32::try_from(nodes.len() + 1).unwrap();
+        let mut strings = vec![0];
+        let mut table = vec![];
+        table.extend(0x0100_0000u32.to_be_bytes());
+        table.extend(0u32.to_be_bytes());
+        table.extend(count.to_be_bytes());
+        for &(kind, name, value, size) in nodes {
+            let name_offset = u32::try_from(strings.len()).unwrap();
+            table.extend((u32::from(kind) << 24 | name_offset).to_be_bytes());
+            table.extend(value.to_be_bytes());
+            table.extend(size.to_be_bytes());
+            strings.extend(name.as_bytes());
+            strings.push(0);
+        }
+        let mut archive = U8_MAGIC.to_vec();
+        archive.extend(0x20u32.to_be_bytes());
+        archive.extend([0; 0x18]);
+        archive.extend(table);
+        archive.extend(strings);
+        archive
+    }
+
+    #[test]
+    fn rarc_members() {
+        let nodes = [(4, 0), (3, 4)];
+        let entries = [
+            (0x01, "a.bin", 0, 3),
+            (0x02, ".", 0, 0),
+            (0x02, "..", u32::MAX, 0),
+            (0x02, "sub", 1, 0),
+            (0x01, "b.bin", 3, 2),
+            (0x02, ".", 1, 0),
+            (0x02, "..", 0, 0),
+        ];
+        let archive = rarc(&nodes, &entries, b"abcde");
+        let data = archive.len() - 5;
+        assert!(is_archive(&archive));
+        assert_eq!(
+            paths(&parse(&archive).unwrap(), ""),
+            [
+                format!("a.bin {data} 3"),
+                "sub/".into(),
+                format!("sub/b.bin {} 2", data + 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn rarc_shared_nodes() {
+        let nodes = [(2, 0), (0, 0)];
+        let entries = [(0x02, "x", 1, 0), (0x02, "y", 1, 0)];
+        assert!(parse(&rarc(&nodes, &entries, &[])).is_none());
+        // Or nodes holding themselves
+        let entries = [(0x02, "x", 0, 0)];
+        assert!(parse(&rarc(&[(1, 0)], &entries, &[])).is_none());
+    }
+
+    #[test]
+    fn rarc_past_end() {
+        let entries = [(0x01, "a.bin", 0, 4)];
+        assert!(parse(&rarc(&[(1, 0)], &entries, b"abc")).is_none());
+    }
+
+    #[test]
+    fn u8_members() {
+        let archive = u8(&[(0, "top", 0, 4), (1, "dir", 0, 4), (0, "f", 2, 2)]);
+        assert!(is_archive(&archive));
+        assert_eq!(
+            paths(&parse(&archive).unwrap(), ""),
+            ["top 0 4", "dir/", "dir/f 2 2"]
+        );
+    }
+
+    #[test]
+    fn u8_names() {
+        let archive = u8(&[
+            (0, "", 0, 4),
+            (0, "..", 0, 4),
+            (0, "a/b", 0, 4),
+            (0, "ok", 0, 4),
+        ]);
+        assert_eq!(
+            paths(&parse(&archive).unwrap(), ""),
+            ["entry-1 0 4", "entry-2 0 4", "entry-3 0 4", "ok 0 4"]
+        );
+    }
+
+    #[test]
+    fn u8_nesting() {
+        let count = 100_000;
+        let nodes = vec![(1, "d", 0, count + 1); count as usize];
+        assert!(parse(&u8(&nodes)).is_none());
+        let nodes = vec![(1, "d", 0, 11); 10];
+        assert_eq!(paths(&parse(&u8(&nodes)).unwrap(), "").len(), 10);
+    }
+}
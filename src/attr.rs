@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+#[cfg(unix)]
+pub use fuser::FileAttr;
+#[cfg(unix)]
+pub use fuser::FileType;
+#[cfg(windows)]
+use std::time::SystemTime;
+
+/// The kinds of file a disc shows, as fuser has them, on Windows where fuser doesn't build.
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FileType {
+    Directory,
+    RegularFile,
+}
+
+/// The attributes of a file, as fuser has them, on Windows where fuser doesn't build.
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct FileAttr {
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    pub atime: SystemTime,
+    pub mtime: SystemTime,
+    pub ctime: SystemTime,
+    pub crtime: SystemTime,
+    pub kind: FileType,
+    pub perm: u16,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u32,
+    pub blksize: u32,
+    pub flags: u32,
+}
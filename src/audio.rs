@@ -0,0 +1,319 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+/// Size of the standard DSP ADPCM header.
+pub const DSP_HEADER_SIZE: usize = 0x60;
+
+/// Size of a canonical WAV header, before the PCM data.
+const WAV_HEADER_SIZE: usize = 44;
+
+const DSP_FRAME_SIZE: usize = 8;
+const DSP_SAMPLES_PER_FRAME: usize = 14;
+/// Size of a frame of ADP audio, which streamed audio is always made of.
+pub const ADP_FRAME_SIZE: usize = 32;
+const ADP_SAMPLES_PER_FRAME: usize = 28;
+
+/// Sample rate of DTK streaming audio and `.adp` files.
+pub const ADP_SAMPLE_RATE: u32 = 48_000;
+
+/// Audio formats that can be converted to WAV.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// Nintendo DSP ADPCM with the standard 0x60 byte header, mono.
+    Dsp,
+    /// Headerless stereo ADPCM as streamed by the drive (DTK), usually in `.adp` files.
+    Adp,
+}
+
+impl Codec {
+    /// Returns the codec for files with the given name, going by their extension.
+    #[must_use]
+    pub fn for_name(name: &str) -> Option<Self> {
+        let (_, extension) = name.rsplit_once('.')?;
+        if extension.eq_ignore_ascii_case("dsp") {
+            Some(Self::Dsp)
+        } else if extension.eq_ignore_ascii_case("adp") {
+            Some(Self::Adp)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the size of the WAV file for audio of `size` bytes starting with `header`, or
+    /// `None` if it doesn't look like audio in this format. `header` needs to hold at least
+    /// [`DSP_HEADER_SIZE`] bytes for DSP files.
+    #[must_use]
+    pub fn wav_size(self, header: &[u8], size: u64) -> Option<u64> {
+        match self {
+            Self::Dsp => {
+                let header = DspHeader::parse(header)?;
+                Some(WAV_HEADER_SIZE as u64 + u64::from(header.samples) * 2)
+            }
+            Self::Adp => Some(WAV_HEADER_SIZE as u64 + adp_pcm_size(size)),
+        }
+    }
+
+    /// Decodes the audio in `data` into a complete WAV file, or returns `None` if it doesn't
+    /// look like audio in this format.
+    #[must_use]
+    pub fn to_wav(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::Dsp => {
+                let header = DspHeader::parse(data)?;
+                let pcm_size = u64::from(header.samples) * 2;
+                let mut out = wav_header(1, header.sample_rate, pcm_size);
+                decode_dsp(&header, &data[DSP_HEADER_SIZE..], &mut out);
+                out.resize(WAV_HEADER_SIZE + usize::try_from(pcm_size).ok()?, 0);
+                Some(out)
+            }
+            Self::Adp => Some(adp_to_wav(data)),
+        }
+    }
+}
+
+/// The fields of a DSP header needed to decode it.
+struct DspHeader {
+    samples: u32,
+    sample_rate: u32,
+    coefficients: [i16; 16],
+    history: [i16; 2],
+}
+
+impl DspHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let header = data.get(..DSP_HEADER_SIZE)?;
+        let be32 = |offset: usize| {
+            u32::from_be_bytes([
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            ])
+        };
+        let be16 = |offset: usize| i16::from_be_bytes([header[offset], header[offset + 1]]);
+        let samples = be32(0x00);
+        let nibbles = be32(0x04);
+        let sample_rate = be32(0x08);
+        // Only the ADPCM format (0) exists, and every 16 nibbles hold 14 samples
+        let format = be16(0x0E);
+        if format != 0 || sample_rate == 0 || u64::from(samples) > u64::from(nibbles) {
+            return None;
+        }
+        let mut coefficients = [0; 16];
+        for (i, coefficient) in coefficients.iter_mut().enumerate() {
+            *coefficient = be16(0x1C + i * 2);
+        }
+        Some(Self {
+            samples,
+            sample_rate,
+            coefficients,
+            history: [be16(0x40), be16(0x42)],
+        })
+    }
+}
+
+/// Returns the RIFF WAV header for 16-bit PCM data of `size` bytes.
+///
+/// Sizes that don't fit in the header are saturated, as WAV can't describe them anyway.
+fn wav_header(channels: u16, sample_rate: u32, size: u64) -> Vec<u8> {
+    let size = u32::try_from(size).unwrap_or(u32::MAX - 36);
+    let block_align = channels * 2;
+    let mut header = Vec::with_capacity(WAV_HEADER_SIZE);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(size + 36).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&size.to_le_bytes());
+    header
+}
+
+/// Clamps an intermediate sample to the 16-bit range.
+fn clamp(sample: i32) -> i16 {
+    // The value was just clamped to the i16 range
+    #[allow(clippy::cast_possible_truncation)]
+    let sample = sample.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16;
+    sample
+}
+
+/// Returns the signed value of the low nibble of `nibble`.
+const fn signed_nibble(nibble: u8) -> i32 {
+    // Shifting into the top of a byte and back sign extends it
+    #[allow(clippy::cast_possible_wrap)]
+    let nibble = ((nibble << 4) as i8 >> 4) as i32;
+    nibble
+}
+
+fn decode_dsp(header: &DspHeader, data: &[u8], out: &mut Vec<u8>) {
+    let [mut history1, mut history2] = header.history.map(i32::from);
+    let mut remaining = header.samples as usize;
+    for frame in data.chunks_exact(DSP_FRAME_SIZE) {
+        let scale = 1 << (frame[0] & 0x0F);
+        let predictor = usize::from((frame[0] >> 4) & 0x07);
+        let coefficient1 = i32::from(header.coefficients[predictor * 2]);
+        let coefficient2 = i32::from(header.coefficients[predictor * 2 + 1]);
+        for i in 0..DSP_SAMPLES_PER_FRAME.min(remaining) {
+            let byte = frame[1 + i / 2];
+            let nibble = if i % 2 == 0 { byte >> 4 } else { byte };
+            let sample = ((scale * signed_nibble(nibble)) << 11)
+                + 1024
+                + coefficient1 * history1
+                + coefficient2 * history2;
+            let sample = clamp(sample >> 11);
+            out.extend_from_slice(&sample.to_le_bytes());
+            history2 = history1;
+            history1 = sample.into();
+        }
+        remaining = remaining.saturating_sub(DSP_SAMPLES_PER_FRAME);
+        if remaining == 0 {
+            break;
+        }
+    }
+}
+
+/// Returns the size of the 16-bit stereo PCM data decoded from `size` bytes of ADP audio.
+#[must_use]
+pub const fn adp_pcm_size(size: u64) -> u64 {
+    size / ADP_FRAME_SIZE as u64 * ADP_SAMPLES_PER_FRAME as u64 * 4
+}
+
+/// Decoder state for one channel of ADP audio.
+#[derive(Default)]
+struct AdpChannel {
+    history1: i32,
+    history2: i32,
+}
+
+impl AdpChannel {
+    fn decode(&mut self, nibble: u8, header: u8) -> i16 {
+        let history = match header >> 4 {
+            0 => 0,
+            1 => self.history1 * 0x3C,
+            2 => self.history1 * 0x73 - self.history2 * 0x34,
+            _ => self.history1 * 0x62 - self.history2 * 0x37,
+        };
+        let history = ((history + 0x20) >> 6).clamp(-0x20_0000, 0x1F_FFFF);
+        let sample = ((signed_nibble(nibble) << 12) >> (header & 0x0F) << 6) + history;
+        self.history2 = self.history1;
+        self.history1 = sample;
+        clamp(sample >> 6)
+    }
+}
+
+/// Decodes ADP audio into a complete WAV file.
+#[must_use]
+pub fn adp_to_wav(data: &[u8]) -> Vec<u8> {
+    let mut out = wav_header(2, ADP_SAMPLE_RATE, adp_pcm_size(data.len() as u64));
+    let mut left = AdpChannel::default();
+    let mut right = AdpChannel::default();
+    for frame in data.chunks_exact(ADP_FRAME_SIZE) {
+        for &byte in &frame[ADP_FRAME_SIZE - ADP_SAMPLES_PER_FRAME..] {
+            out.extend_from_slice(&left.decode(byte, frame[0]).to_le_bytes());
+            out.extend_from_slice(&right.decode(byte >> 4, frame[1]).to_le_bytes());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a DSP file of `samples` samples at 32 kHz, whose second predictor just repeats
+    /// the last sample, followed by `frames`.
+    fn dsp(samples: u32, frames: &[[u8; DSP_FRAME_SIZE]]) -> Vec<u8> {
+        let mut data = vec![0; DSP_HEADER_SIZE];
+        data[0x00..0x04].copy_from_slice(&samples.to_be_bytes());
+        data[0x04..0x08].copy_from_slice(&(samples * 2).to_be_bytes());
+        data[0x08..0x0C].copy_from_slice(&32_000u32.to_be_bytes());
+        // 1.0 in 5.11 fixed point
+        data[0x20..0x22].copy_from_slice(&2048i16.to_be_bytes());
+        data.extend(frames.iter().flatten());
+        data
+    }
+
+    /// Returns the samples of the PCM data of a WAV file.
+    fn samples(wav: &[u8]) -> Vec<i16> {
+        wav[WAV_HEADER_SIZE..]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn dsp_decodes() {
+        // Predictor 1 with a scale of 4, then nibbles 1, -1, 7 and 0
+        let data = dsp(4, &[[0x12, 0x1F, 0x70, 0, 0, 0, 0, 0]]);
+        let wav = Codec::Dsp.to_wav(&data).unwrap();
+        assert_eq!(
+            Codec::Dsp.wav_size(&data, data.len() as u64),
+            Some(wav.len() as u64)
+        );
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 1);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 32_000);
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 8);
+        assert_eq!(samples(&wav), [4, 0, 28, 28]);
+    }
+
+    #[test]
+    fn dsp_clamps_and_pads() {
+        // Predictor 0, which ignores history, with the largest scale
+        let wav = Codec::Dsp
+            .to_wav(&dsp(2, &[[0x0F, 0x78, 0, 0, 0, 0, 0, 0]]))
+            .unwrap();
+        assert_eq!(samples(&wav), [i16::MAX, i16::MIN]);
+        // Missing frames are silence
+        let wav = Codec::Dsp.to_wav(&dsp(3, &[])).unwrap();
+        assert_eq!(samples(&wav), [0, 0, 0]);
+    }
+
+    #[test]
+    fn dsp_header_checked() {
+        let mut data = dsp(4, &[]);
+        data[0x0F] = 1;
+        assert!(Codec::Dsp.to_wav(&data).is_none());
+        let mut data = dsp(4, &[]);
+        data[0x04..0x08].copy_from_slice(&3u32.to_be_bytes());
+        assert!(Codec::Dsp.to_wav(&data).is_none());
+        assert!(
+            Codec::Dsp
+                .to_wav(&dsp(4, &[])[..DSP_HEADER_SIZE - 1])
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn adp_decodes() {
+        let mut data = vec![0x71; ADP_FRAME_SIZE * 2];
+        // Both channels start without prediction or shifting
+        data[..4].fill(0);
+        // Then the left channel predicts from the last sample, and the right one from the last
+        // two, with a left nibble of 0 and a right one of 7 in the first byte
+        data[ADP_FRAME_SIZE..ADP_FRAME_SIZE + 4].copy_from_slice(&[0x10, 0x30, 0, 0]);
+        data[ADP_FRAME_SIZE + 4] = 0x70;
+        data[ADP_FRAME_SIZE + 5..].fill(0);
+        // A partial frame is ignored
+        data.push(0x71);
+        let wav = adp_to_wav(&data);
+        assert_eq!(
+            Codec::Adp.wav_size(&[], data.len() as u64),
+            Some(wav.len() as u64)
+        );
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 2);
+        let samples = samples(&wav);
+        assert_eq!(samples.len(), ADP_SAMPLES_PER_FRAME * 4);
+        assert_eq!(samples[..2], [4096, 28672]);
+        assert_eq!(samples[ADP_SAMPLES_PER_FRAME * 2 - 2..][..2], [4096, 28672]);
+        assert_eq!(
+            samples[ADP_SAMPLES_PER_FRAME * 2..][..4],
+            [3840, i16::MAX, 3600, i16::MAX]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long reads took, as [`read_sequential`] and [`read_random`] measure them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Measurement {
+    /// How many bytes were read.
+    pub bytes: u64,
+    /// How long all the reads took together.
+    pub elapsed: Duration,
+    /// How long each read took, sorted.
+    pub latencies: Vec<Duration>,
+}
+
+impl Measurement {
+    fn new(bytes: u64, mut latencies: Vec<Duration>) -> Self {
+        latencies.sort_unstable();
+        Self {
+            bytes,
+            elapsed: latencies.iter().sum(),
+            latencies,
+        }
+    }
+
+    /// Returns how many megabytes (10^6 bytes) were read per second.
+    #[must_use]
+    pub fn throughput(&self) -> f64 {
+        // Precise enough for a rate
+        #[allow(clippy::cast_precision_loss)]
+        let bytes = self.bytes as f64;
+        bytes / self.elapsed.as_secs_f64() / 1e6
+    }
+
+    /// Returns the latency that `percent` percent of the reads took at most.
+    #[must_use]
+    pub fn percentile(&self, percent: usize) -> Duration {
+        let Some(last) = self.latencies.len().checked_sub(1) else {
+            return Duration::ZERO;
+        };
+        self.latencies[(last * percent).div_ceil(100)]
+    }
+}
+
+/// Reads `size` bytes from the start of `io`, `read_size` bytes at a time.
+///
+/// # Errors
+///
+/// [`io::Error`] if a read fails.
+pub fn read_sequential<T: Read + Seek>(
+    io: &mut T,
+    size: u64,
+    read_size: usize,
+) -> io::Result<Measurement> {
+    let mut buf = vec![0; read_size];
+    let mut latencies = vec![];
+    io.seek(SeekFrom::Start(0))?;
+    let mut done = 0;
+    while done < size {
+        // At most a read
+        #[allow(clippy::cast_possible_truncation)]
+        let len = (size - done).min(read_size as u64) as usize;
+        let start = Instant::now();
+        io.read_exact(&mut buf[..len])?;
+        latencies.push(start.elapsed());
+        done += len as u64;
+    }
+    Ok(Measurement::new(done, latencies))
+}
+
+/// Makes `reads` reads of `read_size` bytes at offsets of `io` aligned to `read_size`, picked at
+/// random among the first `size` bytes. The offsets are the same every time, so runs can be
+/// compared.
+///
+/// # Errors
+///
+/// [`io::Error`] if a read fails.
+pub fn read_random<T: Read + Seek>(
+    io: &mut T,
+    size: u64,
+    read_size: usize,
+    reads: usize,
+) -> io::Result<Measurement> {
+    let mut buf = vec![0; read_size];
+    let mut latencies = vec![];
+    let blocks = size.div_ceil(read_size as u64).max(1);
+    // xorshift64, seeded so every run reads the same offsets
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut bytes = 0;
+    for _ in 0..reads {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let offset = state % blocks * read_size as u64;
+        // At most a read
+        #[allow(clippy::cast_possible_truncation)]
+        let len = (size.saturating_sub(offset)).min(read_size as u64) as usize;
+        let start = Instant::now();
+        io.seek(SeekFrom::Start(offset))?;
+        io.read_exact(&mut buf[..len])?;
+        latencies.push(start.elapsed());
+        bytes += len as u64;
+    }
+    Ok(Measurement::new(bytes, latencies))
+}
@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::env;
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+/// How much the caches hold together when the process has no memory limit.
+const DEFAULT_LIMIT: u64 = 256 << 20;
+
+/// Share of a cgroup's memory limit the caches may use.
+const CGROUP_SHARE: u64 = 4;
+
+/// Limits at least this large mean there's no limit; cgroup v1 reports the largest page-aligned
+/// `i64` then.
+const UNLIMITED: u64 = 1 << 62;
+
+static LIMIT: OnceLock<AtomicU64> = OnceLock::new();
+static USED: AtomicU64 = AtomicU64::new(0);
+
+/// Sets how many bytes the in-memory caches may hold together.
+///
+/// These are the caches of decoded files and of windows of remote images. Without a limit set,
+/// they get a quarter of the process's cgroup memory limit, or 256 MiB if there's none. Caches
+/// holding more than a lowered limit shrink as they're next added to.
+pub fn set_cache_limit(bytes: u64) {
+    LIMIT
+        .get_or_init(|| AtomicU64::new(bytes))
+        .store(bytes, Ordering::Relaxed);
+}
+
+/// Returns how many bytes the caches may hold together.
+#[must_use]
+pub fn cache_limit() -> u64 {
+    LIMIT
+        .get_or_init(|| {
+            AtomicU64::new(
+                cgroup_memory_limit().map_or(DEFAULT_LIMIT, |limit| limit / CGROUP_SHARE),
+            )
+        })
+        .load(Ordering::Relaxed)
+}
+
+/// Returns how many bytes the caches hold together.
+#[must_use]
+pub fn cache_used() -> u64 {
+    USED.load(Ordering::Relaxed)
+}
+
+/// Keeps all of the process's memory in RAM, caches included, both what it uses now and what it
+/// uses later, so reads answered from the caches never wait for it to be swapped back in.
+///
+/// Allocations that went over `RLIMIT_MEMLOCK` would fail, so it's raised to unlimited, which
+/// needs root.
+///
+/// # Errors
+///
+/// [`io::Error`] if the limit on locked memory can't be lifted or memory can't be locked.
+#[cfg(target_os = "linux")]
+pub fn lock_memory() -> io::Result<()> {
+    let unlimited = libc::rlimit {
+        rlim_cur: libc::RLIM_INFINITY,
+        rlim_max: libc::RLIM_INFINITY,
+    };
+    // SAFETY: setrlimit only reads the limit given
+    if unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &raw const unlimited) } != 0 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(
+            err.kind(),
+            format!("its limit can't be lifted, which needs root: {err}"),
+        ));
+    }
+    // Pages are locked as they're first used, not while they're only reserved, like most of a
+    // thread's stack
+    let flags = libc::MCL_CURRENT | libc::MCL_FUTURE | libc::MCL_ONFAULT;
+    // SAFETY: mlockall only takes flags
+    if unsafe { libc::mlockall(flags) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns the directory gcnfuse keeps its files in across runs, following the XDG base
+/// directory spec.
+#[must_use]
+pub fn xdg_cache_dir() -> Option<PathBuf> {
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".cache")))?;
+    Some(cache.join("gcnfuse"))
+}
+
+/// Returns the limit in the file at `path`, if it has one.
+fn read_limit(path: &Path) -> Option<u64> {
+    let limit = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    (limit < UNLIMITED).then_some(limit)
+}
+
+/// Returns the memory limit of the process's cgroup, the smallest of its own and its parents',
+/// if any of them has one. Both cgroup v1 and v2 are supported.
+fn cgroup_memory_limit() -> Option<u64> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let mut smallest: Option<u64> = None;
+    for line in cgroups.lines() {
+        // hierarchy-ID:controllers:path, with no controllers for v2
+        let mut fields = line.splitn(3, ':');
+        let (Some(_), Some(controllers), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let (root, file) = if controllers.is_empty() {
+            ("/sys/fs/cgroup", "memory.max")
+        } else if controllers
+            .split(',')
+            .any(|controller| controller == "memory")
+        {
+            ("/sys/fs/cgroup/memory", "memory.limit_in_bytes")
+        } else {
+            continue;
+        };
+        // Inside a cgroup namespace the path may not exist, but its ancestors up to the root do
+        let mut path = Some(Path::new(path.trim_start_matches('/')));
+        while let Some(current) = path {
+            if let Some(limit) = read_limit(&Path::new(root).join(current).join(file)) {
+                smallest = Some(smallest.map_or(limit, |smallest| smallest.min(limit)));
+            }
+            path = current.parent();
+        }
+    }
+    smallest
+}
+
+/// A cache of byte buffers by key, most recently used last, that shares [`cache_limit`] with the
+/// others. Inserting evicts this cache's least recently used entries until all the caches fit,
+/// but the newest entry is always kept.
+#[derive(Debug, Default)]
+pub struct Cache<K> {
+    entries: Vec<(K, Vec<u8>)>,
+}
+
+impl<K: PartialEq> Cache<K> {
+    pub const fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    /// Makes the entry for `key` the most recently used one. Returns whether there was one.
+    pub fn promote(&mut self, key: &K) -> bool {
+        let Some(position) = self.entries.iter().position(|(cached, _)| cached == key) else {
+            return false;
+        };
+        let entry = self.entries.remove(position);
+        self.entries.push(entry);
+        true
+    }
+
+    /// Adds `value` as the most recently used entry.
+    pub fn insert(&mut self, key: K, value: Vec<u8>) {
+        let size = value.len() as u64;
+        let limit = cache_limit();
+        while !self.entries.is_empty() && USED.load(Ordering::Relaxed) + size > limit {
+            let (_, evicted) = self.entries.remove(0);
+            USED.fetch_sub(evicted.len() as u64, Ordering::Relaxed);
+        }
+        USED.fetch_add(size, Ordering::Relaxed);
+        self.entries.push((key, value));
+    }
+
+    /// Returns the entries, least recently used first.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &[u8])> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key, value.as_slice()))
+    }
+
+    /// Returns the most recently used entry, or nothing if there's none.
+    pub fn most_recent(&self) -> &[u8] {
+        self.entries.last().map_or(&[], |(_, value)| value)
+    }
+}
+
+impl<K> Drop for Cache<K> {
+    fn drop(&mut self) {
+        let size = self
+            .entries
+            .iter()
+            .map(|(_, value)| value.len() as u64)
+            .sum();
+        USED.fetch_sub(size, Ordering::Relaxed);
+    }
+}
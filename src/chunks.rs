@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::cache;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::OnceLock;
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory decompressed RVZ chunks are saved in, so later mounts of the same image
+/// don't decompress them again.
+///
+/// Without a directory set, chunks are only cached in memory. Only has an effect before any RVZ
+/// image is opened.
+pub fn set_chunk_cache_dir(dir: PathBuf) {
+    // Once images are open, they keep saving where they started to
+    let _ = DIR.set(dir);
+}
+
+/// Returns the directory decompressed RVZ chunks are saved in, if any.
+#[must_use]
+pub fn chunk_cache_dir() -> Option<&'static Path> {
+    DIR.get().map(PathBuf::as_path)
+}
+
+/// Returns the directory to save decompressed RVZ chunks in when none is given, following the
+/// XDG base directory spec.
+#[must_use]
+pub fn default_chunk_cache_dir() -> Option<PathBuf> {
+    Some(cache::xdg_cache_dir()?.join("chunks"))
+}
+
+/// Decompressed chunks of an RVZ image saved on disk, a file per chunk named by its index, in a
+/// directory named by the hash of the image's header.
+///
+/// Saving is best effort: a chunk that can't be saved is decompressed again next time.
+pub struct ChunkCache {
+    dir: PathBuf,
+    /// Whether failing to save a chunk was already reported.
+    warned: bool,
+}
+
+impl ChunkCache {
+    /// Returns the chunk cache for the image with header hash `hash` in [`chunk_cache_dir`], or
+    /// nothing if there's no directory set or the image has no hash to tell it apart by.
+    pub fn open(hash: &[u8; 20]) -> Option<Self> {
+        let root = chunk_cache_dir()?;
+        if hash.iter().all(|&byte| byte == 0) {
+            return None;
+        }
+        let name = hash.iter().fold(String::new(), |mut name, byte| {
+            let _ = write!(name, "{byte:02x}");
+            name
+        });
+        let dir = root.join(name);
+        if let Err(err) = fs::create_dir_all(&dir) {
+            eprintln!("can't make chunk cache {}: {err}", dir.display());
+            return None;
+        }
+        Some(Self { dir, warned: false })
+    }
+
+    /// Returns the saved chunk `index`, if it's there and `len` bytes long.
+    pub fn get(&self, index: u64, len: usize) -> Option<Vec<u8>> {
+        fs::read(self.dir.join(index.to_string()))
+            .ok()
+            .filter(|chunk| chunk.len() == len)
+    }
+
+    /// Saves `chunk` as chunk `index`.
+    pub fn put(&mut self, index: u64, chunk: &[u8]) {
+        // Write it under another name first, so other mounts never see a partial chunk
+        let partial = self.dir.join(format!(".{index}.{}.partial", process::id()));
+        let saved = fs::write(&partial, chunk)
+            .and_then(|()| fs::rename(&partial, self.dir.join(index.to_string())));
+        if let Err(err) = saved {
+            let _ = fs::remove_file(&partial);
+            if !self.warned {
+                eprintln!("can't save chunks in {}: {err}", self.dir.display());
+                self.warned = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,459 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use bzip2::write::BzEncoder;
+use clap::ValueEnum;
+use sha1::Digest;
+use sha1::Sha1;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::thread;
+
+/// Size of the RVZ file header.
+const HEADER_SIZE: usize = 0x48;
+
+/// Size of the RVZ disc header following it.
+const DISC_SIZE: usize = 0xdc;
+
+/// Size of the start of the disc kept in the RVZ disc header instead of in the groups.
+const DISC_HEAD_SIZE: usize = 0x80;
+
+/// Size of the partition entries, which Wii discs have.
+const PARTITION_SIZE: u32 = 48;
+
+/// Offset and value of the magic word of Wii discs.
+const WII_MAGIC: (usize, u32) = (0x18, 0x5d1c_9ea3);
+
+/// Chunks larger than this have to be a multiple of it, smaller ones a power of two.
+const LARGE_CHUNK: u32 = 2 << 20;
+
+/// Smallest chunk RVZ allows.
+const MIN_CHUNK: u32 = 0x8000;
+
+/// How much of both images is compared at once when verifying.
+const VERIFY_SIZE: usize = 1 << 20;
+
+/// Compression of the chunks of a new RVZ image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Codec {
+    /// Store chunks as they are.
+    None,
+    /// bzip2, levels 1 to 9.
+    Bzip2,
+    /// Zstandard, levels 1 to 22.
+    #[default]
+    Zstd,
+}
+
+impl Codec {
+    /// Returns the level used when none is given, Dolphin's default.
+    #[must_use]
+    pub const fn default_level(self) -> i32 {
+        match self {
+            Self::None => 0,
+            Self::Bzip2 => 9,
+            Self::Zstd => 5,
+        }
+    }
+
+    /// Returns the number RVZ headers store the codec as.
+    const fn id(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Bzip2 => 2,
+            Self::Zstd => 5,
+        }
+    }
+
+    const fn levels(self) -> (i32, i32) {
+        match self {
+            Self::None => (0, 0),
+            Self::Bzip2 => (1, 9),
+            Self::Zstd => (1, 22),
+        }
+    }
+
+    fn compress(self, data: &[u8], level: i32) -> io::Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            // The level was checked to be 1 to 9
+            #[allow(clippy::cast_sign_loss)]
+            Self::Bzip2 => {
+                let mut encoder = BzEncoder::new(Vec::new(), bzip2::Compression::new(level as u32));
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Self::Zstd => zstd::bulk::compress(data, level),
+        }
+    }
+}
+
+/// Options controlling how an image is compressed.
+#[derive(Copy, Clone, Debug)]
+pub struct CompressOptions {
+    pub codec: Codec,
+    /// Level of the codec, from [`Codec::default_level`] if unsure.
+    pub level: i32,
+    /// Size of the chunks compressed separately, which are what has to be decompressed to read
+    /// any byte of them. Must be a power of two from 32 KiB to 2 MiB, or a multiple of 2 MiB.
+    pub chunk_size: u32,
+    /// How many chunks are compressed at once.
+    pub threads: usize,
+}
+
+/// A group, as stored in the RVZ group table: where its data is, divided by 4, its size with
+/// the top bit set if compressed, and its size before RVZ packing, which isn't used.
+type Group = [u32; 3];
+
+/// A chunk as it's stored and whether it's compressed, or `None` if it's all zeroes.
+type Stored = Option<(Vec<u8>, bool)>;
+
+fn check(options: &CompressOptions) -> Result<(), Error> {
+    let chunk_size = options.chunk_size;
+    if chunk_size < MIN_CHUNK
+        || (chunk_size <= LARGE_CHUNK && !chunk_size.is_power_of_two())
+        || (chunk_size > LARGE_CHUNK && !chunk_size.is_multiple_of(LARGE_CHUNK))
+    {
+        return Err(Error::Layout(format!(
+            "a chunk size of {chunk_size} isn't a power of two from 32 KiB to 2 MiB or a multiple \
+             of 2 MiB"
+        )));
+    }
+    let (min, max) = options.codec.levels();
+    if options.codec != Codec::None && !(min..=max).contains(&options.level) {
+        return Err(Error::Layout(format!(
+            "the level must be from {min} to {max}, not {}",
+            options.level
+        )));
+    }
+    Ok(())
+}
+
+/// Compresses the chunks in `chunks` on threads of their own, returning each one's data and
+/// whether it's compressed.
+fn compress_chunks(chunks: &[Vec<u8>], options: &CompressOptions) -> io::Result<Vec<Stored>> {
+    let compress = |chunk: &Vec<u8>| -> io::Result<Stored> {
+        if chunk.iter().all(|&b| b == 0) {
+            return Ok(None);
+        }
+        if options.codec == Codec::None {
+            return Ok(Some((chunk.clone(), false)));
+        }
+        let compressed = options.codec.compress(chunk, options.level)?;
+        // Chunks that don't shrink are kept as they are
+        if compressed.len() < chunk.len() {
+            Ok(Some((compressed, true)))
+        } else {
+            Ok(Some((chunk.clone(), false)))
+        }
+    };
+    thread::scope(|scope| {
+        // Every thread has to be started before waiting for any of them
+        #[allow(clippy::needless_collect)]
+        let threads: Vec<_> = chunks
+            .iter()
+            .map(|chunk| scope.spawn(move || compress(chunk)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| {
+                thread
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// Reads the next chunk of up to `len` bytes of `image`.
+fn read_chunk<R: Read>(image: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::new();
+    image.take(len).read_to_end(&mut chunk)?;
+    if (chunk.len() as u64) < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the image ended early",
+        ));
+    }
+    Ok(chunk)
+}
+
+/// Writes `data` at the end of `out`, padded to a multiple of 4 bytes as RVZ offsets are, and
+/// returns where it is.
+fn append<W: Write + Seek>(out: &mut W, data: &[u8]) -> io::Result<u64> {
+    let position = out.stream_position()?;
+    out.write_all(data)?;
+    out.write_all(&[0; 3][..data.len().wrapping_neg() % 4])?;
+    Ok(position)
+}
+
+/// Returns `offset`, a multiple of 4, divided by 4 as group tables store them.
+fn div4(offset: u64) -> io::Result<u32> {
+    u32::try_from(offset / 4)
+        .map_err(|_| io::Error::new(io::ErrorKind::FileTooLarge, "the RVZ image is too large"))
+}
+
+/// Writes the `size` bytes of the Gamecube disc image read from `image` to `out` as an RVZ
+/// image, as Dolphin reads them.
+///
+/// Chunks of zeroes take no space, but the pseudo-random padding discs have isn't packed with
+/// RVZ's scheme for it, so images of discs with a lot of it come out larger than Dolphin's.
+///
+/// Nothing but the disc and the options goes into the image, and chunks are written in order
+/// however many threads compress them, so compressing a disc again with the same codec, level
+/// and chunk size gives the same bytes, which can be checked by their hash.
+///
+/// # Errors
+///
+/// [`Error::Layout`] if the options are invalid or the image is of a Wii disc, and
+/// [`Error::Io`] for errors reading the image or writing the new one.
+pub fn compress<R: Read, W: Write + Seek>(
+    image: &mut R,
+    size: u64,
+    options: &CompressOptions,
+    out: &mut W,
+) -> Result<(), Error> {
+    check(options)?;
+    if size < DISC_HEAD_SIZE as u64 {
+        return Err(Error::Layout(
+            "the image is smaller than a disc header".into(),
+        ));
+    }
+    let mut head = [0; DISC_HEAD_SIZE];
+    image.read_exact(&mut head)?;
+    if head[WII_MAGIC.0..WII_MAGIC.0 + 4] == WII_MAGIC.1.to_be_bytes() {
+        return Err(Error::Layout("Wii discs can't be compressed".into()));
+    }
+
+    out.seek(SeekFrom::Start(0))?;
+    out.write_all(&[0; HEADER_SIZE + DISC_SIZE])?;
+    let chunk_size = u64::from(options.chunk_size);
+    let mut groups: Vec<Group> = Vec::new();
+    // The first chunk holds the start of the disc too, which reading replaces with the head
+    let mut first = head.to_vec();
+    first.extend(read_chunk(
+        image,
+        chunk_size.min(size) - DISC_HEAD_SIZE as u64,
+    )?);
+    let mut chunks = vec![first];
+    let mut position = chunk_size.min(size);
+    let batch = options.threads.max(1);
+    loop {
+        while chunks.len() < batch && position < size {
+            let len = chunk_size.min(size - position);
+            chunks.push(read_chunk(image, len)?);
+            position += len;
+        }
+        if chunks.is_empty() {
+            break;
+        }
+        for chunk in compress_chunks(&chunks, options)? {
+            let group = match chunk {
+                None => [div4(out.stream_position()?)?, 0, 0],
+                Some((data, compressed)) => {
+                    let offset = append(out, &data)?;
+                    // Chunks are at most 2 GiB or they wouldn't have been read
+                    #[allow(clippy::cast_possible_truncation)]
+                    let data_size = data.len() as u32 | if compressed { 1 << 31 } else { 0 };
+                    [div4(offset)?, data_size, 0]
+                }
+            };
+            groups.push(group);
+        }
+        chunks.clear();
+    }
+
+    let group_count = u32::try_from(groups.len())
+        .map_err(|_| Error::Layout("the image has too many chunks".into()))?;
+    let mut raw_data = Vec::new();
+    raw_data.extend_from_slice(&(DISC_HEAD_SIZE as u64).to_be_bytes());
+    raw_data.extend_from_slice(&(size - DISC_HEAD_SIZE as u64).to_be_bytes());
+    raw_data.extend_from_slice(&0u32.to_be_bytes());
+    raw_data.extend_from_slice(&group_count.to_be_bytes());
+    let raw_data = options.codec.compress(&raw_data, options.level)?;
+    let raw_data_offset = append(out, &raw_data)?;
+    let group_table: Vec<u8> = groups
+        .iter()
+        .flatten()
+        .flat_map(|word| word.to_be_bytes())
+        .collect();
+    let group_table = options.codec.compress(&group_table, options.level)?;
+    let group_offset = append(out, &group_table)?;
+    let file_size = out.stream_position()?;
+
+    // Tables are much smaller than 4 GiB
+    #[allow(clippy::cast_possible_truncation)]
+    let (raw_data_size, group_size) = (raw_data.len() as u32, group_table.len() as u32);
+    let mut disc = Vec::with_capacity(DISC_SIZE);
+    // A GameCube disc
+    disc.extend_from_slice(&1u32.to_be_bytes());
+    disc.extend_from_slice(&options.codec.id().to_be_bytes());
+    disc.extend_from_slice(&options.level.to_be_bytes());
+    disc.extend_from_slice(&options.chunk_size.to_be_bytes());
+    disc.extend_from_slice(&head);
+    // No partitions, whose entries would be at the raw data's
+    disc.extend_from_slice(&0u32.to_be_bytes());
+    disc.extend_from_slice(&PARTITION_SIZE.to_be_bytes());
+    disc.extend_from_slice(&raw_data_offset.to_be_bytes());
+    disc.extend_from_slice(&Sha1::digest([]));
+    disc.extend_from_slice(&1u32.to_be_bytes());
+    disc.extend_from_slice(&raw_data_offset.to_be_bytes());
+    disc.extend_from_slice(&raw_data_size.to_be_bytes());
+    disc.extend_from_slice(&group_count.to_be_bytes());
+    disc.extend_from_slice(&group_offset.to_be_bytes());
+    disc.extend_from_slice(&group_size.to_be_bytes());
+    // Neither codec has any compressor data
+    disc.extend_from_slice(&[0; 8]);
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(b"RVZ\x01");
+    // Version 1.0, readable by readers of versions since 0.3
+    header.extend_from_slice(&0x0100_0000u32.to_be_bytes());
+    header.extend_from_slice(&0x0003_0000u32.to_be_bytes());
+    // Its size is a constant
+    #[allow(clippy::cast_possible_truncation)]
+    header.extend_from_slice(&(DISC_SIZE as u32).to_be_bytes());
+    header.extend_from_slice(&Sha1::digest(&disc));
+    header.extend_from_slice(&size.to_be_bytes());
+    header.extend_from_slice(&file_size.to_be_bytes());
+    let hash = Sha1::digest(&header);
+    header.extend_from_slice(&hash);
+
+    out.seek(SeekFrom::Start(0))?;
+    out.write_all(&header)?;
+    out.write_all(&disc)?;
+    out.seek(SeekFrom::Start(file_size))?;
+    Ok(())
+}
+
+/// Checks that `new`, an image written by [`compress`], holds the same `size` bytes as
+/// `original`, which is read again from the start.
+///
+/// # Errors
+///
+/// [`Error::Io`] if either image can't be read, or with [`io::ErrorKind::InvalidData`] if they
+/// differ.
+pub fn verify<R: Read, S: Read>(original: &mut R, new: &mut S, size: u64) -> Result<(), Error> {
+    let mut expected = vec![0; VERIFY_SIZE];
+    let mut actual = vec![0; VERIFY_SIZE];
+    let mut position = 0;
+    while position < size {
+        // Smaller than the buffers
+        #[allow(clippy::cast_possible_truncation)]
+        let len = (size - position).min(VERIFY_SIZE as u64) as usize;
+        original.read_exact(&mut expected[..len])?;
+        new.read_exact(&mut actual[..len])?;
+        if let Some(offset) = (0..len).find(|&i| expected[i] != actual[i]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the new image differs from the original at {:#x}",
+                    position + offset as u64
+                ),
+            )
+            .into());
+        }
+        position += len as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an image of a chunk of text, one of zeroes, one that doesn't compress and a bit
+    /// of a last one.
+    fn image() -> Vec<u8> {
+        let chunk = MIN_CHUNK as usize;
+        let mut image: Vec<u8> = b"GALE01, not really a disc. "
+            .iter()
+            .copied()
+            .cycle()
+            .take(chunk)
+            .collect();
+        image.resize(chunk * 2, 0);
+        let mut state = 0x2545_F491_u32;
+        image.extend((0..chunk).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state.to_be_bytes()[0]
+        }));
+        image.extend([0xAA; 0x1000]);
+        image
+    }
+
+    fn options(codec: Codec) -> CompressOptions {
+        CompressOptions {
+            codec,
+            level: codec.default_level(),
+            chunk_size: MIN_CHUNK,
+            threads: 2,
+        }
+    }
+
+    fn compressed(image: &[u8], options: &CompressOptions) -> Result<Vec<u8>, Error> {
+        let mut out = io::Cursor::new(vec![]);
+        compress(&mut &image[..], image.len() as u64, options, &mut out)?;
+        Ok(out.into_inner())
+    }
+
+    #[test]
+    fn round_trip() {
+        let image = image();
+        for codec in [Codec::None, Codec::Bzip2, Codec::Zstd] {
+            let out = compressed(&image, &options(codec)).unwrap();
+            let mut rvz = rvz::Rvz::new(io::Cursor::new(out.clone())).unwrap();
+            let mut contents = vec![];
+            rvz.read_to_end(&mut contents).unwrap();
+            assert!(contents == image, "{codec:?} images differ");
+            verify(&mut &image[..], &mut &contents[..], image.len() as u64).unwrap();
+            // The same options give the same bytes
+            assert!(compressed(&image, &options(codec)).unwrap() == out);
+        }
+    }
+
+    #[test]
+    fn threads() {
+        let image = image();
+        for codec in [Codec::None, Codec::Bzip2, Codec::Zstd] {
+            let [one, four] = [1, 4].map(|threads| {
+                let options = CompressOptions {
+                    threads,
+                    ..options(codec)
+                };
+                compressed(&image, &options).unwrap()
+            });
+            assert!(one == four, "{codec:?} images differ");
+        }
+    }
+
+    #[test]
+    fn rejected() {
+        let image = image();
+        let mut bad = options(Codec::Zstd);
+        bad.chunk_size = MIN_CHUNK * 3;
+        assert!(compressed(&image, &bad).is_err());
+        bad = options(Codec::Bzip2);
+        bad.level = 10;
+        assert!(compressed(&image, &bad).is_err());
+        let mut wii = image.clone();
+        wii[WII_MAGIC.0..WII_MAGIC.0 + 4].copy_from_slice(&WII_MAGIC.1.to_be_bytes());
+        assert!(compressed(&wii, &options(Codec::Zstd)).is_err());
+        assert!(compressed(&image[..0x40], &options(Codec::Zstd)).is_err());
+    }
+
+    #[test]
+    fn verify_differs() {
+        let image = image();
+        let mut changed = image.clone();
+        changed[MIN_CHUNK as usize * 2 + 1] ^= 1;
+        let size = image.len() as u64;
+        assert!(verify(&mut &image[..], &mut &changed[..], size).is_err());
+    }
+}
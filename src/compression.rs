@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+const YAZ0_MAGIC: &[u8] = b"Yaz0";
+const YAY0_MAGIC: &[u8] = b"Yay0";
+
+/// Size of the Yaz0 and Yay0 headers, which is all [`decompressed_size`] needs.
+pub const HEADER_SIZE: usize = 0x10;
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(*data.get(offset..)?.first_chunk()?))
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(*data.get(offset..)?.first_chunk()?))
+}
+
+/// Returns the decompressed size of the data, or `None` if it isn't Yaz0 or Yay0 compressed.
+#[must_use]
+pub fn decompressed_size(header: &[u8]) -> Option<u64> {
+    if header.starts_with(YAZ0_MAGIC) || header.starts_with(YAY0_MAGIC) {
+        be32(header, 0x04).map(u64::from)
+    } else {
+        None
+    }
+}
+
+/// Appends `len` bytes starting `distance` bytes back from the end of `out`. The two ranges
+/// may overlap, repeating the bytes in between.
+fn copy_back(out: &mut Vec<u8>, distance: usize, len: usize) -> Option<()> {
+    let start = out.len().checked_sub(distance)?;
+    for i in start..start + len {
+        out.push(out[i]);
+    }
+    Some(())
+}
+
+/// Decompresses Yaz0 or Yay0 data.
+///
+/// Returns `None` if the data isn't compressed in either format or is truncated.
+#[must_use]
+pub fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    let size = usize::try_from(be32(data, 0x04)?).ok()?;
+    // Don't trust the header with huge allocations up front
+    let mut out = Vec::with_capacity(size.min(data.len().saturating_mul(16)));
+    if data.starts_with(YAZ0_MAGIC) {
+        decompress_yaz0(data, size, &mut out)?;
+    } else if data.starts_with(YAY0_MAGIC) {
+        decompress_yay0(data, size, &mut out)?;
+    } else {
+        return None;
+    }
+    out.truncate(size);
+    Some(out)
+}
+
+/// Yaz0 interleaves a group byte, one bit per chunk, with the chunks themselves: literal bytes
+/// for set bits, and back references for clear ones.
+fn decompress_yaz0(data: &[u8], size: usize, out: &mut Vec<u8>) -> Option<()> {
+    let mut position = HEADER_SIZE;
+    let mut next = || {
+        let byte = *data.get(position)?;
+        position += 1;
+        Some(byte)
+    };
+    while out.len() < size {
+        let group = next()?;
+        for bit in (0..8).rev() {
+            if out.len() >= size {
+                break;
+            }
+            if group & (1 << bit) != 0 {
+                out.push(next()?);
+                continue;
+            }
+            let first = next()?;
+            let second = next()?;
+            let distance = (usize::from(first & 0x0F) << 8 | usize::from(second)) + 1;
+            let len = match first >> 4 {
+                0 => usize::from(next()?) + 0x12,
+                len => usize::from(len) + 2,
+            };
+            copy_back(out, distance, len)?;
+        }
+    }
+    Some(())
+}
+
+/// Yay0 keeps the group bits, back references and literal bytes in three separate streams.
+fn decompress_yay0(data: &[u8], size: usize, out: &mut Vec<u8>) -> Option<()> {
+    let mut links = usize::try_from(be32(data, 0x08)?).ok()?;
+    let mut chunks = usize::try_from(be32(data, 0x0C)?).ok()?;
+    let mut masks = HEADER_SIZE;
+    while out.len() < size {
+        let mask = be32(data, masks)?;
+        masks += 4;
+        for bit in (0..32).rev() {
+            if out.len() >= size {
+                break;
+            }
+            if mask & (1 << bit) != 0 {
+                out.push(*data.get(chunks)?);
+                chunks += 1;
+                continue;
+            }
+            let link = be16(data, links)?;
+            links += 2;
+            let distance = usize::from(link & 0x0FFF) + 1;
+            let len = match link >> 12 {
+                0 => {
+                    let len = usize::from(*data.get(chunks)?) + 0x12;
+                    chunks += 1;
+                    len
+                }
+                len => usize::from(len) + 2,
+            };
+            copy_back(out, distance, len)?;
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three literals, a back reference overlapping itself, a literal and a long back reference.
+    const DECOMPRESSED: &[u8] = b"abcabcabcabcXXXXXXXXXXXXXXXXXXXXX";
+
+    fn header(magic: &[u8], links: u32, chunks: u32) -> Vec<u8> {
+        let mut data = magic.to_vec();
+        data.extend(u32::try_from(DECOMPRESSED.len()).unwrap().to_be_bytes());
+        data.extend(links.to_be_bytes());
+        data.extend(chunks.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn yaz0() {
+        let mut data = header(YAZ0_MAGIC, 0, 0);
+        data.extend([0xE8, b'a', b'b', b'c', 0x70, 0x02, b'X', 0x00, 0x00, 0x02]);
+        assert_eq!(decompressed_size(&data), Some(33));
+        assert_eq!(decompress(&data).unwrap(), DECOMPRESSED);
+        // Truncated
+        assert!(decompress(&data[..data.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn yay0() {
+        let mut data = header(YAY0_MAGIC, 0x14, 0x18);
+        data.extend(0xE800_0000u32.to_be_bytes());
+        data.extend([0x70, 0x02, 0x00, 0x00]);
+        data.extend([b'a', b'b', b'c', b'X', 0x02]);
+        assert_eq!(decompressed_size(&data), Some(33));
+        assert_eq!(decompress(&data).unwrap(), DECOMPRESSED);
+        assert!(decompress(&data[..data.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn before_start() {
+        // A back reference before anything was decompressed
+        let mut data = header(YAZ0_MAGIC, 0, 0);
+        data.extend([0x00, 0x10, 0x00]);
+        assert!(decompress(&data).is_none());
+    }
+
+    #[test]
+    fn uncompressed() {
+        assert_eq!(decompressed_size(b"RARC\0\0\0\x10"), None);
+        assert!(decompress(b"RARC\0\0\0\x10").is_none());
+    }
+}
@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::cache;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Returns the directory downloaded covers are cached in when no cover directory is given,
+/// following the XDG base directory spec.
+#[must_use]
+pub fn cache_dir() -> Option<PathBuf> {
+    Some(cache::xdg_cache_dir()?.join("covers"))
+}
+
+/// Returns the path of the cover art for the game with the given ID in `dir`, named
+/// `GAMEID.png` with either the six or four character ID.
+///
+/// With `online`, covers that aren't in `dir` yet are downloaded from `GameTDB` and saved there.
+///
+/// # Errors
+///
+/// [`io::Error`] if the cover can't be downloaded or saved.
+pub fn find(game_id: &str, dir: &Path, online: bool) -> io::Result<Option<PathBuf>> {
+    let short_id = game_id.get(..4).unwrap_or(game_id);
+    for id in [game_id, short_id] {
+        let path = dir.join(format!("{id}.png"));
+        if path.is_file() {
+            return Ok(Some(path));
+        }
+    }
+    if !online {
+        return Ok(None);
+    }
+    let Some(cover) = download(game_id)? else {
+        return Ok(None);
+    };
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{game_id}.png"));
+    // Write it under another name first, so other mounts never see a partial cover
+    let partial = dir.join(format!(".{game_id}.png.partial"));
+    fs::write(&partial, cover)?;
+    fs::rename(&partial, &path)?;
+    Ok(Some(path))
+}
+
+/// Returns the `GameTDB` cover regions to try for a game, most specific first, going by the
+/// country code in its ID.
+#[cfg(feature = "online")]
+fn regions(game_id: &str) -> Vec<&'static str> {
+    let region = match game_id.as_bytes().get(3) {
+        Some(b'E') => "US",
+        Some(b'J') => "JA",
+        Some(b'K') => "KO",
+        Some(b'D') => "DE",
+        Some(b'F') => "FR",
+        Some(b'S') => "ES",
+        Some(b'I') => "IT",
+        Some(b'H') => "NL",
+        _ => "EN",
+    };
+    let mut regions = vec![region];
+    for fallback in ["EN", "US"] {
+        if !regions.contains(&fallback) {
+            regions.push(fallback);
+        }
+    }
+    regions
+}
+
+/// Downloads the cover for the game, returning `None` if `GameTDB` doesn't have one.
+#[cfg(feature = "online")]
+fn download(game_id: &str) -> io::Result<Option<Vec<u8>>> {
+    for region in regions(game_id) {
+        let url = format!("https://art.gametdb.com/wii/cover/{region}/{game_id}.png");
+        match ureq::get(&url).call() {
+            Ok(mut response) => {
+                let cover = response
+                    .body_mut()
+                    .read_to_vec()
+                    .map_err(io::Error::other)?;
+                return Ok(Some(cover));
+            }
+            Err(ureq::Error::StatusCode(404)) => {}
+            Err(err) => return Err(io::Error::other(err)),
+        }
+    }
+    Ok(None)
+}
+
+/// Downloads are only supported with the `online` feature.
+#[cfg(not(feature = "online"))]
+fn download(_game_id: &str) -> io::Result<Option<Vec<u8>>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "gcnfuse was built without the online feature",
+    ))
+}
@@ -0,0 +1,373 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::cache;
+use crate::error::Error;
+use crate::fuse::GcnFuse;
+use crate::fuse::Stats;
+use crate::image::Image;
+use crate::json::Json;
+use crate::mount::MountHandle;
+use crate::pool;
+use fuser::MountOption;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::iter;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// How long the daemon waits for a request before looking for mounts that were unmounted.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a client may take to send its request, or to take the reply.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Requests longer than this are refused, so nothing can make the daemon buffer without end.
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// Returns the socket the daemon listens on by default, `gcnfuse.sock` in `$XDG_RUNTIME_DIR`.
+#[must_use]
+pub fn default_socket_path() -> Option<PathBuf> {
+    let runtime = PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?);
+    runtime.is_absolute().then(|| runtime.join("gcnfuse.sock"))
+}
+
+/// Sends `request` to the daemon listening at `socket`, returning its reply.
+///
+/// # Errors
+///
+/// [`io::Error`] if the daemon can't be reached, or it doesn't reply with JSON.
+pub fn request(socket: &Path, request: &Json) -> io::Result<Json> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    writeln!(stream, "{request}")?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Json::parse(&reply).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the daemon's reply isn't valid JSON",
+        )
+    })
+}
+
+/// A filesystem for the daemon to mount, with what's shown about it.
+pub struct Opened {
+    pub filesystem: GcnFuse<Image>,
+    /// The ID of the disc's game.
+    pub game_id: String,
+    /// The game's title, if known.
+    pub title: Option<String>,
+    /// The options to mount it with.
+    pub options: Vec<MountOption>,
+}
+
+/// Opens an image for the daemon to mount.
+type Opener = Box<dyn FnMut(&Path) -> Result<Opened, Error>>;
+
+/// An image mounted by the daemon.
+struct Mounted {
+    image: PathBuf,
+    /// Where it's mounted, as an absolute path.
+    dir: PathBuf,
+    game_id: String,
+    title: Option<String>,
+    since: SystemTime,
+    stats: Arc<Stats>,
+    handle: MountHandle,
+}
+
+/// Mounts images from a single process, so they share its caches and decompression threads, and
+/// answers requests to change or report them on a Unix socket.
+///
+/// Requests and replies are JSON objects on a line each, one of each per connection. Requests
+/// have a `command`, one of:
+///
+/// - `mount`, mounting the `image` at `mount`;
+/// - `unmount`, unmounting whatever is at `mount`;
+/// - `list`, replying with the `mounts` and what's mounted there;
+/// - `stats`, replying with how much the caches hold and how much was read;
+/// - `set-cache-limit`, setting how many `bytes` the caches may hold.
+///
+/// Replies have `ok` set to whether the request worked, and an `error` if it didn't.
+/// Relative paths in requests are taken from the daemon's working directory.
+pub struct Daemon {
+    /// Opens the images to mount, showing them as the daemon was asked to.
+    open: Opener,
+    mounts: Vec<Mounted>,
+    /// Whether anything was ever mounted, after which the daemon ends with its last mount.
+    mounted_any: bool,
+    /// Set to have the daemon unmount everything and end.
+    stop: Arc<AtomicBool>,
+}
+
+impl Daemon {
+    /// Returns a daemon opening images to mount with `open`.
+    pub fn new(open: impl FnMut(&Path) -> Result<Opened, Error> + 'static) -> Self {
+        Self {
+            open: Box::new(open),
+            mounts: vec![],
+            mounted_any: false,
+            stop: Arc::default(),
+        }
+    }
+
+    /// Returns a flag that, once set, has [`serve`](Self::serve) unmount everything and return.
+    #[must_use]
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Mounts the image at `image` at `dir`.
+    ///
+    /// # Errors
+    ///
+    /// [`Error`] if the image can't be opened or mounted, or something is already mounted at
+    /// `dir` by the daemon.
+    pub fn mount(&mut self, image: &Path, dir: &Path) -> Result<(), Error> {
+        let dir = fs::canonicalize(dir)?;
+        self.forget_unmounted();
+        if self.mounts.iter().any(|mounted| mounted.dir == dir) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is already mounted", dir.display()),
+            )
+            .into());
+        }
+        let opened = (self.open)(image)?;
+        let stats = opened.filesystem.stats();
+        let handle = opened.filesystem.spawn_mount(&dir, &opened.options)?;
+        self.mounts.push(Mounted {
+            image: image.to_path_buf(),
+            dir,
+            game_id: opened.game_id,
+            title: opened.title,
+            since: SystemTime::now(),
+            stats,
+            handle,
+        });
+        self.mounted_any = true;
+        Ok(())
+    }
+
+    /// Unmounts whatever the daemon mounted at `dir`.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the daemon mounted nothing there, or answering the mount had failed.
+    pub fn unmount(&mut self, dir: &Path) -> io::Result<()> {
+        let dir = fs::canonicalize(dir)?;
+        let position = self
+            .mounts
+            .iter()
+            .position(|mounted| mounted.dir == dir)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("nothing is mounted at {}", dir.display()),
+                )
+            })?;
+        self.mounts.remove(position).handle.join()
+    }
+
+    /// Answers requests on `listener`, if given, until every mount is unmounted or the
+    /// [`stop_flag`](Self::stop_flag) is set. A daemon that never mounted anything keeps waiting
+    /// for requests to.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if waiting for requests fails.
+    pub fn serve(mut self, listener: Option<&UnixListener>) -> io::Result<()> {
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                // Each is unmounted as it's dropped
+                self.mounts.clear();
+                return Ok(());
+            }
+            self.forget_unmounted();
+            if self.mounted_any && self.mounts.is_empty() {
+                return Ok(());
+            }
+            let Some(listener) = listener else {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            };
+            if !wait_readable(listener, POLL_INTERVAL)? {
+                continue;
+            }
+            // A client that went away, or misbehaves, is no reason to stop
+            if let Ok((stream, _)) = listener.accept()
+                && let Err(err) = self.answer(&stream)
+            {
+                eprintln!("can't answer a request: {err}");
+            }
+        }
+    }
+
+    /// Drops the mounts that were unmounted from outside, such as with `umount`.
+    fn forget_unmounted(&mut self) {
+        self.mounts.retain(|mounted| mounted.handle.is_mounted());
+    }
+
+    /// Reads a request from `stream` and replies to it.
+    fn answer(&mut self, stream: &UnixStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new(io::Read::take(stream, MAX_REQUEST_SIZE)).read_line(&mut line)?;
+        let reply = Json::parse(&line).map_or_else(
+            || failure("the request isn't valid JSON"),
+            |request| self.handle(&request),
+        );
+        writeln!(&mut &*stream, "{reply}")
+    }
+
+    /// Carries out `request`, returning the reply.
+    fn handle(&mut self, request: &Json) -> Json {
+        let path = |key| request.get(key).and_then(Json::as_str).map(Path::new);
+        match request.get("command").and_then(Json::as_str) {
+            Some("mount") => {
+                let (Some(image), Some(dir)) = (path("image"), path("mount")) else {
+                    return failure("mounting takes an image and a mount");
+                };
+                reply(self.mount(image, dir), |()| vec![])
+            }
+            Some("unmount") => {
+                let Some(dir) = path("mount") else {
+                    return failure("unmounting takes a mount");
+                };
+                reply(self.unmount(dir), |()| vec![])
+            }
+            Some("list") => {
+                self.forget_unmounted();
+                let mounts = self.mounts.iter().map(Mounted::describe).collect();
+                success(vec![("mounts", Json::Array(mounts))])
+            }
+            Some("stats") => {
+                let (reads, bytes_read) = self.mounts.iter().fold((0, 0), |(reads, bytes), m| {
+                    (reads + m.stats.reads(), bytes + m.stats.bytes_read())
+                });
+                success(vec![
+                    ("mounts", (self.mounts.len() as u64).into()),
+                    ("cache_used", cache::cache_used().into()),
+                    ("cache_limit", cache::cache_limit().into()),
+                    (
+                        "decompression_threads",
+                        (pool::decompression_threads() as u64).into(),
+                    ),
+                    ("reads", reads.into()),
+                    ("bytes_read", bytes_read.into()),
+                ])
+            }
+            Some("set-cache-limit") => {
+                let Some(bytes) = request.get("bytes").and_then(Json::as_u64) else {
+                    return failure("setting the cache limit takes a number of bytes");
+                };
+                cache::set_cache_limit(bytes);
+                success(vec![])
+            }
+            Some(command) => failure(&format!("unknown command \"{command}\"")),
+            None => failure("the request has no command"),
+        }
+    }
+}
+
+impl Mounted {
+    /// Returns what's mounted and where, as listed.
+    fn describe(&self) -> Json {
+        let since = self
+            .since
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        Json::object([
+            ("image", self.image.to_string_lossy().as_ref().into()),
+            ("mount", self.dir.to_string_lossy().as_ref().into()),
+            ("game_id", self.game_id.as_str().into()),
+            ("title", self.title.as_deref().into()),
+            ("since", since.into()),
+            ("reads", self.stats.reads().into()),
+            ("bytes_read", self.stats.bytes_read().into()),
+        ])
+    }
+}
+
+/// Returns a reply to a request that worked, with `members` added.
+fn success(members: Vec<(&str, Json)>) -> Json {
+    Json::object(iter::once(("ok", true.into())).chain(members))
+}
+
+/// Returns a reply to a request that failed because of `error`.
+fn failure(error: &str) -> Json {
+    Json::object([("ok", false.into()), ("error", error.into())])
+}
+
+/// Returns a reply to a request with the given result, with members from `members` if it worked.
+fn reply<T, E: ToString>(
+    result: Result<T, E>,
+    members: impl FnOnce(T) -> Vec<(&'static str, Json)>,
+) -> Json {
+    match result {
+        Ok(value) => success(members(value)),
+        Err(err) => failure(&err.to_string()),
+    }
+}
+
+/// Waits up to `timeout` for a client to connect to `listener`, returning whether one did.
+fn wait_readable(listener: &UnixListener, timeout: Duration) -> io::Result<bool> {
+    let mut fd = libc::pollfd {
+        fd: listener.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // Intervals are short
+    #[allow(clippy::cast_possible_truncation)]
+    let timeout = timeout.as_millis() as libc::c_int;
+    // SAFETY: the pollfd outlives the call, which is told there's one
+    match unsafe { libc::poll(&raw mut fd, 1, timeout) } {
+        -1 => {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                Ok(false)
+            } else {
+                Err(err)
+            }
+        }
+        ready => Ok(ready > 0),
+    }
+}
+
+/// Listens for requests at `socket`, only letting the user running the daemon connect. A socket
+/// left behind by a daemon that's gone is replaced.
+///
+/// # Errors
+///
+/// [`io::Error`] if the socket can't be made, or another daemon is listening there.
+pub fn listen(socket: &Path) -> io::Result<UnixListener> {
+    if UnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another daemon is listening at {}", socket.display()),
+        ));
+    }
+    match fs::remove_file(socket) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let listener = UnixListener::bind(socket)?;
+    // Whoever can connect can mount anything the daemon can read
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
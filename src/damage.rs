@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::layout;
+use crate::tree::FileData;
+use crate::tree::Kind;
+use crate::tree::Tree;
+use gcn_disk::Entry;
+use gcn_disk::Fst;
+use std::fmt::Write;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::ops::Range;
+
+/// Granularity of the scan, the size of a disc sector.
+const BLOCK_SIZE: u64 = 0x8000;
+
+/// Returns the ranges of `range` in the image that can't be read, along with why. The image is
+/// read a sector at a time, so ranges are as precise as that.
+fn scan<T: Read + Seek>(io: &mut T, range: Range<u64>) -> Vec<(Range<u64>, String)> {
+    let mut damage: Vec<(Range<u64>, String)> = vec![];
+    let mut block = range.start;
+    while block < range.end {
+        let block_end = ((block / BLOCK_SIZE + 1) * BLOCK_SIZE).min(range.end);
+        // At most a block
+        #[allow(clippy::cast_possible_truncation)]
+        let mut buffer = vec![0; (block_end - block) as usize];
+        let read = io
+            .seek(SeekFrom::Start(block))
+            .and_then(|_| io.read_exact(&mut buffer));
+        if let Err(err) = read {
+            let error = err.to_string();
+            match damage.last_mut() {
+                Some((last, last_error)) if last.end == block && *last_error == error => {
+                    last.end = block_end;
+                }
+                _ => damage.push((block..block_end, error)),
+            }
+        }
+        block = block_end;
+    }
+    damage
+}
+
+/// Reads the data of every disc file in `tree` and returns a report listing the files with data
+/// that can't be read, and which of their bytes those are.
+pub fn report<T: Read + Seek>(io: &mut T, tree: &Tree, fs: &Fst) -> String {
+    let mut files = 0;
+    let mut damaged = String::new();
+    let mut damaged_files = 0;
+    for inode in layout::files(tree) {
+        let Some(Kind::File(FileData::Disc(index))) = tree.get(inode).map(|node| &node.kind) else {
+            continue;
+        };
+        let Entry::File(entry) = &fs.entries[index.as_usize()] else {
+            unreachable!("disc file nodes always point to FST file entries");
+        };
+        files += 1;
+        let start = u64::from(entry.offset);
+        let damage = scan(io, start..start + u64::from(entry.size));
+        if damage.is_empty() {
+            continue;
+        }
+        damaged_files += 1;
+        let path = tree.path(inode);
+        let _ = writeln!(damaged, "\n/{} ({} bytes):", path.display(), entry.size);
+        for (range, error) in damage {
+            let _ = writeln!(
+                damaged,
+                "  {:#x}..{:#x}: {error}",
+                range.start - start,
+                range.end - start
+            );
+        }
+    }
+    format!("{damaged_files} of {files} files have data that can't be read\n{damaged}")
+}
@@ -0,0 +1,254 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::hashes;
+use crate::hashes::Hashes;
+use crate::image::Image;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+
+/// A dump listed in a DAT, the file of one of its games.
+#[derive(Clone, Debug)]
+pub struct Dump {
+    /// Name of the game, such as `Ikaruga (Japan)`.
+    pub game: String,
+    /// Name of the file, such as `Ikaruga (Japan).iso`.
+    pub file: String,
+    pub size: u64,
+    pub crc32: Option<u32>,
+    pub sha1: Option<[u8; 20]>,
+}
+
+impl Dump {
+    /// Returns whether an image of `size` bytes with `hashes` is this dump. The SHA-1 is compared
+    /// if the DAT has it, and the CRC32 otherwise.
+    fn matches(&self, size: u64, hashes: &Hashes) -> bool {
+        self.size == size
+            && match (self.sha1, self.crc32) {
+                (Some(sha1), _) => sha1 == hashes.sha1,
+                (None, Some(crc32)) => crc32 == hashes.crc32,
+                (None, None) => false,
+            }
+    }
+}
+
+/// What verifying an image against a DAT found.
+#[derive(Clone, Debug)]
+pub enum Verdict {
+    /// The image is one of the DAT's dumps.
+    Good(Dump),
+    /// The image has the name of one of the DAT's dumps, or of its game, but not its contents,
+    /// or it can't be read, as said.
+    Bad(String),
+    /// The image isn't in the DAT.
+    Unknown,
+}
+
+/// Dumps known to be good, as listed in a DAT in the Logiqx XML format redump and No-Intro
+/// publish theirs in.
+#[derive(Clone, Debug, Default)]
+pub struct Dat {
+    dumps: Vec<Dump>,
+}
+
+/// Returns `text` with XML's entities and character references replaced.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        if let Some(c) = c {
+            unescaped.push(c);
+            rest = &rest[end + 1..];
+        } else {
+            // Not a reference, so taken as is
+            unescaped.push('&');
+            rest = &rest[1..];
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Returns the name and attributes of each start tag in the XML document `text`, in order.
+/// Comments, declarations, end tags and text are skipped.
+fn tags(text: &str) -> Vec<(&str, Vec<(&str, String)>)> {
+    let mut tags = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        // Declarations and end tags have nothing of interest
+        if rest.starts_with(['!', '?', '/']) {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        rest = &rest[name_end..];
+        let mut attributes = vec![];
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with(['>', '/', '?']) {
+                break;
+            }
+            let Some((attribute, value)) = rest
+                .split_once('=')
+                .filter(|(attribute, _)| !attribute.contains(['<', '>']))
+            else {
+                break;
+            };
+            let value = value.trim_start();
+            let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') else {
+                break;
+            };
+            let Some(end) = value[1..].find(quote) else {
+                break;
+            };
+            attributes.push((attribute.trim(), unescape(&value[1..=end])));
+            rest = &value[end + 2..];
+        }
+        tags.push((name, attributes));
+    }
+    tags
+}
+
+/// Returns `path`'s file name without its extension.
+fn stem(path: &str) -> &str {
+    Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(path)
+}
+
+impl Dat {
+    /// Loads a DAT in the Logiqx XML format, where each `game` element has a `rom` element for
+    /// each of its files with their sizes and hashes. Files without a size are ignored.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the file can't be read.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read(path)?;
+        let contents = String::from_utf8_lossy(&contents);
+        let mut dumps = vec![];
+        let mut game = String::new();
+        for (tag, attributes) in tags(&contents) {
+            let attribute = |name| {
+                attributes
+                    .iter()
+                    .find(|(attribute, _)| *attribute == name)
+                    .map(|(_, value)| value.as_str())
+            };
+            match tag {
+                // Older DATs call games machines
+                "game" | "machine" => game = attribute("name").unwrap_or_default().to_string(),
+                "rom" => {
+                    let Some(size) = attribute("size").and_then(|size| size.parse().ok()) else {
+                        continue;
+                    };
+                    dumps.push(Dump {
+                        game: game.clone(),
+                        file: attribute("name").unwrap_or_default().to_string(),
+                        size,
+                        crc32: attribute("crc").and_then(|crc| u32::from_str_radix(crc, 16).ok()),
+                        sha1: attribute("sha1").and_then(hashes::parse_hex),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(Self { dumps })
+    }
+
+    /// Returns whether the DAT lists no dumps.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.dumps.is_empty()
+    }
+
+    /// Returns whether the image at `path` is one of the dumps, comparing the hashes of the
+    /// whole disc it holds, decompressed if it's compressed.
+    ///
+    /// Images that aren't are bad if they have the name of a dump or its game, extension aside,
+    /// as they're most likely a bad dump of it, and unknown otherwise.
+    #[must_use]
+    pub fn verify(&self, path: &Path) -> Verdict {
+        let hashes = Image::open(path).and_then(|mut image| {
+            let size = image.disc_size()?;
+            Ok((size, Hashes::compute(&mut image, 0, size)?))
+        });
+        let (size, hashes) = match hashes {
+            Ok(hashes) => hashes,
+            Err(err) => return Verdict::Bad(err.to_string()),
+        };
+        if let Some(dump) = self.dumps.iter().find(|dump| dump.matches(size, &hashes)) {
+            return Verdict::Good(dump.clone());
+        }
+        let name = path.file_stem().and_then(|stem| stem.to_str());
+        self.dumps
+            .iter()
+            .find(|dump| name == Some(stem(&dump.file)) || name == Some(&dump.game))
+            .map_or(Verdict::Unknown, |dump| {
+                Verdict::Bad(format!("not the dump {} of the DAT", dump.file))
+            })
+    }
+}
+
+/// Verifies each of `images` against `dat` with `jobs` threads, returning what was found for
+/// each, in the same order.
+#[must_use]
+pub fn verify_all(dat: &Dat, images: &[PathBuf], jobs: usize) -> Vec<Verdict> {
+    let next = Mutex::new(images.iter().enumerate());
+    let verdicts = Mutex::new(vec![Verdict::Unknown; images.len()]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, images.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    // Nothing is left half done by a thread that panicked holding the lock
+                    let image = next
+                        .lock()
+                        .unwrap_or_else(std::sync::PoisonError::into_inner)
+                        .next();
+                    let Some((index, path)) = image else {
+                        break;
+                    };
+                    let verdict = dat.verify(path);
+                    verdicts
+                        .lock()
+                        .unwrap_or_else(std::sync::PoisonError::into_inner)[index] = verdict;
+                }
+            });
+        }
+    });
+    verdicts
+        .into_inner()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
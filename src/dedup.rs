@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::layout;
+use gcn_disk::Disc;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::hash::DefaultHasher;
+use std::hash::Hasher;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+/// How much of each file is read at once.
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Files of a disc with the same contents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Duplicates {
+    /// Size of each of the files.
+    pub size: u64,
+    /// Paths of the files, as [`layout::contents`] names them, sorted.
+    pub paths: Vec<String>,
+    /// How much smaller the image would be if the files shared a single copy of their contents.
+    /// Files whose FST entries already point to the same data don't count.
+    pub wasted: u64,
+}
+
+/// Reads `len` bytes at `offset` in `io`, a chunk at a time.
+fn for_each_chunk<T: Read + Seek>(
+    io: &mut T,
+    offset: u64,
+    len: u64,
+    mut f: impl FnMut(&[u8]) -> bool,
+) -> io::Result<()> {
+    io.seek(SeekFrom::Start(offset))?;
+    let mut done = 0;
+    while done < len {
+        // At most a chunk
+        #[allow(clippy::cast_possible_truncation)]
+        let size = (len - done).min(CHUNK_SIZE) as usize;
+        let mut chunk = vec![0; size];
+        io.read_exact(&mut chunk)?;
+        if !f(&chunk) {
+            break;
+        }
+        done += size as u64;
+    }
+    Ok(())
+}
+
+/// Returns a hash of `len` bytes at `offset` in `io`.
+fn hash<T: Read + Seek>(io: &mut T, offset: u64, len: u64) -> io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    for_each_chunk(io, offset, len, |chunk| {
+        hasher.write(chunk);
+        true
+    })?;
+    Ok(hasher.finish())
+}
+
+/// Returns whether the `len` bytes at `a` in `io` are the same as those at `b`.
+fn same<T: Read + Seek>(io: &mut T, a: u64, b: u64, len: u64) -> io::Result<bool> {
+    if a == b {
+        return Ok(true);
+    }
+    let mut chunks = vec![];
+    for_each_chunk(io, a, len, |chunk| {
+        chunks.push(chunk.to_vec());
+        true
+    })?;
+    let mut chunks = chunks.into_iter();
+    let mut same = true;
+    for_each_chunk(io, b, len, |chunk| {
+        same = chunks.next().is_some_and(|other| other == chunk);
+        same
+    })?;
+    Ok(same)
+}
+
+/// Splits files of `size` bytes with the same hash into sets with the same contents, in case the
+/// hashes collide, returning the sets of more than one file.
+fn identical<T: Read + Seek>(
+    io: &mut T,
+    size: u64,
+    files: Vec<(String, u64)>,
+) -> io::Result<Vec<Duplicates>> {
+    let mut sets: Vec<Vec<(String, u64)>> = vec![];
+    for (path, offset) in files {
+        let mut found = None;
+        for (index, set) in sets.iter().enumerate() {
+            if same(io, set[0].1, offset, size)? {
+                found = Some(index);
+                break;
+            }
+        }
+        match found {
+            Some(index) => sets[index].push((path, offset)),
+            None => sets.push(vec![(path, offset)]),
+        }
+    }
+    Ok(sets
+        .into_iter()
+        .filter(|set| set.len() > 1)
+        .map(|set| {
+            let copies = set
+                .iter()
+                .map(|&(_, offset)| offset)
+                .collect::<BTreeSet<_>>();
+            let mut paths = set.into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+            paths.sort();
+            Duplicates {
+                size,
+                paths,
+                wasted: size * (copies.len() as u64 - 1),
+            }
+        })
+        .collect())
+}
+
+/// Finds the system files and FST files of a disc with the same contents.
+///
+/// The sets are sorted by how much space they waste. Only files of the same size are read, and those with the same hash are
+/// compared byte by byte. Empty files are left out.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the image can't be read.
+pub fn duplicates<T: Read + Seek>(io: &mut T, disc: &mut Disc) -> Result<Vec<Duplicates>, Error> {
+    let mut by_size: BTreeMap<u64, Vec<(String, u64)>> = BTreeMap::new();
+    for (path, (offset, size)) in layout::contents(io, disc)? {
+        if size > 0 {
+            by_size.entry(size).or_default().push((path, offset));
+        }
+    }
+    let mut groups = vec![];
+    for (size, files) in by_size {
+        if files.len() < 2 {
+            continue;
+        }
+        let mut by_hash: HashMap<u64, Vec<(String, u64)>> = HashMap::new();
+        for (path, offset) in files {
+            let hash = hash(io, offset, size)?;
+            by_hash.entry(hash).or_default().push((path, offset));
+        }
+        for files in by_hash.into_values() {
+            groups.extend(identical(io, size, files)?);
+        }
+    }
+    groups.sort_by(|a, b| b.wasted.cmp(&a.wasted).then_with(|| a.paths.cmp(&b.paths)));
+    Ok(groups)
+}
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::layout;
+use gcn_disk::Disc;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+/// How much of each file is compared at once.
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// A difference between two discs, with the path of what differs as [`layout::contents`] names
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Only in the second disc.
+    Added(String),
+    /// Only in the first disc.
+    Removed(String),
+    /// In both discs, with different contents.
+    Changed(String),
+}
+
+impl Change {
+    /// Returns the path of what differs.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added(path) | Self::Removed(path) | Self::Changed(path) => path,
+        }
+    }
+}
+
+/// Returns whether `len` bytes at `offset_a` in `a` are the same as those at `offset_b` in `b`.
+fn same<A: Read + Seek, B: Read + Seek>(
+    a: &mut A,
+    offset_a: u64,
+    b: &mut B,
+    offset_b: u64,
+    len: u64,
+) -> io::Result<bool> {
+    let mut done = 0;
+    while done < len {
+        // At most a chunk
+        #[allow(clippy::cast_possible_truncation)]
+        let size = (len - done).min(CHUNK_SIZE) as usize;
+        let mut chunk_a = vec![0; size];
+        let mut chunk_b = vec![0; size];
+        a.seek(SeekFrom::Start(offset_a + done))?;
+        a.read_exact(&mut chunk_a)?;
+        b.seek(SeekFrom::Start(offset_b + done))?;
+        b.read_exact(&mut chunk_b)?;
+        if chunk_a != chunk_b {
+            return Ok(false);
+        }
+        done += size as u64;
+    }
+    Ok(true)
+}
+
+/// Compares the system files and FST files of two discs, returning what changed from the first
+/// to the second, sorted by path. Files of the same size are compared byte by byte.
+///
+/// # Errors
+///
+/// [`Error::Io`] if either image can't be read.
+pub fn diff<A: Read + Seek, B: Read + Seek>(
+    a: &mut A,
+    disc_a: &mut Disc,
+    b: &mut B,
+    disc_b: &mut Disc,
+) -> Result<Vec<Change>, Error> {
+    let contents_a = layout::contents(a, disc_a)?;
+    let mut contents_b = layout::contents(b, disc_b)?;
+    let mut changes = vec![];
+    for (path, (offset_a, size_a)) in contents_a {
+        let Some((offset_b, size_b)) = contents_b.remove(&path) else {
+            changes.push(Change::Removed(path));
+            continue;
+        };
+        if size_a != size_b || !same(a, offset_a, b, offset_b, size_a)? {
+            changes.push(Change::Changed(path));
+        }
+    }
+    changes.extend(contents_b.into_keys().map(Change::Added));
+    changes.sort_by(|x, y| x.path().cmp(y.path()));
+    Ok(changes)
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+/// Size of the DOL header.
+pub const HEADER_SIZE: usize = 0x100;
+
+/// A loadable section of a DOL executable.
+#[derive(Copy, Clone, Debug)]
+pub struct Section {
+    /// Number of the section among the header's text or data sections.
+    pub number: usize,
+    /// Offset of the section from the start of the DOL.
+    pub offset: u32,
+    /// Address the section is loaded at.
+    pub address: u32,
+    /// Size of the section.
+    pub size: u32,
+}
+
+/// Header of a DOL executable, the format of Gamecube boot files.
+#[derive(Clone, Debug)]
+pub struct Dol {
+    /// Text sections, skipping unused ones.
+    pub text: Vec<Section>,
+    /// Data sections, skipping unused ones.
+    pub data: Vec<Section>,
+    /// Address of the zero initialized section.
+    pub bss_address: u32,
+    /// Size of the zero initialized section.
+    pub bss_size: u32,
+    /// Address execution starts at.
+    pub entry_point: u32,
+}
+
+fn be_u32(header: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(header[offset..offset + 4].try_into().unwrap())
+}
+
+impl Dol {
+    /// Parses the DOL header found at `offset` in `io`.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the header can't be read.
+    pub fn read<T: Read + Seek>(io: &mut T, offset: u64) -> io::Result<Self> {
+        let mut header = [0u8; HEADER_SIZE];
+        io.seek(SeekFrom::Start(offset))?;
+        io.read_exact(&mut header)?;
+        Ok(Self::from(&header))
+    }
+
+    /// Returns the size of the DOL, computed as the end of its furthest section.
+    #[must_use]
+    pub fn size(&self) -> u32 {
+        self.text
+            .iter()
+            .chain(&self.data)
+            .map(|section| section.offset.saturating_add(section.size))
+            .fold(u32::try_from(HEADER_SIZE).unwrap(), u32::max)
+    }
+}
+
+impl From<&[u8; HEADER_SIZE]> for Dol {
+    fn from(header: &[u8; HEADER_SIZE]) -> Self {
+        // 7 text sections followed by 11 data sections for each field
+        let section = |i: usize| Section {
+            number: if i < 7 { i } else { i - 7 },
+            offset: be_u32(header, i * 4),
+            address: be_u32(header, 0x48 + i * 4),
+            size: be_u32(header, 0x90 + i * 4),
+        };
+        let used = |section: &Section| section.size != 0;
+        Self {
+            text: (0..7).map(section).filter(used).collect(),
+            data: (7..18).map(section).filter(used).collect(),
+            bss_address: be_u32(header, 0xD8),
+            bss_size: be_u32(header, 0xDC),
+            entry_point: be_u32(header, 0xE0),
+        }
+    }
+}
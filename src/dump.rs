@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::dol::Dol;
+use crate::error::Error;
+use crate::json::Json;
+use crate::layout;
+use crate::layout::APPLOADER_OFFSET;
+use crate::layout::BI2_SIZE;
+use crate::layout::HEADER_SIZE;
+use clap::ValueEnum;
+use gcn_disk::Disc;
+use gcn_disk::Entry;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+/// Format of the tables [`dump_fst`] writes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TableFormat {
+    /// A single JSON object, with `regions` and `entries` arrays.
+    #[default]
+    Json,
+    /// Comma separated values with a header line, a row for each region and then each entry.
+    Csv,
+}
+
+/// An entry of the FST, as the disc records it.
+struct Row {
+    index: u64,
+    /// The innermost directory the entry is in, or `None` for the root.
+    parent: Option<u64>,
+    directory: bool,
+    /// `None` if it can't be read.
+    name: Option<String>,
+    /// Where a file's contents are in the image, and how many bytes.
+    offset: Option<u64>,
+    size: Option<u64>,
+    /// Index just past a directory's last entry.
+    end: Option<u64>,
+}
+
+/// Returns the regions of the image holding the system files and FST, as `(name, offset, size)`,
+/// named after the files under `sys/` that [`layout::contents`] lists them as.
+fn regions<T: Read + Seek>(
+    io: &mut T,
+    disc: &Disc,
+) -> Result<Vec<(&'static str, u64, u64)>, Error> {
+    let header = &disc.header;
+    let dol_offset = u64::from(header.executable_offset);
+    let dol = Dol::read(io, dol_offset)?;
+    Ok(vec![
+        ("boot.bin", 0, HEADER_SIZE as u64),
+        ("bi2.bin", HEADER_SIZE as u64, BI2_SIZE as u64),
+        (
+            "apploader.img",
+            APPLOADER_OFFSET,
+            layout::apploader_size(io)?.into(),
+        ),
+        ("main.dol", dol_offset, dol.size().into()),
+        ("fst.bin", header.fst_offset.into(), header.fst_size.into()),
+    ])
+}
+
+/// Returns every entry of the FST in order, with names read as is, not made unique the way
+/// mounts list them.
+fn rows<T: Read + Seek>(io: &mut T, disc: &Disc) -> Result<Vec<Row>, Error> {
+    let image_size = io.seek(SeekFrom::End(0))?;
+    let fs = &disc.filesystem;
+    let mut rows = vec![];
+    // Index and end of the directories holding the current entry, innermost last
+    let mut parents: Vec<(u64, u64)> = vec![];
+    for (index, entry) in (0u64..).zip(&fs.entries) {
+        while parents.last().is_some_and(|&(_, end)| index >= end) {
+            parents.pop();
+        }
+        let parent = parents.last().map(|&(parent, _)| parent);
+        let filename_offset = match entry {
+            Entry::File(file) => file.filename_offset,
+            Entry::Directory(directory) => directory.filename_offset,
+        };
+        let name = if index == 0 {
+            Some(String::new())
+        // gcn_disk can't read a name starting at the end of the image
+        } else if u64::from(fs.string_table_offset) + u64::from(filename_offset) >= image_size {
+            None
+        } else {
+            match fs.get_entry_filename(io, entry) {
+                Ok(name) => Some(name),
+                Err(gcn_disk::Error::Io(err)) => return Err(err.into()),
+                Err(_) => None,
+            }
+        };
+        rows.push(match entry {
+            Entry::File(file) => Row {
+                index,
+                parent,
+                directory: false,
+                name,
+                offset: Some(file.offset.into()),
+                size: Some(file.size.into()),
+                end: None,
+            },
+            Entry::Directory(directory) => {
+                parents.push((index, directory.end_index.into()));
+                Row {
+                    index,
+                    parent,
+                    directory: true,
+                    name,
+                    offset: None,
+                    size: None,
+                    end: Some(directory.end_index.into()),
+                }
+            }
+        });
+    }
+    Ok(rows)
+}
+
+fn write_json<W: Write>(
+    regions: &[(&str, u64, u64)],
+    rows: Vec<Row>,
+    out: &mut W,
+) -> Result<(), Error> {
+    let regions = regions
+        .iter()
+        .map(|&(name, offset, size)| {
+            Json::object([
+                ("name", name.into()),
+                ("offset", offset.into()),
+                ("size", size.into()),
+            ])
+        })
+        .collect();
+    let entries = rows
+        .into_iter()
+        .map(|row| {
+            Json::object([
+                ("index", row.index.into()),
+                ("parent", row.parent.into()),
+                (
+                    "type",
+                    if row.directory { "directory" } else { "file" }.into(),
+                ),
+                ("name", row.name.into()),
+                ("offset", row.offset.into()),
+                ("size", row.size.into()),
+                ("end", row.end.into()),
+            ])
+        })
+        .collect();
+    let table = Json::object([
+        ("regions", Json::Array(regions)),
+        ("entries", Json::Array(entries)),
+    ]);
+    writeln!(out, "{table}")?;
+    Ok(())
+}
+
+/// Quotes `field` for CSV, if it needs to be.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_csv<W: Write>(
+    regions: &[(&str, u64, u64)],
+    rows: Vec<Row>,
+    out: &mut W,
+) -> Result<(), Error> {
+    let number = |number: Option<u64>| number.map(|number| number.to_string()).unwrap_or_default();
+    writeln!(out, "type,index,parent,name,offset,size,end")?;
+    for &(name, offset, size) in regions {
+        writeln!(out, "region,,,{name},{offset},{size},")?;
+    }
+    for row in rows {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            if row.directory { "directory" } else { "file" },
+            row.index,
+            number(row.parent),
+            row.name.as_deref().map(csv_field).unwrap_or_default(),
+            number(row.offset),
+            number(row.size),
+            number(row.end),
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes every entry of the FST of a disc to `out` as a table, with its index, parent, name,
+/// and the offset and size of files, after the regions holding the system files and the FST.
+///
+/// Names are as the FST records them, so they may repeat or be missing where they can't be read.
+/// Entries aren't checked or fixed first, so broken FSTs dump as they are.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the DOL, apploader or FST names can't be read, or `out` can't be written.
+pub fn dump_fst<T: Read + Seek, W: Write>(
+    io: &mut T,
+    disc: &Disc,
+    format: TableFormat,
+    out: &mut W,
+) -> Result<(), Error> {
+    let regions = regions(io, disc)?;
+    let rows = rows(io, disc)?;
+    match format {
+        TableFormat::Json => write_json(&regions, rows, out),
+        TableFormat::Csv => write_csv(&regions, rows, out),
+    }
+}
@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::dol::Dol;
+use crate::dol::Section;
+
+const ELF_HEADER_SIZE: u32 = 0x34;
+const PROGRAM_HEADER_SIZE: u32 = 0x20;
+/// Alignment of segment data in the file. Segments with lower aligned addresses are placed at
+/// the same offset modulo this, as ELF requires.
+const SEGMENT_ALIGNMENT: u32 = 0x20;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+/// A loadable segment of the ELF file.
+struct Segment {
+    /// Offset of the segment's data in the ELF file.
+    offset: u32,
+    /// Offset of the segment's data in the DOL, or `None` for zero initialized memory.
+    source: Option<u32>,
+    address: u32,
+    size: u32,
+    flags: u32,
+}
+
+/// Returns the ranges of the bss that no section loads data into, as `(address, size)` pairs.
+///
+/// Linkers usually place small data sections inside the range the DOL header gives for the bss,
+/// and segments overlapping them would confuse disassemblers.
+fn bss_gaps(dol: &Dol) -> Vec<(u32, u32)> {
+    let mut sections: Vec<&Section> = dol.text.iter().chain(&dol.data).collect();
+    sections.sort_by_key(|section| section.address);
+    let bss_end = dol.bss_address.saturating_add(dol.bss_size);
+    let mut gaps = vec![];
+    let mut address = dol.bss_address;
+    for section in sections {
+        let end = section.address.saturating_add(section.size);
+        if end <= address || section.address >= bss_end {
+            continue;
+        }
+        if section.address > address {
+            gaps.push((address, section.address - address));
+        }
+        address = end;
+    }
+    if address < bss_end {
+        gaps.push((address, bss_end - address));
+    }
+    gaps
+}
+
+/// Lays out the segments of the ELF file for `dol`, returning them and the size of the file.
+fn layout(dol: &Dol) -> (Vec<Segment>, u32) {
+    let text = dol.text.iter().map(|section| (section, PF_R | PF_X));
+    let data = dol.data.iter().map(|section| (section, PF_R | PF_W));
+    let sections: Vec<_> = text.chain(data).collect();
+    let bss = bss_gaps(dol);
+    // There are at most 18 sections plus their gaps in the bss
+    #[allow(clippy::cast_possible_truncation)]
+    let headers = (sections.len() + bss.len()) as u32;
+    let mut end = ELF_HEADER_SIZE + headers * PROGRAM_HEADER_SIZE;
+    let mut segments = vec![];
+    for (section, flags) in sections {
+        let offset = end.next_multiple_of(SEGMENT_ALIGNMENT) + section.address % SEGMENT_ALIGNMENT;
+        end = offset.saturating_add(section.size);
+        segments.push(Segment {
+            offset,
+            source: Some(section.offset),
+            address: section.address,
+            size: section.size,
+            flags,
+        });
+    }
+    for (address, size) in bss {
+        // Nothing is read from the file for these, but the offset still needs to be aligned
+        segments.push(Segment {
+            offset: end / SEGMENT_ALIGNMENT * SEGMENT_ALIGNMENT + address % SEGMENT_ALIGNMENT,
+            source: None,
+            address,
+            size,
+            flags: PF_R | PF_W,
+        });
+    }
+    (segments, end)
+}
+
+/// Returns the size of the ELF file [`from_dol`] makes out of `dol`.
+#[must_use]
+pub fn size(dol: &Dol) -> u64 {
+    layout(dol).1.into()
+}
+
+/// Converts the DOL executable `data`, whose header is `dol`, into a big endian PowerPC ELF
+/// executable with a program header for each section.
+///
+/// Sections that extend past the end of `data` are padded with zeroes.
+#[must_use]
+pub fn from_dol(dol: &Dol, data: &[u8]) -> Vec<u8> {
+    let (segments, size) = layout(dol);
+    let mut out = Vec::with_capacity(size as usize);
+    let put16 = |out: &mut Vec<u8>, value: u16| out.extend_from_slice(&value.to_be_bytes());
+    let put32 = |out: &mut Vec<u8>, value: u32| out.extend_from_slice(&value.to_be_bytes());
+
+    // Identification: 32-bit, big endian, current version, System V ABI
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 2, 1, 0]);
+    out.resize(0x10, 0);
+    put16(&mut out, 2); // ET_EXEC
+    put16(&mut out, 20); // EM_PPC
+    put32(&mut out, 1);
+    put32(&mut out, dol.entry_point);
+    put32(&mut out, ELF_HEADER_SIZE);
+    put32(&mut out, 0); // No section headers
+    put32(&mut out, 0);
+    // The header sizes are tiny constants
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        put16(&mut out, ELF_HEADER_SIZE as u16);
+        put16(&mut out, PROGRAM_HEADER_SIZE as u16);
+        put16(&mut out, segments.len() as u16);
+    }
+    put16(&mut out, 0);
+    put16(&mut out, 0);
+    put16(&mut out, 0);
+
+    for segment in &segments {
+        let file_size = if segment.source.is_some() {
+            segment.size
+        } else {
+            0
+        };
+        put32(&mut out, PT_LOAD);
+        put32(&mut out, segment.offset);
+        put32(&mut out, segment.address);
+        put32(&mut out, segment.address);
+        put32(&mut out, file_size);
+        put32(&mut out, segment.size);
+        put32(&mut out, segment.flags);
+        put32(&mut out, SEGMENT_ALIGNMENT);
+    }
+
+    for segment in &segments {
+        let Some(source) = segment.source else {
+            continue;
+        };
+        out.resize(segment.offset as usize, 0);
+        let start = (source as usize).min(data.len());
+        let end = source.saturating_add(segment.size) as usize;
+        out.extend_from_slice(&data[start..end.min(data.len())]);
+        out.resize(segment.offset as usize + segment.size as usize, 0);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dol::HEADER_SIZE;
+    use std::array;
+    use std::io;
+
+    /// Returns a DOL with a text section, and a data section placed inside its bss.
+    fn dol() -> Vec<u8> {
+        let mut dol = vec![0; HEADER_SIZE];
+        let mut put = |offset: usize, value: u32| {
+            dol[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        };
+        // Text section 0 and data section 0
+        for (i, offset, address, size) in
+            [(0, 0x100, 0x8000_3100, 0x20), (7, 0x120, 0x8000_5004, 0x10)]
+        {
+            put(i * 4, offset);
+            put(0x48 + i * 4, address);
+            put(0x90 + i * 4, size);
+        }
+        put(0xD8, 0x8000_5000);
+        put(0xDC, 0x100);
+        put(0xE0, 0x8000_3100);
+        dol.extend((0..0x30).map(|i: u8| i + 1));
+        dol
+    }
+
+    /// Returns the fields of the ELF file's program headers.
+    fn program_headers(elf: &[u8]) -> Vec<[u32; 8]> {
+        let count = u16::from_be_bytes([elf[0x2C], elf[0x2D]]);
+        (0..usize::from(count))
+            .map(|i| {
+                let header = &elf[0x34 + i * 0x20..];
+                array::from_fn(|field| {
+                    u32::from_be_bytes(header[field * 4..field * 4 + 4].try_into().unwrap())
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn converts() {
+        let data = dol();
+        let header = Dol::read(&mut io::Cursor::new(&data), 0).unwrap();
+        let elf = from_dol(&header, &data);
+        assert_eq!(elf.len() as u64, size(&header));
+        assert_eq!(&elf[..6], b"\x7FELF\x01\x02");
+        assert_eq!(
+            u32::from_be_bytes(elf[0x18..0x1C].try_into().unwrap()),
+            0x8000_3100
+        );
+        let rwx = [PF_R | PF_X, PF_R | PF_W];
+        assert_eq!(
+            program_headers(&elf),
+            [
+                [
+                    PT_LOAD,
+                    0xC0,
+                    0x8000_3100,
+                    0x8000_3100,
+                    0x20,
+                    0x20,
+                    rwx[0],
+                    0x20
+                ],
+                [
+                    PT_LOAD,
+                    0xE4,
+                    0x8000_5004,
+                    0x8000_5004,
+                    0x10,
+                    0x10,
+                    rwx[1],
+                    0x20
+                ],
+                // The bss around the data section
+                [PT_LOAD, 0xE0, 0x8000_5000, 0x8000_5000, 0, 4, rwx[1], 0x20],
+                [
+                    PT_LOAD,
+                    0xF4,
+                    0x8000_5014,
+                    0x8000_5014,
+                    0,
+                    0xEC,
+                    rwx[1],
+                    0x20
+                ],
+            ]
+        );
+        assert_eq!(elf[0xC0..0xE0], data[0x100..0x120]);
+        assert_eq!(elf[0xE4..0xF4], data[0x120..0x130]);
+    }
+
+    #[test]
+    fn pads_truncated_sections() {
+        let data = dol();
+        let header = Dol::read(&mut io::Cursor::new(&data), 0).unwrap();
+        let elf = from_dol(&header, &data[..0x128]);
+        assert_eq!(elf.len(), 0xF4);
+        assert_eq!(elf[0xE4..0xEC], data[0x120..0x128]);
+        assert_eq!(elf[0xEC..], [0; 8]);
+    }
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the disc's metadata failed.
+    Disc(gcn_disk::Error),
+    /// Opening the RVZ container failed.
+    Rvz(rvz::Error),
+    /// Reading the overlay directory failed.
+    Overlay(io::Error),
+    /// Any other IO error, such as while reading the disc or writing a new image.
+    Io(io::Error),
+    /// The requested image can't be laid out, such as when a name can't be encoded.
+    Layout(String),
+    /// The patch applied to the image can't be used.
+    Patch(String),
+    /// The disc's FST has problems, and [`Strictness::Strict`](crate::Strictness::Strict) was
+    /// requested.
+    Fst(Vec<String>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Disc(e) => e.fmt(f),
+            Self::Rvz(e) => e.fmt(f),
+            Self::Overlay(e) => write!(f, "error reading overlay directory: {e}"),
+            Self::Io(e) => e.fmt(f),
+            Self::Layout(e) => write!(f, "unable to lay out image: {e}"),
+            Self::Patch(e) => write!(f, "unable to apply patch: {e}"),
+            Self::Fst(problems) => {
+                write!(f, "invalid FST:")?;
+                for problem in problems {
+                    write!(f, "\n  {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<gcn_disk::Error> for Error {
+    fn from(err: gcn_disk::Error) -> Self {
+        Self::Disc(err)
+    }
+}
+
+impl From<rvz::Error> for Error {
+    fn from(err: rvz::Error) -> Self {
+        Self::Rvz(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
@@ -0,0 +1,455 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::layout;
+use crate::options::Options;
+use crate::options::Strictness;
+use crate::tree::Inode;
+use crate::tree::Kind;
+use crate::tree::Tree;
+use clap::ValueEnum;
+use gcn_disk::Disc;
+use std::collections::BTreeMap;
+#[cfg(unix)]
+use std::ffi::CString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+#[cfg(unix)]
+use std::mem::MaybeUninit;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStrExt;
+#[cfg(windows)]
+use std::path;
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(windows)]
+use std::ptr;
+use std::time::SystemTime;
+#[cfg(windows)]
+use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+#[cfg(windows)]
+use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceW;
+#[cfg(windows)]
+use windows_sys::Win32::Storage::FileSystem::GetVolumePathNameW;
+
+/// How much of each file is read at once.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Size of the runs of zeros that are skipped instead of written, the usual filesystem block size.
+const BLOCK_SIZE: usize = 0x1000;
+
+/// Size of tar's blocks, which headers and file data are padded to.
+const TAR_BLOCK: usize = 512;
+
+/// Longest name and prefix a ustar header holds.
+const TAR_NAME: usize = 100;
+const TAR_PREFIX: usize = 155;
+
+/// Format of the archives [`extract_archive`] writes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveFormat {
+    /// POSIX tar, with pax headers for paths too long for ustar.
+    Tar,
+}
+
+/// Copies `size` bytes at `offset` in `io` into `file`, seeking over blocks of zeros so they
+/// become holes on filesystems that support sparse files.
+fn write_sparse<T: Read + Seek>(
+    io: &mut T,
+    offset: u64,
+    size: u64,
+    file: &mut File,
+) -> io::Result<()> {
+    io.seek(SeekFrom::Start(offset))?;
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut done = 0;
+    while done < size {
+        // At most a chunk
+        #[allow(clippy::cast_possible_truncation)]
+        let len = (size - done).min(CHUNK_SIZE as u64) as usize;
+        let chunk = &mut chunk[..len];
+        io.read_exact(chunk)?;
+        // Start of the data not yet written or skipped
+        let mut pending = 0;
+        for start in (0..len).step_by(BLOCK_SIZE) {
+            let end = (start + BLOCK_SIZE).min(len);
+            if chunk[start..end].iter().all(|&byte| byte == 0) {
+                file.write_all(&chunk[pending..start])?;
+                // At most a block
+                #[allow(clippy::cast_possible_wrap)]
+                file.seek(SeekFrom::Current((end - start) as i64))?;
+                pending = end;
+            }
+        }
+        file.write_all(&chunk[pending..])?;
+        done += len as u64;
+    }
+    // Trailing holes only exist once the file covers them
+    file.set_len(size)
+}
+
+/// Adds the paths of the directories under `inode` to `directories`, parents first.
+fn directories(tree: &Tree, inode: Inode, directories: &mut Vec<PathBuf>) {
+    directories.push(tree.path(inode));
+    for &child in tree.children(inode).unwrap_or_default() {
+        if matches!(tree.get(child).unwrap().kind, Kind::Directory(_)) {
+            self::directories(tree, child, directories);
+        }
+    }
+}
+
+/// The system files and FST files of a disc to extract, where each one is in the image and the
+/// directories holding them.
+struct Contents {
+    /// `(offset, size)` of each file by path, cut off past the end of the image for
+    /// [`Strictness::Lenient`].
+    files: BTreeMap<String, (u64, u64)>,
+    /// Paths of the directories, including empty ones, relative to `files/`.
+    directories: Vec<PathBuf>,
+}
+
+fn contents<T: Read + Seek>(
+    io: &mut T,
+    disc: &mut Disc,
+    strictness: Strictness,
+) -> Result<Contents, Error> {
+    let mut files = layout::contents(io, disc)?;
+    let options = Options {
+        strictness,
+        ..Options::default()
+    };
+    let tree = Tree::new(io, disc, &options)?;
+    let mut directories = Vec::new();
+    self::directories(&tree, Inode(1), &mut directories);
+    if strictness == Strictness::Lenient {
+        // Cut off past the end of the image, as files are when mounted
+        let image_size = io.seek(SeekFrom::End(0))?;
+        for (offset, size) in files.values_mut() {
+            *size = (*size).min(image_size.saturating_sub(*offset));
+        }
+    }
+    Ok(Contents { files, directories })
+}
+
+/// Copies the system files and FST files of a disc into `dir`, with the files under `files/` and
+/// the system files under `sys/`, as Dolphin extracts them.
+///
+/// Blocks of zeros, such as padding or zero-filled files, aren't written, leaving holes in the
+/// files on filesystems that support them.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the image can't be read or the files can't be written. Files past the end of
+/// the image are only cut off for [`Strictness::Lenient`].
+pub fn extract<T: Read + Seek>(
+    io: &mut T,
+    disc: &mut Disc,
+    dir: &Path,
+    strictness: Strictness,
+) -> Result<(), Error> {
+    let contents = contents(io, disc, strictness)?;
+    fs::create_dir_all(dir.join("sys"))?;
+    for directory in &contents.directories {
+        fs::create_dir_all(dir.join("files").join(directory))?;
+    }
+    for (path, (offset, size)) in contents.files {
+        let mut file = File::create(dir.join(path))?;
+        write_sparse(io, offset, size, &mut file)?;
+    }
+    Ok(())
+}
+
+/// What [`extract`] would write.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Estimate {
+    /// How many files would be written, the system files included.
+    pub files: u64,
+    /// How many directories would be made, `files/` and `sys/` included.
+    pub directories: u64,
+    /// How many bytes the files hold.
+    pub bytes: u64,
+    /// How much space they'd take at most on a filesystem with the blocks asked for, each file
+    /// and directory taking whole blocks. Blocks of zeros left as holes take less.
+    pub space: u64,
+}
+
+/// Returns what [`extract`] would write for a disc, without writing anything, for a filesystem
+/// with blocks of `block_size` bytes.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the image can't be read. Files past the end of the image are only cut off
+/// for [`Strictness::Lenient`].
+pub fn estimate_extract<T: Read + Seek>(
+    io: &mut T,
+    disc: &mut Disc,
+    strictness: Strictness,
+    block_size: u64,
+) -> Result<Estimate, Error> {
+    let contents = contents(io, disc, strictness)?;
+    let blocks = |size: u64| size.div_ceil(block_size.max(1)) * block_size;
+    // `sys/` is made as well as the directories under, and including, `files/`
+    let directories = contents.directories.len() as u64 + 1;
+    let mut estimate = Estimate {
+        directories,
+        space: directories * blocks(1),
+        ..Estimate::default()
+    };
+    for &(_, size) in contents.files.values() {
+        estimate.files += 1;
+        estimate.bytes += size;
+        estimate.space += blocks(size);
+    }
+    Ok(estimate)
+}
+
+/// Returns how many bytes unprivileged users have free on the filesystem holding `path`, or that
+/// it would be made on if it doesn't exist, and the size of the filesystem's blocks.
+///
+/// # Errors
+///
+/// [`io::Error`] if the filesystem can't be asked.
+pub fn free_space(path: &Path) -> io::Result<(u64, u64)> {
+    let existing = path
+        .ancestors()
+        .map(|path| {
+            if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path
+            }
+        })
+        .find(|path| path.exists())
+        .unwrap_or_else(|| Path::new("/"));
+    filesystem_space(existing)
+}
+
+/// Returns how many bytes unprivileged users have free on the filesystem holding `path`, which
+/// exists, and the size of the filesystem's blocks.
+#[cfg(unix)]
+fn filesystem_space(path: &Path) -> io::Result<(u64, u64)> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: the path is NUL terminated and the buffer is the size statvfs fills in
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: statvfs succeeded, so it filled it in
+    let stat = unsafe { stat.assume_init() };
+    // The field types differ between platforms, but are never wider than 64 bits
+    #[allow(clippy::unnecessary_cast, clippy::cast_lossless)]
+    Ok((
+        stat.f_bavail as u64 * stat.f_frsize as u64,
+        stat.f_bsize as u64,
+    ))
+}
+
+/// Returns how many bytes the user has free on the volume holding `path`, which exists, and the
+/// size of the volume's clusters.
+#[cfg(windows)]
+fn filesystem_space(path: &Path) -> io::Result<(u64, u64)> {
+    let path: Vec<u16> = path::absolute(path)?
+        .as_os_str()
+        .encode_wide()
+        .chain([0])
+        .collect();
+    // The volume's root is the start of the path, with a separator at most added
+    let mut root = vec![0; path.len() + 1];
+    let root_len = u32::try_from(root.len()).unwrap_or(u32::MAX);
+    let mut available = 0;
+    let (mut sectors_per_cluster, mut bytes_per_sector) = (0, 0);
+    let (mut free_clusters, mut clusters) = (0, 0);
+    // SAFETY: the paths are NUL terminated, the root's buffer is as long as it's said to be, and
+    // the rest are only written to
+    let asked = unsafe {
+        GetDiskFreeSpaceExW(
+            path.as_ptr(),
+            &raw mut available,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        ) != 0
+            && GetVolumePathNameW(path.as_ptr(), root.as_mut_ptr(), root_len) != 0
+            && GetDiskFreeSpaceW(
+                root.as_ptr(),
+                &raw mut sectors_per_cluster,
+                &raw mut bytes_per_sector,
+                &raw mut free_clusters,
+                &raw mut clusters,
+            ) != 0
+    };
+    if !asked {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((
+        available,
+        u64::from(sectors_per_cluster) * u64::from(bytes_per_sector),
+    ))
+}
+
+/// Writes `value` in octal into `field`, NUL terminated, as tar headers store numbers.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+}
+
+/// Returns the pax extended header record setting `key` to `value`, which starts with its own
+/// length.
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {key}={value}\n");
+    let mut len = rest.len() + 1;
+    while len.to_string().len() + rest.len() != len {
+        len += 1;
+    }
+    format!("{len}{rest}")
+}
+
+/// Splits `path` into the prefix and name of a ustar header, if it fits in one.
+fn ustar_name(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= TAR_NAME {
+        return Some(("", path));
+    }
+    path.match_indices('/')
+        .map(|(index, _)| (&path[..index], &path[index + 1..]))
+        .find(|(prefix, name)| prefix.len() <= TAR_PREFIX && name.len() <= TAR_NAME)
+}
+
+/// Writes the header of a tar entry of `kind` (`b'0'` for files, `b'5'` for directories) at
+/// `path`, preceded by a pax header if the path doesn't fit in it.
+fn write_tar_header<W: Write>(
+    out: &mut W,
+    path: &str,
+    kind: u8,
+    size: u64,
+    mtime: u64,
+) -> io::Result<()> {
+    let (prefix, name) = if let Some(split) = ustar_name(path) {
+        split
+    } else {
+        let record = pax_record("path", path);
+        write_tar_header(out, "././@PaxHeader", b'x', record.len() as u64, mtime)?;
+        write_tar_data(out, &mut record.as_bytes(), record.len() as u64)?;
+        // Readers that don't know pax headers get the path cut off
+        ("", &path[..path.floor_char_boundary(TAR_NAME)])
+    };
+    let mut header = [0; TAR_BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(
+        &mut header[100..108],
+        if kind == b'5' { 0o755 } else { 0o644 },
+    );
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], mtime);
+    header[148..156].fill(b' ');
+    header[156] = kind;
+    header[257..265].copy_from_slice(b"ustar\x0000");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    let checksum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+    octal(&mut header[148..155], checksum.into());
+    out.write_all(&header)
+}
+
+/// Copies the `size` bytes of an entry's data from `data` to `out`, padded to a whole block.
+fn write_tar_data<R: Read, W: Write>(out: &mut W, data: &mut R, size: u64) -> io::Result<()> {
+    if io::copy(&mut data.take(size), out)? != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the image ended early",
+        ));
+    }
+    // Less than a block
+    #[allow(clippy::cast_possible_truncation)]
+    let padding = (size.wrapping_neg() % TAR_BLOCK as u64) as usize;
+    out.write_all(&[0; TAR_BLOCK][..padding])
+}
+
+/// Writes `contents` to `out` as a tar archive, the files' data read from `io`.
+fn write_tar<T: Read + Seek, W: Write>(
+    io: &mut T,
+    contents: Contents,
+    out: &mut W,
+    mtime: u64,
+) -> io::Result<()> {
+    write_tar_header(out, "sys/", b'5', 0, mtime)?;
+    for directory in &contents.directories {
+        let path = if directory.as_os_str().is_empty() {
+            "files/".to_string()
+        } else {
+            format!("files/{}/", directory.display())
+        };
+        write_tar_header(out, &path, b'5', 0, mtime)?;
+    }
+    for (path, (offset, size)) in contents.files {
+        write_tar_header(out, &path, b'0', size, mtime)?;
+        io.seek(SeekFrom::Start(offset))?;
+        write_tar_data(out, io, size)?;
+    }
+    // The end of the archive
+    out.write_all(&[0; 2 * TAR_BLOCK])
+}
+
+/// Writes the system files and FST files of a disc to `out` as an archive in `format`, laid out
+/// as [`extract`] does in a directory.
+///
+/// The archive is written in order, so `out` can be a pipe. Files are dated with the
+/// apploader's build date, as they are when mounted.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the image can't be read or the archive can't be written. Files past the end
+/// of the image are only cut off for [`Strictness::Lenient`].
+pub fn extract_archive<T: Read + Seek, W: Write>(
+    io: &mut T,
+    disc: &mut Disc,
+    format: ArchiveFormat,
+    out: &mut W,
+    strictness: Strictness,
+) -> Result<(), Error> {
+    let contents = contents(io, disc, strictness)?;
+    let mtime = layout::apploader_date(io)?
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_secs());
+    match format {
+        ArchiveFormat::Tar => write_tar(io, contents, out, mtime)?,
+    }
+    Ok(())
+}
+
+/// Copies the contents of the file at `path` on a disc to `out`, a path as [`layout::contents`]
+/// names it, such as `sys/main.dol`, or a path starting with `/` as mounted.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the image can't be read, `out` can't be written, or with
+/// [`io::ErrorKind::NotFound`] if there's no such file.
+pub fn extract_file<T: Read + Seek, W: Write>(
+    io: &mut T,
+    disc: &mut Disc,
+    path: &str,
+    out: &mut W,
+) -> Result<(), Error> {
+    let name = path
+        .strip_prefix('/')
+        .map_or_else(|| path.to_string(), |path| format!("files/{path}"));
+    let Some(&(offset, size)) = layout::contents(io, disc)?.get(&name) else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("there's no file {path} on the disc"),
+        )
+        .into());
+    };
+    io.seek(SeekFrom::Start(offset))?;
+    if io::copy(&mut io.take(size), out)? != size {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the image ended early").into());
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::layout;
+use gcn_disk::Disc;
+use regex::Regex;
+use std::io::Read;
+use std::io::Seek;
+
+/// A pattern the paths of files on a disc are matched against, relative to its root, such as
+/// `stg_01/map.szs`.
+#[derive(Clone, Debug)]
+pub enum PathPattern {
+    /// A shell glob, split into its components. `*` and `?` match any run of characters or any
+    /// single one within a component, `[...]` any character in a set (or not in it, with `!` or
+    /// `^` first), and a `**` component any number of directories.
+    Glob(Vec<String>),
+    /// A regular expression matching anywhere in the path, unless anchored.
+    Regex(Regex),
+}
+
+impl PathPattern {
+    /// Parses a shell glob. Globs without a `/`, such as `*.bmg`, match the names of files in
+    /// any directory, and a leading `/` is ignored, as all paths are from the disc's root.
+    #[must_use]
+    pub fn glob(pattern: &str) -> Self {
+        let mut components = Vec::new();
+        if !pattern.contains('/') {
+            components.push("**".to_string());
+        }
+        let pattern = pattern.trim_start_matches('/');
+        components.extend(pattern.split('/').map(str::to_string));
+        Self::Glob(components)
+    }
+
+    /// Parses a regular expression.
+    ///
+    /// # Errors
+    ///
+    /// [`regex::Error`] if the expression is invalid.
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self::Regex)
+    }
+
+    /// Returns whether `path`, relative to the disc's root, matches.
+    #[must_use]
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            Self::Glob(components) => {
+                let path: Vec<_> = path.split('/').collect();
+                match_components(components, &path)
+            }
+            Self::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+/// Returns whether the components of a path match those of a glob.
+fn match_components(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skipped| match_components(rest, &path[skipped..]))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(component, path)| {
+            let pattern: Vec<_> = first.chars().collect();
+            let component: Vec<_> = component.chars().collect();
+            match_component(&pattern, &component) && match_components(rest, path)
+        }),
+    }
+}
+
+/// Returns whether a component of a path matches one of a glob.
+fn match_component(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => {
+            (0..=name.len()).any(|skipped| match_component(rest, &name[skipped..]))
+        }
+        Some(('?', rest)) => !name.is_empty() && match_component(rest, &name[1..]),
+        Some(('[', rest)) => {
+            let Some((&c, name)) = name.split_first() else {
+                return false;
+            };
+            match match_set(rest, c) {
+                Some((true, rest)) => match_component(rest, name),
+                Some((false, _)) => false,
+                // Unclosed, so a plain `[`
+                None => c == '[' && match_component(rest, name),
+            }
+        }
+        Some(('\\', [escaped, rest @ ..])) => {
+            name.first() == Some(escaped) && match_component(rest, &name[1..])
+        }
+        Some((&literal, rest)) => {
+            name.first() == Some(&literal) && match_component(rest, &name[1..])
+        }
+    }
+}
+
+/// Returns whether `c` is in the set at the start of `pattern`, just after its `[`, and the rest
+/// of the pattern after the set, or `None` if it isn't closed.
+fn match_set(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match pattern.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+    let mut found = false;
+    let mut first = true;
+    loop {
+        match rest {
+            // A `]` first is part of the set
+            [']', after @ ..] if !first => return Some((found != negated, after)),
+            [start, '-', end, after @ ..] if *end != ']' => {
+                found |= (*start..=*end).contains(&c);
+                rest = after;
+            }
+            [member, after @ ..] => {
+                found |= *member == c;
+                rest = after;
+            }
+            [] => return None,
+        }
+        first = false;
+    }
+}
+
+/// Returns the paths, relative to the root, and sizes of the files on a disc whose paths match
+/// `pattern`, in order.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the FST names can't be read.
+pub fn find<T: Read + Seek>(
+    io: &mut T,
+    disc: &mut Disc,
+    pattern: &PathPattern,
+) -> Result<Vec<(String, u64)>, Error> {
+    Ok(layout::contents(io, disc)?
+        .into_iter()
+        .filter_map(|(path, (_, size))| Some((path.strip_prefix("files/")?.to_string(), size)))
+        .filter(|(path, _)| pattern.matches(path))
+        .collect())
+}
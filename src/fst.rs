@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::options::Strictness;
+use gcn_disk::Entry;
+use gcn_disk::Fst;
+
+/// Checks that directories in `fs` nest properly and that files fit in an image of
+/// `image_size` bytes, returning a description of every problem found.
+///
+/// Directories that end before their first entry or past their parent, or that name the wrong
+/// parent, are fixed unless `strictness` is [`Strictness::Strict`], as listing them could
+/// otherwise loop forever or go past the end of the FST. Files past the end of the image are only
+/// cut off for [`Strictness::Lenient`].
+pub fn check(fs: &mut Fst, image_size: u64, strictness: Strictness) -> Vec<String> {
+    let mut problems = vec![];
+    // The FST's entry count comes from a u32
+    #[allow(clippy::cast_possible_truncation)]
+    let len = fs.entries.len() as u32;
+    // Index and end of the directories holding the current entry, innermost last
+    let mut parents: Vec<(u32, u32)> = vec![];
+    for (index, entry) in (0..len).zip(&mut fs.entries) {
+        while parents.last().is_some_and(|&(_, end)| index >= end) {
+            parents.pop();
+        }
+        let (parent, parent_end) = parents.last().copied().unwrap_or((0, len));
+        match entry {
+            Entry::Directory(directory) => {
+                if directory.parent_index != parent {
+                    problems.push(format!(
+                        "directory at FST index {index} has parent {}, but is in directory {parent}",
+                        directory.parent_index
+                    ));
+                    directory.parent_index = parent;
+                }
+                let end = directory.end_index.clamp(index + 1, parent_end);
+                if end != directory.end_index {
+                    problems.push(format!(
+                        "directory at FST index {index} ends at index {}, outside of {}..={parent_end}",
+                        directory.end_index,
+                        index + 1
+                    ));
+                    directory.end_index = end;
+                }
+                parents.push((index, end));
+            }
+            Entry::File(file) => {
+                let end = u64::from(file.offset) + u64::from(file.size);
+                if end <= image_size {
+                    continue;
+                }
+                problems.push(format!(
+                    "file at FST index {index} ends at {end:#x}, past the end of the image at \
+                     {image_size:#x}"
+                ));
+                if strictness == Strictness::Lenient {
+                    // Smaller than the original size, so it fits
+                    #[allow(clippy::cast_possible_truncation)]
+                    let size = image_size.saturating_sub(file.offset.into()) as u32;
+                    file.size = size;
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Returns what's wrong with `name` as the name of an entry, if anything.
+pub fn name_problem(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        Some("is empty")
+    } else if name == "." || name == ".." {
+        Some("is a relative path")
+    } else if name.contains('/') {
+        Some("contains a slash")
+    } else {
+        None
+    }
+}
+
+/// Fails with every problem found for [`Strictness::Strict`], and otherwise just reports them.
+///
+/// # Errors
+///
+/// [`Error::Fst`] if `strictness` is [`Strictness::Strict`] and there are problems.
+pub fn report(problems: Vec<String>, strictness: Strictness) -> Result<(), Error> {
+    if strictness == Strictness::Strict && !problems.is_empty() {
+        return Err(Error::Fst(problems));
+    }
+    for problem in problems {
+        eprintln!("invalid FST: {problem}");
+    }
+    Ok(())
+}
@@ -0,0 +1,443 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::attr::FileAttr;
+use crate::attr::FileType;
+use crate::error::Error;
+use crate::fuse::GcnFuse;
+use crate::http;
+use crate::tree::Inode;
+use std::fmt::Write as _;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+/// Longest command line accepted, well over the path lengths on a disc.
+const MAX_LINE_SIZE: u64 = 4096;
+
+/// Size of the chunks file data is read and sent in, so other connections get a turn in between.
+const CHUNK_SIZE: u32 = 1 << 20;
+
+/// Longest the client has to make the data connection after `PASV` or `EPSV`.
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a passive listener is checked for the data connection.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Commands that would change the filesystem, which is always read-only over FTP.
+const WRITE_COMMANDS: &[&str] = &[
+    "STOR", "STOU", "APPE", "DELE", "RMD", "XRMD", "MKD", "XMKD", "RNFR", "RNTO", "SITE",
+];
+
+const FEATURES: &str = "211-Features:\r\n MDTM\r\n MLST type*;size*;modify*;\r\n REST STREAM\r\n \
+                        SIZE\r\n EPSV\r\n UTF8\r\n211 End\r\n";
+
+/// Where the next transfer's data connection comes from.
+enum DataConnection {
+    /// The client connects to this listener, after `PASV` or `EPSV`.
+    Passive(TcpListener),
+    /// The server connects to the client at this address, after `PORT` or `EPRT`.
+    Active(SocketAddr),
+}
+
+/// The state of one client's control connection.
+struct Session<'a, T: Read + Seek> {
+    fuse: &'a Mutex<GcnFuse<T>>,
+    control: TcpStream,
+    /// The working directory, as an absolute path without a trailing `/`, or `/`.
+    cwd: String,
+    /// Offset to start the next `RETR` at, set with `REST`.
+    rest: u64,
+    data: Option<DataConnection>,
+}
+
+/// Resolves `path` against the working directory `cwd`, returning an absolute path with any `.`
+/// and `..` components removed.
+fn join(cwd: &str, path: &str) -> String {
+    let mut components: Vec<&str> = vec![];
+    let base = if path.starts_with('/') { "" } else { cwd };
+    for component in base.split('/').chain(path.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    format!("/{}", components.join("/"))
+}
+
+/// Formats a time as the `YYYYMMDDHHMMSS` timestamps used by `MDTM` and `MLSD`.
+fn timestamp(time: SystemTime) -> String {
+    let (year, month, day, hours, minutes, seconds, _) = http::civil(time);
+    format!("{year:04}{month:02}{day:02}{hours:02}{minutes:02}{seconds:02}")
+}
+
+/// Appends a line in the format of `ls -l` describing an entry, which is what clients expect
+/// from `LIST`.
+fn write_list_entry(listing: &mut String, name: &str, attr: &FileAttr) {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (year, month, day, ..) = http::civil(attr.mtime);
+    // In range by construction
+    #[allow(clippy::cast_possible_truncation)]
+    let month = MONTHS[month as usize - 1];
+    let mode = if attr.kind == FileType::Directory {
+        "dr-xr-xr-x"
+    } else {
+        "-r--r--r--"
+    };
+    let _ = write!(
+        listing,
+        "{mode} {} gcn gcn {:>12} {month} {day:>2} {year:>5} {name}\r\n",
+        attr.nlink, attr.size,
+    );
+}
+
+/// Appends a machine readable line describing an entry, as sent by `MLSD` and `MLST`.
+fn write_facts(listing: &mut String, name: &str, attr: &FileAttr) {
+    let kind = if attr.kind == FileType::Directory {
+        "dir"
+    } else {
+        "file"
+    };
+    let _ = write!(
+        listing,
+        "type={kind};size={};modify={}; {name}\r\n",
+        attr.size,
+        timestamp(attr.mtime),
+    );
+}
+
+impl<T: Read + Seek> Session<'_, T> {
+    fn reply(&mut self, code: u16, text: &str) -> io::Result<()> {
+        write!(self.control, "{code} {text}\r\n")
+    }
+
+    /// Resolves a path argument against the working directory.
+    fn resolve(&self, path: &str) -> Option<(String, Inode)> {
+        let path = join(&self.cwd, path);
+        let inode = http::lock(self.fuse).resolve(&path)?;
+        Some((path, inode))
+    }
+
+    /// Opens the data connection set up by the last `PASV`, `EPSV`, `PORT` or `EPRT`.
+    fn open_data(&mut self) -> io::Result<TcpStream> {
+        let stream = match self.data.take() {
+            Some(DataConnection::Passive(listener)) => self.accept(&listener)?,
+            Some(DataConnection::Active(address)) => TcpStream::connect(address)?,
+            None => return Err(io::ErrorKind::NotConnected.into()),
+        };
+        Ok(stream)
+    }
+
+    /// Waits up to [`ACCEPT_TIMEOUT`] for the client to make the data connection to `listener`.
+    /// Connections from anywhere but the client's own address are refused, so other hosts can't
+    /// take its data.
+    fn accept(&self, listener: &TcpListener) -> io::Result<TcpStream> {
+        let client = self.control.peer_addr()?.ip();
+        let deadline = Instant::now() + ACCEPT_TIMEOUT;
+        listener.set_nonblocking(true)?;
+        loop {
+            match listener.accept() {
+                Ok((stream, address)) if address.ip() == client => {
+                    stream.set_nonblocking(false)?;
+                    return Ok(stream);
+                }
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                    thread::sleep(ACCEPT_INTERVAL);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Sends `data` over a new data connection, replying to the command it answers.
+    fn send_data(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.data.is_none() {
+            return self.reply(425, "Use PASV or PORT first");
+        }
+        self.reply(150, "Opening data connection")?;
+        let sent = self
+            .open_data()
+            .and_then(|mut stream| stream.write_all(data));
+        match sent {
+            Ok(()) => self.reply(226, "Transfer complete"),
+            Err(_) => self.reply(426, "Connection closed, transfer aborted"),
+        }
+    }
+
+    /// Answers `LIST`, `NLST` and `MLSD` with a listing of the given path, a directory or a file,
+    /// with one line per entry written by `write_line`.
+    fn list(&mut self, path: &str, write_line: fn(&mut String, &str, &FileAttr)) -> io::Result<()> {
+        // Options like `-la` are sent by many clients, and can only be ignored
+        let path = path
+            .split_whitespace()
+            .rfind(|arg| !arg.starts_with('-'))
+            .unwrap_or_default();
+        let Some((path, inode)) = self.resolve(path) else {
+            return self.reply(550, "No such file or directory");
+        };
+        let mut listing = String::new();
+        {
+            let fuse = http::lock(self.fuse);
+            let name = path.rsplit('/').next().unwrap_or_default();
+            match fuse.entries(inode) {
+                Some(entries) => {
+                    for (name, child) in entries {
+                        if let Some(attr) = fuse.get_attr(child) {
+                            write_line(&mut listing, &name, &attr);
+                        }
+                    }
+                }
+                None => {
+                    if let Some(attr) = fuse.get_attr(inode) {
+                        write_line(&mut listing, name, &attr);
+                    }
+                }
+            }
+        }
+        self.send_data(listing.as_bytes())
+    }
+
+    /// Answers `RETR`, sending the file from the offset given by any preceding `REST`.
+    fn retrieve(&mut self, path: &str) -> io::Result<()> {
+        let offset = std::mem::take(&mut self.rest);
+        let Some((_, inode)) = self.resolve(path) else {
+            return self.reply(550, "No such file or directory");
+        };
+        let Some(attr) = http::lock(self.fuse).get_attr(inode) else {
+            return self.reply(550, "No such file or directory");
+        };
+        if attr.kind == FileType::Directory {
+            return self.reply(550, "Not a regular file");
+        }
+        if self.data.is_none() {
+            return self.reply(425, "Use PASV or PORT first");
+        }
+        self.reply(
+            150,
+            &format!("Opening data connection for {} bytes", attr.size),
+        )?;
+        let sent = self.open_data().and_then(|mut stream| {
+            let mut offset = offset;
+            while offset < attr.size {
+                let data = http::lock(self.fuse).read_data(inode, offset, CHUNK_SIZE)?;
+                if data.is_empty() {
+                    break;
+                }
+                stream.write_all(&data)?;
+                offset += data.len() as u64;
+            }
+            Ok(())
+        });
+        match sent {
+            Ok(()) => self.reply(226, "Transfer complete"),
+            Err(_) => self.reply(426, "Connection closed, transfer aborted"),
+        }
+    }
+
+    /// Answers `PASV`, or `EPSV` if `extended`, by listening for the data connection on the
+    /// address the client reached the server on. `PASV` only works over IPv4.
+    fn passive(&mut self, extended: bool) -> io::Result<()> {
+        let ip = self.control.local_addr()?.ip();
+        let listener = TcpListener::bind((ip, 0))?;
+        let port = listener.local_addr()?.port();
+        let reply = match (extended, ip) {
+            (true, _) => format!("Entering Extended Passive Mode (|||{port}|)"),
+            (false, IpAddr::V4(ip)) => {
+                let [a, b, c, d] = ip.octets();
+                let [high, low] = port.to_be_bytes();
+                format!("Entering Passive Mode ({a},{b},{c},{d},{high},{low})")
+            }
+            (false, IpAddr::V6(_)) => return self.reply(522, "Use EPSV over IPv6"),
+        };
+        self.data = Some(DataConnection::Passive(listener));
+        self.reply(if extended { 229 } else { 227 }, &reply)
+    }
+
+    /// Answers `PORT`, or `EPRT` if `extended`. Only the client's own address is accepted, so the
+    /// server can't be used to connect elsewhere.
+    fn active(&mut self, arg: &str, extended: bool) -> io::Result<()> {
+        let address = if extended {
+            // Like `|1|192.168.1.2|6446|`, with any delimiter
+            let delimiter = arg.chars().next().unwrap_or('|');
+            let parts: Vec<&str> = arg.split(delimiter).collect();
+            match parts.as_slice() {
+                ["", _, ip, port, ""] => ip
+                    .parse::<IpAddr>()
+                    .ok()
+                    .zip(port.parse::<u16>().ok())
+                    .map(SocketAddr::from),
+                _ => None,
+            }
+        } else {
+            // Like `192,168,1,2,25,46`
+            let numbers: Option<Vec<u8>> = arg.split(',').map(|n| n.trim().parse().ok()).collect();
+            match numbers.as_deref() {
+                Some(&[a, b, c, d, high, low]) => Some(SocketAddr::from((
+                    [a, b, c, d],
+                    u16::from_be_bytes([high, low]),
+                ))),
+                _ => None,
+            }
+        };
+        let Some(address) = address else {
+            return self.reply(501, "Malformed address");
+        };
+        if address.ip() != self.control.peer_addr()?.ip() {
+            return self.reply(504, "Data connections only go to the client's own address");
+        }
+        self.data = Some(DataConnection::Active(address));
+        self.reply(200, "Command okay")
+    }
+
+    /// Handles one command, returning whether the session should continue.
+    fn command(&mut self, command: &str, arg: &str) -> io::Result<bool> {
+        match command {
+            // Anyone can read the disc, so any login is accepted
+            "USER" => self.reply(331, "Any password will do")?,
+            "PASS" => self.reply(230, "Logged in")?,
+            "SYST" => self.reply(215, "UNIX Type: L8")?,
+            "FEAT" => self.control.write_all(FEATURES.as_bytes())?,
+            "OPTS" if arg.eq_ignore_ascii_case("UTF8 ON") => self.reply(200, "Always in UTF-8")?,
+            "NOOP" | "MODE" | "STRU" | "TYPE" | "ALLO" => self.reply(200, "Command okay")?,
+            "PWD" | "XPWD" => {
+                let reply = format!(
+                    "\"{}\" is the current directory",
+                    self.cwd.replace('"', "\"\"")
+                );
+                self.reply(257, &reply)?;
+            }
+            "CWD" | "XCWD" | "CDUP" | "XCUP" => {
+                let arg = if matches!(command, "CDUP" | "XCUP") {
+                    ".."
+                } else {
+                    arg
+                };
+                match self.resolve(arg) {
+                    Some((path, inode)) if http::lock(self.fuse).entries(inode).is_some() => {
+                        self.cwd = path;
+                        self.reply(250, "Directory changed")?;
+                    }
+                    _ => self.reply(550, "No such directory")?,
+                }
+            }
+            "SIZE" | "MDTM" | "MLST" => {
+                let path = if arg.is_empty() { "." } else { arg };
+                let attr = self
+                    .resolve(path)
+                    .and_then(|(path, inode)| Some((path, http::lock(self.fuse).get_attr(inode)?)));
+                match attr {
+                    Some((_, attr)) if command == "SIZE" => {
+                        self.reply(213, &attr.size.to_string())?;
+                    }
+                    Some((_, attr)) if command == "MDTM" => {
+                        self.reply(213, &timestamp(attr.mtime))?;
+                    }
+                    Some((path, attr)) => {
+                        let mut facts = String::new();
+                        write_facts(&mut facts, &path, &attr);
+                        write!(self.control, "250-Listing {path}\r\n {facts}250 End\r\n")?;
+                    }
+                    None => self.reply(550, "No such file or directory")?,
+                }
+            }
+            "REST" => match arg.parse() {
+                Ok(offset) => {
+                    self.rest = offset;
+                    self.reply(350, "Restarting at the given offset")?;
+                }
+                Err(_) => self.reply(501, "Malformed offset")?,
+            },
+            "PASV" => self.passive(false)?,
+            "EPSV" => self.passive(true)?,
+            "PORT" => self.active(arg, false)?,
+            "EPRT" => self.active(arg, true)?,
+            "LIST" => self.list(arg, write_list_entry)?,
+            "NLST" => self.list(arg, |listing, name, _| {
+                let _ = write!(listing, "{name}\r\n");
+            })?,
+            "MLSD" => self.list(arg, write_facts)?,
+            "RETR" => self.retrieve(arg)?,
+            "ABOR" => self.reply(226, "Nothing to abort")?,
+            "QUIT" => {
+                self.reply(221, "Goodbye")?;
+                return Ok(false);
+            }
+            command if WRITE_COMMANDS.contains(&command) => {
+                self.reply(550, "Read-only filesystem")?;
+            }
+            _ => self.reply(502, "Command not implemented")?,
+        }
+        Ok(true)
+    }
+}
+
+fn connection<T: Read + Seek>(stream: TcpStream, fuse: &Mutex<GcnFuse<T>>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut session = Session {
+        fuse,
+        control: stream,
+        cwd: String::from("/"),
+        rest: 0,
+        data: None,
+    };
+    session.reply(220, "gcnfuse ready")?;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if (&mut reader).take(MAX_LINE_SIZE).read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        if !session.command(&command.to_ascii_uppercase(), arg)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Serves the filesystem read-only over FTP on `listen`, an address like `0.0.0.0:2121`.
+/// This never returns unless the address can't be listened on.
+///
+/// Each client is served from its own thread, and any login is accepted.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the address can't be listened on.
+pub fn serve_ftp<T: Read + Seek + Send>(fuse: GcnFuse<T>, listen: &str) -> Result<(), Error> {
+    let listener = http::bind(listen)?;
+    let fuse = Mutex::new(fuse);
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let fuse = &fuse;
+                    // Clients drop connections all the time, so errors just end the session
+                    scope.spawn(move || connection(stream, fuse).ok());
+                }
+                Err(err) => eprintln!("unable to accept a connection: {err}"),
+            }
+        }
+    });
+    Ok(())
+}
@@ -1,85 +1,1405 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
 // SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
 
-use fuser::FileAttr;
-use fuser::FileType;
+use crate::archive;
+use crate::attr::FileAttr;
+use crate::attr::FileType;
+use crate::audio;
+use crate::audio::Codec;
+use crate::cache::Cache;
+use crate::compression;
+use crate::covers;
+use crate::damage;
+use crate::dol::Dol;
+use crate::elf;
+use crate::error::Error;
+#[cfg(target_os = "linux")]
+use crate::hashes;
+#[cfg(unix)]
+use crate::hashes::HASH_XATTRS;
+#[cfg(unix)]
+use crate::hashes::HashCache;
+#[cfg(unix)]
+use crate::hashes::Hashes;
+use crate::image::CompressedSizes;
+#[cfg(unix)]
+use crate::interrupt;
+use crate::layout;
+#[cfg(unix)]
+use crate::mount::MountHandle;
+use crate::options::Options;
+use crate::options::Sort;
+#[cfg(target_os = "linux")]
+use crate::prefetch;
+use crate::prefetch::Patterns;
+use crate::readahead::ReadAhead;
+use crate::readahead::Region;
+#[cfg(target_os = "linux")]
+use crate::sandbox::Sandbox;
+use crate::titles;
+use crate::tree;
+use crate::tree::FileData;
+use crate::tree::Index;
+use crate::tree::Inode;
+use crate::tree::Kind;
+#[cfg(unix)]
+use crate::tree::OPAQUE_MARKER;
+use crate::tree::Tree;
+#[cfg(unix)]
+use crate::tree::WHITEOUT_PREFIX;
+#[cfg(unix)]
 use fuser::Filesystem;
+#[cfg(unix)]
+use fuser::KernelConfig;
+#[cfg(unix)]
+use fuser::MountOption;
+#[cfg(unix)]
 use fuser::ReplyAttr;
+#[cfg(unix)]
+use fuser::ReplyCreate;
+#[cfg(unix)]
 use fuser::ReplyData;
+#[cfg(unix)]
 use fuser::ReplyDirectory;
+#[cfg(unix)]
+use fuser::ReplyEmpty;
+#[cfg(unix)]
 use fuser::ReplyEntry;
+#[cfg(unix)]
+use fuser::ReplyIoctl;
+#[cfg(unix)]
+use fuser::ReplyOpen;
+#[cfg(unix)]
+use fuser::ReplyWrite;
+#[cfg(unix)]
+use fuser::ReplyXattr;
+#[cfg(unix)]
 use fuser::Request;
+#[cfg(unix)]
+use fuser::TimeOrNow;
+#[cfg(unix)]
+use fuser::consts;
 use gcn_disk::Disc;
 use gcn_disk::Entry;
-use gcn_disk::Fst;
-use std::cmp;
+#[cfg(any(unix, feature = "winfsp"))]
+use std::borrow::Cow;
+use std::collections::HashMap;
+#[cfg(unix)]
 use std::ffi::OsStr;
+#[cfg(unix)]
+use std::fs;
+use std::fs::File;
+#[cfg(unix)]
+use std::fs::OpenOptions;
+use std::io;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
+use std::mem;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+#[cfg(unix)]
 use std::time::Duration;
 use std::time::SystemTime;
 
-#[derive(Copy, Clone, Debug)]
-struct Inode(u64);
+pub struct GcnFuse<T: Read + Seek> {
+    /// The image, read ahead so that sequential reads of files take fewer reads of it.
+    io: ReadAhead<T>,
+    disc: Disc,
+    options: Options,
+    tree: Tree,
+    /// Recently decoded files.
+    decoded: Cache<Inode>,
+    /// Extended attributes of virtual files, by name.
+    xattrs: HashMap<Inode, Vec<(&'static str, String)>>,
+    /// Timestamp reported for every entry.
+    time: SystemTime,
+    /// Generation reported for every inode, which identifies the image in file handles.
+    generation: u64,
+    /// The order files were read in, if [`Options::prefetch`] is set.
+    patterns: Option<Patterns>,
+    /// Where to ask for regions of the image to be read in the background, once warming up.
+    prefetch: Option<mpsc::Sender<(u64, u64)>>,
+    stats: Arc<Stats>,
+    /// How much of the image's file each part of the disc takes up, if it's compressed.
+    compressed: Option<CompressedSizes>,
+    /// Hashes of files stored in the image as they're shown, loaded when one is first asked for.
+    #[cfg(unix)]
+    hashes: Option<HashCache>,
+    /// What identifies the image's contents, which hashes of its files are saved under, if
+    /// anything does.
+    #[cfg(unix)]
+    image_key: Option<[u8; 20]>,
+    /// Entries of the open directories, by handle.
+    #[cfg(unix)]
+    listings: HashMap<u64, Listing>,
+    /// Handle for the next directory opened.
+    #[cfg(unix)]
+    next_listing: u64,
+    /// Size of the image, past which reads of files of corrupt FSTs fail.
+    image_size: u64,
+}
 
-#[derive(Copy, Clone, Debug)]
-struct Index(u32);
+/// A directory's entries as they were when it was opened, `.` and `..` first, which `readdir`
+/// goes through by index, so changes to the directory while it's read don't skip or repeat any.
+#[cfg(any(unix, feature = "winfsp"))]
+pub type Listing = Vec<(Inode, FileType, String)>;
 
-impl From<u32> for Index {
-    fn from(val: u32) -> Self {
-        Self(val)
-    }
+/// Counts of the reads a filesystem answered, shared with whatever reports them while it's
+/// mounted.
+#[derive(Debug, Default)]
+pub struct Stats {
+    reads: AtomicU64,
+    bytes_read: AtomicU64,
 }
 
-impl From<Index> for u32 {
-    fn from(val: Index) -> Self {
-        val.0
+impl Stats {
+    /// Returns how many reads were answered.
+    #[must_use]
+    pub fn reads(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
     }
-}
 
-impl From<u64> for Inode {
-    fn from(val: u64) -> Self {
-        Self(val)
+    /// Returns how many bytes reads were answered with.
+    #[must_use]
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
     }
 }
 
-impl From<Inode> for u64 {
-    fn from(val: Inode) -> Self {
-        val.0
+/// Files larger than this aren't prefetched, as they'd take too much of the cache.
+const PREFETCH_LIMIT: u32 = 16 << 20;
+
+/// How much `copy_file_range` reads at once.
+#[cfg(target_os = "linux")]
+const COPY_CHUNK_SIZE: u32 = 1 << 20;
+
+/// Size of the disc's sectors, the block size reported for files by default.
+const SECTOR_SIZE: u32 = 0x8000;
+
+impl<T: Read + Seek> GcnFuse<T> {
+    /// Returns a new filesystem serving the given disc.
+    ///
+    /// All entry names are read from the disc's string table up front, so that duplicate names in
+    /// the same directory can be disambiguated consistently for both `lookup` and `readdir`. If
+    /// [`Options::overlay`] is set, the overlay directory is merged on top of the disc here too.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Fst`] if the FST has problems and [`Options::strictness`] is strict,
+    /// [`Error::Overlay`] if the overlay directory can't be read, and [`Error::Io`] if the FST
+    /// names, apploader or DOL can't be read.
+    pub fn new(mut io: T, mut disc: Disc, options: Options) -> Result<Self, Error> {
+        let image_size = io.seek(SeekFrom::End(0))?;
+        let mut tree = Tree::new(&mut io, &mut disc, &options)?;
+        if let Some(overlay) = &options.overlay {
+            tree.overlay(Inode(1), overlay, &options)
+                .map_err(Error::Overlay)?;
+        }
+        // The patterns are saved under the game ID, which is whatever the disc says it is
+        let game_id = titles::game_id(&disc.header);
+        let patterns = (options.prefetch && game_id.bytes().all(|c| c.is_ascii_alphanumeric()))
+            .then(|| Patterns::load(&game_id, disc.header.disk_id));
+        let mut fuse = Self {
+            io: ReadAhead::new(io),
+            disc,
+            options,
+            tree,
+            decoded: Cache::new(),
+            xattrs: HashMap::new(),
+            time: SystemTime::UNIX_EPOCH,
+            generation: 0,
+            patterns,
+            prefetch: None,
+            stats: Arc::default(),
+            compressed: None,
+            #[cfg(unix)]
+            hashes: None,
+            #[cfg(unix)]
+            image_key: None,
+            #[cfg(unix)]
+            listings: HashMap::new(),
+            #[cfg(unix)]
+            next_listing: 1,
+            image_size,
+        };
+        if fuse.options.export {
+            fuse.generation = fuse.image_generation()?;
+        }
+        fuse.time = match fuse.options.mtime {
+            Some(time) => time,
+            None => layout::apploader_date(&mut fuse.io)?.unwrap_or(SystemTime::UNIX_EPOCH),
+        };
+        if fuse.options.damage_report {
+            let report = damage::report(&mut fuse.io, &fuse.tree, &fuse.disc.filesystem);
+            let directory = fuse.add_to_root(".gcnfuse", Kind::Directory(vec![]));
+            let data = FileData::Generated(report.into_bytes());
+            fuse.tree
+                .add(directory, "damage.txt".into(), Kind::File(data));
+        }
+        if fuse.options.meta {
+            fuse.add_meta()?;
+        }
+        if fuse.options.dtk {
+            fuse.add_stream()?;
+        }
+        fuse.unpack_files();
+        Ok(fuse)
+    }
+
+    /// Returns the counts of the reads the filesystem answers, which keep counting once it's
+    /// mounted.
+    #[must_use]
+    pub fn stats(&self) -> Arc<Stats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Sets how much of the image's file each part of the disc takes up, for images that are
+    /// compressed, which [`Options::compressed_blocks`] reports blocks from.
+    pub fn set_compressed_sizes(&mut self, sizes: CompressedSizes) {
+        self.compressed = Some(sizes);
+    }
+
+    /// Sets what identifies the image's contents, such as [`Image::content_key`], so hashes of
+    /// its files are saved for later mounts of it. Without it they're only kept while mounted.
+    ///
+    /// [`Image::content_key`]: crate::Image::content_key
+    #[cfg(unix)]
+    pub fn set_image_key(&mut self, key: [u8; 20]) {
+        self.image_key = Some(key);
+    }
+
+    /// Returns the sandbox letting the filesystem keep reading the host files it shows, the
+    /// overlay and cover art, changing the overlay if the mount is writable, and saving the order
+    /// files are read in if prefetching.
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn sandbox(&self) -> Sandbox {
+        let mut sandbox = Sandbox::default();
+        let overlay = self.options.overlay.as_deref();
+        if let Some(overlay) = overlay {
+            if self.options.writable {
+                sandbox.writable.push(overlay.to_path_buf());
+            } else {
+                sandbox.readable.push(overlay.to_path_buf());
+            }
+        }
+        for inode in layout::files(&self.tree) {
+            if let Some(Kind::File(FileData::Host(path))) =
+                self.tree.get(inode).map(|node| &node.kind)
+                && overlay.is_none_or(|overlay| !path.starts_with(overlay))
+            {
+                sandbox.readable.push(path.clone());
+            }
+        }
+        if self.patterns.is_some() {
+            sandbox.writable.extend(prefetch::patterns_dir());
+        }
+        // Made now, as only directories that exist can be allowed
+        if let Some(dir) = hashes::hashes_dir()
+            && fs::create_dir_all(&dir).is_ok()
+        {
+            sandbox.writable.push(dir);
+        }
+        sandbox
+    }
+
+    /// Adds `stream.adp` to the root directory with the disc's streaming audio, if it uses
+    /// audio streaming.
+    ///
+    /// Games start streams at whatever offset they like, so where the audio is isn't recorded
+    /// anywhere. Streamed audio that isn't one of the FST's files is placed after everything
+    /// else, so the track is taken to be whatever non-zero data follows the last used part of
+    /// the image.
+    fn add_stream(&mut self) -> Result<(), Error> {
+        let header = &self.disc.header;
+        if header.audio_streaming == 0 {
+            return Ok(());
+        }
+        let dol = Dol::read(&mut self.io, header.executable_offset.into())?;
+        let apploader_size = layout::apploader_size(&mut self.io)?;
+        let used = layout::used_extents(&self.disc, apploader_size, dol.size());
+        let frame = audio::ADP_FRAME_SIZE as u64;
+        let after_files = used.iter().map(|&(_, end)| end).max().unwrap_or(0);
+        let disc_end = self.io.seek(SeekFrom::End(0))?;
+        // Leave out the padding around the track
+        let Some((first, last)) = self.nonzero_range(after_files, disc_end)? else {
+            eprintln!("the disc uses audio streaming, but there is no data after its last file");
+            return Ok(());
+        };
+        let start = first / frame * frame;
+        let size = (last + 1 - start).div_ceil(frame) * frame;
+
+        let data = FileData::Image {
+            offset: start,
+            size,
+        };
+        self.add_to_root("stream.adp", Kind::File(data));
+        Ok(())
+    }
+
+    /// Adds the `.meta` directory to the root directory, with files describing the disc rather
+    /// than being on it: the boot DOL converted to `main.elf`, its sections in `.meta/dol` with
+    /// their load addresses in extended attributes, and `cover.png` if there's cover art for the
+    /// game.
+    fn add_meta(&mut self) -> Result<(), Error> {
+        let meta = self.add_to_root(".meta", Kind::Directory(vec![]));
+        let dol_offset = u64::from(self.disc.header.executable_offset);
+        let dol = Dol::read(&mut self.io, dol_offset)?;
+        let elf = FileData::Elf {
+            size: elf::size(&dol),
+        };
+        self.tree.add(meta, "main.elf".into(), Kind::File(elf));
+        if let Some(cover) = self.cover() {
+            self.tree
+                .add(meta, "cover.png".into(), Kind::File(FileData::Host(cover)));
+        }
+        let directory = self.tree.add(meta, "dol".into(), Kind::Directory(vec![]));
+        let hex = |value: u32| format!("{value:#010x}");
+        self.xattrs.insert(
+            directory,
+            vec![
+                ("user.gcnfuse.entry_point", hex(dol.entry_point)),
+                ("user.gcnfuse.bss_address", hex(dol.bss_address)),
+                ("user.gcnfuse.bss_size", dol.bss_size.to_string()),
+            ],
+        );
+        for (kind, sections) in [("text", &dol.text), ("data", &dol.data)] {
+            for section in sections {
+                let data = FileData::Image {
+                    offset: dol_offset + u64::from(section.offset),
+                    size: section.size.into(),
+                };
+                let name = format!("{kind}{}.bin", section.number);
+                let inode = self.tree.add(directory, name, Kind::File(data));
+                self.xattrs.insert(
+                    inode,
+                    vec![
+                        ("user.gcnfuse.address", hex(section.address)),
+                        ("user.gcnfuse.size", section.size.to_string()),
+                    ],
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the game's cover art, looking for it as set by [`Options::covers`] and
+    /// [`Options::online`]. Covers are only nice to have, so errors are just reported.
+    fn cover(&self) -> Option<PathBuf> {
+        let dir = match &self.options.covers {
+            Some(dir) => dir.clone(),
+            None if self.options.online => covers::cache_dir()?,
+            None => return None,
+        };
+        // Covers are named and downloaded by the game ID, which is whatever the disc says it is
+        let game_id = titles::game_id(&self.disc.header);
+        if !game_id.bytes().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        covers::find(&game_id, &dir, self.options.online).unwrap_or_else(|err| {
+            eprintln!("unable to get the cover for {game_id}: {err}");
+            None
+        })
+    }
+
+    /// Adds a virtual entry to the root directory, renaming it if the disc already has something
+    /// by that name. Returns the new entry's inode.
+    fn add_to_root(&mut self, name: &str, kind: Kind) -> Inode {
+        let unique = self.unique_name(Inode(1), name);
+        self.tree.add(Inode(1), unique, kind)
+    }
+
+    /// Returns `name`, renamed like duplicate FST entries if the directory `parent` already has
+    /// an entry with that name.
+    fn unique_name(&self, parent: Inode, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 2;
+        while self.tree.lookup(parent, &unique, &self.options).is_some() {
+            unique = tree::disambiguate(name, n);
+            n += 1;
+        }
+        unique
+    }
+
+    /// Returns the offsets of the first and last non-zero bytes of the image between `start` and
+    /// `end`, or `None` if it's all zeroes.
+    fn nonzero_range(&mut self, start: u64, end: u64) -> io::Result<Option<(u64, u64)>> {
+        const BLOCK_SIZE: u64 = 0x8000;
+        let mut read_block = |offset: u64| -> io::Result<Vec<u8>> {
+            // At most a block
+            #[allow(clippy::cast_possible_truncation)]
+            let mut block = vec![0; BLOCK_SIZE.min(end - offset) as usize];
+            self.io.seek(SeekFrom::Start(offset))?;
+            self.io.read_exact(&mut block)?;
+            Ok(block)
+        };
+        let mut first = None;
+        let mut offset = start;
+        while offset < end {
+            let block = read_block(offset)?;
+            if let Some(position) = block.iter().position(|&byte| byte != 0) {
+                first = Some(offset + position as u64);
+                break;
+            }
+            offset += block.len() as u64;
+        }
+        let Some(first) = first else {
+            return Ok(None);
+        };
+        let mut offset = end;
+        loop {
+            offset = offset.saturating_sub(BLOCK_SIZE).max(first);
+            let block = read_block(offset)?;
+            if let Some(position) = block.iter().rposition(|&byte| byte != 0) {
+                return Ok(Some((first, offset + position as u64)));
+            }
+        }
     }
-}
 
-impl From<Inode> for Index {
-    fn from(val: Inode) -> Self {
-        // FST can only have u32 worth of inodes/entries, so this cast is guaranteed to work
+    /// Returns a generation number for the disc's inodes that depends only on its header and FST,
+    /// so file handles survive remounts of the same image but not of a different one.
+    fn image_generation(&mut self) -> io::Result<u64> {
+        let header = &self.disc.header;
+        let mut data = vec![0; layout::HEADER_SIZE];
+        self.io.seek(SeekFrom::Start(0))?;
+        self.io.read_exact(&mut data)?;
+        let mut fst = vec![0; header.fst_size as usize];
+        self.io.seek(SeekFrom::Start(header.fst_offset.into()))?;
+        self.io.read_exact(&mut fst)?;
+        data.extend_from_slice(&fst);
+        // The options that add entries change which inodes exist, so they're part of it too
+        let options = &self.options;
+        data.extend(
+            [
+                options.overlay.is_some(),
+                options.expand_archives,
+                options.decompress,
+                options.wav,
+                options.dtk,
+                options.meta,
+            ]
+            .map(u8::from),
+        );
+        // FNV-1a, which unlike the standard library's hashers is stable across builds
+        let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+        for byte in data {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3);
+        }
+        Ok(hash)
+    }
+
+    /// Returns the hashes of the `size` bytes of the image at `offset`, as much of them as there
+    /// is, computing them if they aren't known yet.
+    #[cfg(unix)]
+    fn hashes(&mut self, offset: u64, size: u64) -> io::Result<Hashes> {
+        let size = self.readable(offset, size)? as u64;
+        if self.hashes.is_none() {
+            self.hashes = Some(
+                self.image_key
+                    .map_or_else(HashCache::default, |key| HashCache::load(&key)),
+            );
+        }
+        let cache = self.hashes.as_mut().unwrap();
+        if let Some(hashes) = cache.get(offset, size) {
+            return Ok(hashes);
+        }
+        let hashes = Hashes::compute(&mut self.io, offset, size)?;
+        cache.insert(offset, size, hashes);
+        Ok(hashes)
+    }
+
+    /// Returns how long the kernel may cache entries and attributes.
+    #[cfg(unix)]
+    pub(crate) const fn ttl(&self) -> Duration {
+        if self.options.export {
+            // Nothing changes in export mode, so the frequent revalidations of NFS clients can be
+            // answered from the kernel's cache
+            Duration::from_hours(1)
+        } else {
+            Duration::from_secs(1)
+        }
+    }
+
+    /// Decompresses and expands files as requested by [`Options::decompress`] and
+    /// [`Options::expand_archives`], including files inside archives.
+    fn unpack_files(&mut self) {
+        let mut pending = vec![];
+        let mut directories = vec![Inode(1)];
+        while let Some(directory) = directories.pop() {
+            for &child in self.tree.children(directory).unwrap_or_default() {
+                match self.tree.children(child) {
+                    Some(_) => directories.push(child),
+                    None => pending.push(child),
+                }
+            }
+        }
+        while let Some(inode) = pending.pop() {
+            if self.options.decompress {
+                self.detect_compression(inode);
+            }
+            if self.options.wav {
+                self.add_wav(inode);
+            }
+            if self.options.expand_archives {
+                pending.extend(self.expand_archive(inode));
+            }
+        }
+    }
+
+    /// Makes the file serve its decompressed contents if it's Yaz0 or Yay0 compressed.
+    fn detect_compression(&mut self, inode: Inode) {
+        // The header is tiny
         #[allow(clippy::cast_possible_truncation)]
-        ((val.0 - 1) as u32).into()
+        let header_size = compression::HEADER_SIZE as u32;
+        let size = self
+            .read_data(inode, 0, header_size)
+            .ok()
+            .and_then(|header| compression::decompressed_size(&header));
+        if let Some(size) = size
+            && let Kind::File(data) = &mut self.tree.node_mut(inode).kind
+        {
+            let data = Box::new(data.clone());
+            self.tree.node_mut(inode).kind = Kind::File(FileData::Compressed { data, size });
+        }
+    }
+
+    /// Adds a `name.wav` sibling serving the file converted to WAV if it's DSP or ADP audio.
+    fn add_wav(&mut self, inode: Inode) {
+        let Some(node) = self.tree.get(inode) else {
+            return;
+        };
+        let Some(codec) = Codec::for_name(&node.name) else {
+            return;
+        };
+        let name = format!("{}.wav", node.name);
+        let parent = node.parent;
+        if self.tree.lookup(parent, &name, &self.options).is_some() {
+            return;
+        }
+        // The header is tiny
+        #[allow(clippy::cast_possible_truncation)]
+        let header_size = audio::DSP_HEADER_SIZE as u32;
+        let size = self.file_size(inode).unwrap_or(0);
+        let wav_size = self
+            .read_data(inode, 0, header_size)
+            .ok()
+            .and_then(|header| codec.wav_size(&header, size));
+        if let Some(size) = wav_size {
+            let data = FileData::Wav {
+                source: inode,
+                codec,
+                size,
+            };
+            self.tree.add(parent, name, Kind::File(data));
+        }
+    }
+
+    /// Replaces the file with a directory showing its contents if it's a RARC or U8 archive,
+    /// renaming the archive itself to `name.raw`. Returns the inodes of the files in the archive.
+    fn expand_archive(&mut self, inode: Inode) -> Vec<Inode> {
+        // Archives are at most a few MiB, so the whole archive is parsed in memory
+        #[allow(clippy::cast_possible_truncation)]
+        let magic = archive::MAGIC_SIZE as u32;
+        let is_archive = self
+            .read_data(inode, 0, magic)
+            .is_ok_and(|data| archive::is_archive(&data));
+        if !is_archive {
+            return vec![];
+        }
+        let size = self.file_size(inode).unwrap_or(0);
+        let members = self
+            .read_data(inode, 0, u32::try_from(size).unwrap_or(u32::MAX))
+            .ok()
+            .and_then(|data| archive::parse(&data));
+        let Some(members) = members else {
+            let path = self.tree.path(inode);
+            eprintln!(
+                "{} looks like an archive but can't be read, leaving it as is",
+                path.display()
+            );
+            return vec![];
+        };
+        let node = self.tree.node_mut(inode);
+        let name = mem::take(&mut node.name);
+        let parent = node.parent;
+        // The disc may already have a file with the name the archive is renamed to
+        self.tree.node_mut(inode).name = self.unique_name(parent, &format!("{name}.raw"));
+        let directory = self.tree.add(parent, name, Kind::Directory(vec![]));
+        self.tree
+            .add_archive(directory, inode, members, &self.options)
+    }
+
+    /// Returns the inode at `path`, a `/` separated path relative to the root directory, for
+    /// frontends other than FUSE.
+    pub(crate) fn resolve(&self, path: &str) -> Option<Inode> {
+        path.split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+            .try_fold(Inode(1), |inode, component| match component {
+                ".." => self.tree.get(inode).map(|node| node.parent),
+                _ => self.tree.lookup(inode, component, &self.options),
+            })
+    }
+
+    /// Returns the directory holding the given inode, which for the root directory is itself.
+    #[cfg(unix)]
+    pub(crate) fn parent(&self, inode: Inode) -> Option<Inode> {
+        self.tree.get(inode).map(|node| node.parent)
+    }
+
+    /// Returns the extended attributes of the given inode, by name. Files stored in compressed
+    /// images as they're shown have `user.gcn.compressed_size`, how many bytes of the image
+    /// they take up.
+    #[cfg(unix)]
+    pub(crate) fn xattrs(&self, inode: Inode) -> Vec<(&'static str, String)> {
+        let mut xattrs = self.xattrs.get(&inode).cloned().unwrap_or_default();
+        if let Some(compressed) = &self.compressed
+            && let Some((offset, size)) = self.extent(inode)
+        {
+            let size = compressed.of(offset, size);
+            xattrs.push(("user.gcn.compressed_size", size.to_string()));
+        }
+        xattrs
+    }
+
+    /// Returns the names of the extended attributes of the given inode, with the hashes in
+    /// [`HASH_XATTRS`] for files stored in the image as they're shown.
+    #[cfg(unix)]
+    pub(crate) fn xattr_names(&self, inode: Inode) -> Vec<&'static str> {
+        let mut names: Vec<_> = self
+            .xattrs(inode)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        if self.extent(inode).is_some() {
+            names.extend(HASH_XATTRS);
+        }
+        names
+    }
+
+    /// Returns the extended attribute `name` of the given inode, if it has it, hashing the file's
+    /// contents for those in [`HASH_XATTRS`] the first time they're asked for.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the file's contents can't be read.
+    #[cfg(unix)]
+    pub(crate) fn xattr(&mut self, inode: Inode, name: &OsStr) -> io::Result<Option<String>> {
+        if let Some((_, value)) = self
+            .xattrs(inode)
+            .into_iter()
+            .find(|(xattr, _)| OsStr::new(xattr) == name)
+        {
+            return Ok(Some(value));
+        }
+        let Some(name) = name.to_str().filter(|name| HASH_XATTRS.contains(name)) else {
+            return Ok(None);
+        };
+        let Some((offset, size)) = self.extent(inode) else {
+            return Ok(None);
+        };
+        Ok(self.hashes(offset, size)?.xattr(name))
+    }
+
+    /// Returns the offset and size of the contents of the given file in the image, or `None` if
+    /// they aren't stored there as they are shown.
+    pub(crate) fn extent(&self, inode: Inode) -> Option<(u64, u64)> {
+        match self.tree.get(inode)?.kind {
+            Kind::File(FileData::Disc(index)) => {
+                let Entry::File(entry) = &self.disc.filesystem.entries[index.as_usize()] else {
+                    unreachable!("disc file nodes always point to FST file entries");
+                };
+                Some((entry.offset.into(), entry.size.into()))
+            }
+            Kind::File(FileData::Image { offset, size }) => Some((offset, size)),
+            Kind::File(FileData::Slice {
+                source,
+                offset,
+                size,
+            }) => {
+                let (start, len) = self.extent(source)?;
+                Some((start + offset, size.min(len.saturating_sub(offset))))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the names and inodes of the entries in the given directory, or `None` if it isn't
+    /// one, in the order [`Options::sort`] asks for.
+    pub(crate) fn entries(&self, inode: Inode) -> Option<Vec<(String, Inode)>> {
+        let children = self.tree.children(inode)?;
+        let mut entries: Vec<_> = children
+            .iter()
+            .map(|&child| (self.tree.get(child).unwrap().name.clone(), child))
+            .collect();
+        if self.options.sort == Sort::Name {
+            entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        }
+        Some(entries)
+    }
+
+    /// Returns the entries of the given directory for `readdir`, or `ENOENT` if it doesn't exist
+    /// and `ENOTDIR` if it isn't a directory.
+    #[cfg(any(unix, feature = "winfsp"))]
+    pub(crate) fn listing(&self, inode: Inode) -> Result<Listing, c_int> {
+        let node = self.tree.get(inode).ok_or(libc::ENOENT)?;
+        let entries = self.entries(inode).ok_or(libc::ENOTDIR)?;
+        let mut listing = vec![
+            (inode, FileType::Directory, ".".to_string()),
+            (node.parent, FileType::Directory, "..".to_string()),
+        ];
+        for (name, child) in entries {
+            let type_ = match self.tree.get(child).unwrap().kind {
+                Kind::File(_) => FileType::RegularFile,
+                Kind::Directory(_) => FileType::Directory,
+            };
+            listing.push((child, type_, name));
+        }
+        Ok(listing)
+    }
+
+    /// Returns the generation reported for every inode.
+    #[cfg(unix)]
+    pub(crate) const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Finds the entry `name` of the directory `parent` for `lookup`, returning `None` if there's
+    /// none, which the kernel is told with [`reply_missing`], and failing with `ENOENT` if the
+    /// directory doesn't exist and `EIO` if it isn't a directory.
+    #[cfg(unix)]
+    pub(crate) fn find(&self, parent: Inode, name: &OsStr) -> Result<Option<Inode>, c_int> {
+        // Names on the disc are always valid strings, so a name that isn't can't match anything
+        let Some(name) = name.to_str() else {
+            return Ok(None);
+        };
+        // The kernel resolves these itself, except to find inodes from NFS file handles, which
+        // can be for files too
+        match name {
+            "." => self
+                .tree
+                .get(parent)
+                .map(|_| Some(parent))
+                .ok_or(libc::ENOENT),
+            ".." => self
+                .tree
+                .get(parent)
+                .map(|node| Some(node.parent))
+                .ok_or(libc::ENOENT),
+            _ if self.tree.children(parent).is_none() => {
+                eprintln!("parent inode does not point to a directory");
+                Err(libc::EIO)
+            }
+            // Indexers and file managers keep looking for the same missing names
+            _ => Ok(self.tree.lookup(parent, name, &self.options)),
+        }
+    }
+
+    /// Takes a snapshot of the entries of the given directory for `opendir`, returning the handle
+    /// `readdir` is given for it.
+    #[cfg(unix)]
+    pub(crate) fn open_dir(&mut self, inode: Inode) -> Result<u64, c_int> {
+        let listing = self.listing(inode)?;
+        let fh = self.next_listing;
+        self.next_listing += 1;
+        self.listings.insert(fh, listing);
+        Ok(fh)
+    }
+
+    /// Returns the entries of the given directory for `readdir`, from the snapshot taken when it
+    /// was opened as `fh`.
+    #[cfg(unix)]
+    pub(crate) fn listed(&self, inode: Inode, fh: u64) -> Result<Cow<'_, Listing>, c_int> {
+        // Directories are always opened first, but a listing as it is now does otherwise
+        self.listings.get(&fh).map_or_else(
+            || self.listing(inode).map(Cow::Owned),
+            |listing| Ok(Cow::Borrowed(listing)),
+        )
+    }
+
+    /// Drops the snapshot of the directory opened as `fh`, for `releasedir`.
+    #[cfg(unix)]
+    pub(crate) fn close_dir(&mut self, fh: u64) {
+        self.listings.remove(&fh);
+    }
+
+    /// Answers a read of up to `size` bytes at `offset` of the given file, counting it in the
+    /// stats.
+    #[cfg(unix)]
+    pub(crate) fn reply_read(&mut self, inode: Inode, offset: i64, size: u32, reply: ReplyData) {
+        match self.read_contents(inode, offset, size) {
+            Ok(data) => reply.data(&data),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    /// Returns up to `size` bytes at `offset` of the given file for `read`, counting them in the
+    /// stats, or the errno to reply with.
+    #[cfg(any(unix, feature = "winfsp"))]
+    pub(crate) fn read_contents(
+        &mut self,
+        inode: Inode,
+        offset: i64,
+        size: u32,
+    ) -> Result<Cow<'_, [u8]>, c_int> {
+        let node = self.tree.get(inode).ok_or(libc::ENOENT)?;
+        if let Kind::Directory(_) = node.kind {
+            return Err(libc::ENOTDIR);
+        }
+        let offset = u64::try_from(offset).map_err(|_| libc::EINVAL)?;
+        // Reads of what's been read ahead are answered from it, rather than from a copy
+        if let Some((start, len)) = self.stored(inode, offset, size) {
+            if let Kind::File(FileData::Disc(index)) = node.kind {
+                self.learn(index);
+            }
+            // Borrowed again to return it, as returning the first borrow only if it's there keeps
+            // the reader borrowed for the copying read below too. The second finds it at once
+            if self.io.borrow(start, len).is_some() {
+                self.stats.reads.fetch_add(1, Ordering::Relaxed);
+                self.stats
+                    .bytes_read
+                    .fetch_add(len as u64, Ordering::Relaxed);
+                return Ok(Cow::Borrowed(self.io.borrow(start, len).unwrap()));
+            }
+        }
+        let buffer = self
+            .read_data(inode, offset, size)
+            .map_err(|err| errno(&err))?;
+        self.stats.reads.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .bytes_read
+            .fetch_add(buffer.len() as u64, Ordering::Relaxed);
+        Ok(Cow::Owned(buffer))
+    }
+
+    /// Returns the size of the contents of the given file, or `None` if it isn't a file.
+    fn file_size(&self, inode: Inode) -> Option<u64> {
+        match &self.tree.get(inode)?.kind {
+            Kind::File(FileData::Disc(index)) => {
+                let Entry::File(file) = &self.disc.filesystem.entries[index.as_usize()] else {
+                    unreachable!("disc file nodes always point to FST file entries");
+                };
+                Some(file.size.into())
+            }
+            // A host file that vanished is just reported as empty until the next remount
+            Kind::File(FileData::Host(path)) => {
+                Some(path.metadata().map_or(0, |metadata| metadata.len()))
+            }
+            Kind::File(
+                FileData::Slice { size, .. }
+                | FileData::Compressed { size, .. }
+                | FileData::Wav { size, .. }
+                | FileData::Image { size, .. }
+                | FileData::Elf { size },
+            ) => Some(*size),
+            Kind::File(FileData::Generated(contents)) => Some(contents.len() as u64),
+            Kind::Directory(_) => None,
+        }
+    }
+
+    /// Reads up to `size` bytes at `offset` from the contents of the given file.
+    pub(crate) fn read_data(
+        &mut self,
+        inode: Inode,
+        offset: u64,
+        size: u32,
+    ) -> io::Result<Vec<u8>> {
+        let node = self.tree.get(inode).ok_or(io::ErrorKind::NotFound)?;
+        let Kind::File(data) = &node.kind else {
+            return Err(io::Error::from_raw_os_error(libc::EISDIR));
+        };
+        // Only what needs the rest of the filesystem to be read is copied out of the tree, and
+        // the files decoded whole only when they aren't cached
+        let data = match data {
+            FileData::Host(path) => return read_host(path, offset, size),
+            FileData::Generated(contents) => return Ok(slice(contents, offset, size)),
+            FileData::Compressed { .. } | FileData::Wav { .. } | FileData::Elf { .. }
+                if self.decoded.promote(&inode) =>
+            {
+                return Ok(slice(self.decoded.most_recent(), offset, size));
+            }
+            data => data.clone(),
+        };
+        self.read_file(inode, &data, offset, size)
+    }
+
+    /// Returns how much of the `len` bytes of the image at `start` can be read, cutting them off
+    /// at the end of the image, or fails with `EIO` if none can, as for files of corrupt FSTs
+    /// that start past it.
+    fn readable(&self, start: u64, len: u64) -> io::Result<usize> {
+        if len == 0 {
+            return Ok(0);
+        }
+        if start >= self.image_size {
+            return Err(io::Error::from_raw_os_error(libc::EIO));
+        }
+        // The read is capped at what was asked for, which is a u32
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(len.min(self.image_size - start) as usize)
+    }
+
+    /// Returns where the `size` bytes at `offset` of the given file are in the image, and how many
+    /// of them there are, if it's stored there as it's shown and they can be read.
+    #[cfg(any(unix, feature = "winfsp"))]
+    fn stored(&self, inode: Inode, offset: u64, size: u32) -> Option<(u64, usize)> {
+        let (start, len) = self.extent(inode)?;
+        let available = len.saturating_sub(offset);
+        let start = start + offset;
+        Some((
+            start,
+            self.readable(start, available.min(size.into())).ok()?,
+        ))
+    }
+
+    /// Notes that the FST file `index` is being read, and asks for the file likely read next to
+    /// be read in the background.
+    fn learn(&mut self, index: Index) {
+        let Some(patterns) = &mut self.patterns else {
+            return;
+        };
+        let next = patterns.record(index.0);
+        if let Err(err) = patterns.save_if_due() {
+            eprintln!("can't save the order files were read in: {err}");
+        }
+        let Some(next) = next else {
+            return;
+        };
+        let Some(Entry::File(entry)) = self.disc.filesystem.entries.get(Index(next).as_usize())
+        else {
+            return;
+        };
+        if let Some(prefetch) = &self.prefetch
+            && entry.size != 0
+            && entry.size <= PREFETCH_LIMIT
+        {
+            let start = u64::from(entry.offset);
+            // Prefetching stops if the reading thread is gone, which is no reason to fail reads
+            let _ = prefetch.send((start, start + u64::from(entry.size)));
+        }
+    }
+
+    /// Reads up to `size` bytes at `offset` from `data`, the contents of the given file.
+    fn read_file(
+        &mut self,
+        inode: Inode,
+        data: &FileData,
+        offset: u64,
+        size: u32,
+    ) -> io::Result<Vec<u8>> {
+        match data {
+            FileData::Disc(index) => {
+                let Entry::File(entry) = &self.disc.filesystem.entries[index.as_usize()] else {
+                    unreachable!("disc file nodes always point to FST file entries");
+                };
+                let available = u64::from(entry.size).saturating_sub(offset);
+                let start = u64::from(entry.offset) + offset;
+                let mut buffer = vec![0; self.readable(start, available.min(size.into()))?];
+                self.learn(*index);
+                self.io.seek(SeekFrom::Start(start))?;
+                self.io.read_exact(&mut buffer)?;
+                Ok(buffer)
+            }
+            FileData::Host(path) => read_host(path, offset, size),
+            &FileData::Image {
+                offset: start,
+                size: len,
+            } => {
+                let available = len.saturating_sub(offset);
+                let start = start + offset;
+                let mut buffer = vec![0; self.readable(start, available.min(size.into()))?];
+                self.io.seek(SeekFrom::Start(start))?;
+                self.io.read_exact(&mut buffer)?;
+                Ok(buffer)
+            }
+            &FileData::Slice {
+                source,
+                offset: start,
+                size: len,
+            } => {
+                let available = len.saturating_sub(offset);
+                // The read is capped at size, which is a u32
+                #[allow(clippy::cast_possible_truncation)]
+                let size = available.min(size.into()) as u32;
+                self.read_data(source, start + offset, size)
+            }
+            FileData::Compressed { data, .. } => {
+                let contents = self.decoded(inode, |fuse| {
+                    let compressed = fuse.read_file(inode, data, 0, u32::MAX)?;
+                    compression::decompress(&compressed)
+                        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))
+                })?;
+                Ok(slice(contents, offset, size))
+            }
+            &FileData::Wav { source, codec, .. } => {
+                let contents = self.decoded(inode, |fuse| {
+                    let audio = fuse.read_data(source, 0, u32::MAX)?;
+                    codec
+                        .to_wav(&audio)
+                        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))
+                })?;
+                Ok(slice(contents, offset, size))
+            }
+            FileData::Elf { .. } => {
+                let contents = self.decoded(inode, |fuse| {
+                    let dol_offset = u64::from(fuse.disc.header.executable_offset);
+                    let dol = Dol::read(&mut fuse.io, dol_offset)?;
+                    // The sizes in the header are the disc's, which may claim more than there is
+                    let len = fuse.readable(dol_offset, dol.size().into())?;
+                    if len as u64 != u64::from(dol.size()) {
+                        return Err(io::Error::from(io::ErrorKind::InvalidData));
+                    }
+                    let mut data = vec![0; len];
+                    fuse.io.seek(SeekFrom::Start(dol_offset))?;
+                    fuse.io.read_exact(&mut data)?;
+                    Ok(elf::from_dol(&dol, &data))
+                })?;
+                Ok(slice(contents, offset, size))
+            }
+            FileData::Generated(contents) => Ok(slice(contents, offset, size)),
+        }
+    }
+
+    /// Returns the decoded contents of the given file, calling `decode` to produce them if they
+    /// aren't cached.
+    ///
+    /// Files served decompressed or converted are decoded whole, so the most recent ones that fit
+    /// in [`crate::cache_limit`] are kept around for the reads that usually follow.
+    fn decoded(
+        &mut self,
+        inode: Inode,
+        decode: impl FnOnce(&mut Self) -> io::Result<Vec<u8>>,
+    ) -> io::Result<&[u8]> {
+        if !self.decoded.promote(&inode) {
+            let contents = decode(self)?;
+            self.decoded.insert(inode, contents);
+        }
+        Ok(self.decoded.most_recent())
+    }
+
+    /// Returns the attributes of the given inode, or `None` if it doesn't exist.
+    pub(crate) fn get_attr(&self, inode: Inode) -> Option<FileAttr> {
+        let node = self.tree.get(inode)?;
+        let block_size = self.options.block_size.unwrap_or(SECTOR_SIZE);
+        let mut attr = FileAttr {
+            ino: inode.into(),
+            size: 0,
+            blocks: 0,
+            atime: self.time,
+            mtime: self.time,
+            ctime: self.time,
+            crtime: self.time,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: block_size,
+            flags: 0,
+        };
+        match &node.kind {
+            Kind::File(_) => {
+                attr.size = self.file_size(inode).unwrap_or(0);
+                // Counted in 512 byte units whatever the block size, of which files take up whole
+                let block_size = u64::from(block_size);
+                attr.blocks = (attr.size.div_ceil(block_size) * block_size).div_ceil(512);
+                if self.options.compressed_blocks
+                    && let Some(compressed) = &self.compressed
+                    && let Some((offset, size)) = self.extent(inode)
+                {
+                    attr.blocks = compressed.of(offset, size).div_ceil(512);
+                }
+            }
+            Kind::Directory(children) => {
+                attr.nlink = 2;
+                attr.kind = FileType::Directory;
+                #[allow(clippy::cast_possible_truncation)]
+                let subdirs = children
+                    .iter()
+                    .filter(|&&child| self.tree.children(child).is_some())
+                    .count() as u32; // This cast is fine, there are at most u32 entries
+                attr.nlink += subdirs;
+                attr.perm = 0o555;
+            }
+        }
+        if self.writable_overlay().is_ok() {
+            attr.perm |= 0o200;
+        }
+        Some(attr)
+    }
+
+    /// Returns the overlay directory if changes to the mount are allowed, or `EROFS` otherwise.
+    fn writable_overlay(&self) -> Result<&Path, c_int> {
+        match &self.options.overlay {
+            Some(overlay) if self.options.writable && !self.options.unpacks_files() => Ok(overlay),
+            _ => Err(libc::EROFS),
+        }
+    }
+
+    /// Creates the empty file `name` in `parent`, in the overlay.
+    #[cfg(unix)]
+    fn create_file(&mut self, parent: u64, name: &OsStr) -> Result<Inode, c_int> {
+        self.writable_overlay()?;
+        let (parent, name) = self.parent_of_new(parent, name)?;
+        let path = self
+            .copy_up(parent)
+            .and_then(|parent_path| {
+                let path = parent_path.join(&name);
+                File::create_new(&path)?;
+                Self::remove_whiteout(&parent_path, &name)?;
+                Ok(path)
+            })
+            .map_err(|err| errno(&err))?;
+        let kind = Kind::File(FileData::Host(path));
+        Ok(self.tree.add(parent, name, kind))
+    }
+
+    /// Writes `data` at `offset` in the file, copying it to the overlay first if needed.
+    #[cfg(unix)]
+    fn write_file(&mut self, inode: Inode, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().write(true).open(self.copy_up(inode)?)?;
+        file.seek(SeekFrom::Start(offset))?;
+        io::Write::write_all(&mut file, data)
+    }
+
+    /// Cuts off or extends the file to `size` bytes, copying it to the overlay first if needed.
+    #[cfg(unix)]
+    fn truncate(&mut self, inode: Inode, size: u64) -> io::Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .open(self.copy_up(inode)?)?
+            .set_len(size)
     }
-}
 
-impl From<Index> for Inode {
-    fn from(val: Index) -> Self {
-        // FST can only have u32 worth of inodes/entries, so this cast is guaranteed to work
+    /// Returns why entries can't be renamed: `EROFS` if changes aren't allowed, and `EXDEV`
+    /// otherwise, as like overlayfs, moving is left to copying and deleting, which tools fall
+    /// back to.
+    #[cfg(unix)]
+    fn rename_error(&self) -> c_int {
+        self.writable_overlay().err().unwrap_or(libc::EXDEV)
+    }
+
+    /// Copies up to `len` bytes at `from_offset` in `from` to `to_offset` in `to`, a chunk at a
+    /// time, and returns how many were copied.
+    #[cfg(target_os = "linux")]
+    fn copy_range(
+        &mut self,
+        from: Inode,
+        from_offset: u64,
+        to: Inode,
+        to_offset: u64,
+        len: u64,
+    ) -> io::Result<u32> {
+        let mut file = OpenOptions::new().write(true).open(self.copy_up(to)?)?;
+        file.seek(SeekFrom::Start(to_offset))?;
+        // Replies can only count up to u32 bytes
+        let len = len.min(u32::MAX.into());
+        let mut copied = 0;
+        while copied < len {
+            // At most a chunk
+            #[allow(clippy::cast_possible_truncation)]
+            let size = (len - copied).min(COPY_CHUNK_SIZE.into()) as u32;
+            let data = self.read_data(from, from_offset + copied, size)?;
+            if data.is_empty() {
+                break;
+            }
+            io::Write::write_all(&mut file, &data)?;
+            copied += data.len() as u64;
+        }
+        // Capped at u32 above
         #[allow(clippy::cast_possible_truncation)]
-        (u64::from(val.0) + 1).into()
+        Ok(copied as u32)
+    }
+
+    /// Returns the directory child `name` of `parent` lives in or would live in.
+    #[cfg(unix)]
+    fn parent_of_new(&self, parent: u64, name: &OsStr) -> Result<(Inode, String), c_int> {
+        let name = name.to_str().ok_or(libc::EINVAL)?;
+        if name.starts_with(WHITEOUT_PREFIX) {
+            // These would be treated as deletions on the next mount
+            return Err(libc::EINVAL);
+        }
+        let parent: Inode = parent.into();
+        if self.tree.children(parent).is_none() {
+            return Err(libc::ENOTDIR);
+        }
+        if self.tree.lookup(parent, name, &self.options).is_some() {
+            return Err(libc::EEXIST);
+        }
+        Ok((parent, name.to_string()))
+    }
+
+    /// Makes sure the node's contents live in the overlay, copying them from the disc first if
+    /// needed, and returns their overlay path.
+    #[cfg(unix)]
+    fn copy_up(&mut self, inode: Inode) -> io::Result<PathBuf> {
+        let overlay = self
+            .writable_overlay()
+            .map_err(io::Error::from_raw_os_error)?;
+        let path = overlay.join(self.tree.path(inode));
+        let node = self.tree.get(inode).ok_or(io::ErrorKind::NotFound)?;
+        match &node.kind {
+            Kind::File(FileData::Host(host)) => Ok(host.clone()),
+            Kind::File(FileData::Disc(index)) => {
+                let Entry::File(entry) = &self.disc.filesystem.entries[index.as_usize()] else {
+                    unreachable!("disc file nodes always point to FST file entries");
+                };
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut file = File::create(&path)?;
+                self.io.seek(SeekFrom::Start(entry.offset.into()))?;
+                let copied = io::copy(&mut (&mut self.io).take(entry.size.into()), &mut file);
+                // A disc ending early would leave a truncated copy shadowing the file for good
+                if !matches!(copied, Ok(len) if len == u64::from(entry.size)) {
+                    drop(file);
+                    // The copy is useless either way, and the read's error says why
+                    let _ = fs::remove_file(&path);
+                    return Err(copied
+                        .err()
+                        .unwrap_or_else(|| io::Error::from_raw_os_error(libc::EIO)));
+                }
+                self.tree.node_mut(inode).kind = Kind::File(FileData::Host(path.clone()));
+                Ok(path)
+            }
+            // Only shown when unpacking files, which makes the mount read-only
+            Kind::File(
+                FileData::Slice { .. }
+                | FileData::Compressed { .. }
+                | FileData::Wav { .. }
+                | FileData::Image { .. }
+                | FileData::Elf { .. }
+                | FileData::Generated(_),
+            ) => Err(io::Error::from_raw_os_error(libc::EROFS)),
+            Kind::Directory(_) => {
+                fs::create_dir_all(&path)?;
+                Ok(path)
+            }
+        }
+    }
+
+    /// Records in the overlay that the entry `name` in `parent` was deleted, and removes its
+    /// overlay copy if it had one.
+    #[cfg(unix)]
+    fn delete(&mut self, parent: u64, name: &OsStr, directory: bool) -> Result<(), c_int> {
+        self.writable_overlay()?;
+        let name = name.to_str().ok_or(libc::ENOENT)?;
+        let parent: Inode = parent.into();
+        let inode = self
+            .tree
+            .lookup(parent, name, &self.options)
+            .ok_or(libc::ENOENT)?;
+        match (self.tree.children(inode), directory) {
+            (Some(children), true) if !children.is_empty() => return Err(libc::ENOTEMPTY),
+            (Some(_), false) => return Err(libc::EISDIR),
+            (None, true) => return Err(libc::ENOTDIR),
+            _ => {}
+        }
+
+        let to_errno = |err: io::Error| errno(&err);
+        let parent_path = self.copy_up(parent).map_err(to_errno)?;
+        let path = parent_path.join(&self.tree.get(inode).unwrap().name);
+        let removed = if directory {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match removed {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(to_errno(err)),
+            _ => {}
+        }
+        if self.tree.is_from_disc(inode) {
+            let whiteout = format!("{WHITEOUT_PREFIX}{}", self.tree.get(inode).unwrap().name);
+            File::create(parent_path.join(whiteout)).map_err(to_errno)?;
+        }
+        self.tree.remove(inode);
+        Ok(())
+    }
+
+    /// Removes the whiteout for `name` in the overlay directory at `parent_path`, returning
+    /// whether there was one.
+    #[cfg(unix)]
+    fn remove_whiteout(parent_path: &Path, name: &str) -> io::Result<bool> {
+        match fs::remove_file(parent_path.join(format!("{WHITEOUT_PREFIX}{name}"))) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
     }
 }
 
-pub struct GcnFuse<T: Read + Seek> {
-    io: T,
-    disc: Disc,
+/// The errno for a missing extended attribute, which Linux calls `ENODATA`.
+#[cfg(target_os = "linux")]
+pub const ENOATTR: c_int = libc::ENODATA;
+#[cfg(all(unix, not(target_os = "linux")))]
+pub const ENOATTR: c_int = libc::ENOATTR;
+
+/// The ioctl returning where the contents of a file are in the disc image, `_IOR('G', 1, [u64; 2])`.
+///
+/// It fills in two native-endian `u64`s, the offset of the contents in the image (after any
+/// patch is applied) and their size. Files whose contents aren't stored in the image as they are
+/// shown, such as converted files or files from the overlay, fail it with `EOPNOTSUPP`.
+#[cfg(target_os = "linux")]
+pub const EXTENT_IOCTL: u32 = 0x8010_4701;
+#[cfg(all(unix, not(target_os = "linux")))]
+pub const EXTENT_IOCTL: u32 = 0x4010_4701;
+
+/// Replies to an ioctl asking for [`EXTENT_IOCTL`] with `extent`, the offset and size of a file
+/// in the image if it has one.
+#[cfg(unix)]
+pub fn reply_extent(cmd: u32, out_size: u32, extent: Option<(u64, u64)>, reply: ReplyIoctl) {
+    if cmd != EXTENT_IOCTL {
+        reply.error(libc::ENOTTY);
+        return;
+    }
+    if out_size < 16 {
+        reply.error(libc::EINVAL);
+        return;
+    }
+    let Some((offset, size)) = extent else {
+        reply.error(libc::EOPNOTSUPP);
+        return;
+    };
+    let mut data = offset.to_ne_bytes().to_vec();
+    data.extend_from_slice(&size.to_ne_bytes());
+    reply.ioctl(0, &data);
 }
 
-impl<T: Read + Seek> GcnFuse<T> {
-    pub const fn new(io: T, disc: Disc) -> Self {
-        Self { io, disc }
+/// Returns whether `open` flags ask to change the file.
+#[cfg(unix)]
+pub const fn opens_for_writing(flags: i32) -> bool {
+    flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_TRUNC != 0
+}
+
+/// Converts an IO error into the errno to reply with.
+#[cfg(unix)]
+pub fn errno(err: &io::Error) -> c_int {
+    err.raw_os_error().unwrap_or(libc::EIO)
+}
+
+/// Converts an IO error into the errno to reply with. Windows' own error codes aren't errnos,
+/// so all that's kept of them is whether something was missing.
+#[cfg(all(windows, feature = "winfsp"))]
+pub fn errno(err: &io::Error) -> c_int {
+    match err.kind() {
+        io::ErrorKind::NotFound => libc::ENOENT,
+        _ => libc::EIO,
     }
 }
 
-fn get_attr(fs: &Fst, index: Index) -> FileAttr {
-    let entry = &fs.entries[usize::try_from(u32::from(index)).unwrap()];
-    let mut attr = FileAttr {
+/// Replies to a lookup of a name that doesn't exist, letting the kernel remember that for `ttl`
+/// rather than asking again on every access.
+///
+/// Inode 0 is how the kernel is told an entry is missing, and the attributes are ignored.
+#[cfg(unix)]
+pub fn reply_missing(ttl: &Duration, reply: ReplyEntry) {
+    let attr = FileAttr {
         ino: 0,
         size: 0,
         blocks: 0,
@@ -88,209 +1408,594 @@ fn get_attr(fs: &Fst, index: Index) -> FileAttr {
         ctime: SystemTime::UNIX_EPOCH,
         crtime: SystemTime::UNIX_EPOCH,
         kind: FileType::RegularFile,
-        perm: 0o444,
-        nlink: 1,
+        perm: 0,
+        nlink: 0,
         uid: 0,
         gid: 0,
         rdev: 0,
         blksize: 512,
         flags: 0,
     };
-    match entry {
-        Entry::File(file) => {
-            attr.ino = Inode::from(index).into();
-            attr.size = file.size.into();
-            attr.blocks = ((file.size / 512) + 1).into();
-        }
-        Entry::Directory(directory) => {
-            attr.nlink = 2;
-            attr.ino = Inode::from(index).into();
-            attr.kind = FileType::Directory;
-            let index_start = usize::try_from(directory.index + 1).unwrap();
-            let index_end = usize::try_from(directory.end_index).unwrap();
-            #[allow(clippy::cast_possible_truncation)]
-            let subdirs = fs.entries[index_start..index_end]
-                .iter()
-                .filter(|&e| matches!(e, Entry::Directory(_)))
-                .count() as u32; // This cast is fine, index_start..index_end is u32
-            attr.nlink += subdirs;
-            attr.perm = 0o555;
+    reply.entry(ttl, &attr, 0);
+}
+
+/// Replies with an extended attribute value or list, or just its size if `size` is 0.
+#[cfg(unix)]
+pub fn reply_xattr(data: &[u8], size: u32, reply: ReplyXattr) {
+    // Attributes are tiny
+    #[allow(clippy::cast_possible_truncation)]
+    let len = data.len() as u32;
+    if size == 0 {
+        reply.size(len);
+    } else if len <= size {
+        reply.data(data);
+    } else {
+        reply.error(libc::ERANGE);
+    }
+}
+
+/// Replies to `readdir` with `entries`, as `(ino, type, name)`, from the one at `offset` on,
+/// as many as fit.
+#[cfg(unix)]
+pub fn reply_listing<'a>(
+    entries: impl Iterator<Item = (u64, FileType, &'a str)>,
+    offset: i64,
+    mut reply: ReplyDirectory,
+) {
+    let offset = usize::try_from(offset).unwrap_or(0);
+    for (i, (ino, type_, name)) in entries.enumerate().skip(offset) {
+        // There will always be u32 max entries, so there's no i64 possible wrapping
+        #[allow(clippy::cast_possible_wrap)]
+        if reply.add(ino, (i + 1) as i64, type_, name) {
+            break;
         }
     }
-    attr
+    reply.ok();
+}
+
+/// Returns up to `size` bytes at `offset` in `contents`.
+fn slice(contents: &[u8], offset: u64, size: u32) -> Vec<u8> {
+    let start = usize::try_from(offset).map_or(contents.len(), |offset| offset.min(contents.len()));
+    let end = contents.len().min(start + size as usize);
+    contents[start..end].to_vec()
+}
+
+/// Reads up to `size` bytes at `offset` from the host file at `path`.
+fn read_host(path: &Path, offset: u64, size: u32) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buffer = Vec::with_capacity(size as usize);
+    file.take(size.into()).read_to_end(&mut buffer)?;
+    Ok(buffer)
 }
 
-#[must_use]
-fn get_entry(fs: &Fst, inode: Inode) -> &Entry {
-    &fs.entries[usize::try_from(u32::from(Index::from(inode))).unwrap()]
+impl<T: Read + Seek + Send + 'static> GcnFuse<T> {
+    /// Mounts the filesystem at `path` with `options`, answering it on a thread of its own rather
+    /// than blocking. It's unmounted when the returned handle is joined or dropped.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Io`] if the filesystem can't be mounted.
+    #[cfg(unix)]
+    pub fn spawn_mount(
+        self,
+        path: impl AsRef<Path>,
+        options: &[MountOption],
+    ) -> Result<MountHandle, Error> {
+        Ok(MountHandle::new(fuser::spawn_mount2(self, path, options)?))
+    }
+
+    /// Starts reading the header, apploader, DOL and FST from `io`, another reader of the same
+    /// image, in the background, so the first reads of them don't wait for decompression. With
+    /// [`Options::prefetch`], the files likely read next are read there too from then on.
+    ///
+    /// The header, apploader, DOL and FST are kept in memory for good, as everything reading the
+    /// disc starts with them.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Io`] if the DOL or apploader headers can't be read.
+    pub fn warm_up(&mut self, mut io: T) -> Result<(), Error> {
+        let dol = Dol::read(&mut self.io, self.disc.header.executable_offset.into())?;
+        let apploader_size = layout::apploader_size(&mut self.io)?;
+        let system = layout::system_extents(&self.disc, apploader_size, dol.size());
+        let (sender, receiver) = mpsc::channel();
+        let (prefetch, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let kept = system.into_iter().map(|extent| (extent, true));
+            let prefetched = requests.into_iter().map(|extent| (extent, false));
+            for ((start, end), keep) in kept.chain(prefetched) {
+                // Anything that can't be read is left to be read, or fail, when asked for
+                #[allow(clippy::cast_possible_truncation)] // At most an FST, DOL or prefetch limit
+                let mut data = vec![0; (end - start) as usize];
+                let read = io
+                    .seek(SeekFrom::Start(start))
+                    .and_then(|_| io.read_exact(&mut data));
+                if read.is_ok() && sender.send(Region { start, data, keep }).is_err() {
+                    return;
+                }
+            }
+        });
+        self.io.warm_up(receiver);
+        if self.patterns.is_some() {
+            self.prefetch = Some(prefetch);
+        }
+        Ok(())
+    }
 }
 
+#[cfg(unix)]
 impl<T: Read + Seek> Filesystem for GcnFuse<T> {
+    fn destroy(&mut self) {
+        if let Some(patterns) = &mut self.patterns
+            && let Err(err) = patterns.save()
+        {
+            eprintln!("can't save the order files were read in: {err}");
+        }
+    }
+
+    fn init(&mut self, _req: &Request, config: &mut KernelConfig) -> Result<(), c_int> {
+        let export = consts::FUSE_EXPORT_SUPPORT;
+        if self.options.export && config.add_capabilities(export).is_err() {
+            eprintln!("the kernel doesn't support exporting FUSE filesystems");
+        }
+        if let Some(readahead) = self.options.max_readahead {
+            // The kernel's limit wins over a larger value
+            if let Err(nearest) = config.set_max_readahead(readahead) {
+                let _ = config.set_max_readahead(nearest);
+            }
+        }
+        Ok(())
+    }
+
     fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        let parent: Inode = parent.into();
-        let parent_entry = get_entry(&self.disc.filesystem, parent);
-        let Entry::Directory(parent) = parent_entry else {
-            eprintln!("parent inode does not point to a directory");
-            reply.error(libc::EIO);
+        match self.find(parent.into(), name) {
+            Ok(Some(inode)) => {
+                let attr = self.get_attr(inode).unwrap();
+                reply.entry(&self.ttl(), &attr, self.generation);
+            }
+            Ok(None) => reply_missing(&self.ttl(), reply),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.get_attr(ino.into()) {
+            Some(attr) => reply.attr(&self.ttl(), &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        match self.xattr(ino.into(), name) {
+            Ok(Some(value)) => reply_xattr(value.as_bytes(), size, reply),
+            Ok(None) => reply.error(ENOATTR),
+            Err(err) => reply.error(errno(&err)),
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        let mut names = vec![];
+        for name in self.xattr_names(ino.into()) {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        reply_xattr(&names, size, reply);
+    }
+
+    fn opendir(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
+        match self.open_dir(ino.into()) {
+            Ok(fh) => reply.opened(fh, 0),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, fh: u64, offset: i64, reply: ReplyDirectory) {
+        match self.listed(ino.into(), fh) {
+            Ok(listing) => {
+                let entries = listing
+                    .iter()
+                    .map(|(inode, type_, name)| ((*inode).into(), *type_, name.as_str()));
+                reply_listing(entries, offset, reply);
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn releasedir(&mut self, _req: &Request, _ino: u64, fh: u64, _flags: i32, reply: ReplyEmpty) {
+        self.close_dir(fh);
+        reply.ok();
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        let Some(node) = self.tree.get(ino.into()) else {
+            reply.error(libc::ENOENT);
             return;
         };
-
-        let mut index: Index = (parent.index + 1).into();
-        while u32::from(index) < parent.end_index {
-            let current_inode: Inode = index.into();
-            let entry_name = self
-                .disc
-                .filesystem
-                .get_filename(&mut self.io, index.into());
-            if let Err(error) = entry_name {
-                match error {
-                    gcn_disk::Error::Io(err) => {
-                        if let Some(err) = err.raw_os_error() {
-                            reply.error(err);
-                        }
-                    }
-                    gcn_disk::Error::Utf8(err) => {
-                        eprintln!("{err}");
-                        reply.error(libc::EIO);
-                    }
-                    gcn_disk::Error::Parse(msg) => {
-                        eprintln!("{msg}");
-                        reply.error(libc::EIO);
-                    }
-                }
+        if opens_for_writing(flags) {
+            if let Err(err) = self.writable_overlay() {
+                reply.error(err);
                 return;
             }
-            let entry_name = entry_name.unwrap();
-            if entry_name.as_str() == name {
-                let attr = get_attr(&self.disc.filesystem, index);
-                reply.entry(&Duration::from_secs(1), &attr, 0);
+            if let Kind::Directory(_) = node.kind {
+                reply.error(libc::EISDIR);
                 return;
             }
-
-            let current_entry = get_entry(&self.disc.filesystem, current_inode);
-            match current_entry {
-                Entry::File(_) => {
-                    index = (u32::from(index) + 1).into();
-                }
-                Entry::Directory(directory) => {
-                    index = directory.end_index.into();
-                }
-            }
         }
-        reply.error(libc::ENOENT);
+        reply.opened(0, 0);
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
-        let ino: Inode = ino.into();
-        let attr = get_attr(&self.disc.filesystem, ino.into());
-        reply.attr(&Duration::from_secs(1), &attr);
+    fn read(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let _serving = interrupt::serve(req.pid());
+        self.reply_read(ino.into(), offset, size, reply);
     }
 
-    fn readdir(
+    fn ioctl(
         &mut self,
         _req: &Request,
         ino: u64,
         _fh: u64,
-        offset: i64,
-        mut reply: ReplyDirectory,
+        _flags: u32,
+        cmd: u32,
+        _in_data: &[u8],
+        out_size: u32,
+        reply: ReplyIoctl,
     ) {
+        reply_extent(cmd, out_size, self.extent(ino.into()), reply);
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        if let Err(err) = self.writable_overlay() {
+            reply.error(err);
+            return;
+        }
         let ino: Inode = ino.into();
-        let entry = get_entry(&self.disc.filesystem, ino);
-        let entry = match entry {
-            Entry::File(_) => {
-                reply.error(libc::ENOTDIR);
+        // Only the size is meaningful, ownership, modes and times can't be stored on the disc
+        if let Some(size) = size
+            && let Err(err) = self.truncate(ino, size)
+        {
+            reply.error(errno(&err));
+            return;
+        }
+        match self.get_attr(ino) {
+            Some(attr) => reply.attr(&Duration::from_secs(1), &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        if let Err(err) = self.writable_overlay() {
+            reply.error(err);
+            return;
+        }
+        let (parent, name) = match self.parent_of_new(parent, name) {
+            Ok(new) => new,
+            Err(err) => {
+                reply.error(err);
                 return;
             }
-            Entry::Directory(dir) => dir,
         };
-
-        let parent_index: Index = entry.parent_index.into();
-
-        let mut entries = vec![
-            (ino, FileType::Directory, ".".to_string()),
-            (parent_index.into(), FileType::Directory, "..".to_string()),
-        ];
-
-        let mut index: Index = (u32::from(Index::from(ino)) + 1).into();
-        while u32::from(index) < entry.end_index {
-            let sub_entry =
-                &self.disc.filesystem.entries[usize::try_from(u32::from(index)).unwrap()];
-            let inode: Inode = index.into();
-            let type_ = match sub_entry {
-                Entry::File(_) => FileType::RegularFile,
-                Entry::Directory(_) => FileType::Directory,
-            };
-            let name = self
-                .disc
-                .filesystem
-                .get_filename(&mut self.io, index.into());
-            if let Err(error) = name {
-                match error {
-                    gcn_disk::Error::Io(err) => {
-                        if let Some(err) = err.raw_os_error() {
-                            reply.error(err);
-                        }
-                    }
-                    _ => reply.error(libc::EIO),
-                }
-                return;
+        let created = self.copy_up(parent).and_then(|parent_path| {
+            let path = parent_path.join(&name);
+            fs::create_dir(&path)?;
+            // A directory replacing a deleted disc directory must not show the old contents
+            if Self::remove_whiteout(&parent_path, &name)? {
+                File::create(path.join(OPAQUE_MARKER))?;
             }
-            entries.push((inode, type_, name.unwrap()));
+            Ok(())
+        });
+        if let Err(err) = created {
+            reply.error(errno(&err));
+            return;
+        }
+        let inode = self.tree.add(parent, name, Kind::Directory(vec![]));
+        reply.entry(&Duration::from_secs(1), &self.get_attr(inode).unwrap(), 0);
+    }
 
-            let current_entry = get_entry(&self.disc.filesystem, inode);
-            match current_entry {
-                Entry::File(_) => {
-                    index = (u32::from(index) + 1).into();
-                }
-                Entry::Directory(directory) => {
-                    index = directory.end_index.into();
-                }
-            }
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        match self.delete(parent, name, false) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
         }
+    }
 
-        let offset = usize::try_from(offset).unwrap();
-        for (i, entry) in entries.into_iter().enumerate().skip(offset) {
-            // There will always be u32 max entries, so there's no i64 possible wrapping
-            #[allow(clippy::cast_possible_wrap)]
-            if reply.add(entry.0.into(), (i + 1) as i64, entry.1, entry.2) {
-                break;
-            }
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        match self.delete(parent, name, true) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
         }
-        reply.ok();
     }
 
-    fn read(
+    fn write(
         &mut self,
         _req: &Request,
         ino: u64,
         _fh: u64,
         offset: i64,
-        size: u32,
+        data: &[u8],
+        _write_flags: u32,
         _flags: i32,
-        _lock: Option<u64>,
-        reply: ReplyData,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
     ) {
-        let ino: Inode = ino.into();
-        let entry = get_entry(&self.disc.filesystem, ino);
-        let entry = match entry {
-            Entry::File(file) => file,
-            Entry::Directory(_) => {
-                reply.error(libc::ENOTDIR);
-                return;
+        if let Err(err) = self.writable_overlay() {
+            reply.error(err);
+            return;
+        }
+        // Negative offsets can't happen here
+        #[allow(clippy::cast_sign_loss)]
+        let written = self.write_file(ino.into(), offset as u64, data);
+        match written {
+            // FUSE writes are at most u32 in size
+            #[allow(clippy::cast_possible_truncation)]
+            Ok(()) => reply.written(data.len() as u32),
+            Err(err) => reply.error(errno(&err)),
+        }
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        match self.create_file(parent, name) {
+            Ok(inode) => {
+                let attr = self.get_attr(inode).unwrap();
+                reply.created(&Duration::from_secs(1), &attr, 0, 0, 0);
             }
-        };
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn mknod(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        _umask: u32,
+        _rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        if let Err(err) = self.writable_overlay() {
+            reply.error(err);
+            return;
+        }
+        // Only regular files can be put on a disc. mode_t is narrower than the mode on macOS
+        #[allow(clippy::useless_conversion)]
+        let regular = mode & u32::from(libc::S_IFMT) == u32::from(libc::S_IFREG);
+        if !regular {
+            reply.error(libc::EPERM);
+            return;
+        }
+        match self.create_file(parent, name) {
+            Ok(inode) => reply.entry(&Duration::from_secs(1), &self.get_attr(inode).unwrap(), 0),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    // Only Linux mounts get the newer protocol it's part of
+    #[cfg(target_os = "linux")]
+    fn copy_file_range(
+        &mut self,
+        req: &Request,
+        ino_in: u64,
+        _fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        _fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        _flags: u32,
+        reply: ReplyWrite,
+    ) {
+        if let Err(err) = self.writable_overlay() {
+            reply.error(err);
+            return;
+        }
+        let _serving = interrupt::serve(req.pid());
         // Negative offsets can't happen here
-        // Additionally, in this filesystem files max size is capped at u32
-        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-        let offset = offset as u32;
-        let read_size = cmp::min(size, entry.size - offset);
-        let mut buffer = vec![0; read_size as usize];
-        self.io
-            .seek(SeekFrom::Start(
-                u64::from(entry.offset).strict_add(offset.into()),
-            ))
-            .unwrap();
-        self.io.read_exact(&mut buffer).unwrap();
-        reply.data(&buffer);
+        #[allow(clippy::cast_sign_loss)]
+        let copied = self.copy_range(
+            ino_in.into(),
+            offset_in as u64,
+            ino_out.into(),
+            offset_out as u64,
+            len,
+        );
+        match copied {
+            Ok(copied) => reply.written(copied),
+            Err(err) => reply.error(errno(&err)),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request,
+        _parent: u64,
+        _name: &OsStr,
+        _newparent: u64,
+        _newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        reply.error(self.rename_error());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashes;
+    use crate::lenient;
+    use crate::mkiso;
+    use crate::options::Strictness;
+    use sha1::Digest;
+    use sha1::Sha1;
+    use std::env;
+    use std::process;
+
+    /// Returns a writable mount of a small disc, with its overlay in a new directory named after
+    /// `name`.
+    fn mount(name: &str) -> (GcnFuse<io::Cursor<Vec<u8>>>, PathBuf) {
+        let image = mkiso::tests::image(
+            name,
+            &[
+                ("a.txt", b"disc a"),
+                ("dir/b.txt", b"disc b"),
+                ("empty/c.txt", b""),
+            ],
+        );
+        let overlay = env::temp_dir().join(format!(".gcnfuse-{name}-overlay-{}", process::id()));
+        fs::create_dir_all(&overlay).unwrap();
+        let mut io = io::Cursor::new(image);
+        let disc = lenient::read_disc(&mut io, Strictness::default()).unwrap();
+        let options = Options {
+            overlay: Some(overlay.clone()),
+            writable: true,
+            ..Options::default()
+        };
+        (GcnFuse::new(io, disc, options).unwrap(), overlay)
+    }
+
+    #[test]
+    fn writes_copy_up() {
+        let (mut fuse, overlay) = mount("fuse-writes");
+        let a = fuse.resolve("a.txt").unwrap();
+        let b = fuse.resolve("dir/b.txt").unwrap();
+        let written = fuse.write_file(a, 5, b"A!");
+        let truncated = fuse.truncate(b, 2);
+        let copies = [
+            fs::read(overlay.join("a.txt")),
+            fs::read(overlay.join("dir/b.txt")),
+        ];
+        let read = fuse.read_data(a, 0, 100);
+        let size = fuse.file_size(b);
+        let _ = fs::remove_dir_all(&overlay);
+        written.unwrap();
+        truncated.unwrap();
+        assert_eq!(copies.map(Result::unwrap), [&b"disc A!"[..], b"di"]);
+        assert_eq!(read.unwrap(), b"disc A!");
+        assert_eq!(size, Some(2));
+    }
+
+    #[test]
+    fn deletes_leave_whiteouts() {
+        let (mut fuse, overlay) = mount("fuse-deletes");
+        let unlinked = fuse.delete(1, OsStr::new("a.txt"), false);
+        let whiteout = overlay.join(".wh.a.txt").exists();
+        let gone = fuse.resolve("a.txt").is_none();
+        let recreated = fuse.create_file(1, OsStr::new("a.txt"));
+        let whiteout_gone = !overlay.join(".wh.a.txt").exists();
+        let read = recreated.map(|a| fuse.read_data(a, 0, 100).map_err(|err| errno(&err)));
+        let _ = fs::remove_dir_all(&overlay);
+        assert_eq!(unlinked, Ok(()));
+        assert!(whiteout && gone);
+        assert_eq!(read, Ok(Ok(vec![])));
+        assert!(whiteout_gone);
+    }
+
+    #[test]
+    fn refused_changes() {
+        let (mut fuse, overlay) = mount("fuse-refused");
+        let errors = [
+            fuse.delete(1, OsStr::new("dir"), true),
+            fuse.delete(1, OsStr::new("dir"), false),
+            fuse.delete(1, OsStr::new("a.txt"), true),
+            fuse.create_file(1, OsStr::new("a.txt")).map(|_| ()),
+            fuse.create_file(1, OsStr::new(".wh.dir")).map(|_| ()),
+            Err(fuse.rename_error()),
+        ];
+        let untouched = fs::read_dir(&overlay).map(Iterator::count);
+        let _ = fs::remove_dir_all(&overlay);
+        assert_eq!(
+            errors,
+            [
+                Err(libc::ENOTEMPTY),
+                Err(libc::EISDIR),
+                Err(libc::ENOTDIR),
+                Err(libc::EEXIST),
+                Err(libc::EINVAL),
+                Err(libc::EXDEV),
+            ]
+        );
+        assert_eq!(untouched.unwrap(), 0);
+        assert!(fuse.resolve("dir/b.txt").is_some());
+    }
+
+    #[test]
+    fn read_only_without_writable() {
+        let (mut fuse, overlay) = mount("fuse-read-only");
+        fuse.options.writable = false;
+        let a = fuse.resolve("a.txt").unwrap();
+        let written = fuse.write_file(a, 0, b"x");
+        let _ = fs::remove_dir_all(&overlay);
+        assert_eq!(written.unwrap_err().raw_os_error(), Some(libc::EROFS));
+        assert_eq!(fuse.rename_error(), libc::EROFS);
+    }
+
+    #[test]
+    fn hashes_follow_contents() {
+        let dir = env::temp_dir().join(format!(".gcnfuse-fuse-hashes-images-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Same FST and size, different data, as a good and a bad dump of a game would be
+        let sha1s = [b"good", b"bad!"].map(|contents| {
+            let path = dir.join(str::from_utf8(contents).unwrap());
+            let image = mkiso::tests::image("fuse-hashes", &[("a.bin", contents)]);
+            fs::write(&path, image).unwrap();
+            let mut image = crate::Image::open(&path).unwrap();
+            let disc = lenient::read_disc(&mut image, Strictness::default()).unwrap();
+            let key = image.content_key().unwrap().unwrap();
+            let mut fuse = GcnFuse::new(image, disc, Options::default()).unwrap();
+            fuse.hashes = Some(HashCache::load_in(Some(dir.join("hashes")), &key));
+            let a = fuse.resolve("a.bin").unwrap();
+            let sha1 = fuse.xattr(a, OsStr::new("user.gcn.sha1"));
+            (sha1.unwrap().unwrap(), hashes::hex(&Sha1::digest(contents)))
+        });
+        let _ = fs::remove_dir_all(&dir);
+        let [(good, good_expected), (bad, bad_expected)] = sha1s;
+        assert_eq!(good, good_expected);
+        assert_eq!(bad, bad_expected);
+        assert_ne!(good, bad);
     }
 }
@@ -0,0 +1,368 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::image::CompressedSizes;
+use flate2::read::ZlibDecoder;
+use sha1::Digest;
+use sha1::Sha1;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+/// Magic word GCZ images start with, little-endian like the rest of the header.
+const MAGIC: u32 = 0xb10b_c001;
+
+/// Size of the GCZ header, before the block pointers.
+const HEADER_SIZE: usize = 32;
+
+/// Bit of a block pointer set when the block is stored as it is instead of compressed.
+const STORED: u64 = 1 << 63;
+
+/// Blocks larger than this are refused, to not allocate whatever a damaged header says.
+const MAX_BLOCK: u32 = 64 << 20;
+
+/// A GCZ image, Dolphin's older format compressing the disc into blocks with zlib, with an
+/// Adler-32 checksum of each block as it's stored.
+pub struct Gcz<R> {
+    io: R,
+    /// Size of the disc.
+    data_size: u64,
+    block_size: u32,
+    /// Where the file holds each block, from the start of the data after the tables, with the
+    /// top bit set if it isn't compressed.
+    pointers: Vec<u64>,
+    /// Adler-32 checksum of each block as it's stored.
+    hashes: Vec<u32>,
+    /// Where the data after the tables starts in the file.
+    data_start: u64,
+    /// Size of the data after the tables, where the last block ends.
+    data_len: u64,
+    position: u64,
+    /// The last block read and its index, as most reads are of the same block as the last.
+    block: Option<(u64, Vec<u8>)>,
+}
+
+/// Returns whether `io` starts with the magic word of GCZ images.
+pub fn has_gcz_magic<R: Read + Seek>(io: &mut R) -> bool {
+    let mut magic = [0; 4];
+    io.seek(SeekFrom::Start(0)).is_ok()
+        && io.read_exact(&mut magic).is_ok()
+        && u32::from_le_bytes(magic) == MAGIC
+}
+
+/// Returns an error for a GCZ image whose tables can't be right.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid GCZ image: {message}"),
+    )
+}
+
+impl<R: Read + Seek> Gcz<R> {
+    /// Reads the header and tables of the GCZ image in `io`.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if they can't be read, with [`io::ErrorKind::InvalidData`] if they don't
+    /// describe a GCZ image.
+    pub fn new(mut io: R) -> io::Result<Self> {
+        io.seek(SeekFrom::Start(0))?;
+        let mut header = [0; HEADER_SIZE];
+        io.read_exact(&mut header)?;
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+        if u32_at(0) != MAGIC {
+            return Err(invalid("bad magic word"));
+        }
+        let data_len = u64_at(8);
+        let data_size = u64_at(16);
+        let block_size = u32_at(24);
+        let count = u32_at(28);
+        if block_size == 0 || block_size > MAX_BLOCK {
+            return Err(invalid(&format!("a block size of {block_size}")));
+        }
+        if u64::from(count) != data_size.div_ceil(u64::from(block_size)) {
+            return Err(invalid(&format!(
+                "{count} blocks can't hold {data_size} bytes"
+            )));
+        }
+        let data_start = HEADER_SIZE as u64 + u64::from(count) * 12;
+        if io.seek(SeekFrom::End(0))? < data_start + data_len {
+            return Err(invalid("the file ends before its data"));
+        }
+        io.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+        let mut tables = vec![0; count as usize * 12];
+        io.read_exact(&mut tables)?;
+        let (pointers, hashes) = tables.split_at(count as usize * 8);
+        let pointers: Vec<u64> = pointers
+            .chunks_exact(8)
+            .map(|pointer| u64::from_le_bytes(pointer.try_into().unwrap()))
+            .collect();
+        let hashes = hashes
+            .chunks_exact(4)
+            .map(|hash| u32::from_le_bytes(hash.try_into().unwrap()))
+            .collect();
+        let mut end = data_len;
+        for &pointer in pointers.iter().rev() {
+            if pointer & !STORED > end {
+                return Err(invalid("blocks are out of order"));
+            }
+            end = pointer & !STORED;
+        }
+        Ok(Self {
+            io,
+            data_size,
+            block_size,
+            pointers,
+            hashes,
+            data_start,
+            data_len,
+            position: 0,
+            block: None,
+        })
+    }
+
+    /// Returns the size of the disc.
+    pub const fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
+    /// Returns the size of the blocks the disc is compressed in.
+    pub const fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    /// Returns where the file holds the block at `index`, from the start of the data, and
+    /// whether it's compressed.
+    fn extent(&self, index: usize) -> (u64, u64, bool) {
+        let pointer = self.pointers[index];
+        let end = self
+            .pointers
+            .get(index + 1)
+            .map_or(self.data_len, |next| next & !STORED);
+        (pointer & !STORED, end, pointer & STORED == 0)
+    }
+
+    /// Returns a hash of the header and tables, which hold a checksum of every block, so images
+    /// holding different data get different hashes.
+    pub fn tables_hash(&self) -> [u8; 20] {
+        let mut sha1 = Sha1::new();
+        for field in [self.data_len, self.data_size, self.block_size.into()] {
+            sha1.update(field.to_le_bytes());
+        }
+        for pointer in &self.pointers {
+            sha1.update(pointer.to_le_bytes());
+        }
+        for hash in &self.hashes {
+            sha1.update(hash.to_le_bytes());
+        }
+        sha1.finalize().into()
+    }
+
+    /// Returns how much of the file each block takes up.
+    pub fn sizes(&self) -> CompressedSizes {
+        let block_size = u64::from(self.block_size);
+        let groups = (0..self.pointers.len())
+            .map(|index| {
+                let (start, end, _) = self.extent(index);
+                let offset = index as u64 * block_size;
+                (
+                    offset,
+                    (offset + block_size).min(self.data_size),
+                    end - start,
+                )
+            })
+            .collect();
+        CompressedSizes { groups }
+    }
+
+    /// Reads the block at `index`, checking it against its checksum, and decompresses it.
+    fn read_block(&mut self, index: u64) -> io::Result<&[u8]> {
+        if self.block.as_ref().is_none_or(|(last, _)| *last != index) {
+            self.block = None;
+            // Within the tables, which were read whole
+            #[allow(clippy::cast_possible_truncation)]
+            let (start, end, compressed) = self.extent(index as usize);
+            // Blocks are at most MAX_BLOCK before compression, and zlib grows them very little
+            #[allow(clippy::cast_possible_truncation)]
+            let mut stored = vec![0; (end - start) as usize];
+            self.io.seek(SeekFrom::Start(self.data_start + start))?;
+            self.io.read_exact(&mut stored)?;
+            #[allow(clippy::cast_possible_truncation)] // As above
+            let hash = self.hashes[index as usize];
+            if adler2::adler32_slice(&stored) != hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("GCZ block {index} doesn't match its checksum"),
+                ));
+            }
+            let len =
+                u64::from(self.block_size).min(self.data_size - index * u64::from(self.block_size));
+            let mut data = if compressed {
+                let mut data = Vec::new();
+                ZlibDecoder::new(stored.as_slice())
+                    .take(len)
+                    .read_to_end(&mut data)?;
+                data
+            } else {
+                stored
+            };
+            if (data.len() as u64) < len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("GCZ block {index} is short"),
+                ));
+            }
+            // Stored blocks are as large as the others even at the end of the disc
+            #[allow(clippy::cast_possible_truncation)] // At most a block
+            data.truncate(len as usize);
+            self.block = Some((index, data));
+        }
+        Ok(self
+            .block
+            .as_ref()
+            .map_or(&[][..], |(_, data)| data.as_slice()))
+    }
+}
+
+impl<R: Read + Seek> Read for Gcz<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.data_size || buf.is_empty() {
+            return Ok(0);
+        }
+        let block_size = u64::from(self.block_size);
+        let index = self.position / block_size;
+        // Within a block
+        #[allow(clippy::cast_possible_truncation)]
+        let skip = (self.position % block_size) as usize;
+        let block = self.read_block(index)?;
+        let len = buf.len().min(block.len() - skip);
+        buf[..len].copy_from_slice(&block[skip..skip + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R> Seek for Gcz<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.data_size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Cursor;
+    use std::io::Write;
+
+    /// Builds a GCZ image of `disc` in blocks of `block_size`, storing the blocks for which
+    /// `stored` is true as they are.
+    fn build(disc: &[u8], block_size: usize, stored: impl Fn(usize) -> bool) -> Vec<u8> {
+        let blocks: Vec<(Vec<u8>, bool)> = disc
+            .chunks(block_size)
+            .enumerate()
+            .map(|(index, block)| {
+                if stored(index) {
+                    (block.to_vec(), true)
+                } else {
+                    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(block).unwrap();
+                    (encoder.finish().unwrap(), false)
+                }
+            })
+            .collect();
+        let data_len: usize = blocks.iter().map(|(data, _)| data.len()).sum();
+        let mut image = Vec::new();
+        image.extend_from_slice(&MAGIC.to_le_bytes());
+        image.extend_from_slice(&0u32.to_le_bytes());
+        image.extend_from_slice(&(data_len as u64).to_le_bytes());
+        image.extend_from_slice(&(disc.len() as u64).to_le_bytes());
+        image.extend_from_slice(&u32::try_from(block_size).unwrap().to_le_bytes());
+        image.extend_from_slice(&u32::try_from(blocks.len()).unwrap().to_le_bytes());
+        let mut offset = 0;
+        for (data, stored) in &blocks {
+            let flag = if *stored { STORED } else { 0 };
+            image.extend_from_slice(&(offset | flag).to_le_bytes());
+            offset += data.len() as u64;
+        }
+        for (data, _) in &blocks {
+            image.extend_from_slice(&adler2::adler32_slice(data).to_le_bytes());
+        }
+        for (data, _) in &blocks {
+            image.extend_from_slice(data);
+        }
+        image
+    }
+
+    fn disc() -> Vec<u8> {
+        (0..10_000u32)
+            .map(|i| (i * 7 / 13).to_le_bytes()[0])
+            .collect()
+    }
+
+    #[test]
+    fn reads_compressed_and_stored_blocks() {
+        let disc = disc();
+        let image = build(&disc, 4096, |index| index == 1);
+        let mut cursor = Cursor::new(image);
+        assert!(has_gcz_magic(&mut cursor));
+        let mut gcz = Gcz::new(cursor).unwrap();
+        assert_eq!(gcz.data_size(), disc.len() as u64);
+        assert_eq!(gcz.block_size(), 4096);
+        let mut read = Vec::new();
+        gcz.read_to_end(&mut read).unwrap();
+        assert_eq!(read, disc);
+
+        // Across the stored block's end into the last, shorter one
+        gcz.seek(SeekFrom::Start(8000)).unwrap();
+        let mut buf = [0; 500];
+        gcz.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, disc[8000..8500]);
+    }
+
+    #[test]
+    fn sizes_are_of_the_stored_blocks() {
+        let disc = disc();
+        let gcz = Gcz::new(Cursor::new(build(&disc, 4096, |index| index == 1))).unwrap();
+        let sizes = gcz.sizes();
+        assert_eq!(sizes.of(4096, 4096), 4096);
+        assert!(sizes.of(0, 4096) < 4096);
+    }
+
+    #[test]
+    fn rejects_damaged_blocks() {
+        let disc = disc();
+        let mut image = build(&disc, 4096, |_| true);
+        let last = image.len() - 1;
+        image[last] ^= 1;
+        let mut gcz = Gcz::new(Cursor::new(image)).unwrap();
+        let mut buf = vec![0; 8192];
+        gcz.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, disc[..8192]);
+        let err = gcz.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let disc = disc();
+        let mut image = build(&disc, 4096, |_| false);
+        image[28] = 2;
+        assert!(Gcz::new(Cursor::new(image)).is_err());
+        assert!(!has_gcz_magic(&mut Cursor::new(b"RVZ\x01".to_vec())));
+    }
+}
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::find::PathPattern;
+use crate::layout;
+use gcn_disk::Disc;
+use memchr::memmem::Finder;
+use regex::bytes::Regex;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+/// How much of each file is searched at once for [`ContentPattern::Bytes`].
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// What the contents of files are searched for.
+#[derive(Clone, Debug)]
+pub enum ContentPattern {
+    /// These bytes, which mustn't be empty, such as text in the encoding the game uses.
+    Bytes(Vec<u8>),
+    /// A regular expression over bytes. Files are read whole to search them.
+    Regex(Regex),
+}
+
+/// Calls `found` with the offsets in `data` where `finder` matches, starting `base` bytes into
+/// the file.
+fn report(finder: &Finder, data: &[u8], base: u64, found: &mut impl FnMut(u64)) {
+    for offset in finder.find_iter(data) {
+        found(base + offset as u64);
+    }
+}
+
+/// Searches the `size` bytes from the current position of `io` for `pattern`, calling `found`
+/// with the offset of each match from there.
+fn search<T: Read>(
+    io: &mut T,
+    size: u64,
+    pattern: &ContentPattern,
+    found: &mut impl FnMut(u64),
+) -> io::Result<()> {
+    let mut io = io.take(size);
+    match pattern {
+        ContentPattern::Bytes(bytes) => {
+            let finder = Finder::new(bytes);
+            // The end of the last chunk, too short to match on its own but which the start of
+            // a match might be in
+            let overlap = bytes.len().saturating_sub(1);
+            let mut data = Vec::with_capacity(overlap + CHUNK_SIZE);
+            let mut base = 0;
+            loop {
+                let kept = data.len();
+                io.by_ref().take(CHUNK_SIZE as u64).read_to_end(&mut data)?;
+                if data.len() == kept {
+                    break;
+                }
+                report(&finder, &data, base, found);
+                let drop = data.len().saturating_sub(overlap);
+                data.drain(..drop);
+                base += drop as u64;
+            }
+        }
+        ContentPattern::Regex(regex) => {
+            let mut data = Vec::new();
+            io.read_to_end(&mut data)?;
+            for found_match in regex.find_iter(&data) {
+                found(found_match.start() as u64);
+            }
+        }
+    }
+    if io.limit() != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the image ended early",
+        ));
+    }
+    Ok(())
+}
+
+/// Searches the contents of the files on a disc for `pattern`, calling `found` with the path of
+/// each file relative to the root and the offset in it of every match, in order.
+///
+/// Only files whose paths match `paths` are searched, if given.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the FST names or the files can't be read.
+pub fn grep<T: Read + Seek>(
+    io: &mut T,
+    disc: &mut Disc,
+    pattern: &ContentPattern,
+    paths: Option<&PathPattern>,
+    mut found: impl FnMut(&str, u64),
+) -> Result<(), Error> {
+    for (path, (offset, size)) in layout::contents(io, disc)? {
+        let Some(path) = path.strip_prefix("files/") else {
+            continue;
+        };
+        if paths.is_some_and(|paths| !paths.matches(path)) {
+            continue;
+        }
+        io.seek(SeekFrom::Start(offset))?;
+        search(io, size, pattern, &mut |offset| found(path, offset))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+#[cfg(unix)]
+use crate::cache;
+use crc32fast::Hasher;
+use sha1::Digest;
+use sha1::Sha1;
+#[cfg(unix)]
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::fmt::Write as _;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// How much of a file is read at once to hash it.
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Extended attributes with hashes of the contents of files stored in the image as they're
+/// shown, computed the first time they're asked for.
+#[cfg(unix)]
+pub const HASH_XATTRS: [&str; 2] = ["user.gcn.crc32", "user.gcn.sha1"];
+
+/// Returns the directory hashes of files are kept in, following the XDG base directory spec.
+#[cfg(unix)]
+pub fn hashes_dir() -> Option<PathBuf> {
+    Some(cache::xdg_cache_dir()?.join("hashes"))
+}
+
+/// Returns `bytes` in lowercase hexadecimal.
+#[cfg(unix)]
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Parses bytes in hexadecimal, as [`hex`] writes them.
+pub fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Hashes of some of the bytes of an image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Hashes {
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+}
+
+impl Hashes {
+    /// Hashes the `size` bytes at `offset` in `io`.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if they can't be read.
+    pub fn compute<T: Read + Seek>(io: &mut T, offset: u64, size: u64) -> io::Result<Self> {
+        io.seek(SeekFrom::Start(offset))?;
+        let mut io = io.take(size);
+        let mut crc32 = Hasher::new();
+        let mut sha1 = Sha1::new();
+        let mut chunk = Vec::new();
+        loop {
+            chunk.clear();
+            io.by_ref().take(CHUNK_SIZE).read_to_end(&mut chunk)?;
+            if chunk.is_empty() {
+                break;
+            }
+            crc32.update(&chunk);
+            sha1.update(&chunk);
+        }
+        if io.limit() != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the image ended early",
+            ));
+        }
+        Ok(Self {
+            crc32: crc32.finalize(),
+            sha1: sha1.finalize().into(),
+        })
+    }
+
+    /// Returns the value of the extended attribute `name`, if it's one of [`HASH_XATTRS`].
+    #[cfg(unix)]
+    #[must_use]
+    pub fn xattr(&self, name: &str) -> Option<String> {
+        match name {
+            "user.gcn.crc32" => Some(format!("{:08x}", self.crc32)),
+            "user.gcn.sha1" => Some(hex(&self.sha1)),
+            _ => None,
+        }
+    }
+}
+
+/// Hashes of the contents of an image's files by where they are in it, kept in [`hashes_dir`]
+/// in a file named by a key identifying the image's contents, so later mounts of it don't read
+/// the files again.
+///
+/// Saving is best effort: hashes that can't be saved are computed again next time.
+#[cfg(unix)]
+#[derive(Debug, Default)]
+pub struct HashCache {
+    /// Where the hashes are kept, if anywhere.
+    path: Option<PathBuf>,
+    hashes: HashMap<(u64, u64), Hashes>,
+    /// Whether failing to save hashes was already reported.
+    warned: bool,
+}
+
+#[cfg(unix)]
+impl HashCache {
+    /// Returns the hashes saved for the image identified by `key`, if any.
+    pub fn load(key: &[u8; 20]) -> Self {
+        Self::load_in(hashes_dir(), key)
+    }
+
+    /// Returns the hashes saved in `dir` for the image identified by `key`, if any.
+    pub fn load_in(dir: Option<PathBuf>, key: &[u8; 20]) -> Self {
+        let Some(dir) = dir else {
+            return Self::default();
+        };
+        let path = dir.join(hex(key));
+        let mut hashes = HashMap::new();
+        // Lines are `offset size crc32 sha1`, and anything else is skipped
+        for line in fs::read_to_string(&path).unwrap_or_default().lines() {
+            let mut fields = line.split(' ');
+            let mut field = || fields.next();
+            if let (Some(Ok(offset)), Some(Ok(size)), Some(Ok(crc32)), Some(Some(sha1))) = (
+                field().map(str::parse),
+                field().map(str::parse),
+                field().map(|crc32| u32::from_str_radix(crc32, 16)),
+                field().map(parse_hex),
+            ) {
+                hashes.insert((offset, size), Hashes { crc32, sha1 });
+            }
+        }
+        Self {
+            path: Some(path),
+            hashes,
+            warned: false,
+        }
+    }
+
+    /// Returns the hashes of the `size` bytes at `offset` in the image, if they're known.
+    #[must_use]
+    pub fn get(&self, offset: u64, size: u64) -> Option<Hashes> {
+        self.hashes.get(&(offset, size)).copied()
+    }
+
+    /// Adds the hashes of the `size` bytes at `offset` in the image, saving them.
+    pub fn insert(&mut self, offset: u64, size: u64, hashes: Hashes) {
+        self.hashes.insert((offset, size), hashes);
+        let Some(path) = &self.path else {
+            return;
+        };
+        let line = format!(
+            "{offset} {size} {:08x} {}\n",
+            hashes.crc32,
+            hex(&hashes.sha1)
+        );
+        // Lines are appended whole, so mounts of the same image saving at once don't mix them
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| OpenOptions::new().create(true).append(true).open(path))
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(err) = saved
+            && !self.warned
+        {
+            eprintln!("can't save hashes in {}: {err}", path.display());
+            self.warned = true;
+        }
+    }
+}
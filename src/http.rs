@@ -0,0 +1,587 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::attr::FileType;
+use crate::error::Error;
+use crate::fuse::GcnFuse;
+use crate::tree::Inode;
+use std::fmt::Write as _;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// Largest request head or body accepted. Requests to a read-only server are all small.
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// Size of the chunks file data is read and sent in, so other connections get a turn in between.
+const CHUNK_SIZE: u32 = 1 << 20;
+
+/// Longest a client can leave a connection idle, or take to accept data, before it's closed.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Most connections served at once. Clients connecting beyond it are turned away, so idle ones
+/// can't take up threads without limit.
+const MAX_CONNECTIONS: usize = 64;
+
+/// The response clients connecting beyond [`MAX_CONNECTIONS`] get.
+const BUSY: &[u8] =
+    b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// A parsed request. Any body is read and discarded, as nothing served needs one.
+pub struct Request {
+    pub method: String,
+    /// The request path, percent decoded and without its query string.
+    pub path: String,
+    headers: Vec<(String, String)>,
+    /// Whether the connection should be closed after responding.
+    close: bool,
+}
+
+impl Request {
+    /// Reads the next request on the connection, or returns `None` if the client closed it.
+    fn read(reader: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let mut head = reader.take(MAX_REQUEST_SIZE);
+        let mut line = String::new();
+        if head.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed request");
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target), Some(version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let method = method.to_string();
+        let target = target.split(['?', '#']).next().unwrap_or_default();
+        let path = percent_decode(target);
+        let mut close = version == "HTTP/1.0";
+
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            if head.read_line(&mut line)? == 0 {
+                return Err(invalid());
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').ok_or_else(invalid)?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+        let mut request = Self {
+            method,
+            path,
+            headers,
+            close,
+        };
+        match request.header("connection").map(str::to_ascii_lowercase) {
+            Some(connection) if connection == "close" => close = true,
+            Some(connection) if connection == "keep-alive" => close = false,
+            _ => {}
+        }
+        request.close = close;
+
+        let length = match request.header("content-length") {
+            Some(length) => length.parse().map_err(|_| invalid())?,
+            None => 0,
+        };
+        if length > MAX_REQUEST_SIZE || request.header("transfer-encoding").is_some() {
+            return Err(invalid());
+        }
+        io::copy(&mut reader.take(length), &mut io::sink())?;
+        Ok(Some(request))
+    }
+
+    /// Returns the value of the header with the given lowercase name.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The body of a response.
+pub enum Body {
+    Bytes(Vec<u8>),
+    /// `size` bytes at `offset` in the contents of a file, read as they are sent.
+    File {
+        inode: Inode,
+        offset: u64,
+        size: u64,
+    },
+}
+
+impl Body {
+    const fn len(&self) -> u64 {
+        match self {
+            Self::Bytes(bytes) => bytes.len() as u64,
+            Self::File { size, .. } => *size,
+        }
+    }
+}
+
+/// A response to send.
+pub struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Body,
+}
+
+impl Response {
+    /// Returns an empty response with the given status.
+    #[must_use]
+    pub const fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: Body::Bytes(vec![]),
+        }
+    }
+
+    /// Adds a header to the response.
+    #[must_use]
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Changes the status of the response.
+    #[must_use]
+    pub const fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Sets the body of the response.
+    #[must_use]
+    pub fn body(mut self, content_type: &str, body: Body) -> Self {
+        self.headers
+            .push(("Content-Type", content_type.to_string()));
+        self.body = body;
+        self
+    }
+}
+
+/// Returns the standard reason phrase for the status codes used.
+const fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        301 => "Moved Permanently",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        _ => "",
+    }
+}
+
+/// Locks the filesystem. Reads leave it consistent even if one panicked, so poisoning is ignored.
+pub fn lock<T: Read + Seek>(fuse: &Mutex<GcnFuse<T>>) -> MutexGuard<'_, GcnFuse<T>> {
+    fuse.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Listens on `listen`, an address like `0.0.0.0:8080`. A bare `:8080` listens on all
+/// interfaces.
+pub fn bind(listen: &str) -> io::Result<TcpListener> {
+    let listener = if listen.starts_with(':') {
+        TcpListener::bind(format!("[::]{listen}"))
+            .or_else(|_| TcpListener::bind(format!("0.0.0.0{listen}")))?
+    } else {
+        TcpListener::bind(listen)?
+    };
+    eprintln!("listening on {}", listener.local_addr()?);
+    Ok(listener)
+}
+
+/// Listens on `listen` and calls `handler` for every request, serving each connection from its
+/// own thread, up to [`MAX_CONNECTIONS`] at once.
+///
+/// This is a minimal HTTP/1.1 server, handling only what serving files read-only needs: there's
+/// no support for chunked request bodies, compression or TLS.
+///
+/// # Errors
+///
+/// [`io::Error`] if the address can't be listened on.
+pub fn serve<T, F>(fuse: GcnFuse<T>, listen: &str, handler: F) -> io::Result<()>
+where
+    T: Read + Seek + Send,
+    F: Fn(&Request, &Mutex<GcnFuse<T>>) -> Response + Sync,
+{
+    let listener = bind(listen)?;
+    let fuse = Mutex::new(fuse);
+    let active = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream.and_then(|stream| {
+                stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+                stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+                Ok(stream)
+            }) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("unable to accept a connection: {err}");
+                    continue;
+                }
+            };
+            if active.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                active.fetch_sub(1, Ordering::Relaxed);
+                (&stream).write_all(BUSY).ok();
+                continue;
+            }
+            let (fuse, handler, active) = (&fuse, &handler, &active);
+            scope.spawn(move || {
+                // Clients drop connections all the time, so errors just end the connection
+                connection(stream, fuse, handler).ok();
+                active.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+    Ok(())
+}
+
+fn connection<T, F>(stream: TcpStream, fuse: &Mutex<GcnFuse<T>>, handler: &F) -> io::Result<()>
+where
+    T: Read + Seek,
+    F: Fn(&Request, &Mutex<GcnFuse<T>>) -> Response,
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    while let Some(request) = Request::read(&mut reader)? {
+        let response = handler(&request, fuse);
+        write_response(&mut writer, fuse, &request, response)?;
+        writer.flush()?;
+        if request.close {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn write_response<T: Read + Seek>(
+    out: &mut impl Write,
+    fuse: &Mutex<GcnFuse<T>>,
+    request: &Request,
+    response: Response,
+) -> io::Result<()> {
+    write!(
+        out,
+        "HTTP/1.1 {} {}\r\n",
+        response.status,
+        reason(response.status)
+    )?;
+    for (name, value) in &response.headers {
+        write!(out, "{name}: {value}\r\n")?;
+    }
+    write!(out, "Content-Length: {}\r\n", response.body.len())?;
+    if request.close {
+        write!(out, "Connection: close\r\n")?;
+    }
+    write!(out, "\r\n")?;
+    if request.method == "HEAD" {
+        return Ok(());
+    }
+    match response.body {
+        Body::Bytes(bytes) => out.write_all(&bytes),
+        Body::File {
+            inode,
+            offset,
+            size,
+        } => {
+            let end = offset + size;
+            let mut offset = offset;
+            while offset < end {
+                // At most a chunk
+                #[allow(clippy::cast_possible_truncation)]
+                let len = (end - offset).min(CHUNK_SIZE.into()) as u32;
+                let data = lock(fuse).read_data(inode, offset, len)?;
+                if data.is_empty() {
+                    // The promised length can't be delivered, so the connection has to go
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                out.write_all(&data)?;
+                offset += data.len() as u64;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Parses the `Range` header of a request for a file of `size` bytes, returning the requested
+/// `(offset, size)`. Returns `Ok(None)` to send the whole file, and `Err` if the range can't be
+/// satisfied.
+fn parse_range(range: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(range) = range.strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    // Serving the whole file is a valid answer to multiple ranges, and much simpler
+    if range.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = range.trim().split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = match (start, end) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().map_err(|_| ())?;
+            (size.saturating_sub(suffix), size.checked_sub(1).ok_or(())?)
+        }
+        (start, "") => (
+            start.parse().map_err(|_| ())?,
+            size.checked_sub(1).ok_or(())?,
+        ),
+        (start, end) => {
+            let end: u64 = end.parse().map_err(|_| ())?;
+            (
+                start.parse().map_err(|_| ())?,
+                end.min(size.saturating_sub(1)),
+            )
+        }
+    };
+    if start >= size || end < start {
+        return Err(());
+    }
+    Ok(Some((start, end - start + 1)))
+}
+
+/// Returns a response serving the given file, honoring any `Range` header in the request.
+pub fn file_response<T: Read + Seek>(
+    fuse: &Mutex<GcnFuse<T>>,
+    request: &Request,
+    inode: Inode,
+) -> Response {
+    let Some(attr) = lock(fuse).get_attr(inode) else {
+        return Response::new(404);
+    };
+    if attr.kind != FileType::RegularFile {
+        return Response::new(405);
+    }
+    let size = attr.size;
+    let response = Response::new(200)
+        .header("Accept-Ranges", "bytes")
+        .header("Last-Modified", http_date(attr.mtime));
+    let range = request
+        .header("range")
+        .map_or(Ok(None), |range| parse_range(range, size));
+    match range {
+        Ok(Some((offset, len))) => response
+            .header(
+                "Content-Range",
+                format!("bytes {offset}-{}/{size}", offset + len - 1),
+            )
+            .body(
+                "application/octet-stream",
+                Body::File {
+                    inode,
+                    offset,
+                    size: len,
+                },
+            )
+            .with_status(206),
+        Ok(None) => response.body(
+            "application/octet-stream",
+            Body::File {
+                inode,
+                offset: 0,
+                size,
+            },
+        ),
+        Err(()) => Response::new(416).header("Content-Range", format!("bytes */{size}")),
+    }
+}
+
+/// Splits a time into UTC `(year, month, day, hours, minutes, seconds, weekday)`, with weekdays
+/// counted from Sunday.
+#[must_use]
+pub fn civil(time: SystemTime) -> (u64, u64, u64, u64, u64, u64, u64) {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    // The inverse of the conversion in layout::parse_date, with years starting in March
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    // 1970-01-01 was a Thursday
+    let weekday = (seconds / 86_400 + 4) % 7;
+    (
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        weekday,
+    )
+}
+
+/// Formats a time as an HTTP date, like `Sun, 06 Nov 1994 08:49:37 GMT`.
+#[must_use]
+pub fn http_date(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (year, month, day, hours, minutes, seconds, weekday) = civil(time);
+    // Both are in range by construction
+    #[allow(clippy::cast_possible_truncation)]
+    let (weekday, month) = (WEEKDAYS[weekday as usize], MONTHS[month as usize - 1]);
+    format!("{weekday}, {day:02} {month} {year} {hours:02}:{minutes:02}:{seconds:02} GMT")
+}
+
+/// Formats a time as an RFC 3339 date, like `1994-11-06T08:49:37Z`.
+#[must_use]
+pub fn rfc3339_date(time: SystemTime) -> String {
+    let (year, month, day, hours, minutes, seconds, _) = civil(time);
+    format!("{year:04}-{month:02}-{day:02}T{hours:02}:{minutes:02}:{seconds:02}Z")
+}
+
+/// Decodes `%XX` escapes in a URL path. Invalid escapes are kept as they are.
+#[must_use]
+pub fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(str::from_utf8(hex).ok()?, 16).ok());
+        if let Some(byte) = escaped {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escapes everything but unreserved characters and `/` in a URL path.
+#[must_use]
+pub fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Escapes text for use in HTML or XML.
+#[must_use]
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns an HTML page listing the entries of the directory at `path`, which ends with `/`.
+fn listing<T: Read + Seek>(fuse: &GcnFuse<T>, path: &str, entries: Vec<(String, Inode)>) -> String {
+    let title = escape(path);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Index of {title}</title>\
+         </head><body><h1>Index of {title}</h1><table>\n"
+    );
+    if path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td></tr>\n");
+    }
+    for (name, child) in entries {
+        let Some(attr) = fuse.get_attr(child) else {
+            continue;
+        };
+        let (suffix, size) = if attr.kind == FileType::Directory {
+            ("/", String::new())
+        } else {
+            ("", attr.size.to_string())
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td><a href=\"{}{suffix}\">{}{suffix}</a></td><td>{size}</td><td>{}</td></tr>",
+            escape(&percent_encode(&name)),
+            escape(&name),
+            http_date(attr.mtime),
+        );
+    }
+    html.push_str("</table></body></html>\n");
+    html
+}
+
+fn handle<T: Read + Seek>(request: &Request, fuse: &Mutex<GcnFuse<T>>) -> Response {
+    if request.method != "GET" && request.method != "HEAD" {
+        return Response::new(405).header("Allow", "GET, HEAD");
+    }
+    let guard = lock(fuse);
+    let Some(inode) = guard.resolve(&request.path) else {
+        return Response::new(404);
+    };
+    let Some(entries) = guard.entries(inode) else {
+        drop(guard);
+        return file_response(fuse, request, inode);
+    };
+    // Relative links in the listing only work from a path ending with `/`
+    if !request.path.ends_with('/') {
+        drop(guard);
+        let location = format!("{}/", percent_encode(&request.path));
+        return Response::new(301).header("Location", location);
+    }
+    let html = listing(&guard, &request.path, entries);
+    drop(guard);
+    Response::new(200).body("text/html; charset=utf-8", Body::Bytes(html.into_bytes()))
+}
+
+/// Serves the filesystem read-only over plain HTTP on `listen`, an address like `0.0.0.0:8080`.
+/// This never returns unless the address can't be listened on.
+///
+/// Directories are served as HTML listings, and files support `Range` requests so they can be
+/// streamed from any offset.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the address can't be listened on.
+pub fn serve_http<T: Read + Seek + Send>(fuse: GcnFuse<T>, listen: &str) -> Result<(), Error> {
+    serve(fuse, listen, handle)?;
+    Ok(())
+}
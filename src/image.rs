@@ -0,0 +1,353 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::chunks::ChunkCache;
+use crate::error::Error;
+use crate::gcz::Gcz;
+use crate::gcz::has_gcz_magic;
+use crate::patch::Patched;
+use crate::pool::Pool;
+use crate::pool::decompression_threads;
+use crate::source::Source;
+use crate::watch::Watch;
+use rvz::HeaderRead;
+use rvz::Rvz;
+use sha1::Digest;
+use sha1::Sha1;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+
+/// A disc image, either compressed in an RVZ or GCZ container or stored raw (ISO/GCM),
+/// optionally with a patch applied.
+pub enum Image {
+    Raw(Source),
+    Rvz(Box<Compressed>),
+    Gcz(Box<Gcz<Source>>),
+    Patched(Box<Patched<Self>>),
+}
+
+/// An RVZ image, with threads to decompress reads spanning several chunks if it's a local file,
+/// and the chunks saved on disk if there's a chunk cache.
+pub struct Compressed {
+    rvz: Rvz<Source>,
+    pool: Option<Pool>,
+    chunks: Option<ChunkCache>,
+    /// Whether the file was changed, which the pool's reads of it don't check.
+    watch: Option<Watch>,
+}
+
+/// How much of a compressed image's file each part of the disc takes up, from its table of
+/// groups, the chunks the disc is compressed in.
+#[derive(Clone, Debug)]
+pub struct CompressedSizes {
+    /// The start and end of each group on the disc and its compressed size, by start.
+    pub(crate) groups: Vec<(u64, u64, u64)>,
+}
+
+impl CompressedSizes {
+    /// Returns how many bytes of the image's file the `len` bytes of the disc at `offset` take
+    /// up, counting the share of each group they span as large as their share of its data.
+    #[must_use]
+    pub fn of(&self, offset: u64, len: u64) -> u64 {
+        let end = offset + len;
+        let first = self
+            .groups
+            .partition_point(|&(_, group_end, _)| group_end <= offset);
+        self.groups[first..]
+            .iter()
+            .take_while(|&&(start, _, _)| start < end)
+            .map(|&(start, group_end, size)| {
+                let overlap = group_end.min(end) - start.max(offset);
+                // Groups are at most a few MiB and their sizes fit a u32, so this can't overflow
+                size * overlap / (group_end - start)
+            })
+            .sum()
+    }
+}
+
+impl Image {
+    /// Opens the disc image at `path`, an HTTP(S), S3 or SFTP URL, or `-` for standard input,
+    /// detecting its format.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Io`] if the file can't be opened or looks like a GCZ file but its tables can't be
+    /// parsed, and [`Error::Rvz`] if it looks like an RVZ file but its headers can't be parsed.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::from_source(Source::open(path)?)
+    }
+
+    /// Opens the disc image read from `source`, detecting its format.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Io`] if the source can't be read or looks like a GCZ file but its tables can't be
+    /// parsed, and [`Error::Rvz`] if it looks like an RVZ file but its headers can't be parsed.
+    pub fn from_source(mut source: Source) -> Result<Self, Error> {
+        if source.has_rvz_magic() {
+            let pool = match source.as_file() {
+                Some(file) if decompression_threads() > 1 => Some(Pool::new(file)?),
+                _ => None,
+            };
+            let watch = source.watch().cloned();
+            let rvz = Rvz::new(source)?;
+            let chunks = ChunkCache::open(&rvz.metadata.header.file_head_hash);
+            Ok(Self::Rvz(Box::new(Compressed {
+                rvz,
+                pool,
+                chunks,
+                watch,
+            })))
+        } else if has_gcz_magic(&mut source) {
+            Ok(Self::Gcz(Box::new(Gcz::new(source)?)))
+        } else {
+            source.seek(SeekFrom::Start(0))?;
+            Ok(Self::Raw(source))
+        }
+    }
+
+    /// Applies the IPS, BPS or xdelta patch at `path` to the image. The patch is applied as the
+    /// image is read, so the patched image is never written anywhere.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Io`] if the patch can't be read, and [`Error::Patch`] if it can't be applied to
+    /// this image.
+    pub fn patch(self, path: &Path) -> Result<Self, Error> {
+        let data = fs::read(path)?;
+        let size = self.disc_size()?;
+        Ok(Self::Patched(Box::new(Patched::new(self, size, data)?)))
+    }
+
+    /// Returns the size of the chunks the image is compressed in, if it's compressed.
+    #[must_use]
+    pub fn chunk_size(&self) -> Option<u32> {
+        match self {
+            Self::Raw(_) => None,
+            Self::Rvz(compressed) => Some(compressed.rvz.metadata.disc.chunk_size),
+            Self::Gcz(gcz) => Some(gcz.block_size()),
+            Self::Patched(patched) => patched.get_ref().chunk_size(),
+        }
+    }
+
+    /// Returns how much of the image's file each part of the disc takes up, if it's compressed.
+    /// Patches are ignored, taking the sizes of the data they replace.
+    #[must_use]
+    pub fn compressed_sizes(&self) -> Option<CompressedSizes> {
+        match self {
+            Self::Raw(_) => None,
+            Self::Rvz(compressed) => Some(compressed.sizes()),
+            Self::Gcz(gcz) => Some(gcz.sizes()),
+            Self::Patched(patched) => patched.get_ref().compressed_sizes(),
+        }
+    }
+
+    /// Returns the patch applied to the image, if any.
+    #[must_use]
+    pub fn patch_data(&self) -> Option<&[u8]> {
+        match self {
+            Self::Raw(_) | Self::Rvz(_) | Self::Gcz(_) => None,
+            Self::Patched(patched) => Some(patched.patch()),
+        }
+    }
+
+    /// Returns a hash identifying what the image holds, or `None` if there's no telling without
+    /// reading all of it, which files hashed in it are cached under.
+    ///
+    /// RVZ images are identified by the hash of their header and GCZ images by their tables, which
+    /// change with what they hold. Local files are identified by which file they are and when
+    /// they were last changed, and patched images by the image and the patch.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if a local file's metadata can't be read.
+    pub fn content_key(&self) -> io::Result<Option<[u8; 20]>> {
+        match self {
+            Self::Raw(source) => source.identity(),
+            Self::Rvz(compressed) => Ok(Some(compressed.rvz.metadata.header.file_head_hash)),
+            Self::Gcz(gcz) => Ok(Some(gcz.tables_hash())),
+            Self::Patched(patched) => Ok(patched.get_ref().content_key()?.map(|key| {
+                let mut sha1 = Sha1::new();
+                sha1.update(key);
+                sha1.update(Sha1::digest(patched.patch()));
+                sha1.finalize().into()
+            })),
+        }
+    }
+
+    /// Returns the size of the uncompressed disc image.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the size can't be determined.
+    pub fn disc_size(&self) -> io::Result<u64> {
+        match self {
+            Self::Raw(file) => file.size(),
+            Self::Rvz(compressed) => Ok(compressed.rvz.metadata.header.iso_file_size),
+            Self::Gcz(gcz) => Ok(gcz.data_size()),
+            Self::Patched(patched) => Ok(patched.size()),
+        }
+    }
+}
+
+impl Read for Image {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Raw(file) => file.read(buf),
+            Self::Rvz(compressed) => compressed.read(buf),
+            Self::Gcz(gcz) => gcz.read(buf),
+            Self::Patched(patched) => patched.read(buf),
+        }
+    }
+}
+
+impl Compressed {
+    /// Returns the compressed size of each group, from the regions of the disc they're in.
+    fn sizes(&self) -> CompressedSizes {
+        let metadata = &self.rvz.metadata;
+        let chunk_size = u64::from(metadata.disc.chunk_size);
+        let mut groups = vec![];
+        for region in &metadata.regions {
+            let indices = region.group_index()..region.group_index() + region.group_count();
+            for (start, index) in (region.start()..region.end())
+                .step_by(usize::try_from(chunk_size).unwrap_or(usize::MAX))
+                .zip(indices)
+            {
+                let Some(group) = metadata.groups.get(index as usize) else {
+                    break;
+                };
+                // The top bit only flags the group as compressed
+                let size = u64::from(group.data_size & 0x7fff_ffff);
+                groups.push((start, (start + chunk_size).min(region.end()), size));
+            }
+        }
+        groups.sort_unstable();
+        CompressedSizes { groups }
+    }
+
+    /// Fills `buf` with the disc at `offset`, taking the chunks it spans from the chunk cache if
+    /// they're all there, and decompressing and saving them all otherwise.
+    fn read_cached(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let Some(chunks) = &mut self.chunks else {
+            return read_exact_rvz(&mut self.rvz, self.pool.as_ref(), offset, buf);
+        };
+        let chunk_size = u64::from(self.rvz.metadata.disc.chunk_size);
+        let disc_size = self.rvz.metadata.header.iso_file_size;
+        let first = offset / chunk_size;
+        let last = (offset + buf.len() as u64 - 1) / chunk_size;
+        let start = first * chunk_size;
+        let end = ((last + 1) * chunk_size).min(disc_size);
+        // The span is at most a read, plus a chunk on either end
+        #[allow(clippy::cast_possible_truncation)]
+        let mut span = Vec::with_capacity((end - start) as usize);
+        for index in first..=last {
+            #[allow(clippy::cast_possible_truncation)] // At most a chunk
+            let len = (chunk_size.min(disc_size - index * chunk_size)) as usize;
+            let Some(chunk) = chunks.get(index, len) else {
+                span.clear();
+                break;
+            };
+            span.extend_from_slice(&chunk);
+        }
+        if span.is_empty() {
+            #[allow(clippy::cast_possible_truncation)] // As above
+            span.resize((end - start) as usize, 0);
+            read_exact_rvz(&mut self.rvz, self.pool.as_ref(), start, &mut span)?;
+            #[allow(clippy::cast_possible_truncation)] // At most a chunk
+            for (index, chunk) in (first..).zip(span.chunks(chunk_size as usize)) {
+                chunks.put(index, chunk);
+            }
+        }
+        #[allow(clippy::cast_possible_truncation)] // Within the span
+        let skip = (offset - start) as usize;
+        buf.copy_from_slice(&span[skip..skip + buf.len()]);
+        Ok(())
+    }
+}
+
+impl Read for Compressed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(watch) = &self.watch {
+            watch.check()?;
+        }
+        let position = self.rvz.stream_position()?;
+        let remaining = self
+            .rvz
+            .metadata
+            .header
+            .iso_file_size
+            .saturating_sub(position);
+        let len = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let spans_chunks = self
+            .pool
+            .as_ref()
+            .is_some_and(|pool| pool.spans_chunks(position, len));
+        if len == 0 || (self.chunks.is_none() && !spans_chunks) {
+            return read_rvz(&mut self.rvz, buf);
+        }
+        self.read_cached(position, &mut buf[..len])?;
+        self.rvz.seek(SeekFrom::Start(position + len as u64))?;
+        Ok(len)
+    }
+}
+
+/// Fills `buf` with the disc at `offset` in an RVZ image, decompressing its chunks on `pool` if
+/// given and it spans several.
+fn read_exact_rvz(
+    rvz: &mut Rvz<Source>,
+    pool: Option<&Pool>,
+    offset: u64,
+    mut buf: &mut [u8],
+) -> io::Result<()> {
+    if let Some(pool) = pool.filter(|pool| pool.spans_chunks(offset, buf.len())) {
+        return pool.read_exact_at(offset, buf).inspect_err(|err| {
+            eprintln!("can't read RVZ image at {offset:#x}: {err}");
+        });
+    }
+    rvz.seek(SeekFrom::Start(offset))?;
+    while !buf.is_empty() {
+        match read_rvz(rvz, buf)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            len => buf = &mut buf[len..],
+        }
+    }
+    Ok(())
+}
+
+/// Reads from an RVZ image, turning a damaged chunk into an error for just the reads overlapping
+/// it. The rvz crate can panic on corrupted chunks instead of returning an error.
+fn read_rvz(rvz: &mut Rvz<Source>, buf: &mut [u8]) -> io::Result<usize> {
+    let position = rvz.stream_position()?;
+    // Every read starts with a seek, so a read that panicked leaves nothing behind that matters;
+    // chunks are only cached once decompressed whole
+    let result = panic::catch_unwind(AssertUnwindSafe(|| rvz.read(buf))).unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "damaged compressed chunk",
+        ))
+    });
+    if let Err(err) = &result {
+        eprintln!("can't read RVZ image at {position:#x}: {err}");
+        rvz.seek(SeekFrom::Start(position))?;
+    }
+    result
+}
+
+impl Seek for Image {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Raw(file) => file.seek(pos),
+            Self::Rvz(compressed) => compressed.rvz.seek(pos),
+            Self::Gcz(gcz) => gcz.seek(pos),
+            Self::Patched(patched) => patched.seek(pos),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::cell::Cell;
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::sync::Once;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Longest a wait goes without checking whether it's still wanted.
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+thread_local! {
+    /// The process the request being answered on this thread is for, if any.
+    static REQUESTER: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Marks the requests of process `pid` as being answered on this thread, until the returned
+/// guard is dropped.
+///
+/// A process killed during a read of the filesystem can't exit until the read is answered, but
+/// never looks at what's read. The kernel would say so with interrupt requests, which fuser
+/// doesn't pass on, so slow reads [`check`] on the process they're for as they go instead.
+#[cfg(unix)]
+pub fn serve(pid: u32) -> Serving {
+    Serving(REQUESTER.replace(pid))
+}
+
+/// Ends answering a request on drop, going back to the one answered before, if any.
+#[cfg(unix)]
+pub struct Serving(u32);
+
+#[cfg(unix)]
+impl Drop for Serving {
+    fn drop(&mut self) {
+        REQUESTER.set(self.0);
+    }
+}
+
+/// Returns an error if the process the request being answered on this thread is for was killed.
+///
+/// # Errors
+///
+/// `ECANCELED` if the process was killed. It isn't `EINTR`, which
+/// [`std::io::Read::read_exact`] and the like would retry.
+pub fn check() -> io::Result<()> {
+    let pid = REQUESTER.get();
+    if pid != 0 && killed(pid) {
+        return Err(io::Error::from_raw_os_error(libc::ECANCELED));
+    }
+    Ok(())
+}
+
+/// Waits for `duration`, stopping early with an error if the process the request being answered
+/// on this thread is for is killed in the meantime.
+///
+/// # Errors
+///
+/// `ECANCELED` if the process was killed, like [`check`].
+pub fn sleep(duration: Duration) -> io::Result<()> {
+    let end = Instant::now() + duration;
+    loop {
+        check()?;
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(());
+        }
+        thread::sleep(left.min(CHECK_INTERVAL));
+    }
+}
+
+/// Signals whose default action doesn't end the process.
+#[cfg(target_os = "linux")]
+const HARMLESS: &[libc::c_int] = &[
+    libc::SIGCHLD,
+    libc::SIGCONT,
+    libc::SIGURG,
+    libc::SIGWINCH,
+    libc::SIGSTOP,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+/// Returns whether process `pid` is gone, exiting, or has a signal pending that will end it.
+///
+/// A process waiting for a read can't take signals until it's answered, so they stay pending,
+/// and only the ones it neither blocks, ignores, nor handles, and whose default action is to end
+/// it, will.
+///
+/// Processes whose status can't be read are taken to be alive, with a warning the first time, as
+/// there's no telling.
+#[cfg(target_os = "linux")]
+fn killed(pid: u32) -> bool {
+    static UNREADABLE: Once = Once::new();
+    let status = match fs::read_to_string(format!("/proc/{pid}/status")) {
+        Ok(status) => status,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return true,
+        Err(err) => {
+            UNREADABLE.call_once(|| {
+                eprintln!("can't tell whether processes reading were killed: {err}");
+            });
+            return false;
+        }
+    };
+    let mut pending = 0;
+    let mut spared = HARMLESS
+        .iter()
+        .fold(0, |mask, &signal| mask | 1 << (signal - 1));
+    for line in status.lines() {
+        let Some((field, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let mask = || u64::from_str_radix(value, 16).unwrap_or(0);
+        match field {
+            "State" if value.starts_with(['Z', 'X']) => return true,
+            "SigPnd" | "ShdPnd" => pending |= mask(),
+            "SigBlk" | "SigIgn" | "SigCgt" => spared |= mask(),
+            _ => {}
+        }
+    }
+    pending & !spared != 0
+}
+
+/// Returns whether process `pid` is gone, which takes `/proc` to tell.
+#[cfg(not(target_os = "linux"))]
+const fn killed(_pid: u32) -> bool {
+    false
+}
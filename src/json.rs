@@ -0,0 +1,418 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Nesting deeper than this isn't parsed, so hostile input can't exhaust the stack.
+const MAX_DEPTH: usize = 32;
+
+/// A JSON value, as spoken by the daemon's control socket.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Self>),
+    /// Members in the order they were given.
+    Object(Vec<(String, Self)>),
+}
+
+impl Json {
+    /// Parses `text`, which must hold a single value, or returns `None` if it isn't valid JSON.
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars, 0)?;
+        skip_whitespace(&mut chars);
+        chars.peek().is_none().then_some(value)
+    }
+
+    /// Returns the member `key` of an object, if this is one and has it.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        let Self::Object(members) = self else {
+            return None;
+        };
+        members
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the string, if this is one.
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Returns the number, if this is a whole one that fits a `u64`.
+    #[must_use]
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            // Checked to be whole and in range
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Self::Number(number)
+                if number.fract() == 0.0
+                    && (0.0..18_446_744_073_709_551_616.0).contains(&number) =>
+            {
+                Some(number as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the boolean, if this is one.
+    #[must_use]
+    pub const fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements, if this is an array.
+    #[must_use]
+    pub fn as_array(&self) -> Option<&[Self]> {
+        match self {
+            Self::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    /// Returns an object with `members`.
+    pub fn object<'a>(members: impl IntoIterator<Item = (&'a str, Self)>) -> Self {
+        Self::Object(
+            members
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+}
+
+impl From<&str> for Json {
+    fn from(string: &str) -> Self {
+        Self::String(string.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(string: String) -> Self {
+        Self::String(string)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(number: u64) -> Self {
+        // Counts and sizes stay well below where f64 loses precision
+        #[allow(clippy::cast_precision_loss)]
+        Self::Number(number as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl<T: Into<Self>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+/// Writes `string` as a JSON string, with quotes and escapes.
+fn write_string(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", u32::from(c))?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Writes the value compactly, on a single line.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Number(number) if number.is_finite() => write!(f, "{number}"),
+            // JSON has no infinities or NaN
+            Self::Number(_) => Self::Null.fmt(f),
+            Self::String(string) => write_string(f, string),
+            Self::Array(elements) => {
+                f.write_str("[")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index != 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                f.write_str("]")
+            }
+            Self::Object(members) => {
+                f.write_str("{")?;
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index != 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars
+        .next_if(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        .is_some()
+    {}
+}
+
+/// Takes `word` from the front of `chars`, returning whether it was there.
+fn take_word(chars: &mut Peekable<Chars>, word: &str) -> bool {
+    word.chars().all(|expected| chars.next() == Some(expected))
+}
+
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Json> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    skip_whitespace(chars);
+    match *chars.peek()? {
+        'n' => take_word(chars, "null").then_some(Json::Null),
+        't' => take_word(chars, "true").then_some(Json::Bool(true)),
+        'f' => take_word(chars, "false").then_some(Json::Bool(false)),
+        '"' => parse_string(chars).map(Json::String),
+        '[' => {
+            chars.next();
+            let mut elements = vec![];
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Some(Json::Array(elements));
+            }
+            loop {
+                elements.push(parse_value(chars, depth + 1)?);
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => {}
+                    ']' => return Some(Json::Array(elements)),
+                    _ => return None,
+                }
+            }
+        }
+        '{' => {
+            chars.next();
+            let mut members = vec![];
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Some(Json::Object(members));
+            }
+            loop {
+                skip_whitespace(chars);
+                let name = parse_string(chars)?;
+                skip_whitespace(chars);
+                chars.next_if_eq(&':')?;
+                members.push((name, parse_value(chars, depth + 1)?));
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => {}
+                    '}' => return Some(Json::Object(members)),
+                    _ => return None,
+                }
+            }
+        }
+        _ => parse_number(chars),
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Option<Json> {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(|c| matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E')) {
+        number.push(c);
+    }
+    if !valid_number(&number) {
+        return None;
+    }
+    number.parse().ok().map(Json::Number)
+}
+
+/// Returns whether `number` follows JSON's number syntax, which is stricter than Rust's: no
+/// leading `+` or zeroes, and digits on both sides of the point and after the exponent.
+fn valid_number(number: &str) -> bool {
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|c| c.is_ascii_digit());
+    let number = number.strip_prefix('-').unwrap_or(number);
+    let (mantissa, exponent) = number
+        .split_once(['e', 'E'])
+        .map_or((number, None), |(mantissa, exponent)| {
+            (mantissa, Some(exponent))
+        });
+    let (whole, fraction) = mantissa
+        .split_once('.')
+        .map_or((mantissa, None), |(whole, fraction)| {
+            (whole, Some(fraction))
+        });
+    digits(whole)
+        && (whole == "0" || !whole.starts_with('0'))
+        && fraction.is_none_or(digits)
+        && exponent
+            .is_none_or(|exponent| digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent)))
+}
+
+/// Parses the four hex digits of a `\u` escape.
+fn parse_hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+    (0..4).try_fold(0, |code, _| Some(code << 4 | chars.next()?.to_digit(16)?))
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    chars.next_if_eq(&'"')?;
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => string.push(match chars.next()? {
+                '"' => '"',
+                '\\' => '\\',
+                '/' => '/',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let code = parse_hex(chars)?;
+                    // Characters outside the BMP are escaped as surrogate pairs
+                    if (0xd800..0xdc00).contains(&code) {
+                        if !take_word(chars, "\\u") {
+                            return None;
+                        }
+                        let low = parse_hex(chars)?;
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return None;
+                        }
+                        char::from_u32(0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00))?
+                    } else {
+                        char::from_u32(code)?
+                    }
+                }
+                _ => return None,
+            }),
+            c if c < ' ' => return None,
+            c => string.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            Json::parse(r#""a\"b\\c\/d\b\f\n\r\t""#),
+            Some("a\"b\\c/d\u{8}\u{c}\n\r\t".into())
+        );
+        assert_eq!(
+            Json::parse(r#""\u00e9\u4E2D""#),
+            Some("\u{e9}\u{4e2d}".into())
+        );
+        assert_eq!(Json::parse(r#""\ud83c\udfae""#), Some("\u{1f3ae}".into()));
+        for invalid in [
+            // Lone or reversed surrogates
+            r#""\ud83c""#,
+            r#""\ud83cx""#,
+            r#""\ud83c\u0041""#,
+            r#""\udfae""#,
+            r#""\udfae\ud83c""#,
+            // Unescaped control characters
+            "\"a\nb\"",
+            "\"a\u{1}b\"",
+            // Unknown or short escapes, and no end
+            r#""\x41""#,
+            r#""\u12""#,
+            r#""unterminated"#,
+        ] {
+            assert_eq!(Json::parse(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        for (text, number) in [
+            ("0", 0.0),
+            ("-0", -0.0),
+            ("12", 12.0),
+            ("-1.5", -1.5),
+            ("0.25", 0.25),
+            ("1e3", 1000.0),
+            ("1E+3", 1000.0),
+            ("25e-2", 0.25),
+        ] {
+            assert_eq!(Json::parse(text), Some(Json::Number(number)), "{text}");
+        }
+        for invalid in [
+            "01", "-", "1e", "+1", ".5", "-.5", "1.", "1.e3", "1e+", "--1", "1-2", "inf", "NaN",
+        ] {
+            assert_eq!(Json::parse(invalid), None, "{invalid}");
+        }
+        assert_eq!(Json::parse("42").unwrap().as_u64(), Some(42));
+        assert_eq!(Json::parse("4.2").unwrap().as_u64(), None);
+        assert_eq!(Json::parse("-1").unwrap().as_u64(), None);
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH + 1)).is_some());
+        assert_eq!(Json::parse(&nested(MAX_DEPTH + 2)), None);
+        // Far too deep to parse recursively
+        assert_eq!(Json::parse(&nested(1_000_000)), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = Json::object([
+            ("null", Json::Null),
+            ("bool", true.into()),
+            ("number", Json::Number(-12.5)),
+            ("size", u64::MAX.into()),
+            (
+                "string",
+                "quote \" slash \\ \n\t\u{1} \u{e9} \u{1f3ae}".into(),
+            ),
+            (
+                "array",
+                Json::Array(vec![Json::Array(vec![]), Json::object([]), 1.into()]),
+            ),
+        ]);
+        let text = value.to_string();
+        assert!(!text.contains('\n'));
+        assert_eq!(Json::parse(&text), Some(value));
+        assert_eq!(
+            Json::parse(" { \"a\" : [ 1 , null ] } ").map(|value| value.to_string()),
+            Some(r#"{"a":[1,null]}"#.into())
+        );
+        assert_eq!(Json::Number(f64::NAN).to_string(), "null");
+        assert_eq!(Json::parse("[1] 2"), None);
+        assert_eq!(Json::parse("{\"a\":1,}"), None);
+    }
+}
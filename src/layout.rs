@@ -0,0 +1,504 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::dol::Dol;
+use crate::error::Error;
+use crate::options::Options;
+use crate::tree::FileData;
+use crate::tree::Inode;
+use crate::tree::Kind;
+use crate::tree::Tree;
+use clap::ValueEnum;
+use encoding_rs::SHIFT_JIS;
+use encoding_rs::WINDOWS_1252;
+use gcn_disk::Disc;
+use gcn_disk::Entry;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::str;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// Offset of the apploader in the disc.
+pub const APPLOADER_OFFSET: u64 = 0x2440;
+
+/// Offset of the header fields holding the FST offset, size and maximum size.
+pub const FST_FIELDS_OFFSET: u64 = 0x424;
+
+/// Size of a standard Gamecube disc.
+pub const DISC_SIZE: u64 = 1_459_978_240;
+
+/// Offset of the header (boot.bin) field holding the DOL offset.
+pub const DOL_OFFSET_FIELD: u64 = 0x420;
+
+/// Size of the disc header (boot.bin).
+pub const HEADER_SIZE: usize = 0x440;
+
+/// Size of the disc header information (bi2.bin) following the header.
+pub const BI2_SIZE: usize = 0x2000;
+
+/// Alignment of the DOL and FST when laying out an image from scratch.
+const SYSTEM_ALIGNMENT: u64 = 0x100;
+
+/// Order in which file data is laid out in a new image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Order {
+    /// Keep the order files have in the original image, with new files after them in FST order.
+    /// Images built from a directory use FST order.
+    #[default]
+    Original,
+    /// Sort files by their full path, ignoring case.
+    Alphabetical,
+}
+
+/// How the end of a new image is padded.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Padding {
+    /// End the image right after the last file (aligned to the file alignment).
+    #[default]
+    None,
+    /// Pad the image with zeroes to the size of a real disc.
+    Disc,
+}
+
+/// Options controlling how new images are laid out.
+#[derive(Copy, Clone, Debug)]
+pub struct LayoutOptions {
+    /// Alignment of every file's data. Must be a power of two and at least 4.
+    pub alignment: u64,
+    /// Order of the file data.
+    pub order: Order,
+    /// How to pad the end of the image.
+    pub padding: Padding,
+    /// When rebuilding, lay out every file from scratch instead of keeping unchanged files where
+    /// they are.
+    pub repack: bool,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            alignment: 0x8000,
+            order: Order::default(),
+            padding: Padding::default(),
+            repack: false,
+        }
+    }
+}
+
+impl LayoutOptions {
+    /// Checks that the options describe a valid layout.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Layout`] if the alignment isn't a power of two that is at least 4.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.alignment < 4 || !self.alignment.is_power_of_two() {
+            return Err(Error::Layout(
+                "the file alignment must be a power of two of at least 4".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The system files at the start of an image, before the FST and file data.
+pub struct SystemArea {
+    /// The disc header (boot.bin). The DOL, FST and user area fields are filled in when laying
+    /// out the image.
+    pub header: Vec<u8>,
+    /// The disc header information (bi2.bin).
+    pub bi2: Vec<u8>,
+    /// The apploader, including its header.
+    pub apploader: Vec<u8>,
+    /// The boot DOL.
+    pub dol: Vec<u8>,
+}
+
+/// A file to lay out, in the order the data should be written.
+#[derive(Copy, Clone, Debug)]
+pub struct FileInfo {
+    /// The file's node.
+    pub inode: Inode,
+    /// Size of the file's data.
+    pub size: u32,
+    /// Offset of the file in the original image, if it comes from one.
+    pub original_offset: Option<u32>,
+}
+
+/// Where the data of a file is placed in an image.
+#[derive(Copy, Clone, Debug)]
+pub struct Placement {
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Returns `value` rounded up to the next multiple of `alignment`.
+#[must_use]
+pub const fn align(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Returns the size of the apploader found at [`APPLOADER_OFFSET`], including its header.
+///
+/// # Errors
+///
+/// [`io::Error`] if the apploader header can't be read.
+pub fn apploader_size<T: Read + Seek>(io: &mut T) -> io::Result<u32> {
+    let mut header = [0u8; 0x20];
+    io.seek(SeekFrom::Start(APPLOADER_OFFSET))?;
+    io.read_exact(&mut header)?;
+    Ok(apploader_size_from_header(&header))
+}
+
+/// Returns the build date the apploader at [`APPLOADER_OFFSET`] records, as midnight UTC of that
+/// day, or `None` if it doesn't hold a valid date.
+///
+/// # Errors
+///
+/// [`io::Error`] if the apploader header can't be read.
+pub fn apploader_date<T: Read + Seek>(io: &mut T) -> io::Result<Option<SystemTime>> {
+    let mut date = [0u8; 10];
+    io.seek(SeekFrom::Start(APPLOADER_OFFSET))?;
+    io.read_exact(&mut date)?;
+    Ok(str::from_utf8(&date).ok().and_then(parse_date))
+}
+
+/// Parses a `YYYY/MM/DD` date, as found in apploaders, or a `YYYY-MM-DD` one, returning
+/// midnight UTC of that day.
+#[must_use]
+pub fn parse_date(date: &str) -> Option<SystemTime> {
+    let mut fields = date.split(['/', '-']);
+    let mut field = || fields.next()?.parse::<u32>().ok();
+    let (year, month, day) = (field()?, field()?, field()?);
+    if fields.next().is_some()
+        || year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+    {
+        return None;
+    }
+    // Days since 1970-01-01 for the proleptic Gregorian calendar, counting years from March
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::from(era) * 146_097 + u64::from(day_of_era) - 719_468;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(days * 86_400))
+}
+
+/// Returns the size of an apploader, including its header, given its 0x20 byte header.
+#[must_use]
+pub fn apploader_size_from_header(header: &[u8; 0x20]) -> u32 {
+    let size = u32::from_be_bytes(header[0x14..0x18].try_into().unwrap());
+    let trailer = u32::from_be_bytes(header[0x18..0x1C].try_into().unwrap());
+    0x20u32.saturating_add(size).saturating_add(trailer)
+}
+
+/// Returns where the contents of each system file and FST file of the disc are in the image, as
+/// `(offset, size)` pairs by path. Files on the disc are under `files/` and the system files under
+/// `sys/`, as Dolphin extracts them.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the DOL, apploader or FST names can't be read.
+pub fn contents<T: Read + Seek>(
+    io: &mut T,
+    disc: &mut Disc,
+) -> Result<BTreeMap<String, (u64, u64)>, Error> {
+    let header = &disc.header;
+    let dol_offset = u64::from(header.executable_offset);
+    let dol = Dol::read(io, dol_offset)?;
+    let apploader_size = apploader_size(io)?;
+    let mut contents = BTreeMap::from([
+        ("sys/boot.bin".to_string(), (0, HEADER_SIZE as u64)),
+        (
+            "sys/bi2.bin".to_string(),
+            (HEADER_SIZE as u64, BI2_SIZE as u64),
+        ),
+        (
+            "sys/apploader.img".to_string(),
+            (APPLOADER_OFFSET, apploader_size.into()),
+        ),
+        ("sys/main.dol".to_string(), (dol_offset, dol.size().into())),
+        (
+            "sys/fst.bin".to_string(),
+            (header.fst_offset.into(), header.fst_size.into()),
+        ),
+    ]);
+    let tree = Tree::new(io, disc, &Options::default())?;
+    for inode in files(&tree) {
+        let Some(Kind::File(FileData::Disc(index))) = tree.get(inode).map(|node| &node.kind) else {
+            continue;
+        };
+        let Entry::File(entry) = &disc.filesystem.entries[index.as_usize()] else {
+            unreachable!("disc file nodes always point to FST file entries");
+        };
+        let path = format!("files/{}", tree.path(inode).display());
+        contents.insert(path, (entry.offset.into(), entry.size.into()));
+    }
+    Ok(contents)
+}
+
+/// Returns the ranges of the image in `disc` used by the header, apploader, DOL and FST, as
+/// `(start, end)` pairs.
+#[must_use]
+pub fn system_extents(disc: &Disc, apploader_size: u32, dol_size: u32) -> [(u64, u64); 3] {
+    let header = &disc.header;
+    [
+        (0, APPLOADER_OFFSET + u64::from(apploader_size)),
+        (
+            header.executable_offset.into(),
+            u64::from(header.executable_offset) + u64::from(dol_size),
+        ),
+        (
+            header.fst_offset.into(),
+            u64::from(header.fst_offset) + u64::from(header.fst_size),
+        ),
+    ]
+}
+
+/// Returns the ranges of the image in `disc` used by the system files, FST and file data, as
+/// `(start, end)` pairs.
+#[must_use]
+pub fn used_extents(disc: &Disc, apploader_size: u32, dol_size: u32) -> Vec<(u64, u64)> {
+    let mut used = system_extents(disc, apploader_size, dol_size).to_vec();
+    for entry in &disc.filesystem.entries {
+        if let Entry::File(file) = entry {
+            used.push((
+                file.offset.into(),
+                u64::from(file.offset) + u64::from(file.size),
+            ));
+        }
+    }
+    used
+}
+
+/// Encodes a name for the FST string table, as latin1 if possible or SHIFT JIS otherwise.
+fn encode_name(name: &str) -> Result<Vec<u8>, Error> {
+    let (encoded, _, error) = WINDOWS_1252.encode(name);
+    if !error {
+        return Ok(encoded.into_owned());
+    }
+    let (encoded, _, error) = SHIFT_JIS.encode(name);
+    if error {
+        return Err(Error::Layout(format!(
+            "\"{name}\" can't be encoded as latin1 or SHIFT JIS"
+        )));
+    }
+    Ok(encoded.into_owned())
+}
+
+/// Returns the inodes of every file in the tree, in the order they'd appear in an FST.
+#[must_use]
+pub fn files(tree: &Tree) -> Vec<Inode> {
+    fn visit(tree: &Tree, inode: Inode, files: &mut Vec<Inode>) {
+        for &child in tree.children(inode).unwrap_or_default() {
+            match tree.get(child).unwrap().kind {
+                Kind::File(_) => files.push(child),
+                Kind::Directory(_) => visit(tree, child, files),
+            }
+        }
+    }
+    let mut files = vec![];
+    visit(tree, Inode(1), &mut files);
+    files
+}
+
+/// Serializes the tree as an FST followed by its string table, using the given placements for
+/// file data.
+///
+/// # Errors
+///
+/// [`Error::Layout`] if a name can't be encoded or the string table grows past its 24-bit limit.
+pub fn build_fst(tree: &Tree, placements: &HashMap<Inode, Placement>) -> Result<Vec<u8>, Error> {
+    fn visit(
+        tree: &Tree,
+        inode: Inode,
+        index: u32,
+        placements: &HashMap<Inode, Placement>,
+        entries: &mut Vec<[u32; 3]>,
+        strings: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        for &child in tree.children(inode).unwrap_or_default() {
+            let node = tree.get(child).unwrap();
+            let name_offset = u32::try_from(strings.len()).unwrap();
+            if name_offset >= 1 << 24 {
+                return Err(Error::Layout("the FST string table is too large".into()));
+            }
+            strings.extend(encode_name(&node.name)?);
+            strings.push(0);
+            match &node.kind {
+                Kind::File(_) => {
+                    let placement = placements[&child];
+                    entries.push([name_offset, placement.offset, placement.size]);
+                }
+                Kind::Directory(_) => {
+                    let child_index = u32::try_from(entries.len()).unwrap();
+                    entries.push([(1 << 24) | name_offset, index, 0]);
+                    visit(tree, child, child_index, placements, entries, strings)?;
+                    entries[child_index as usize][2] = u32::try_from(entries.len()).unwrap();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let mut entries = vec![[1 << 24, 0, 0]];
+    let mut strings = vec![];
+    visit(tree, Inode(1), 0, placements, &mut entries, &mut strings)?;
+    entries[0][2] = u32::try_from(entries.len()).unwrap();
+
+    let mut fst = Vec::with_capacity(entries.len() * 12 + strings.len());
+    for entry in entries {
+        for field in entry {
+            fst.extend(field.to_be_bytes());
+        }
+    }
+    fst.extend(strings);
+    Ok(fst)
+}
+
+/// Writes the FST location fields of the disc header.
+///
+/// # Errors
+///
+/// [`io::Error`] if writing to `out` fails.
+pub fn write_fst_fields<W: Write + Seek>(
+    out: &mut W,
+    offset: u32,
+    size: u32,
+    max_size: u32,
+) -> io::Result<()> {
+    out.seek(SeekFrom::Start(FST_FIELDS_OFFSET))?;
+    out.write_all(&offset.to_be_bytes())?;
+    out.write_all(&size.to_be_bytes())?;
+    out.write_all(&max_size.to_be_bytes())
+}
+
+/// Sorts the files per the given order. `files` must be in FST order.
+pub fn order_files(tree: &Tree, files: &mut [FileInfo], order: Order) {
+    match order {
+        // Stable sorting keeps files without an original offset in FST order, after the rest
+        Order::Original => files.sort_by_key(|file| file.original_offset.unwrap_or(u32::MAX)),
+        Order::Alphabetical => {
+            files.sort_by_cached_key(|file| tree.path(file.inode).to_string_lossy().to_lowercase());
+        }
+    }
+}
+
+fn to_u32(value: u64) -> Result<u32, Error> {
+    u32::try_from(value).map_err(|_| Error::Layout("the image grew past 4 GiB".into()))
+}
+
+/// Pads `out` per the padding policy, given where the image's data ends.
+///
+/// # Errors
+///
+/// [`io::Error`] if writing to `out` fails.
+pub fn pad_image<W: Write + Seek>(out: &mut W, end: u64, padding: Padding) -> io::Result<()> {
+    let size = match padding {
+        Padding::None => end,
+        Padding::Disc => end.max(DISC_SIZE),
+    };
+    if out.seek(SeekFrom::End(0))? < size {
+        out.seek(SeekFrom::Start(size - 1))?;
+        out.write_all(&[0])?;
+    }
+    if end > DISC_SIZE {
+        eprintln!("warning: the image is {end} bytes, larger than a real disc ({DISC_SIZE} bytes)");
+    }
+    Ok(())
+}
+
+/// Lays out a complete image from scratch, writing it to `out`.
+///
+/// The image is laid out as the header and bi2.bin, the apploader at [`APPLOADER_OFFSET`], then
+/// the DOL and the FST, followed by the file data in the order of `files`. `copy` is called to
+/// write the data of each file at the current position of `out`.
+///
+/// The image holds no times but those in the system files, so the same files in the same order
+/// always lay out to the same bytes.
+///
+/// # Errors
+///
+/// [`Error::Layout`] if the system files are the wrong size, the FST can't be built or the image
+/// doesn't fit in 4 GiB, and [`Error::Io`] for errors copying files or writing to `out`.
+pub fn write_image<W: Write + Seek>(
+    out: &mut W,
+    system: &SystemArea,
+    tree: &Tree,
+    files: &[FileInfo],
+    options: &LayoutOptions,
+    copy: &mut dyn FnMut(Inode, &mut W) -> io::Result<()>,
+) -> Result<(), Error> {
+    options.validate()?;
+    if system.header.len() != HEADER_SIZE || system.bi2.len() != BI2_SIZE {
+        return Err(Error::Layout(format!(
+            "the header and bi2.bin must be {HEADER_SIZE} and {BI2_SIZE} bytes"
+        )));
+    }
+    let apploader_end = APPLOADER_OFFSET + system.apploader.len() as u64;
+    let dol_offset = align(apploader_end, SYSTEM_ALIGNMENT);
+    let fst_offset = align(dol_offset + system.dol.len() as u64, SYSTEM_ALIGNMENT);
+
+    // The FST size doesn't depend on where files go, so work it out with dummy placements first
+    let mut placements: HashMap<Inode, Placement> = files
+        .iter()
+        .map(|file| (file.inode, Placement { offset: 0, size: 0 }))
+        .collect();
+    let fst_size = build_fst(tree, &placements)?.len() as u64;
+
+    let data_start = align(fst_offset + fst_size, options.alignment);
+    let mut end = data_start;
+    for file in files {
+        let offset = to_u32(end)?;
+        placements.insert(
+            file.inode,
+            Placement {
+                offset,
+                size: file.size,
+            },
+        );
+        end = align(end + u64::from(file.size), options.alignment);
+    }
+    let fst = build_fst(tree, &placements)?;
+
+    let mut header = io::Cursor::new(system.header.clone());
+    for (offset, field) in [
+        (DOL_OFFSET_FIELD, dol_offset),
+        (FST_FIELDS_OFFSET, fst_offset),
+        (FST_FIELDS_OFFSET + 4, fst_size),
+        (FST_FIELDS_OFFSET + 8, fst_size),
+        (FST_FIELDS_OFFSET + 12, data_start),
+        (FST_FIELDS_OFFSET + 16, end - data_start),
+    ] {
+        header.seek(SeekFrom::Start(offset))?;
+        header.write_all(&to_u32(field)?.to_be_bytes())?;
+    }
+
+    out.seek(SeekFrom::Start(0))?;
+    out.write_all(header.get_ref())?;
+    out.write_all(&system.bi2)?;
+    out.write_all(&system.apploader)?;
+    for (offset, data) in [(dol_offset, &system.dol), (fst_offset, &fst)] {
+        out.seek(SeekFrom::Start(offset))?;
+        out.write_all(data)?;
+    }
+    for file in files {
+        out.seek(SeekFrom::Start(placements[&file.inode].offset.into()))?;
+        copy(file.inode, out)?;
+    }
+    pad_image(out, end, options.padding)?;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::options::Strictness;
+use encoding_rs::WINDOWS_1252;
+use gcn_disk::DirectoryEntry;
+use gcn_disk::Disc;
+use gcn_disk::Entry;
+use gcn_disk::FileEntry;
+use gcn_disk::Fst;
+use gcn_disk::Header;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+/// Size of the disc header, up to the end of the user area's length.
+const HEADER_SIZE: usize = 0x440;
+
+/// Size of an FST entry.
+const ENTRY_SIZE: u32 = 12;
+
+/// Reads the disc's header and FST like [`Disc::new`], falling back for [`Strictness::Lenient`]
+/// to reading what it can of discs that don't follow the format, such as Datel's.
+///
+/// # Errors
+///
+/// [`Error`] if the disc can't be read, or it doesn't follow the format and `strictness` isn't
+/// [`Strictness::Lenient`].
+pub fn read_disc<T: Read + Seek>(io: &mut T, strictness: Strictness) -> Result<Disc, Error> {
+    match Disc::new(io) {
+        Err(gcn_disk::Error::Parse(problem)) if strictness == Strictness::Lenient => {
+            eprintln!("invalid disc: {problem}, reading what's there");
+            read_leniently(io)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Reads the disc's header and FST, making do with whatever they hold.
+///
+/// Game IDs that aren't printable ASCII have the rest replaced, and entries of unknown types are
+/// taken as directories, as Dolphin does. An FST that doesn't fit in the image is cut off, or
+/// left empty if even its root doesn't.
+fn read_leniently<T: Read + Seek>(io: &mut T) -> Result<Disc, Error> {
+    io.seek(SeekFrom::Start(0))?;
+    let mut boot = [0; HEADER_SIZE];
+    io.read_exact(&mut boot)?;
+    let word = |offset: usize| u32::from_be_bytes(boot[offset..offset + 4].try_into().unwrap());
+    let text = |bytes: &[u8]| -> String {
+        bytes
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() {
+                    char::from(b)
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    };
+    let game_id = text(&boot[..4]);
+    let name = &boot[0x20..0x400];
+    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+    let header = Header {
+        console_id: game_id[..1].to_string(),
+        game_code: game_id[1..3].to_string(),
+        country_code: game_id[3..].to_string(),
+        maker_code: text(&boot[4..6]),
+        disk_id: boot[6],
+        version: boot[7],
+        audio_streaming: boot[8],
+        stream_buffer_size: boot[9],
+        magic: word(0x1c),
+        game_name: WINDOWS_1252.decode(name).0.trim().to_string(),
+        debug_monitor_offset: word(0x400),
+        debug_monitor_address: word(0x404),
+        executable_offset: word(0x420),
+        fst_offset: word(0x424),
+        fst_size: word(0x428),
+        fst_max_size: word(0x42c),
+        user_position: word(0x430),
+        user_length: word(0x434),
+    };
+    let image_size = io.seek(SeekFrom::End(0))?;
+    let entries = match read_entries(io, &header, image_size) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("can't read the FST: {err}, showing no files");
+            vec![Entry::Directory(DirectoryEntry {
+                filename_offset: 0,
+                parent_index: 0,
+                end_index: 1,
+                index: 0,
+            })]
+        }
+    };
+    // The entries fit in the image, whose offsets fit a u32
+    #[allow(clippy::cast_possible_truncation)]
+    let string_table_offset = header.fst_offset + ENTRY_SIZE * entries.len() as u32;
+    Ok(Disc {
+        header,
+        filesystem: Fst {
+            entries,
+            string_table_offset,
+        },
+    })
+}
+
+/// Reads the entries of the FST described by `header`, as many as fit in its size and in an
+/// image of `image_size` bytes.
+fn read_entries<T: Read + Seek>(
+    io: &mut T,
+    header: &Header,
+    image_size: u64,
+) -> io::Result<Vec<Entry>> {
+    let room = image_size.saturating_sub(header.fst_offset.into()) / u64::from(ENTRY_SIZE);
+    let room = match header.fst_size / ENTRY_SIZE {
+        0 => room,
+        size => room.min(size.into()),
+    };
+    if room == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("the FST at {:#x} is past the end", header.fst_offset),
+        ));
+    }
+    io.seek(SeekFrom::Start(header.fst_offset.into()))?;
+    let mut first = [0; ENTRY_SIZE as usize];
+    io.read_exact(&mut first)?;
+    let count = u32::from_be_bytes(first[8..].try_into().unwrap());
+    // Smaller than the count, a u32
+    #[allow(clippy::cast_possible_truncation)]
+    let count = if u64::from(count) > room {
+        eprintln!("invalid FST: {count} entries don't fit, only reading {room}");
+        room as u32
+    } else {
+        count.max(1)
+    };
+    let mut data = vec![0; count as usize * ENTRY_SIZE as usize];
+    io.seek(SeekFrom::Start(header.fst_offset.into()))?;
+    io.read_exact(&mut data)?;
+    let entries = (0..count).zip(data.chunks_exact(ENTRY_SIZE as usize));
+    Ok(entries
+        .map(|(index, data)| {
+            let word =
+                |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+            let filename_offset = word(0) & 0x00ff_ffff;
+            if index == 0 {
+                Entry::Directory(DirectoryEntry {
+                    filename_offset,
+                    parent_index: 0,
+                    end_index: count,
+                    index,
+                })
+            } else if data[0] == 0 {
+                Entry::File(FileEntry {
+                    filename_offset,
+                    offset: word(4),
+                    size: word(8),
+                    index,
+                })
+            } else {
+                Entry::Directory(DirectoryEntry {
+                    filename_offset,
+                    parent_index: word(4),
+                    end_index: word(8),
+                    index,
+                })
+            }
+        })
+        .collect())
+}
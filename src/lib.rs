@@ -1,6 +1,181 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
 // SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
 
+#[cfg(target_os = "linux")]
+mod advice;
+mod archive;
+mod attr;
+mod audio;
+mod bench;
+mod cache;
+mod chunks;
+mod compress;
+mod compression;
+mod covers;
+#[cfg(unix)]
+mod daemon;
+mod damage;
+mod dat;
+mod dedup;
+mod diff;
+mod dol;
+mod dump;
+mod elf;
+mod error;
+mod extract;
+mod find;
+mod fst;
+mod ftp;
 mod fuse;
+mod gcz;
+mod grep;
+mod hashes;
+mod http;
+mod image;
+mod interrupt;
+mod json;
+mod layout;
+mod lenient;
+mod locate;
+mod mkiso;
+#[cfg(unix)]
+mod mount;
+#[cfg(unix)]
+mod multi;
+mod nbd;
+mod options;
+mod patch;
+mod pool;
+mod prefetch;
+#[cfg(unix)]
+mod privileges;
+mod readahead;
+mod rebuild;
+mod remote;
+mod retry;
+#[cfg(feature = "remote")]
+mod s3;
+#[cfg(target_os = "linux")]
+mod sandbox;
+mod scrub;
+mod source;
+mod stop;
+#[cfg(unix)]
+mod systemd;
+mod titles;
+mod tree;
+#[cfg(all(feature = "ublk", target_os = "linux"))]
+mod ublk;
+#[cfg(all(feature = "virtiofs", target_os = "linux"))]
+mod virtiofs;
+mod watch;
+mod webdav;
+#[cfg(all(feature = "winfsp", windows))]
+mod winfsp;
 
+#[cfg(target_os = "linux")]
+pub use advice::drop_cached;
+pub use bench::Measurement;
+pub use bench::read_random;
+pub use bench::read_sequential;
+pub use cache::cache_limit;
+pub use cache::cache_used;
+#[cfg(target_os = "linux")]
+pub use cache::lock_memory;
+pub use cache::set_cache_limit;
+pub use chunks::chunk_cache_dir;
+pub use chunks::default_chunk_cache_dir;
+pub use chunks::set_chunk_cache_dir;
+pub use compress::Codec;
+pub use compress::CompressOptions;
+pub use compress::compress;
+pub use compress::verify;
+#[cfg(unix)]
+pub use daemon::Daemon;
+#[cfg(unix)]
+pub use daemon::Opened;
+#[cfg(unix)]
+pub use daemon::default_socket_path;
+#[cfg(unix)]
+pub use daemon::listen;
+#[cfg(unix)]
+pub use daemon::request;
+pub use dat::Dat;
+pub use dat::Dump;
+pub use dat::Verdict;
+pub use dat::verify_all;
+pub use dedup::Duplicates;
+pub use dedup::duplicates;
+pub use diff::Change;
+pub use diff::diff;
+pub use dump::TableFormat;
+pub use dump::dump_fst;
+pub use error::Error;
+pub use extract::ArchiveFormat;
+pub use extract::Estimate;
+pub use extract::estimate_extract;
+pub use extract::extract;
+pub use extract::extract_archive;
+pub use extract::extract_file;
+pub use extract::free_space;
+pub use find::PathPattern;
+pub use find::find;
+pub use ftp::serve_ftp;
+#[cfg(unix)]
+pub use fuse::EXTENT_IOCTL;
 pub use fuse::GcnFuse;
+pub use fuse::Stats;
+pub use grep::ContentPattern;
+pub use grep::grep;
+pub use http::serve_http;
+pub use image::CompressedSizes;
+pub use image::Image;
+pub use json::Json;
+pub use layout::LayoutOptions;
+pub use layout::Order;
+pub use layout::Padding;
+pub use layout::parse_date;
+pub use lenient::read_disc;
+pub use locate::Location;
+pub use locate::Locator;
+pub use mkiso::MkisoOptions;
+pub use mkiso::mkiso;
+#[cfg(unix)]
+pub use mount::MountHandle;
+#[cfg(unix)]
+pub use multi::MultiDisc;
+pub use nbd::serve_nbd;
+pub use options::Normalization;
+pub use options::Options;
+pub use options::Sort;
+pub use options::Strictness;
+pub use patch::Patched;
+pub use pool::decompression_threads;
+pub use pool::set_decompression_threads;
+#[cfg(unix)]
+pub use privileges::User;
+#[cfg(unix)]
+pub use privileges::is_root;
+pub use rebuild::rebuild;
+pub use remote::Remote;
+pub use retry::Retrying;
+#[cfg(target_os = "linux")]
+pub use sandbox::Sandbox;
+pub use scrub::scrub;
+pub use source::Source;
+pub use stop::block_stop_signals;
+pub use stop::on_stop;
+#[cfg(unix)]
+pub use systemd::activated_listener;
+#[cfg(unix)]
+pub use systemd::notify;
+pub use titles::TitleDatabase;
+pub use titles::game_id;
+#[cfg(all(feature = "ublk", target_os = "linux"))]
+pub use ublk::serve_ublk;
+#[cfg(all(feature = "virtiofs", target_os = "linux"))]
+pub use virtiofs::serve_virtiofs;
+pub use watch::Watch;
+pub use webdav::serve_webdav;
+#[cfg(all(feature = "winfsp", windows))]
+pub use winfsp::mount_winfsp;
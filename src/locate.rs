@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::layout;
+use gcn_disk::Disc;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+/// What is stored at an offset of a disc image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// Inside the files with these paths, as [`layout::contents`] names them, at the offset
+    /// given for each. Files only share data when they are identical.
+    Files(Vec<(String, u64)>),
+    /// Not part of any file, such as padding between files. Holds the path of the closest file
+    /// before it, if there is one.
+    Unused(Option<String>),
+    /// Past the end of the image.
+    PastEnd,
+}
+
+/// Finds what is stored at offsets of a disc image, such as ones from an emulator's log.
+pub struct Locator {
+    /// Start, end and path of every file, sorted by start.
+    files: Vec<(u64, u64, String)>,
+    image_size: u64,
+}
+
+impl Locator {
+    /// Reads where every file of the disc is in the image.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Io`] if the image can't be read.
+    pub fn new<T: Read + Seek>(io: &mut T, disc: &mut Disc) -> Result<Self, Error> {
+        let mut files: Vec<_> = layout::contents(io, disc)?
+            .into_iter()
+            .map(|(path, (offset, size))| (offset, offset + size, path))
+            .collect();
+        files.sort();
+        let image_size = io.seek(SeekFrom::End(0))?;
+        Ok(Self { files, image_size })
+    }
+
+    /// Returns what is stored at `offset`.
+    #[must_use]
+    pub fn locate(&self, offset: u64) -> Location {
+        if offset >= self.image_size {
+            return Location::PastEnd;
+        }
+        let containing: Vec<_> = self
+            .files
+            .iter()
+            .filter(|(start, end, _)| (*start..*end).contains(&offset))
+            .map(|(start, _, path)| (path.clone(), offset - start))
+            .collect();
+        if !containing.is_empty() {
+            return Location::Files(containing);
+        }
+        let before = self
+            .files
+            .iter()
+            .filter(|(_, end, _)| *end <= offset)
+            .max_by_key(|(_, end, _)| *end);
+        Location::Unused(before.map(|(_, _, path)| path.clone()))
+    }
+}
@@ -1,26 +1,2103 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
 // SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
 
+use clap::CommandFactory;
 use clap::Parser;
+use clap::Subcommand;
+use clap::error::ErrorKind;
+#[cfg(unix)]
+use fuser::Filesystem;
+#[cfg(unix)]
 use fuser::MountOption;
+#[cfg(unix)]
+use fuser::Session;
 use gcn_disk::Disc;
+use gcnfuse::ArchiveFormat;
+use gcnfuse::Change;
+use gcnfuse::Codec;
+use gcnfuse::CompressOptions;
+use gcnfuse::ContentPattern;
+#[cfg(unix)]
+use gcnfuse::Daemon;
+use gcnfuse::Dat;
+use gcnfuse::Error;
 use gcnfuse::GcnFuse;
-use rvz::Rvz;
+use gcnfuse::Image;
+use gcnfuse::Json;
+use gcnfuse::LayoutOptions;
+use gcnfuse::Location;
+use gcnfuse::Locator;
+use gcnfuse::Measurement;
+use gcnfuse::MkisoOptions;
+#[cfg(unix)]
+use gcnfuse::MultiDisc;
+use gcnfuse::Normalization;
+#[cfg(unix)]
+use gcnfuse::Opened;
+use gcnfuse::Options;
+use gcnfuse::Order;
+use gcnfuse::Padding;
+use gcnfuse::PathPattern;
+#[cfg(target_os = "linux")]
+use gcnfuse::Sandbox;
+use gcnfuse::Sort;
+use gcnfuse::Source;
+use gcnfuse::Strictness;
+use gcnfuse::TableFormat;
+use gcnfuse::TitleDatabase;
+#[cfg(unix)]
+use gcnfuse::User;
+use gcnfuse::Verdict;
+use gcnfuse::game_id;
+use gcnfuse::parse_date;
+use gcnfuse::read_random;
+use gcnfuse::read_sequential;
+use std::env;
+#[cfg(unix)]
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::ffi::OsString;
+use std::fs;
 use std::fs::File;
+#[cfg(unix)]
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufWriter;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+#[cfg(target_os = "macos")]
+use std::os::fd::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
 use std::path::PathBuf;
+#[cfg(unix)]
+use std::process;
+use std::process::ExitCode;
+#[cfg(unix)]
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// The type of filesystem mounts are shown as, after `fuse.`.
+#[cfg(unix)]
+const SUBTYPE: &str = "gcnfuse";
 
 #[derive(Parser)]
-struct Args {
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// How much memory caches of decoded files and remote images may use together, in bytes or
+    /// with a K, M or G suffix. By default a quarter of the cgroup memory limit, or 256M
+    #[arg(long, global = true, value_parser = parse_size)]
+    cache_limit: Option<u64>,
+    /// How many threads decompress RVZ chunks, or compress them for `compress`; 1 decompresses
+    /// them as they're read. By default one per core
+    #[arg(long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    threads: Option<u64>,
+    /// Save decompressed RVZ chunks in this directory, so later mounts of the same image don't
+    /// decompress them again. By default `$XDG_CACHE_HOME/gcnfuse/chunks`
+    #[arg(
+        long,
+        global = true,
+        num_args = 0..=1,
+        require_equals = true,
+        value_name = "DIR"
+    )]
+    #[allow(clippy::option_option)] // Not given, given alone, or given a directory
+    chunk_cache: Option<Option<PathBuf>>,
+    /// Keep all memory, caches of decompressed data included, from being swapped out, so reads
+    /// don't stall on swap. Needs root
+    #[cfg(target_os = "linux")]
+    #[arg(long, global = true)]
+    mlock: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Mount disc images, each in a directory of its own if there are several
+    #[cfg(unix)]
+    Mount(MountArgs),
+    /// Mount a disc image read-only as a drive letter or at an empty directory, until Ctrl-C is
+    /// pressed
+    #[cfg(all(feature = "winfsp", windows))]
+    Mount(WinfspMountArgs),
+    /// Mount disc images at mount points of their own from a single process, sharing its caches
+    /// and decompression threads, until all of them are unmounted
+    #[cfg(unix)]
+    Daemon(DaemonArgs),
+    /// Unmount a mount, through the daemon if it's the daemon's
+    #[cfg(unix)]
+    Unmount(UnmountArgs),
+    /// List the mounts of disc images, with what the daemon knows about its own
+    #[cfg(unix)]
+    Mounts(MountsArgs),
+    /// Print the entry of an autofs program map mounting a game from a library through the
+    /// daemon.
+    ///
+    /// The map is a script running `gcnfuse automap --library DIR "$1"`, and mount finds the
+    /// entry's filesystem type in a `mount.gcnfuse` link to gcnfuse, such as in /sbin. autofs
+    /// unmounts games once they're no longer used, so the daemon is best started by its socket
+    #[cfg(unix)]
+    Automap(AutomapArgs),
+    /// Build a new image from a disc image with an overlay's changes applied
+    Rebuild(RebuildArgs),
+    /// Build a new image from the contents of a directory
+    Mkiso(MkisoArgs),
+    /// Compress a disc image into a new RVZ image
+    Compress(CompressArgs),
+    /// Compress an RVZ or GCZ image again as RVZ with other settings, checking the new image holds
+    /// the same disc before keeping it
+    Recompress(CompressArgs),
+    /// Write a copy of a disc image with everything but the system files and the FST's files
+    /// zeroed, so it compresses far better
+    ///
+    /// The pseudo-random padding between files is all that goes, so the game still plays, but
+    /// the copy no longer matches the original dump or its hashes.
+    Scrub(ScrubArgs),
+    /// Serve a disc image read-only over HTTP as a DAV share
+    ServeWebdav(ServeArgs),
+    /// Serve a disc image read-only over HTTP, with directory listings
+    ServeHttp(ServeArgs),
+    /// Serve a disc image read-only over FTP
+    ServeFtp(ServeFtpArgs),
+    /// Serve a disc image read-only to a virtual machine over vhost-user-fs, for QEMU or
+    /// cloud-hypervisor to connect to
+    #[cfg(all(feature = "virtiofs", target_os = "linux"))]
+    ServeVirtiofs(ServeVirtiofsArgs),
+    /// Serve the contents of a disc image read-only as a network block device, for `nbd-client`
+    /// or QEMU
+    ServeNbd(ServeNbdArgs),
+    /// Serve the contents of a disc image read-only as a local ublk block device. Needs root
+    #[cfg(all(feature = "ublk", target_os = "linux"))]
+    ServeUblk(ServeUblkArgs),
+    /// Print information about a disc image
+    Info(InfoArgs),
+    /// List the files added, removed and changed from one disc image to another
+    Diff(DiffArgs),
+    /// Print which files are at offsets of a disc image
+    Locate(LocateArgs),
+    /// List the files of a disc image with the same contents and the space they waste
+    DedupReport(DedupReportArgs),
+    /// Copy the files of a disc image into a directory, as Dolphin extracts them
+    Extract(ExtractArgs),
+    /// Write the contents of a file of a disc image to standard output
+    Cat(CatArgs),
+    /// List the files of a disc image whose paths match a glob or regular expression, with their
+    /// sizes
+    Find(FindArgs),
+    /// Search the contents of the files of a disc image for text or bytes, printing where they
+    /// are
+    Grep(GrepArgs),
+    /// Print every entry of the FST of a disc image and where the system files and FST are,
+    /// for scripts
+    Fst(FstArgs),
+    /// Verify the disc images in a directory against a DAT of good dumps, reporting which are
+    /// good, bad or unknown
+    VerifyAll(VerifyAllArgs),
+    /// Measure how fast a disc image can be read
+    Bench(BenchArgs),
+}
+
+#[cfg(unix)]
+// Each flag is an independent command line switch
+#[allow(clippy::struct_excessive_bools)]
+#[derive(clap::Args)]
+struct MountArgs {
+    /// Disc images to mount. With more than one, each is shown in a directory named after it
+    #[arg(required = true)]
+    images: Vec<PathBuf>,
+    mount: PathBuf,
+    #[command(flatten)]
+    view: ViewArgs,
+    /// Allow changes through the mount, storing them in the overlay directory
+    #[arg(
+        long,
+        requires = "overlay",
+        conflicts_with_all = [
+            "expand_archives",
+            "decompress",
+            "wav",
+            "dtk",
+            "meta",
+            "export",
+            "damage_report",
+        ]
+    )]
+    writable: bool,
+    /// Keep inodes stable and support NFS file handles, for exporting the mount over NFS
+    #[arg(long)]
+    export: bool,
+    /// Learn which files are read after which, keeping it in `$XDG_CACHE_HOME/gcnfuse/patterns`,
+    /// and read the file likely read next in the background
+    #[arg(long)]
+    prefetch: bool,
+    /// Largest read the kernel asks for at once, in bytes or with a K, M or G suffix
+    #[arg(long, value_parser = parse_size)]
+    max_read: Option<u64>,
+    /// How much the kernel reads ahead of reads of a file, in bytes or with a K, M or G suffix.
+    /// By default the chunk size of RVZ images, for reads as large as what's decompressed at
+    /// once. Going over the kernel's default needs root
+    #[arg(long, value_parser = parse_size)]
+    max_readahead: Option<u64>,
+    /// Block size reported for files, a power of two of at least 512, in bytes or with a K or M
+    /// suffix. Tools like `cp` read in blocks of it. By default 32K, the disc's sectors
+    #[arg(long, value_parser = parse_block_size)]
+    blksize: Option<u32>,
+    /// For compressed images, report the blocks each file's share of the image takes up rather
+    /// than its size, so `du` shows how much of the image files take up
+    #[arg(long)]
+    compressed_blocks: bool,
+    /// Once mounted, restrict the process to reading the image and answering the kernel, with
+    /// Landlock and seccomp
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    sandbox: bool,
+    /// User to switch to once mounted, by name or ID. Required when running as root
+    #[arg(long)]
+    user: Option<String>,
+    /// Title database (`wiitdb.txt`) to look up the game's title in, used as the mount's name and
+    /// to name each disc's directory when there are several
+    #[arg(long)]
+    titles: Option<PathBuf>,
+}
+
+#[cfg(all(feature = "winfsp", windows))]
+#[derive(clap::Args)]
+struct WinfspMountArgs {
+    image: PathBuf,
+    /// Drive letter to mount at, such as `G:`, or an empty directory
+    mount: PathBuf,
+    #[command(flatten)]
+    view: ViewArgs,
+    /// Title database (`wiitdb.txt`) to look up the game's title in, used as the drive's name
+    /// rather than the game ID
+    #[arg(long)]
+    titles: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+#[derive(clap::Args)]
+struct DaemonArgs {
+    /// Disc images and where to mount each, as IMAGE=MOUNT. More can be mounted through the
+    /// socket
+    #[arg(value_parser = parse_mount)]
+    mounts: Vec<(PathBuf, PathBuf)>,
+    #[command(flatten)]
+    view: ViewArgs,
+    /// User to switch to once mounted, by name or ID. Required when running as root
+    #[arg(long)]
+    user: Option<String>,
+    /// Title database (`wiitdb.txt`) to look up the games' titles in, used as the mounts' names
+    #[arg(long)]
+    titles: Option<PathBuf>,
+    /// Unix socket to take requests on, such as to mount or unmount images. By default
+    /// `gcnfuse.sock` in `$XDG_RUNTIME_DIR`, if set
+    #[arg(long)]
+    socket: Option<PathBuf>,
+}
+
+/// How the disc's contents are shown, shared by everything that serves them.
+// Each flag is an independent command line switch
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, clap::Args)]
+struct ViewArgs {
+    /// Normalize filenames to this Unicode form when looking them up
+    #[arg(long, value_enum)]
+    normalize: Option<Normalization>,
+    /// Order to list directories in: as in the FST, which is how files are laid out on most
+    /// discs, or by name
+    #[arg(long, value_enum, default_value_t)]
+    sort: Sort,
+    /// Serve files from this directory instead of the disc's copies, and show extra files in it
+    #[arg(long)]
+    overlay: Option<PathBuf>,
+    /// Show RARC and U8 archives as directories, with the archive itself as `name.raw`
+    #[arg(long)]
+    expand_archives: bool,
+    /// Show Yaz0 and Yay0 compressed files decompressed
+    #[arg(long)]
+    decompress: bool,
+    /// Add a `name.wav` next to every DSP and ADP audio file, converted on the fly
+    #[arg(long)]
+    wav: bool,
+    /// Show the streaming audio track as `stream.adp`, if the disc uses audio streaming
+    #[arg(long)]
+    dtk: bool,
+    /// Add a `.meta` directory with virtual files describing the disc, like the DOL as an ELF
+    #[arg(long)]
+    meta: bool,
+    /// Directory with cover art named by game ID, shown as `.meta/cover.png`
+    #[arg(long, requires = "meta")]
+    covers: Option<PathBuf>,
+    /// Download missing covers, saving them in the cover directory or the user cache directory
+    #[arg(long, requires = "meta")]
+    online: bool,
+    /// Date to use for all timestamps, as YYYY-MM-DD, instead of the apploader's build date
+    #[arg(long, value_parser = parse_mtime)]
+    mtime: Option<SystemTime>,
+    /// IPS, BPS or xdelta patch to apply to the image as it is read
+    #[arg(long)]
+    patch: Option<PathBuf>,
+    /// Times to retry failed reads from the image, waiting twice as long before each retry
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+    /// Milliseconds to wait before the first retry of a failed read
+    #[arg(long, default_value_t = 100)]
+    retry_delay: u64,
+    /// Read all file data when opening the image, listing files that can't be fully read in
+    /// `.gcnfuse/damage.txt`
+    #[arg(long)]
+    damage_report: bool,
+    /// Refuse to open discs whose FST has any problem, listing all of them
+    #[arg(long, conflicts_with = "lenient")]
+    strict: bool,
+    /// Work around problems in the disc's header and FST as well as possible, also cutting off
+    /// files past the end of the image, such as for Datel's discs
+    #[arg(long)]
+    lenient: bool,
+}
+
+impl ViewArgs {
+    /// Returns how problems in the disc's FST are handled.
+    const fn strictness(&self) -> Strictness {
+        if self.strict {
+            Strictness::Strict
+        } else if self.lenient {
+            Strictness::Lenient
+        } else {
+            Strictness::Default
+        }
+    }
+
+    /// Opens the source of the image at `path`, retrying failed reads if asked to.
+    fn source(&self, path: &Path) -> io::Result<Source> {
+        let source = Source::open(path)?;
+        if self.retries == 0 {
+            return Ok(source);
+        }
+        Ok(source.retrying(self.retries, Duration::from_millis(self.retry_delay)))
+    }
+
+    /// Returns the filesystem options for these flags, leaving the rest at their defaults.
+    fn options(self) -> Options {
+        Options {
+            strictness: self.strictness(),
+            normalization: self.normalize,
+            sort: self.sort,
+            overlay: self.overlay,
+            expand_archives: self.expand_archives,
+            decompress: self.decompress,
+            wav: self.wav,
+            dtk: self.dtk,
+            meta: self.meta,
+            covers: self.covers,
+            online: self.online,
+            mtime: self.mtime,
+            damage_report: self.damage_report,
+            ..Options::default()
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    path: PathBuf,
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+    #[command(flatten)]
+    view: ViewArgs,
+}
+
+#[derive(clap::Args)]
+struct ServeFtpArgs {
+    path: PathBuf,
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:2121")]
+    listen: String,
+    #[command(flatten)]
+    view: ViewArgs,
+}
+
+#[cfg(all(feature = "virtiofs", target_os = "linux"))]
+#[derive(clap::Args)]
+struct ServeVirtiofsArgs {
+    path: PathBuf,
+    /// Unix socket to listen on for the hypervisor
+    #[arg(long)]
+    socket: PathBuf,
+    #[command(flatten)]
+    view: ViewArgs,
+}
+
+#[derive(clap::Args)]
+struct ServeNbdArgs {
+    path: PathBuf,
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:10809")]
+    listen: String,
+    /// IPS, BPS or xdelta patch to apply to the image as it is read
+    #[arg(long)]
+    patch: Option<PathBuf>,
+}
+
+#[cfg(all(feature = "ublk", target_os = "linux"))]
+#[derive(clap::Args)]
+struct ServeUblkArgs {
+    path: PathBuf,
+    /// IPS, BPS or xdelta patch to apply to the image as it is read
+    #[arg(long)]
+    patch: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct InfoArgs {
+    path: PathBuf,
+    /// IPS, BPS or xdelta patch to apply to the image as it is read
+    #[arg(long)]
+    patch: Option<PathBuf>,
+    /// Title database (`wiitdb.txt`) to look up the game's title in
+    #[arg(long)]
+    titles: Option<PathBuf>,
+    /// Also check the FST the way mounting does, listing every problem found and failing if
+    /// there are any
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(clap::Args)]
+struct DiffArgs {
+    /// Image to compare against
+    old: PathBuf,
+    /// Image whose changes are listed
+    new: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct LocateArgs {
+    path: PathBuf,
+    /// Offsets in the image, in hexadecimal with a `0x` prefix or in decimal
+    #[arg(required = true, value_parser = parse_offset)]
+    offsets: Vec<u64>,
+}
+
+#[derive(clap::Args)]
+struct DedupReportArgs {
+    path: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct ExtractArgs {
+    path: PathBuf,
+    /// Directory to copy the files into, created if it doesn't exist
+    #[arg(required_unless_present = "output")]
+    dir: Option<PathBuf>,
+    /// Write the files as an archive in this format instead of into a directory
+    #[arg(long, value_enum, requires = "output")]
+    format: Option<ArchiveFormat>,
+    /// Where to write the archive, `-` for standard output
+    #[arg(short, long, requires = "format", conflicts_with = "dir")]
+    output: Option<PathBuf>,
+    /// Work around problems in the disc's header and FST as well as possible, such as for
+    /// Datel's discs
+    #[arg(long)]
+    lenient: bool,
+    /// Only print how many files and bytes would be written, failing if the directory's
+    /// filesystem doesn't have the space for them
+    #[arg(long, conflicts_with = "output")]
+    dry_run: bool,
+}
+
+#[derive(clap::Args)]
+struct CatArgs {
+    path: PathBuf,
+    /// The file: a path starting with `/` as mounted, or one like `sys/main.dol` as extracted
+    file: String,
+}
+
+#[derive(clap::Args)]
+struct FindArgs {
     path: PathBuf,
+    /// Glob the files' paths from the root are matched against, such as `stg_*/**/*.szs`
+    pattern: String,
+    /// Take the pattern as a regular expression, matching anywhere in the path unless anchored
+    #[arg(long)]
+    regex: bool,
+}
+
+#[derive(clap::Args)]
+struct GrepArgs {
+    path: PathBuf,
+    /// Text to search for, or bytes with --hex or a regular expression with --regex
+    pattern: String,
+    /// Take the pattern as bytes in hexadecimal, such as `de ad be ef`
+    #[arg(long, conflicts_with = "regex")]
+    hex: bool,
+    /// Take the pattern as a regular expression over the files' bytes, reading each file whole
+    #[arg(long)]
+    regex: bool,
+    /// Only search files whose paths from the root match this glob, such as `*.bmg`
+    #[arg(long)]
+    glob: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct FstArgs {
+    path: PathBuf,
+    /// Format of the table: JSON, or CSV with a row for each region and then each entry
+    #[arg(long, value_enum, default_value_t)]
+    format: TableFormat,
+}
+
+#[derive(clap::Args)]
+struct VerifyAllArgs {
+    /// Directory holding the disc images, searched for `.iso`, `.gcm` and `.rvz` files in it and
+    /// its subdirectories
+    dir: PathBuf,
+    /// DAT in the Logiqx XML format listing the good dumps, such as redump's
+    #[arg(long)]
+    dat: PathBuf,
+    /// How many images to verify at once. By default one per core
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+    /// Print the report as JSON, with the verdict for each image and how many there are of each
+    #[arg(long)]
+    json: bool,
+}
+
+#[cfg(unix)]
+#[derive(clap::Args)]
+struct UnmountArgs {
     mount: PathBuf,
+    /// Socket of the daemon to ask first. By default `gcnfuse.sock` in `$XDG_RUNTIME_DIR`, if set
+    #[arg(long)]
+    socket: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+#[derive(clap::Args)]
+struct MountsArgs {
+    /// Socket of the daemon to ask. By default `gcnfuse.sock` in `$XDG_RUNTIME_DIR`, if set
+    #[arg(long)]
+    socket: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+#[derive(clap::Args)]
+struct AutomapArgs {
+    /// Game to mount, the name of its image in the library without the extension
+    key: String,
+    /// Directory holding the disc images
+    #[arg(long)]
+    library: PathBuf,
+    /// Socket of the daemon to mount through. By default `gcnfuse.sock` in `$XDG_RUNTIME_DIR` of
+    /// the user mounting, if set
+    #[arg(long)]
+    socket: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct BenchArgs {
+    path: PathBuf,
+    /// Size of each read, in bytes or with a K, M or G suffix, by default what the kernel reads
+    /// mounted files in
+    #[arg(long, value_parser = parse_read_size, default_value = "128K")]
+    read_size: usize,
+    /// How many random reads to make
+    #[arg(long, default_value_t = 1000)]
+    reads: usize,
+    /// Also read the largest file on the disc through a temporary mount, bypassing the page cache
+    #[cfg(unix)]
+    #[arg(long)]
+    mount: bool,
+}
+
+#[derive(clap::Args)]
+struct LayoutArgs {
+    /// Alignment of each file's data, a power of two of at least 4
+    #[arg(long, default_value_t = 0x8000)]
+    alignment: u64,
+    /// Order in which file data is written
+    #[arg(long, value_enum, default_value_t)]
+    order: Order,
+    /// How to pad the end of the image
+    #[arg(long, value_enum, default_value_t)]
+    pad: Padding,
+}
+
+impl LayoutArgs {
+    const fn options(&self, repack: bool) -> LayoutOptions {
+        LayoutOptions {
+            alignment: self.alignment,
+            order: self.order,
+            padding: self.pad,
+            repack,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct RebuildArgs {
+    path: PathBuf,
+    /// Directory with the files to add or replace, as used with `mount --overlay`
+    #[arg(long)]
+    overlay: PathBuf,
+    /// Where to write the new image
+    output: PathBuf,
+    /// IPS, BPS or xdelta patch to apply to the image before the overlay
+    #[arg(long)]
+    patch: Option<PathBuf>,
+    /// Lay out every file from scratch instead of only moving files that no longer fit
+    #[arg(long)]
+    repack: bool,
+    #[command(flatten)]
+    layout: LayoutArgs,
+}
+
+#[derive(clap::Args)]
+struct MkisoArgs {
+    /// Directory whose contents become the disc's filesystem
+    dir: PathBuf,
+    /// Where to write the new image
+    output: PathBuf,
+    /// The boot DOL
+    #[arg(long)]
+    dol: PathBuf,
+    /// The apploader image
+    #[arg(long)]
+    apploader: PathBuf,
+    /// A bi2.bin to use instead of a generated one
+    #[arg(long)]
+    bi2: Option<PathBuf>,
+    /// Six character game ID (console, game code, country and maker)
+    #[arg(long, default_value = "GHBE00")]
+    game_id: String,
+    /// Game name stored in the header, defaults to the directory name
+    #[arg(long)]
+    title: Option<String>,
+    #[command(flatten)]
+    layout: LayoutArgs,
+}
+
+#[derive(clap::Args)]
+struct CompressArgs {
+    path: PathBuf,
+    /// Where to write the RVZ image
+    output: PathBuf,
+    /// IPS, BPS or xdelta patch to apply to the image as it is read
+    #[arg(long)]
+    patch: Option<PathBuf>,
+    /// Compression of each chunk
+    #[arg(long, value_enum, default_value_t)]
+    codec: Codec,
+    /// Compression level, 1 to 9 for bzip2 and 1 to 22 for Zstandard. By default 9 for bzip2
+    /// and 5 for Zstandard, as Dolphin does
+    #[arg(long)]
+    level: Option<i32>,
+    /// Size of the chunks compressed separately: larger ones compress better, smaller ones are
+    /// faster to read at random. A power of two from 32K to 2M, or a multiple of 2M
+    #[arg(long, value_parser = parse_chunk_size, default_value = "128K")]
+    chunk_size: u32,
+}
+
+#[derive(clap::Args)]
+struct ScrubArgs {
+    path: PathBuf,
+    /// Where to write the scrubbed image
+    output: PathBuf,
+    /// IPS, BPS or xdelta patch to apply to the image as it is read
+    #[arg(long)]
+    patch: Option<PathBuf>,
+}
+
+fn parse_mtime(date: &str) -> Result<SystemTime, String> {
+    parse_date(date).ok_or_else(|| format!("\"{date}\" isn't a YYYY-MM-DD date"))
+}
+
+fn parse_offset(offset: &str) -> Result<u64, String> {
+    let hex = offset
+        .strip_prefix("0x")
+        .or_else(|| offset.strip_prefix("0X"));
+    let parsed = hex.map_or_else(|| offset.parse(), |hex| u64::from_str_radix(hex, 16));
+    parsed.map_err(|err| format!("\"{offset}\" isn't an offset: {err}"))
+}
+
+/// Parses an image and where to mount it, split at the last `=` as URLs can have them too.
+#[cfg(unix)]
+fn parse_mount(mount: &str) -> Result<(PathBuf, PathBuf), String> {
+    let (image, dir) = mount
+        .rsplit_once('=')
+        .filter(|(image, dir)| !image.is_empty() && !dir.is_empty())
+        .ok_or_else(|| format!("\"{mount}\" isn't IMAGE=MOUNT"))?;
+    Ok((image.into(), dir.into()))
+}
+
+/// Parses a size in bytes, optionally with a `K`, `M` or `G` suffix for multiples of 1024.
+fn parse_size(size: &str) -> Result<u64, String> {
+    let (number, shift) = match size.as_bytes().last() {
+        Some(b'K' | b'k') => (&size[..size.len() - 1], 10),
+        Some(b'M' | b'm') => (&size[..size.len() - 1], 20),
+        Some(b'G' | b'g') => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|err| format!("\"{size}\" isn't a size: {err}"))?;
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("\"{size}\" is too large"))
+}
+
+/// Parses a block size, in bytes or with a K or M suffix, which must be a power of two of at
+/// least 512.
+#[cfg(unix)]
+fn parse_block_size(size: &str) -> Result<u32, String> {
+    let block_size = parse_size(size)?;
+    u32::try_from(block_size)
+        .ok()
+        .filter(|&block_size| block_size >= 512 && block_size.is_power_of_two())
+        .ok_or_else(|| format!("\"{size}\" isn't a power of two of at least 512"))
+}
+
+fn parse_chunk_size(size: &str) -> Result<u32, String> {
+    u32::try_from(parse_size(size)?).map_err(|_| format!("\"{size}\" is too large"))
+}
+
+/// Parses the size of the reads `bench` makes, which can't be empty or they'd never get anywhere.
+fn parse_read_size(size: &str) -> Result<usize, String> {
+    let read_size = parse_size(size)?;
+    if read_size == 0 {
+        return Err("reads have to be of at least one byte".into());
+    }
+    usize::try_from(read_size).map_err(|_| format!("\"{size}\" is too large"))
+}
+
+/// Opens the image read from `source`, applying `patch` to it if given.
+fn open(source: Source, patch: Option<&Path>) -> Result<Image, Error> {
+    let image = Image::from_source(source)?;
+    match patch {
+        Some(patch) => image.patch(patch),
+        None => Ok(image),
+    }
+}
+
+/// Returns the title of the disc's game in the title database at `titles`, if given and the game
+/// is in it.
+fn lookup_title(disc: &Disc, titles: Option<&Path>) -> Result<Option<String>, Error> {
+    let Some(titles) = titles else {
+        return Ok(None);
+    };
+    let database = TitleDatabase::load(titles)?;
+    Ok(database.title(&game_id(&disc.header)).map(str::to_string))
+}
+
+/// Returns the user `name` to switch to once mounted, exiting if that's given without running as
+/// root or missing when running as root.
+#[cfg(unix)]
+fn target_user(name: Option<&str>) -> Result<Option<User>, Error> {
+    // Parsing untrusted images as root is best avoided
+    match name {
+        Some(_) if !gcnfuse::is_root() => Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--user only works when running as root",
+            )
+            .exit(),
+        Some(name) => Ok(Some(User::lookup(name)?)),
+        None if gcnfuse::is_root() => Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "running as root needs --user, the user to switch to once mounted",
+            )
+            .exit(),
+        None => Ok(None),
+    }
+}
+
+#[cfg(unix)]
+fn mount(args: MountArgs) -> Result<(), Error> {
+    if args.images.len() > 1 && (args.writable || args.export || args.view.overlay.is_some()) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--overlay, --writable and --export only work with a single image",
+            )
+            .exit();
+    }
+    let user = target_user(args.user.as_deref())?;
+    gcnfuse::block_stop_signals()?;
+    let mut images = vec![];
+    for path in &args.images {
+        let mut image = open(args.view.source(path)?, args.view.patch.as_deref())?;
+        let disc = gcnfuse::read_disc(&mut image, args.view.strictness())?;
+        images.push((image, disc, reopen(path, &args.view)));
+    }
+    let titles = args
+        .titles
+        .as_deref()
+        .map(TitleDatabase::load)
+        .transpose()?;
+    let title_of = |disc: &Disc| {
+        let titles = titles.as_ref()?;
+        titles.title(&game_id(&disc.header)).map(str::to_string)
+    };
+    // The discs of a game share its title
+    let title = title_of(&images[0].1);
+    let mount_options = mount_options(title, args.writable, args.max_read);
+    let max_readahead = args
+        .max_readahead
+        .map(|size| u32::try_from(size).unwrap_or(u32::MAX))
+        .or_else(|| images[0].0.chunk_size());
+    let options = Options {
+        writable: args.writable,
+        export: args.export,
+        prefetch: args.prefetch,
+        max_readahead,
+        block_size: args.blksize,
+        compressed_blocks: args.compressed_blocks,
+        ..args.view.options()
+    };
+    #[cfg(target_os = "linux")]
+    let mount_point = fs::canonicalize(&args.mount)?;
+    if images.len() == 1 {
+        let (image, disc, warm) = images.remove(0);
+        let mut gcn_fuse = new_filesystem(image, disc, options)?;
+        if let Some(warm) = warm {
+            gcn_fuse.warm_up(warm)?;
+        }
+        #[cfg(target_os = "linux")]
+        let sandbox = args.sandbox.then(|| gcn_fuse.sandbox());
+        let session = Session::new(gcn_fuse, &args.mount, &mount_options)?;
+        #[cfg(target_os = "linux")]
+        let readahead = open_readahead(&mount_point, max_readahead);
+        return run_session(session, user, || {
+            #[cfg(target_os = "linux")]
+            {
+                apply_sandbox(sandbox, &args.images)?;
+                raise_readahead(readahead, &mount_point);
+            }
+            Ok(())
+        });
+    }
+    let mut discs = vec![];
+    for (path, (image, disc, warm)) in args.images.iter().zip(images) {
+        // Titles that can't be directory names are passed over for the image's name
+        let name = title_of(&disc)
+            .filter(|title| !title.contains(['/', '\0']))
+            .unwrap_or_else(|| {
+                path.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into()
+            });
+        let mut gcn_fuse = new_filesystem(image, disc, options.clone())?;
+        if let Some(warm) = warm {
+            gcn_fuse.warm_up(warm)?;
+        }
+        discs.push((name, gcn_fuse));
+    }
+    let multi_disc = MultiDisc::new(discs);
+    #[cfg(target_os = "linux")]
+    let sandbox = args.sandbox.then(|| multi_disc.sandbox());
+    let session = Session::new(multi_disc, &args.mount, &mount_options)?;
+    #[cfg(target_os = "linux")]
+    let readahead = open_readahead(&mount_point, max_readahead);
+    run_session(session, user, || {
+        #[cfg(target_os = "linux")]
+        {
+            apply_sandbox(sandbox, &args.images)?;
+            raise_readahead(readahead, &mount_point);
+        }
+        Ok(())
+    })
+}
+
+/// Answers the kernel for the mounted `session` until it's unmounted, as `user` if given, calling
+/// `switched` once the user is switched. Signals asking the process to stop unmount it.
+#[cfg(unix)]
+fn run_session<FS: Filesystem>(
+    mut session: Session<FS>,
+    user: Option<User>,
+    switched: impl FnOnce() -> io::Result<()>,
+) -> Result<(), Error> {
+    let mut unmounter = session.unmount_callable();
+    gcnfuse::on_stop(move || {
+        let _ = gcnfuse::notify("STOPPING=1");
+        let _ = unmounter.unmount();
+    });
+    if let Some(user) = user {
+        user.switch_to()?;
+    }
+    // Told before the sandbox can keep it from connecting to the service manager
+    gcnfuse::notify("READY=1")?;
+    switched()?;
+    session.run()?;
+    Ok(())
+}
+
+/// Returns the options to mount with, naming the filesystem `title` if given, and letting the
+/// kernel read up to `max_read` bytes at once if given.
+#[cfg(unix)]
+fn mount_options(title: Option<String>, writable: bool, max_read: Option<u64>) -> Vec<MountOption> {
+    let mut mount_options = if writable {
+        vec![MountOption::RW]
+    } else {
+        vec![MountOption::RO]
+    };
+    // Tells the mounts apart from other FUSE filesystems', such as in /proc/mounts
+    mount_options.push(MountOption::Subtype(SUBTYPE.to_string()));
+    if let Some(title) = title {
+        // Finder shows the volume name rather than the filesystem name
+        #[cfg(target_os = "macos")]
+        mount_options.push(MountOption::CUSTOM(format!("volname={title}")));
+        mount_options.push(MountOption::FSName(title));
+    }
+    if let Some(max_read) = max_read {
+        mount_options.push(MountOption::CUSTOM(format!("max_read={max_read}")));
+    }
+    mount_options
+}
+
+#[cfg(unix)]
+fn daemon(args: DaemonArgs) -> Result<(), Error> {
+    let DaemonArgs {
+        mounts,
+        view,
+        user,
+        titles,
+        socket,
+    } = args;
+    let user = target_user(user.as_deref())?;
+    // A socket made by the service manager starting the daemon takes the place of its own
+    let activated = gcnfuse::activated_listener()?;
+    let socket = socket
+        .or_else(gcnfuse::default_socket_path)
+        .filter(|_| activated.is_none());
+    if mounts.is_empty() && socket.is_none() && activated.is_none() {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "nothing to mount, and no --socket to take requests on",
+            )
+            .exit();
+    }
+    gcnfuse::block_stop_signals()?;
+    let titles = titles.as_deref().map(TitleDatabase::load).transpose()?;
+    let options = view.clone().options();
+    let mut opened_any = false;
+    let mut daemon = Daemon::new(move |path| {
+        // Changes to the overlay would show in every image it was used with
+        if opened_any && view.overlay.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--overlay only works with a single image",
+            )
+            .into());
+        }
+        let mut image = open(view.source(path)?, view.patch.as_deref())?;
+        let disc = gcnfuse::read_disc(&mut image, view.strictness())?;
+        let game_id = game_id(&disc.header);
+        let title = titles
+            .as_ref()
+            .and_then(|titles| titles.title(&game_id))
+            .map(str::to_string);
+        let mut filesystem = new_filesystem(image, disc, options.clone())?;
+        if let Some(warm) = reopen(path, &view) {
+            filesystem.warm_up(warm)?;
+        }
+        opened_any = true;
+        Ok(Opened {
+            filesystem,
+            game_id,
+            options: mount_options(title.clone(), false, None),
+            title,
+        })
+    });
+    for (image, dir) in &mounts {
+        daemon.mount(image, dir)?;
+    }
+    let stop = daemon.stop_flag();
+    gcnfuse::on_stop(move || {
+        let _ = gcnfuse::notify("STOPPING=1");
+        stop.store(true, Ordering::Relaxed);
+    });
+    if let Some(user) = user {
+        user.switch_to()?;
+    }
+    // Made after switching, so the daemon's user owns it
+    let listener = match activated {
+        Some(listener) => Some(listener),
+        None => socket.as_deref().map(gcnfuse::listen).transpose()?,
+    };
+    gcnfuse::notify("READY=1")?;
+    let served = daemon.serve(listener.as_ref());
+    if let Some(socket) = &socket {
+        let _ = fs::remove_file(socket);
+    }
+    Ok(served?)
+}
+
+/// Commands unmounting FUSE filesystems as the user, in the order they're tried, ending with the
+/// one for when FUSE's own aren't installed.
+#[cfg(target_os = "linux")]
+const UNMOUNT_COMMANDS: &[&[&str]] = &[&["fusermount3", "-u"], &["fusermount", "-u"], &["umount"]];
+#[cfg(all(unix, not(target_os = "linux")))]
+const UNMOUNT_COMMANDS: &[&[&str]] = &[&["umount"]];
+
+#[cfg(unix)]
+fn unmount(args: UnmountArgs) -> Result<(), Error> {
+    // The mount may be gone already, which rules out canonicalizing it
+    let mount = std::path::absolute(&args.mount)?;
+    if let Some(socket) = args.socket.or_else(gcnfuse::default_socket_path)
+        && socket.exists()
+    {
+        let request = Json::object([
+            ("command", "unmount".into()),
+            ("mount", mount.to_string_lossy().as_ref().into()),
+        ]);
+        // Anything else, such as a mount of another process, is unmounted like any other
+        if let Ok(reply) = gcnfuse::request(&socket, &request)
+            && reply.get("ok").and_then(Json::as_bool) == Some(true)
+        {
+            return Ok(());
+        }
+    }
+    for command in UNMOUNT_COMMANDS {
+        match process::Command::new(command[0])
+            .args(&command[1..])
+            .arg(&mount)
+            .status()
+        {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => {
+                return Err(io::Error::other(format!("{} failed: {status}", command[0])).into());
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "no command to unmount with").into())
+}
+
+#[cfg(unix)]
+fn mounts(args: MountsArgs) -> Result<(), Error> {
+    // Only other processes' mounts found in /proc/mounts are checked against it
+    #[cfg_attr(not(target_os = "linux"), allow(clippy::collection_is_never_read))]
+    let mut listed = vec![];
+    if let Some(socket) = args.socket.or_else(gcnfuse::default_socket_path)
+        && socket.exists()
+    {
+        let reply = gcnfuse::request(&socket, &Json::object([("command", "list".into())]))?;
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let number = |mount: &Json, key| mount.get(key).and_then(Json::as_u64).unwrap_or(0);
+        for mount in reply
+            .get("mounts")
+            .and_then(Json::as_array)
+            .unwrap_or_default()
+        {
+            let text = |key| mount.get(key).and_then(Json::as_str);
+            let Some(dir) = text("mount") else {
+                continue;
+            };
+            let game = text("title").map_or_else(
+                || text("game_id").unwrap_or_default().to_string(),
+                |title| format!("{}, {title}", text("game_id").unwrap_or_default()),
+            );
+            println!(
+                "{dir}: {} ({game}), up {}, {} reads of {} bytes",
+                text("image").unwrap_or_default(),
+                format_uptime(now.saturating_sub(number(mount, "since"))),
+                number(mount, "reads"),
+                number(mount, "bytes_read")
+            );
+            listed.push(dir.to_string());
+        }
+    }
+    // Mounts of other processes only show where they are, and the title they're named after
+    #[cfg(target_os = "linux")]
+    for line in fs::read_to_string("/proc/mounts")?.lines() {
+        let fields: Vec<_> = line.split(' ').map(unescape_mount_field).collect();
+        let [name, dir, kind, ..] = fields.as_slice() else {
+            continue;
+        };
+        // Mounted by root without fusermount, the type leaves out the subtype, which names the
+        // mount unless a title does
+        let ours = *kind == format!("fuse.{SUBTYPE}") || (kind == "fuse" && name == SUBTYPE);
+        if ours && !listed.contains(dir) {
+            if name == SUBTYPE {
+                println!("{dir}");
+            } else {
+                println!("{dir}: {name}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns `seconds` as days, hours, minutes and seconds, leaving out the larger units that are 0.
+#[cfg(unix)]
+fn format_uptime(seconds: u64) -> String {
+    let units = [
+        (seconds / 86400, "d"),
+        (seconds / 3600 % 24, "h"),
+        (seconds / 60 % 60, "m"),
+        (seconds % 60, "s"),
+    ];
+    let start = units
+        .iter()
+        .position(|&(count, _)| count != 0)
+        .unwrap_or(units.len() - 1);
+    units[start..]
+        .iter()
+        .map(|(count, unit)| format!("{count}{unit}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Undoes the octal escapes of spaces, tabs, newlines and backslashes in a field of
+/// `/proc/mounts`.
+#[cfg(target_os = "linux")]
+fn unescape_mount_field(field: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = field;
+    while let Some(start) = rest.find('\\') {
+        unescaped.push_str(&rest[..start]);
+        let escape = rest.get(start + 1..start + 4);
+        if let Some(byte) = escape.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            unescaped.push(char::from(byte));
+            rest = &rest[start + 4..];
+        } else {
+            unescaped.push('\\');
+            rest = &rest[start + 1..];
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(unix)]
+fn automap(args: &AutomapArgs) -> Result<(), Error> {
+    let missing = || io::Error::new(io::ErrorKind::NotFound, format!("no game {}", args.key));
+    // Keys are names looked up in the directory autofs manages, never paths
+    if args.key.starts_with('.') || args.key.contains('/') {
+        return Err(missing().into());
+    }
+    let mut images = vec![];
+    for entry in fs::read_dir(&args.library)? {
+        let path = entry?.path();
+        if path.file_stem() == Some(OsStr::new(&args.key)) && path.is_file() {
+            images.push(path);
+        }
+    }
+    images.sort();
+    let image = fs::canonicalize(images.first().ok_or_else(missing)?)?;
+    let unusable = |path: &Path| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} can't be written in an autofs map", path.display()),
+        )
+    };
+    let mut entry = format!("-fstype={SUBTYPE}");
+    if let Some(socket) = &args.socket {
+        let socket = std::path::absolute(socket)?;
+        // Options have no escapes
+        match socket.to_str() {
+            Some(text) if !text.contains([',', ' ', '\t', '\n']) => {
+                entry.push_str(",socket=");
+                entry.push_str(text);
+            }
+            _ => return Err(unusable(&socket).into()),
+        }
+    }
+    let location = image.to_str().ok_or_else(|| unusable(&image))?;
+    entry.push_str(" :");
+    for c in location.chars() {
+        // autofs puts the key in place of &, and expands variables
+        if c.is_whitespace() || matches!(c, '\\' | '"' | '&' | '$') {
+            entry.push('\\');
+        }
+        entry.push(c);
+    }
+    println!("{entry}");
+    Ok(())
+}
+
+/// What mount expects its helpers to exit with when mounting fails.
+#[cfg(unix)]
+const MOUNT_HELPER_FAILURE: u8 = 32;
+
+/// Options mount passes its helpers that change nothing, as mounts are always read-only and
+/// mount itself sees to the rest.
+#[cfg(unix)]
+const IGNORED_MOUNT_OPTIONS: &[&str] = &[
+    "ro",
+    "rw",
+    "defaults",
+    "auto",
+    "noauto",
+    "nofail",
+    "user",
+    "nouser",
+    "users",
+    "_netdev",
+    "suid",
+    "nosuid",
+    "dev",
+    "nodev",
+    "exec",
+    "noexec",
+    "atime",
+    "noatime",
+    "relatime",
+    "strictatime",
+    "nodiratime",
+    "sync",
+    "async",
+];
+
+/// Mounts an image through the daemon, run by mount as `mount.gcnfuse IMAGE MOUNT -o OPTIONS`
+/// for mounts of the type, such as autofs's. The daemon's socket can be given as the `socket`
+/// option.
+#[cfg(unix)]
+fn mount_helper(args: &[OsString]) -> Result<(), Error> {
+    let invalid =
+        |message: String| Error::from(io::Error::new(io::ErrorKind::InvalidInput, message));
+    let mut paths = vec![];
+    let mut options = String::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("-o") => {
+                let value = args.next().and_then(|value| value.to_str());
+                options = value
+                    .ok_or_else(|| invalid("-o takes options".to_string()))?
+                    .to_string();
+            }
+            // Faking, forking, not writing mtab and verbosity are all mount's own business
+            Some("-f" | "-F" | "-n" | "-s" | "-v") => {}
+            Some("-t" | "-N") => {
+                args.next();
+            }
+            _ => paths.push(std::path::absolute(arg)?),
+        }
+    }
+    let [image, dir] = paths.as_slice() else {
+        return Err(invalid(
+            "usage: mount.gcnfuse IMAGE MOUNT [-o OPTIONS]".to_string(),
+        ));
+    };
+    let mut socket = None;
+    for option in options.split(',').filter(|option| !option.is_empty()) {
+        match option.split_once('=') {
+            Some(("socket", path)) => socket = Some(PathBuf::from(path)),
+            None if IGNORED_MOUNT_OPTIONS.contains(&option) => {}
+            _ => return Err(invalid(format!("unsupported option {option}"))),
+        }
+    }
+    let socket = socket
+        .or_else(gcnfuse::default_socket_path)
+        .ok_or_else(|| invalid("no socket option to reach the daemon at".to_string()))?;
+    let request = Json::object([
+        ("command", "mount".into()),
+        ("image", image.to_string_lossy().as_ref().into()),
+        ("mount", dir.to_string_lossy().as_ref().into()),
+    ]);
+    let reply = gcnfuse::request(&socket, &request)?;
+    if reply.get("ok").and_then(Json::as_bool) == Some(true) {
+        return Ok(());
+    }
+    let error = reply.get("error").and_then(Json::as_str);
+    Err(io::Error::other(error.unwrap_or("the daemon didn't mount it")).into())
+}
+
+/// Opens the setting of how much the kernel reads ahead in the filesystem just mounted at
+/// `mount`, an absolute path, to later raise it to `readahead` bytes, if given.
+///
+/// The kernel only lets that go over its default through sysfs, which takes root, so nothing is
+/// opened otherwise.
+#[cfg(target_os = "linux")]
+fn open_readahead(mount: &Path, readahead: Option<u32>) -> Option<(File, u32)> {
+    let readahead = readahead.filter(|_| gcnfuse::is_root())?;
+    // Looking at the mount itself would wait for the filesystem, which isn't served yet
+    let opened = fs::read_to_string("/proc/self/mountinfo").and_then(|mounts| {
+        let escaped = mount
+            .to_string_lossy()
+            .replace('\\', "\\134")
+            .replace(' ', "\\040")
+            .replace('\t', "\\011")
+            .replace('\n', "\\012");
+        // ID, parent ID, major:minor, root, mount point, and more; the latest mount there is ours
+        let device = mounts
+            .lines()
+            .rev()
+            .map(|line| line.split(' ').collect::<Vec<_>>())
+            .find(|fields| fields.get(4) == Some(&escaped.as_str()))
+            .and_then(|fields| fields.get(2).copied())
+            .ok_or(io::ErrorKind::NotFound)?;
+        OpenOptions::new()
+            .write(true)
+            .open(format!("/sys/class/bdi/{device}/read_ahead_kb"))
+    });
+    match opened {
+        Ok(file) => Some((file, readahead)),
+        Err(err) => {
+            eprintln!("can't set how much the kernel reads ahead: {err}");
+            None
+        }
+    }
+}
+
+/// Raises how much the kernel reads ahead in the filesystem mounted at `mount` through the
+/// setting opened by [`open_readahead`], once the filesystem answers.
+///
+/// The kernel lowers it again to what the filesystem asks for when it's initialized, so it's
+/// written from a thread waiting for that. The thread must only start once the user is switched,
+/// as switching waits for every thread, and a thread waiting for the filesystem never answers.
+#[cfg(target_os = "linux")]
+fn raise_readahead(setting: Option<(File, u32)>, mount: &Path) {
+    let Some((mut file, readahead)) = setting else {
+        return;
+    };
+    let mount = mount.to_path_buf();
+    thread::spawn(move || {
+        // This returns once the filesystem is initialized and answering
+        let set = fs::metadata(&mount)
+            .and_then(|_| file.write_all(readahead.div_ceil(1024).to_string().as_bytes()));
+        if let Err(err) = set {
+            eprintln!("can't set how much the kernel reads ahead: {err}");
+        }
+    });
+}
+
+/// Opens the image at `path` a second time, to warm up the caches of the filesystem showing it
+/// in the background. Standard input can only be read once, so images read from it aren't.
+#[cfg(any(unix, feature = "winfsp"))]
+fn reopen(path: &Path, view: &ViewArgs) -> Option<Image> {
+    if path == Path::new("-") {
+        return None;
+    }
+    let image = view
+        .source(path)
+        .map_err(Error::from)
+        .and_then(|source| open(source, view.patch.as_deref()));
+    image
+        .inspect_err(|err| eprintln!("can't open {} again to warm it up: {err}", path.display()))
+        .ok()
+}
+
+/// Restricts the process with `sandbox` if given, letting it use the network if any of `images`
+/// is read from a server, keep saving decompressed chunks, and tell whether processes reading
+/// were killed.
+#[cfg(target_os = "linux")]
+fn apply_sandbox(sandbox: Option<Sandbox>, images: &[PathBuf]) -> io::Result<()> {
+    let Some(mut sandbox) = sandbox else {
+        return Ok(());
+    };
+    sandbox.network = images
+        .iter()
+        .any(|path| path.to_string_lossy().contains("://"));
+    sandbox
+        .writable
+        .extend(gcnfuse::chunk_cache_dir().map(Path::to_path_buf));
+    // Where reads tell whether the process they're for was killed
+    sandbox.readable.push(PathBuf::from("/proc"));
+    sandbox.apply()
+}
+
+/// Opens the image at `path` and sets up the filesystem showing it as `view` asks.
+fn open_view(path: &Path, view: ViewArgs) -> Result<GcnFuse<Image>, Error> {
+    let mut image = open(view.source(path)?, view.patch.as_deref())?;
+    let disc = gcnfuse::read_disc(&mut image, view.strictness())?;
+    new_filesystem(image, disc, view.options())
+}
+
+/// Sets up the filesystem showing `disc` from `image`, knowing how much of the image each file
+/// takes up if it's compressed.
+fn new_filesystem(image: Image, disc: Disc, options: Options) -> Result<GcnFuse<Image>, Error> {
+    let sizes = image.compressed_sizes();
+    // Hashes of files are only shown as extended attributes, which only FUSE has
+    #[cfg(unix)]
+    let key = image.content_key()?;
+    let mut gcn_fuse = GcnFuse::new(image, disc, options)?;
+    if let Some(sizes) = sizes {
+        gcn_fuse.set_compressed_sizes(sizes);
+    }
+    #[cfg(unix)]
+    if let Some(key) = key {
+        gcn_fuse.set_image_key(key);
+    }
+    Ok(gcn_fuse)
+}
+
+fn serve_webdav(args: ServeArgs) -> Result<(), Error> {
+    let gcn_fuse = open_view(&args.path, args.view)?;
+    gcnfuse::serve_webdav(gcn_fuse, &args.listen)
+}
+
+fn serve_http(args: ServeArgs) -> Result<(), Error> {
+    let gcn_fuse = open_view(&args.path, args.view)?;
+    gcnfuse::serve_http(gcn_fuse, &args.listen)
+}
+
+fn serve_ftp(args: ServeFtpArgs) -> Result<(), Error> {
+    let gcn_fuse = open_view(&args.path, args.view)?;
+    gcnfuse::serve_ftp(gcn_fuse, &args.listen)
+}
+
+#[cfg(all(feature = "virtiofs", target_os = "linux"))]
+fn serve_virtiofs(args: ServeVirtiofsArgs) -> Result<(), Error> {
+    let gcn_fuse = open_view(&args.path, args.view)?;
+    gcnfuse::serve_virtiofs(gcn_fuse, &args.socket)
+}
+
+#[cfg(all(feature = "winfsp", windows))]
+fn mount_winfsp(args: WinfspMountArgs) -> Result<(), Error> {
+    gcnfuse::block_stop_signals()?;
+    let mut image = open(args.view.source(&args.image)?, args.view.patch.as_deref())?;
+    let disc = gcnfuse::read_disc(&mut image, args.view.strictness())?;
+    let label =
+        lookup_title(&disc, args.titles.as_deref())?.unwrap_or_else(|| game_id(&disc.header));
+    let warm = reopen(&args.image, &args.view);
+    let mut gcn_fuse = new_filesystem(image, disc, args.view.options())?;
+    if let Some(warm) = warm {
+        gcn_fuse.warm_up(warm)?;
+    }
+    gcnfuse::mount_winfsp(gcn_fuse, &args.mount, &label)
+}
+
+fn serve_nbd(args: &ServeNbdArgs) -> Result<(), Error> {
+    let image = open(Source::open(&args.path)?, args.patch.as_deref())?;
+    let size = image.disc_size()?;
+    gcnfuse::serve_nbd(image, size, &args.listen)
+}
+
+#[cfg(all(feature = "ublk", target_os = "linux"))]
+fn serve_ublk(args: &ServeUblkArgs) -> Result<(), Error> {
+    gcnfuse::block_stop_signals()?;
+    let image = open(Source::open(&args.path)?, args.patch.as_deref())?;
+    let size = image.disc_size()?;
+    gcnfuse::serve_ublk(image, size, |device| {
+        println!("{}", device.display());
+        gcnfuse::notify("READY=1")
+    })
+}
+
+fn rebuild(args: RebuildArgs) -> Result<(), Error> {
+    let mut image = open(Source::open(&args.path)?, args.patch.as_deref())?;
+    let mut disc = Disc::new(&mut image)?;
+    let disc_size = image.disc_size()?;
+    let options = Options {
+        overlay: Some(args.overlay),
+        ..Options::default()
+    };
+    let mut output = BufWriter::new(File::create(args.output)?);
+    gcnfuse::rebuild(
+        &mut image,
+        &mut disc,
+        disc_size,
+        &options,
+        &args.layout.options(args.repack),
+        &mut output,
+    )?;
+    output.flush()?;
+    Ok(())
+}
+
+fn mkiso(args: MkisoArgs) -> Result<(), Error> {
+    let title = args.title.unwrap_or_else(|| {
+        args.dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    let options = MkisoOptions {
+        game_id: args.game_id,
+        title,
+        dol: args.dol,
+        apploader: args.apploader,
+        bi2: args.bi2,
+        layout: args.layout.options(false),
+    };
+    let mut output = BufWriter::new(File::create(args.output)?);
+    gcnfuse::mkiso(&args.dir, &options, &mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Returns whether `a` and `b` are the same file, so writing one would overwrite the other.
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    fs::metadata(a)
+        .ok()
+        .zip(fs::metadata(b).ok())
+        .is_some_and(|(a, b)| a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+/// Returns whether `a` and `b` are the same file, so writing one would overwrite the other. Only
+/// paths are compared on Windows, which misses hard links.
+#[cfg(windows)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    fs::canonicalize(a)
+        .ok()
+        .zip(fs::canonicalize(b).ok())
+        .is_some_and(|(a, b)| a == b)
+}
+
+/// Compresses the image at `args.path`, which must already be an RVZ or GCZ image and is compared
+/// against the new one afterwards for `recompress`.
+fn compress(args: &CompressArgs, recompress: bool) -> Result<(), Error> {
+    if same_file(&args.path, &args.output) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "the output can't be the image being compressed",
+            )
+            .exit();
+    }
+    let mut image = open(Source::open(&args.path)?, args.patch.as_deref())?;
+    if recompress && image.compressed_sizes().is_none() {
+        Cli::command()
+            .error(
+                ErrorKind::InvalidValue,
+                format!(
+                    "{} isn't an RVZ or GCZ image, which `compress` is for",
+                    args.path.display()
+                ),
+            )
+            .exit();
+    }
+    let size = image.disc_size()?;
+    let options = CompressOptions {
+        codec: args.codec,
+        level: args.level.unwrap_or_else(|| args.codec.default_level()),
+        chunk_size: args.chunk_size,
+        threads: gcnfuse::decompression_threads(),
+    };
+    let mut output = BufWriter::new(File::create(&args.output)?);
+    // A damaged block of a compressed image stops it partway through
+    let written = gcnfuse::compress(&mut image, size, &options, &mut output)
+        .and_then(|()| Ok(output.flush()?));
+    drop(output);
+    if let Err(err) = written {
+        eprintln!("removing {}", args.output.display());
+        fs::remove_file(&args.output)?;
+        return Err(err);
+    }
+    if recompress {
+        image.seek(SeekFrom::Start(0))?;
+        let mut new = Image::from_source(Source::open(&args.output)?)?;
+        if let Err(err) = gcnfuse::verify(&mut image, &mut new, size) {
+            eprintln!("removing {}", args.output.display());
+            fs::remove_file(&args.output)?;
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+fn scrub(args: &ScrubArgs) -> Result<(), Error> {
+    if same_file(&args.path, &args.output) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "the output can't be the image being scrubbed",
+            )
+            .exit();
+    }
+    let mut image = open(Source::open(&args.path)?, args.patch.as_deref())?;
+    let disc = Disc::new(&mut image)?;
+    let disc_size = image.disc_size()?;
+    let mut output = BufWriter::new(File::create(&args.output)?);
+    let zeroed = gcnfuse::scrub(&mut image, &disc, disc_size, &mut output)?;
+    output.flush()?;
+    println!("{zeroed} of {disc_size} bytes zeroed");
+    Ok(())
+}
+
+fn info(args: &InfoArgs) -> Result<(), Error> {
+    let mut image = open(Source::open(&args.path)?, args.patch.as_deref())?;
+    let disc = Disc::new(&mut image)?;
+    let header = &disc.header;
+    let title = lookup_title(&disc, args.titles.as_deref())?;
+    println!("Game ID: {}", game_id(header));
+    println!("Title: {}", title.as_deref().unwrap_or(&header.game_name));
+    if title.is_some() {
+        println!("Internal name: {}", header.game_name);
+    }
+    println!("Disc: {}", header.disk_id + 1);
+    println!("Version: {}", header.version);
+    println!(
+        "Audio streaming: {}",
+        if header.audio_streaming == 0 {
+            "no"
+        } else {
+            "yes"
+        }
+    );
+    println!("Image size: {}", image.disc_size()?);
+    if args.check {
+        let options = Options {
+            strictness: Strictness::Strict,
+            ..Options::default()
+        };
+        GcnFuse::new(image, disc, options)?;
+        println!("FST: no problems found");
+    }
+    Ok(())
+}
+
+fn diff(args: &DiffArgs) -> Result<(), Error> {
+    let mut old = Image::open(&args.old)?;
+    let mut old_disc = Disc::new(&mut old)?;
+    let mut new = Image::open(&args.new)?;
+    let mut new_disc = Disc::new(&mut new)?;
+    for change in gcnfuse::diff(&mut old, &mut old_disc, &mut new, &mut new_disc)? {
+        let kind = match change {
+            Change::Added(_) => "added",
+            Change::Removed(_) => "removed",
+            Change::Changed(_) => "changed",
+        };
+        println!("{kind:<8}{}", change.path());
+    }
+    Ok(())
+}
+
+fn locate(args: &LocateArgs) -> Result<(), Error> {
+    let mut image = Image::open(&args.path)?;
+    let mut disc = Disc::new(&mut image)?;
+    let locator = Locator::new(&mut image, &mut disc)?;
+    for &offset in &args.offsets {
+        match locator.locate(offset) {
+            Location::Files(files) => {
+                for (path, file_offset) in files {
+                    println!("{offset:#x}: {path} at {file_offset:#x}");
+                }
+            }
+            Location::Unused(Some(before)) => println!("{offset:#x}: unused, after {before}"),
+            Location::Unused(None) => println!("{offset:#x}: unused"),
+            Location::PastEnd => println!("{offset:#x}: past the end of the image"),
+        }
+    }
+    Ok(())
+}
+
+fn dedup_report(args: &DedupReportArgs) -> Result<(), Error> {
+    let mut image = Image::open(&args.path)?;
+    let mut disc = Disc::new(&mut image)?;
+    let groups = gcnfuse::duplicates(&mut image, &mut disc)?;
+    for group in &groups {
+        println!(
+            "{} files of {} bytes, {} bytes wasted:",
+            group.paths.len(),
+            group.size,
+            group.wasted
+        );
+        for path in &group.paths {
+            println!("  {path}");
+        }
+    }
+    let wasted: u64 = groups.iter().map(|group| group.wasted).sum();
+    println!(
+        "{wasted} bytes wasted in {} sets of identical files",
+        groups.len()
+    );
+    Ok(())
+}
+
+fn extract(args: &ExtractArgs) -> Result<(), Error> {
+    let strictness = if args.lenient {
+        Strictness::Lenient
+    } else {
+        Strictness::Default
+    };
+    let mut image = Image::open(&args.path)?;
+    let mut disc = gcnfuse::read_disc(&mut image, strictness)?;
+    if args.dry_run
+        && let Some(dir) = &args.dir
+    {
+        let (available, block_size) = gcnfuse::free_space(dir)?;
+        let estimate = gcnfuse::estimate_extract(&mut image, &mut disc, strictness, block_size)?;
+        println!(
+            "{} files in {} directories, {} bytes",
+            estimate.files, estimate.directories, estimate.bytes
+        );
+        println!(
+            "up to {} bytes of space needed, {available} bytes free",
+            estimate.space
+        );
+        if estimate.space > available {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!("{} doesn't have the space to extract into", dir.display()),
+            )
+            .into());
+        }
+        return Ok(());
+    }
+    match (&args.dir, args.format, &args.output) {
+        (_, Some(format), Some(output)) if output.as_os_str() == "-" => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            gcnfuse::extract_archive(&mut image, &mut disc, format, &mut out, strictness)?;
+            out.flush()?;
+        }
+        (_, Some(format), Some(output)) => {
+            let mut out = BufWriter::new(File::create(output)?);
+            gcnfuse::extract_archive(&mut image, &mut disc, format, &mut out, strictness)?;
+            out.flush()?;
+        }
+        (Some(dir), _, _) => gcnfuse::extract(&mut image, &mut disc, dir, strictness)?,
+        // Either a directory or an archive is required
+        _ => unreachable!(),
+    }
+    // Nothing read is needed again soon. Images that aren't local files can't be opened here,
+    // and have nothing cached to drop
+    #[cfg(target_os = "linux")]
+    let _ = gcnfuse::drop_cached(&args.path);
+    Ok(())
+}
+
+fn cat(args: &CatArgs) -> Result<(), Error> {
+    let mut image = Image::open(&args.path)?;
+    let mut disc = Disc::new(&mut image)?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    match gcnfuse::extract_file(&mut image, &mut disc, &args.file, &mut out)
+        .and_then(|()| Ok(out.flush()?))
+    {
+        // Piped into something that stopped reading, such as head
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+fn find(args: &FindArgs) -> Result<(), Error> {
+    let pattern = if args.regex {
+        PathPattern::regex(&args.pattern)
+            .unwrap_or_else(|err| Cli::command().error(ErrorKind::InvalidValue, err).exit())
+    } else {
+        PathPattern::glob(&args.pattern)
+    };
+    let mut image = Image::open(&args.path)?;
+    let mut disc = Disc::new(&mut image)?;
+    for (path, size) in gcnfuse::find(&mut image, &mut disc, &pattern)? {
+        println!("{size}\t/{path}");
+    }
+    Ok(())
+}
+
+/// Parses bytes in hexadecimal, optionally separated by whitespace.
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits: Vec<_> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn grep(args: &GrepArgs) -> Result<(), Error> {
+    let pattern = if args.regex {
+        regex::bytes::Regex::new(&args.pattern).map_or_else(
+            |err| Cli::command().error(ErrorKind::InvalidValue, err).exit(),
+            ContentPattern::Regex,
+        )
+    } else if args.hex {
+        ContentPattern::Bytes(parse_hex(&args.pattern).unwrap_or_else(|| {
+            Cli::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    format!("\"{}\" isn't bytes in hexadecimal", args.pattern),
+                )
+                .exit()
+        }))
+    } else {
+        ContentPattern::Bytes(args.pattern.as_bytes().to_vec())
+    };
+    if matches!(&pattern, ContentPattern::Bytes(bytes) if bytes.is_empty()) {
+        Cli::command()
+            .error(ErrorKind::InvalidValue, "the pattern can't be empty")
+            .exit();
+    }
+    let paths = args.glob.as_deref().map(PathPattern::glob);
+    let mut image = Image::open(&args.path)?;
+    let mut disc = Disc::new(&mut image)?;
+    gcnfuse::grep(
+        &mut image,
+        &mut disc,
+        &pattern,
+        paths.as_ref(),
+        |path, offset| {
+            println!("/{path}: {offset:#x}");
+        },
+    )
+}
+
+fn fst(args: &FstArgs) -> Result<(), Error> {
+    let mut image = Image::open(&args.path)?;
+    let disc = Disc::new(&mut image)?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    match gcnfuse::dump_fst(&mut image, &disc, args.format, &mut out)
+        .and_then(|()| Ok(out.flush()?))
+    {
+        // Piped into something that stopped reading, such as head
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Returns the disc images under `dir`, those with the extensions of the formats that can be
+/// opened, in no particular order.
+fn image_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut images = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            images.extend(image_files(&path)?);
+        } else if path.extension().is_some_and(|extension| {
+            ["gcm", "iso", "rvz"]
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        }) {
+            images.push(path);
+        }
+    }
+    Ok(images)
+}
+
+fn verify_all(args: &VerifyAllArgs) -> Result<(), Error> {
+    let dat = Dat::load(&args.dat)?;
+    if dat.is_empty() {
+        Cli::command()
+            .error(
+                ErrorKind::InvalidValue,
+                format!("{} lists no dumps", args.dat.display()),
+            )
+            .exit();
+    }
+    let mut images = image_files(&args.dir)?;
+    images.sort();
+    let jobs = args.jobs.map_or_else(
+        || thread::available_parallelism().map_or(1, usize::from),
+        |jobs| usize::try_from(jobs).unwrap_or(usize::MAX),
+    );
+    let verdicts = gcnfuse::verify_all(&dat, &images, jobs);
+    let count = |wanted: fn(&Verdict) -> bool| {
+        verdicts.iter().filter(|&verdict| wanted(verdict)).count() as u64
+    };
+    let good = count(|verdict| matches!(verdict, Verdict::Good(_)));
+    let bad = count(|verdict| matches!(verdict, Verdict::Bad(_)));
+    let unknown = count(|verdict| matches!(verdict, Verdict::Unknown));
+    if args.json {
+        let images = images
+            .iter()
+            .zip(verdicts)
+            .map(|(path, verdict)| {
+                let path = ("path", path.to_string_lossy().into_owned().into());
+                match verdict {
+                    Verdict::Good(dump) => Json::object([
+                        path,
+                        ("status", "good".into()),
+                        ("game", dump.game.into()),
+                        ("file", dump.file.into()),
+                    ]),
+                    Verdict::Bad(reason) => {
+                        Json::object([path, ("status", "bad".into()), ("reason", reason.into())])
+                    }
+                    Verdict::Unknown => Json::object([path, ("status", "unknown".into())]),
+                }
+            })
+            .collect();
+        let report = Json::object([
+            ("images", Json::Array(images)),
+            ("good", good.into()),
+            ("bad", bad.into()),
+            ("unknown", unknown.into()),
+        ]);
+        println!("{report}");
+        return Ok(());
+    }
+    for (path, verdict) in images.iter().zip(&verdicts) {
+        match verdict {
+            Verdict::Good(dump) => println!("good    {} ({})", path.display(), dump.game),
+            Verdict::Bad(reason) => println!("bad     {}: {reason}", path.display()),
+            Verdict::Unknown => println!("unknown {}", path.display()),
+        }
+    }
+    println!("{good} good, {bad} bad, {unknown} unknown");
+    Ok(())
+}
+
+fn print_measurement(name: &str, measurement: &Measurement) {
+    println!(
+        "{name}: {:.1} MB/s, latency p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        measurement.throughput(),
+        measurement.percentile(50),
+        measurement.percentile(90),
+        measurement.percentile(99),
+        measurement.percentile(100)
+    );
+}
+
+/// Returns the path and size of the largest file under `dir`.
+#[cfg(unix)]
+fn largest_file(dir: &Path) -> io::Result<Option<(PathBuf, u64)>> {
+    let mut largest: Option<(PathBuf, u64)> = None;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let candidate = if metadata.is_dir() {
+            largest_file(&entry.path())?
+        } else {
+            Some((entry.path(), metadata.len()))
+        };
+        if let Some(candidate) = candidate
+            && largest.as_ref().is_none_or(|(_, size)| candidate.1 > *size)
+        {
+            largest = Some(candidate);
+        }
+    }
+    Ok(largest)
+}
+
+/// Mounts the image at `dir` and measures reads of its largest file.
+#[cfg(unix)]
+fn bench_mounted(image: Image, disc: Disc, dir: &Path, args: &BenchArgs) -> Result<(), Error> {
+    let gcn_fuse = GcnFuse::new(image, disc, Options::default())?;
+    let _session = fuser::spawn_mount2(gcn_fuse, dir, &[MountOption::RO])?;
+    let Some((path, size)) = largest_file(dir)? else {
+        println!("no files to read through the mount");
+        return Ok(());
+    };
+    let name = path.strip_prefix(dir).unwrap_or(&path).display();
+    println!("reading {name} ({size} bytes) through the mount");
+    // Bypass the page cache, so every read goes through the filesystem
+    let mut options = OpenOptions::new();
+    options.read(true);
+    #[cfg(not(target_os = "macos"))]
+    options.custom_flags(libc::O_DIRECT);
+    let mut file = options.open(&path)?;
+    // macOS has no O_DIRECT, caching is turned off for the open file instead
+    #[cfg(target_os = "macos")]
+    // SAFETY: the descriptor is open for as long as `file` is
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let measurement = read_sequential(&mut file, size, args.read_size)?;
+    print_measurement("mounted sequential", &measurement);
+    let measurement = read_random(&mut file, size, args.read_size, args.reads)?;
+    print_measurement("mounted random", &measurement);
+    Ok(())
+}
+
+fn bench(args: &BenchArgs) -> Result<(), Error> {
+    let mut image = Image::open(&args.path)?;
+    let size = image.disc_size()?;
+    let measurement = read_sequential(&mut image, size, args.read_size)?;
+    print_measurement("sequential", &measurement);
+    let measurement = read_random(&mut image, size, args.read_size, args.reads)?;
+    print_measurement("random", &measurement);
+    #[cfg(unix)]
+    if args.mount {
+        let disc = Disc::new(&mut image)?;
+        let dir = env::temp_dir().join(format!(".gcnfuse-bench-{}", process::id()));
+        fs::create_dir(&dir)?;
+        let result = bench_mounted(image, disc, &dir, args);
+        fs::remove_dir(&dir)?;
+        return result;
+    }
+    Ok(())
 }
 
-fn main() {
-    let args = Args::parse();
-    let file = File::open(args.path).expect("error opening file");
-    let mut file = Rvz::new(file).expect("error opening RVZ");
-    let disc = Disc::new(&mut file).unwrap();
-    let gcn_fuse = GcnFuse::new(file, disc);
-    let options = vec![MountOption::RO];
-    fuser::mount2(gcn_fuse, args.mount, &options).unwrap();
+fn main() -> ExitCode {
+    let args: Vec<_> = env::args_os().collect();
+    // mount only runs helpers on Unix
+    #[cfg(unix)]
+    let program = args.first().map(Path::new).and_then(Path::file_name);
+    #[cfg(unix)]
+    if program == Some(OsStr::new(&format!("mount.{SUBTYPE}"))) {
+        return match mount_helper(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Error: {err}");
+                ExitCode::from(MOUNT_HELPER_FAILURE)
+            }
+        };
+    }
+    let cli = Cli::parse_from(args);
+    if let Some(limit) = cli.cache_limit {
+        gcnfuse::set_cache_limit(limit);
+    }
+    if let Some(threads) = cli.threads {
+        gcnfuse::set_decompression_threads(usize::try_from(threads).unwrap_or(usize::MAX));
+    }
+    if let Some(dir) = cli.chunk_cache {
+        let Some(dir) = dir.or_else(gcnfuse::default_chunk_cache_dir) else {
+            Cli::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    "--chunk-cache needs a directory when neither XDG_CACHE_HOME nor HOME is set",
+                )
+                .exit();
+        };
+        gcnfuse::set_chunk_cache_dir(dir);
+    }
+    #[cfg(target_os = "linux")]
+    if cli.mlock
+        && let Err(err) = gcnfuse::lock_memory()
+    {
+        eprintln!("Error: can't lock memory: {err}");
+        return ExitCode::FAILURE;
+    }
+    let result = match cli.command {
+        #[cfg(unix)]
+        Command::Mount(args) => mount(args),
+        #[cfg(all(feature = "winfsp", windows))]
+        Command::Mount(args) => mount_winfsp(args),
+        #[cfg(unix)]
+        Command::Daemon(args) => daemon(args),
+        #[cfg(unix)]
+        Command::Unmount(args) => unmount(args),
+        #[cfg(unix)]
+        Command::Mounts(args) => mounts(args),
+        #[cfg(unix)]
+        Command::Automap(args) => automap(&args),
+        Command::Rebuild(args) => rebuild(args),
+        Command::Mkiso(args) => mkiso(args),
+        Command::Compress(args) => compress(&args, false),
+        Command::Recompress(args) => compress(&args, true),
+        Command::Scrub(args) => scrub(&args),
+        Command::ServeWebdav(args) => serve_webdav(args),
+        Command::ServeHttp(args) => serve_http(args),
+        Command::ServeFtp(args) => serve_ftp(args),
+        #[cfg(all(feature = "virtiofs", target_os = "linux"))]
+        Command::ServeVirtiofs(args) => serve_virtiofs(args),
+        Command::ServeNbd(args) => serve_nbd(&args),
+        #[cfg(all(feature = "ublk", target_os = "linux"))]
+        Command::ServeUblk(args) => serve_ublk(&args),
+        Command::Info(args) => info(&args),
+        Command::Diff(args) => diff(&args),
+        Command::Locate(args) => locate(&args),
+        Command::DedupReport(args) => dedup_report(&args),
+        Command::Extract(args) => extract(&args),
+        Command::Cat(args) => cat(&args),
+        Command::Find(args) => find(&args),
+        Command::Grep(args) => grep(&args),
+        Command::Fst(args) => fst(&args),
+        Command::VerifyAll(args) => verify_all(&args),
+        Command::Bench(args) => bench(&args),
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,236 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::dol;
+use crate::error::Error;
+use crate::layout;
+use crate::layout::FileInfo;
+use crate::layout::LayoutOptions;
+use crate::layout::SystemArea;
+use crate::options::Options;
+use crate::tree::FileData;
+use crate::tree::Inode;
+use crate::tree::Kind;
+use crate::tree::Tree;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Seek;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Magic word identifying Gamecube discs, found at 0x1C in the header.
+const GCN_MAGIC: u32 = 0xC233_9F3D;
+
+/// Describes the image to build with [`mkiso`].
+#[derive(Clone, Debug)]
+pub struct MkisoOptions {
+    /// Six character game ID: console, game code, country and maker code (e.g. `GHBE00`).
+    pub game_id: String,
+    /// The game name stored in the header.
+    pub title: String,
+    /// The boot DOL.
+    pub dol: PathBuf,
+    /// The apploader, including its 0x20 byte header.
+    pub apploader: PathBuf,
+    /// A bi2.bin to use, instead of generating a default one.
+    pub bi2: Option<PathBuf>,
+    /// How to lay out the files.
+    pub layout: LayoutOptions,
+}
+
+/// Returns a default bi2.bin for the given game ID.
+fn default_bi2(game_id: &str) -> Vec<u8> {
+    let mut bi2 = vec![0u8; layout::BI2_SIZE];
+    // Simulated memory size, 24 MiB like retail consoles
+    bi2[0x04..0x08].copy_from_slice(&0x0180_0000u32.to_be_bytes());
+    let country: u32 = match game_id.as_bytes()[3] {
+        b'J' => 0,
+        b'P' | b'D' | b'F' | b'S' | b'I' | b'U' => 2,
+        _ => 1,
+    };
+    bi2[0x18..0x1C].copy_from_slice(&country.to_be_bytes());
+    bi2
+}
+
+/// Builds the disc header (boot.bin), leaving the layout fields for [`layout::write_image`].
+fn header(options: &MkisoOptions) -> Vec<u8> {
+    let mut header = vec![0u8; layout::HEADER_SIZE];
+    header[0..6].copy_from_slice(options.game_id.as_bytes());
+    header[0x1C..0x20].copy_from_slice(&GCN_MAGIC.to_be_bytes());
+    let title = options.title.as_bytes();
+    header[0x20..0x20 + title.len()].copy_from_slice(title);
+    header
+}
+
+/// Builds a new Gamecube image from the contents of the host directory `dir`, writing it to
+/// `out`.
+///
+/// See [`layout::write_image`] for how the image is laid out. Directories are read sorted by
+/// name, whatever order the host lists them in, so directories with the same names and contents
+/// build the same image.
+///
+/// # Errors
+///
+/// [`Error::Layout`] if the game ID, title, apploader or DOL are invalid or the FST can't be
+/// built, [`Error::Overlay`] if `dir` can't be read, and [`Error::Io`] for errors reading the
+/// inputs or writing the new image.
+pub fn mkiso<W: Write + Seek>(
+    dir: &Path,
+    options: &MkisoOptions,
+    out: &mut W,
+) -> Result<(), Error> {
+    if options.game_id.len() != 6 || !options.game_id.bytes().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error::Layout(
+            "the game ID must be 6 alphanumeric ASCII characters".into(),
+        ));
+    }
+    if !options.title.is_ascii() || options.title.len() >= 0x3E0 {
+        return Err(Error::Layout(
+            "the title must be ASCII and shorter than 992 characters".into(),
+        ));
+    }
+    let apploader = fs::read(&options.apploader)?;
+    let truncated = apploader
+        .first_chunk::<0x20>()
+        .is_none_or(|header| layout::apploader_size_from_header(header) as usize > apploader.len());
+    if truncated {
+        return Err(Error::Layout("the apploader is truncated".into()));
+    }
+    let dol = fs::read(&options.dol)?;
+    if dol.len() < dol::HEADER_SIZE {
+        return Err(Error::Layout("the DOL is truncated".into()));
+    }
+    let bi2 = match &options.bi2 {
+        Some(path) => fs::read(path)?,
+        None => default_bi2(&options.game_id),
+    };
+
+    let mut tree = Tree::empty();
+    tree.overlay(Inode(1), dir, &Options::default())
+        .map_err(Error::Overlay)?;
+
+    let mut paths = HashMap::new();
+    let mut files = vec![];
+    for inode in layout::files(&tree) {
+        let Some(Kind::File(FileData::Host(path))) = tree.get(inode).map(|node| &node.kind) else {
+            unreachable!("trees built from host directories only have host files");
+        };
+        let size = u32::try_from(path.metadata()?.len())
+            .map_err(|_| Error::Layout(format!("{} is too large", path.display())))?;
+        files.push(FileInfo {
+            inode,
+            size,
+            original_offset: None,
+        });
+        paths.insert(inode, path.clone());
+    }
+    layout::order_files(&tree, &mut files, options.layout.order);
+
+    let system = SystemArea {
+        header: header(options),
+        bi2,
+        apploader,
+        dol,
+    };
+    layout::write_image(
+        out,
+        &system,
+        &tree,
+        &files,
+        &options.layout,
+        &mut |inode, out| {
+            io::copy(&mut File::open(&paths[&inode])?, out)?;
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    /// Returns an image built from `files`, `(path, contents)` pairs, with an empty DOL and
+    /// apploader, using `name` to keep the directories it's built from apart from other tests'.
+    pub fn image(name: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let base = env::temp_dir().join(format!(".gcnfuse-{name}-{}", process::id()));
+        let dir = base.join("files");
+        fs::create_dir_all(&dir).unwrap();
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::write(base.join("main.dol"), [0; dol::HEADER_SIZE]).unwrap();
+        fs::write(base.join("apploader.img"), [0; 0x20]).unwrap();
+        let options = MkisoOptions {
+            game_id: "GTST01".into(),
+            title: "Test".into(),
+            dol: base.join("main.dol"),
+            apploader: base.join("apploader.img"),
+            bi2: None,
+            layout: LayoutOptions {
+                alignment: 4,
+                ..LayoutOptions::default()
+            },
+        };
+        let mut out = io::Cursor::new(vec![]);
+        let built = mkiso(&dir, &options, &mut out);
+        let _ = fs::remove_dir_all(&base);
+        built.unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn reproducible() {
+        let files: &[(&str, &[u8])] = &[
+            ("a.txt", b"aaaa"),
+            ("b/c.bin", b"cc"),
+            ("b/d.bin", b"ddd"),
+            ("e/f/g.txt", b"g"),
+        ];
+        let base = env::temp_dir().join(format!(".gcnfuse-mkiso-reproducible-{}", process::id()));
+        // Written in opposite orders, with different times, so the host lists them differently
+        let built = [false, true].map(|reversed| {
+            let dir = base.join(format!("{reversed}"));
+            fs::create_dir_all(&dir).unwrap();
+            let mut order = files.to_vec();
+            if reversed {
+                order.reverse();
+            }
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(u64::from(reversed) << 30);
+            for (path, contents) in order {
+                let path = dir.join("files").join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, contents).unwrap();
+                File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(modified)
+                    .unwrap();
+            }
+            fs::write(dir.join("main.dol"), [0; dol::HEADER_SIZE]).unwrap();
+            fs::write(dir.join("apploader.img"), [0; 0x20]).unwrap();
+            let options = MkisoOptions {
+                game_id: "GTST01".into(),
+                title: "Test".into(),
+                dol: dir.join("main.dol"),
+                apploader: dir.join("apploader.img"),
+                bi2: None,
+                layout: LayoutOptions::default(),
+            };
+            let mut out = io::Cursor::new(vec![]);
+            mkiso(&dir.join("files"), &options, &mut out).map(|()| out.into_inner())
+        });
+        let _ = fs::remove_dir_all(&base);
+        let [first, second] = built.map(Result::unwrap);
+        assert!(first == second);
+    }
+}
@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use fuser::BackgroundSession;
+use std::io;
+
+/// A filesystem mounted and answered in the background, unmounted when the handle is joined or
+/// dropped.
+#[derive(Debug)]
+pub struct MountHandle {
+    session: Option<BackgroundSession>,
+}
+
+impl MountHandle {
+    pub(crate) const fn new(session: BackgroundSession) -> Self {
+        Self {
+            session: Some(session),
+        }
+    }
+
+    /// Returns whether the filesystem is still mounted, rather than unmounted from outside, such
+    /// as with `umount`.
+    #[must_use]
+    pub fn is_mounted(&self) -> bool {
+        self.session
+            .as_ref()
+            .is_some_and(|session| !session.guard.is_finished())
+    }
+
+    /// Unmounts the filesystem and waits for the thread answering it to finish.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if answering the kernel failed before the filesystem was unmounted.
+    pub fn join(mut self) -> io::Result<()> {
+        self.unmount()
+    }
+
+    /// Unmounts the filesystem, if it still is, and waits for the thread answering it.
+    fn unmount(&mut self) -> io::Result<()> {
+        // Everything but the thread is dropped right away, which unmounts and so ends the thread's
+        // loop
+        let Some(BackgroundSession { guard, .. }) = self.session.take() else {
+            return Ok(());
+        };
+        guard
+            .join()
+            .map_err(|_| io::Error::other("the thread answering the filesystem panicked"))?
+    }
+}
+
+impl Drop for MountHandle {
+    fn drop(&mut self) {
+        // Nothing is left to report errors to
+        let _ = self.unmount();
+    }
+}
@@ -0,0 +1,434 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::fuse;
+use crate::fuse::GcnFuse;
+use crate::interrupt;
+#[cfg(target_os = "linux")]
+use crate::sandbox::Sandbox;
+use crate::tree;
+use crate::tree::Inode;
+use fuser::FileAttr;
+use fuser::FileType;
+use fuser::Filesystem;
+use fuser::KernelConfig;
+use fuser::ReplyAttr;
+use fuser::ReplyCreate;
+use fuser::ReplyData;
+use fuser::ReplyDirectory;
+use fuser::ReplyEmpty;
+use fuser::ReplyEntry;
+use fuser::ReplyIoctl;
+use fuser::ReplyOpen;
+use fuser::ReplyWrite;
+use fuser::ReplyXattr;
+use fuser::Request;
+use fuser::TimeOrNow;
+use std::ffi::OsStr;
+use std::io::Read;
+use std::io::Seek;
+use std::os::raw::c_int;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// Bits of an inode number holding the inode within its disc.
+const DISC_SHIFT: u32 = 40;
+
+/// Several discs shown under one mount, each in a directory of its own, such as the discs of a
+/// multi-disc game.
+///
+/// The root directory is inode 1, and inode `n` of the `i`th disc is `(i + 1) << 40 | n`. The
+/// discs are always read-only.
+pub struct MultiDisc<T: Read + Seek> {
+    discs: Vec<(String, GcnFuse<T>)>,
+}
+
+/// Returns the index of the disc holding `ino` and its inode in that disc, or `None` for the
+/// root directory.
+const fn split(ino: u64) -> Option<(usize, Inode)> {
+    let disc = ino >> DISC_SHIFT;
+    if disc == 0 {
+        return None;
+    }
+    // There are far fewer discs than that
+    #[allow(clippy::cast_possible_truncation)]
+    Some(((disc - 1) as usize, Inode(ino & ((1 << DISC_SHIFT) - 1))))
+}
+
+/// Returns the inode number of `inode` in the disc at `index`.
+const fn join(index: usize, inode: Inode) -> u64 {
+    ((index as u64 + 1) << DISC_SHIFT) | inode.0
+}
+
+impl<T: Read + Seek> MultiDisc<T> {
+    /// Returns a filesystem showing each of `discs` in a directory with the given name. Repeated
+    /// names are renamed like duplicate FST entries.
+    #[must_use]
+    pub fn new(discs: Vec<(String, GcnFuse<T>)>) -> Self {
+        let mut named: Vec<(String, GcnFuse<T>)> = vec![];
+        for (name, disc) in discs {
+            let mut unique = name.clone();
+            let mut n = 2;
+            while named.iter().any(|(taken, _)| *taken == unique) {
+                unique = tree::disambiguate(&name, n);
+                n += 1;
+            }
+            named.push((unique, disc));
+        }
+        Self { discs: named }
+    }
+
+    /// Returns the sandbox letting every disc keep reading the host files it shows.
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn sandbox(&self) -> Sandbox {
+        let mut sandbox = Sandbox::default();
+        for (_, disc) in &self.discs {
+            let disc = disc.sandbox();
+            sandbox.readable.extend(disc.readable);
+            sandbox.writable.extend(disc.writable);
+        }
+        sandbox
+    }
+
+    /// Returns the attributes of the given inode, or `None` if it doesn't exist.
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let Some((index, inode)) = split(ino) else {
+            // The root directory looks like the first disc's
+            let mut attr = self.discs.first()?.1.get_attr(Inode(1))?;
+            attr.ino = 1;
+            // Fewer discs than that
+            #[allow(clippy::cast_possible_truncation)]
+            let discs = self.discs.len() as u32;
+            attr.nlink = 2 + discs;
+            return Some(attr);
+        };
+        let mut attr = self.discs.get(index)?.1.get_attr(inode)?;
+        attr.ino = ino;
+        Some(attr)
+    }
+
+    /// Returns the inode number of the directory holding `ino`.
+    fn parent(&self, ino: u64) -> Option<u64> {
+        let Some((index, inode)) = split(ino) else {
+            return Some(1);
+        };
+        match self.discs.get(index)?.1.parent(inode)? {
+            // The root directory of each disc is its own parent
+            parent if parent == inode => Some(1),
+            parent => Some(join(index, parent)),
+        }
+    }
+
+    /// Returns how long the kernel may cache attributes and entries, which is the same for every
+    /// disc.
+    fn ttl(&self) -> Duration {
+        self.discs
+            .first()
+            .map_or(Duration::from_secs(1), |(_, disc)| disc.ttl())
+    }
+
+    /// Returns the generation of the given inode, which is that of the disc holding it.
+    fn generation(&self, ino: u64) -> u64 {
+        split(ino)
+            .and_then(|(index, _)| self.discs.get(index))
+            .map_or(0, |(_, disc)| disc.generation())
+    }
+
+    /// Returns the inode number of the entry `name` of the directory `parent`, or `None` if
+    /// there's none, as [`GcnFuse::find`] does.
+    fn find(&self, parent: u64, name: &OsStr) -> Result<Option<u64>, c_int> {
+        let Some((index, inode)) = split(parent) else {
+            return Ok(match name.to_str() {
+                Some("." | "..") => Some(1),
+                name => (0..self.discs.len())
+                    .find(|&index| Some(self.discs[index].0.as_str()) == name)
+                    .map(|index| join(index, Inode(1))),
+            });
+        };
+        let (_, disc) = self.discs.get(index).ok_or(libc::ENOENT)?;
+        // The root directory of each disc is its own parent
+        if name == ".." {
+            return disc.find(inode, name).map(|_| self.parent(parent));
+        }
+        Ok(disc.find(inode, name)?.map(|child| join(index, child)))
+    }
+}
+
+impl<T: Read + Seek> Filesystem for MultiDisc<T> {
+    fn destroy(&mut self) {
+        for (_, disc) in &mut self.discs {
+            disc.destroy();
+        }
+    }
+
+    fn init(&mut self, req: &Request, config: &mut KernelConfig) -> Result<(), c_int> {
+        for (_, disc) in &mut self.discs {
+            disc.init(req, config)?;
+        }
+        Ok(())
+    }
+
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let ttl = self.ttl();
+        match self.find(parent, name) {
+            Ok(Some(ino)) => match self.attr(ino) {
+                Some(attr) => reply.entry(&ttl, &attr, self.generation(ino)),
+                None => reply.error(libc::ENOENT),
+            },
+            Ok(None) => fuse::reply_missing(&ttl, reply),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&self.ttl(), &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let value = split(ino).map_or(Ok(None), |(index, inode)| {
+            self.discs
+                .get_mut(index)
+                .map_or(Ok(None), |(_, disc)| disc.xattr(inode, name))
+        });
+        match value {
+            Ok(Some(value)) => fuse::reply_xattr(value.as_bytes(), size, reply),
+            Ok(None) => reply.error(fuse::ENOATTR),
+            Err(err) => reply.error(fuse::errno(&err)),
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        let mut names = vec![];
+        if let Some((index, inode)) = split(ino)
+            && let Some((_, disc)) = self.discs.get(index)
+        {
+            for name in disc.xattr_names(inode) {
+                names.extend_from_slice(name.as_bytes());
+                names.push(0);
+            }
+        }
+        fuse::reply_xattr(&names, size, reply);
+    }
+
+    fn opendir(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
+        // The discs never change, so the root directory needs no snapshot
+        let Some((index, inode)) = split(ino) else {
+            reply.opened(0, 0);
+            return;
+        };
+        match self
+            .discs
+            .get_mut(index)
+            .map(|(_, disc)| disc.open_dir(inode))
+        {
+            Some(Ok(fh)) => reply.opened(fh, 0),
+            Some(Err(err)) => reply.error(err),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, fh: u64, offset: i64, reply: ReplyDirectory) {
+        let Some((index, inode)) = split(ino) else {
+            let dots = [
+                (1, FileType::Directory, "."),
+                (1, FileType::Directory, ".."),
+            ];
+            let discs = (0..self.discs.len())
+                .zip(&self.discs)
+                .map(|(index, (name, _))| {
+                    (join(index, Inode(1)), FileType::Directory, name.as_str())
+                });
+            fuse::reply_listing(dots.into_iter().chain(discs), offset, reply);
+            return;
+        };
+        let Some(parent) = self.parent(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        match self.discs[index].1.listed(inode, fh) {
+            Ok(listing) => {
+                let entries = listing.iter().enumerate().map(|(i, (child, type_, name))| {
+                    // The root directory of each disc is its own parent
+                    let ino = if i == 1 { parent } else { join(index, *child) };
+                    (ino, *type_, name.as_str())
+                });
+                fuse::reply_listing(entries, offset, reply);
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn releasedir(&mut self, _req: &Request, ino: u64, fh: u64, _flags: i32, reply: ReplyEmpty) {
+        if let Some((index, _)) = split(ino)
+            && let Some((_, disc)) = self.discs.get_mut(index)
+        {
+            disc.close_dir(fh);
+        }
+        reply.ok();
+    }
+
+    fn open(&mut self, _req: &Request, _ino: u64, flags: i32, reply: ReplyOpen) {
+        if fuse::opens_for_writing(flags) {
+            reply.error(libc::EROFS);
+        } else {
+            reply.opened(0, 0);
+        }
+    }
+
+    fn read(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some((index, inode)) = split(ino) else {
+            reply.error(libc::EISDIR);
+            return;
+        };
+        let Some((_, disc)) = self.discs.get_mut(index) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let _serving = interrupt::serve(req.pid());
+        disc.reply_read(inode, offset, size, reply);
+    }
+
+    fn ioctl(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        _flags: u32,
+        cmd: u32,
+        _in_data: &[u8],
+        out_size: u32,
+        reply: ReplyIoctl,
+    ) {
+        let extent = split(ino).and_then(|(index, inode)| self.discs.get(index)?.1.extent(inode));
+        fuse::reply_extent(cmd, out_size, extent, reply);
+    }
+
+    // The discs are read-only, so everything changing them fails right away
+
+    fn setattr(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        _size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        reply.error(libc::EROFS);
+    }
+
+    fn mknod(
+        &mut self,
+        _req: &Request,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        reply.error(libc::EROFS);
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        reply.error(libc::EROFS);
+    }
+
+    fn unlink(&mut self, _req: &Request, _parent: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(libc::EROFS);
+    }
+
+    fn rmdir(&mut self, _req: &Request, _parent: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(libc::EROFS);
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request,
+        _parent: u64,
+        _name: &OsStr,
+        _newparent: u64,
+        _newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        reply.error(libc::EROFS);
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        reply.error(libc::EROFS);
+    }
+
+    // Only Linux mounts get the newer protocol it's part of
+    #[cfg(target_os = "linux")]
+    fn copy_file_range(
+        &mut self,
+        _req: &Request,
+        _ino_in: u64,
+        _fh_in: u64,
+        _offset_in: i64,
+        _ino_out: u64,
+        _fh_out: u64,
+        _offset_out: i64,
+        _len: u64,
+        _flags: u32,
+        reply: ReplyWrite,
+    ) {
+        reply.error(libc::EROFS);
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        reply.error(libc::EROFS);
+    }
+}
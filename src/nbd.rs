@@ -0,0 +1,308 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::http;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::thread;
+
+const NBD_MAGIC: &[u8; 8] = b"NBDMAGIC";
+const OPTION_MAGIC: &[u8; 8] = b"IHAVEOPT";
+const OPTION_REPLY_MAGIC: u64 = 0x0003_e889_0455_65a9;
+const REQUEST_MAGIC: u32 = 0x2560_9513;
+const SIMPLE_REPLY_MAGIC: u32 = 0x6744_6698;
+
+const FLAG_FIXED_NEWSTYLE: u16 = 1 << 0;
+const FLAG_NO_ZEROES: u16 = 1 << 1;
+
+const FLAG_HAS_FLAGS: u16 = 1 << 0;
+const FLAG_READ_ONLY: u16 = 1 << 1;
+const FLAG_SEND_FLUSH: u16 = 1 << 2;
+const FLAG_CAN_MULTI_CONN: u16 = 1 << 8;
+/// Flags of the export, which is read-only and the same for every connection.
+const TRANSMISSION_FLAGS: u16 =
+    FLAG_HAS_FLAGS | FLAG_READ_ONLY | FLAG_SEND_FLUSH | FLAG_CAN_MULTI_CONN;
+
+const OPT_EXPORT_NAME: u32 = 1;
+const OPT_ABORT: u32 = 2;
+const OPT_LIST: u32 = 3;
+const OPT_INFO: u32 = 6;
+const OPT_GO: u32 = 7;
+
+const REP_ACK: u32 = 1;
+const REP_SERVER: u32 = 2;
+const REP_INFO: u32 = 3;
+const REP_ERR_UNSUP: u32 = (1 << 31) | 1;
+const REP_ERR_INVALID: u32 = (1 << 31) | 3;
+
+const INFO_EXPORT: u16 = 0;
+const INFO_BLOCK_SIZE: u16 = 3;
+
+const CMD_READ: u16 = 0;
+const CMD_WRITE: u16 = 1;
+const CMD_DISC: u16 = 2;
+const CMD_FLUSH: u16 = 3;
+
+/// Longest option or write accepted, and largest read answered.
+const MAX_LENGTH: u32 = 32 << 20;
+
+/// Size of the discs' sectors, which reads are best aligned to.
+const SECTOR_SIZE: u32 = 0x8000;
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Fails for clients asking for more than is ever sent or accepted, which are dropped.
+fn check_length(length: u32) -> io::Result<usize> {
+    if length > MAX_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{length} bytes is too long"),
+        ));
+    }
+    Ok(length as usize)
+}
+
+/// Sends the reply of type `kind` to `option`, with `data`.
+fn reply_option(writer: &mut impl Write, option: u32, kind: u32, data: &[u8]) -> io::Result<()> {
+    writer.write_all(&OPTION_REPLY_MAGIC.to_be_bytes())?;
+    writer.write_all(&option.to_be_bytes())?;
+    writer.write_all(&kind.to_be_bytes())?;
+    // Never more than the longest option
+    #[allow(clippy::cast_possible_truncation)]
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(data)
+}
+
+/// Sends the information about the export asked for by `NBD_OPT_INFO` or `NBD_OPT_GO`, whose data
+/// is `data`, and whether it was valid.
+fn reply_info(writer: &mut impl Write, option: u32, data: &[u8], size: u64) -> io::Result<bool> {
+    let mut data = data;
+    let name_length = read_u32(&mut data).ok().map(|length| length as usize);
+    let Some(requests) = name_length
+        .and_then(|length| data.get(length..))
+        .and_then(|mut rest| {
+            let count = read_u16(&mut rest).ok()?;
+            (0..count)
+                .map(|_| read_u16(&mut rest).ok())
+                .collect::<Option<Vec<_>>>()
+        })
+    else {
+        reply_option(writer, option, REP_ERR_INVALID, b"malformed request")?;
+        return Ok(false);
+    };
+    let mut export = INFO_EXPORT.to_be_bytes().to_vec();
+    export.extend_from_slice(&size.to_be_bytes());
+    export.extend_from_slice(&TRANSMISSION_FLAGS.to_be_bytes());
+    reply_option(writer, option, REP_INFO, &export)?;
+    if requests.contains(&INFO_BLOCK_SIZE) {
+        let mut block_size = INFO_BLOCK_SIZE.to_be_bytes().to_vec();
+        for size in [1, SECTOR_SIZE, MAX_LENGTH] {
+            block_size.extend_from_slice(&size.to_be_bytes());
+        }
+        reply_option(writer, option, REP_INFO, &block_size)?;
+    }
+    reply_option(writer, option, REP_ACK, &[])?;
+    Ok(true)
+}
+
+/// Goes through the options the client sends, returning whether it picked the export rather
+/// than leaving.
+fn negotiate(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    size: u64,
+    zeroes: bool,
+) -> io::Result<bool> {
+    loop {
+        writer.flush()?;
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != OPTION_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected an option",
+            ));
+        }
+        let option = read_u32(reader)?;
+        let mut data = vec![0; check_length(read_u32(reader)?)?];
+        reader.read_exact(&mut data)?;
+        // There's a single export, whatever name it's asked for by
+        match option {
+            OPT_EXPORT_NAME => {
+                writer.write_all(&size.to_be_bytes())?;
+                writer.write_all(&TRANSMISSION_FLAGS.to_be_bytes())?;
+                if zeroes {
+                    writer.write_all(&[0; 124])?;
+                }
+                return Ok(true);
+            }
+            OPT_ABORT => {
+                reply_option(writer, option, REP_ACK, &[])?;
+                writer.flush()?;
+                return Ok(false);
+            }
+            OPT_LIST => {
+                reply_option(writer, option, REP_SERVER, &0u32.to_be_bytes())?;
+                reply_option(writer, option, REP_ACK, &[])?;
+            }
+            OPT_INFO | OPT_GO => {
+                if reply_info(writer, option, &data, size)? && option == OPT_GO {
+                    return Ok(true);
+                }
+            }
+            _ => reply_option(writer, option, REP_ERR_UNSUP, &[])?,
+        }
+    }
+}
+
+/// Sends the reply to the request `cookie`, with `error` or 0 for success.
+fn reply(writer: &mut impl Write, cookie: u64, error: i32) -> io::Result<()> {
+    writer.write_all(&SIMPLE_REPLY_MAGIC.to_be_bytes())?;
+    // Errors are positive errno values
+    #[allow(clippy::cast_sign_loss)]
+    writer.write_all(&(error as u32).to_be_bytes())?;
+    writer.write_all(&cookie.to_be_bytes())
+}
+
+/// Reads `length` bytes of `image` at `offset`, or returns the error to reply with.
+fn read_image<T: Read + Seek>(
+    image: &Mutex<T>,
+    size: u64,
+    offset: u64,
+    length: u32,
+) -> Result<Vec<u8>, i32> {
+    if length > MAX_LENGTH {
+        return Err(libc::EOVERFLOW);
+    }
+    if offset
+        .checked_add(length.into())
+        .is_none_or(|end| end > size)
+    {
+        return Err(libc::EINVAL);
+    }
+    let mut data = vec![0; length as usize];
+    // A thread that panicked while reading left nothing that matters, as every read seeks
+    let result = {
+        let mut image = image
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        image
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| image.read_exact(&mut data))
+    };
+    result.map_err(|err| {
+        eprintln!("can't read the image at {offset:#x}: {err}");
+        libc::EIO
+    })?;
+    Ok(data)
+}
+
+/// Answers the client's requests until it disconnects.
+fn transmit<T: Read + Seek>(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    image: &Mutex<T>,
+    size: u64,
+) -> io::Result<()> {
+    loop {
+        writer.flush()?;
+        if read_u32(reader)? != REQUEST_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a request",
+            ));
+        }
+        let _flags = read_u16(reader)?;
+        let kind = read_u16(reader)?;
+        let cookie = read_u64(reader)?;
+        let offset = read_u64(reader)?;
+        let length = read_u32(reader)?;
+        match kind {
+            CMD_READ => match read_image(image, size, offset, length) {
+                Ok(data) => {
+                    reply(writer, cookie, 0)?;
+                    writer.write_all(&data)?;
+                }
+                Err(error) => reply(writer, cookie, error)?,
+            },
+            CMD_WRITE => {
+                // The data comes along anyway, and has to be skipped
+                let length = check_length(length)?;
+                io::copy(&mut reader.take(length as u64), &mut io::sink())?;
+                reply(writer, cookie, libc::EPERM)?;
+            }
+            CMD_DISC => return Ok(()),
+            CMD_FLUSH => reply(writer, cookie, 0)?,
+            _ => reply(writer, cookie, libc::EINVAL)?,
+        }
+    }
+}
+
+fn connection<T: Read + Seek>(stream: &TcpStream, image: &Mutex<T>, size: u64) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream);
+    let mut writer = BufWriter::new(stream);
+    writer.write_all(NBD_MAGIC)?;
+    writer.write_all(OPTION_MAGIC)?;
+    writer.write_all(&(FLAG_FIXED_NEWSTYLE | FLAG_NO_ZEROES).to_be_bytes())?;
+    writer.flush()?;
+    let client_flags = read_u32(&mut reader)?;
+    // Clients that don't ask for it get the padding after the export's flags the first version
+    // of the protocol had
+    let zeroes = client_flags & u32::from(FLAG_NO_ZEROES) == 0;
+    if negotiate(&mut reader, &mut writer, size, zeroes)? {
+        transmit(&mut reader, &mut writer, image, size)?;
+    }
+    Ok(())
+}
+
+/// Serves the `size` bytes of the disc image read from `image` as a read-only network block
+/// device on `listen`, an address like `0.0.0.0:10809`.
+///
+/// It can be attached with `nbd-client` or QEMU. This never returns unless the address can't be
+/// listened on. Each client is served from its own thread, whatever export name it asks for.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the address can't be listened on.
+pub fn serve_nbd<T: Read + Seek + Send>(image: T, size: u64, listen: &str) -> Result<(), Error> {
+    let listener = http::bind(listen)?;
+    let image = Mutex::new(image);
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let image = &image;
+                    // Clients drop connections all the time, so errors just end the session
+                    scope.spawn(move || connection(&stream, image, size).ok());
+                }
+                Err(err) => eprintln!("unable to accept a connection: {err}"),
+            }
+        }
+    });
+    Ok(())
+}
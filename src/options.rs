@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use clap::ValueEnum;
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode normalization form applied to filenames when comparing them during lookups.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Normalization {
+    /// Canonical composition (what most Linux and Windows clients send).
+    Nfc,
+    /// Canonical decomposition (what macOS and some SMB stacks send).
+    Nfd,
+}
+
+impl Normalization {
+    /// Returns the given name normalized to this form.
+    #[must_use]
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            Self::Nfc => name.nfc().collect(),
+            Self::Nfd => name.nfd().collect(),
+        }
+    }
+}
+
+/// Order directory entries are listed in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Sort {
+    /// The order of the FST, which is also the order files are laid out in on most discs.
+    #[default]
+    Fst,
+    /// By name, byte by byte.
+    Name,
+}
+
+/// How problems in a disc's FST, like directories ending past their parent or names that can't
+/// be read, are handled.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Problems are reported, and only those that would keep the disc from being listed are
+    /// fixed, such as by renaming entries with invalid names.
+    #[default]
+    Default,
+    /// Any problem is an error.
+    Strict,
+    /// Problems are reported and worked around as well as possible, also cutting off files that go
+    /// past the end of the image, and reading what there is of discs whose header or FST don't
+    /// follow the format, such as Datel's.
+    Lenient,
+}
+
+/// Runtime options controlling how the disc is exposed through FUSE.
+// These are independent switches from the command line, not a state machine
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// If set, both stored and requested names are normalized to this form before comparing them
+    /// in `lookup`.
+    pub normalization: Option<Normalization>,
+    /// Host directory whose contents are shown on top of the disc's, replacing disc files with
+    /// the same path.
+    pub overlay: Option<PathBuf>,
+    /// Whether changes made through the mount are allowed. They are written to
+    /// [`Options::overlay`], which is required for this, and the disc image is never modified.
+    pub writable: bool,
+    /// Whether RARC and U8 archives are shown as directories with their contents, with the
+    /// archive itself renamed to `name.raw`. Mounts with this set are always read-only.
+    pub expand_archives: bool,
+    /// Whether Yaz0 and Yay0 compressed files are shown decompressed. Mounts with this set are
+    /// always read-only.
+    pub decompress: bool,
+    /// Whether DSP and ADP audio files get a `name.wav` sibling with the audio converted to WAV.
+    /// Mounts with this set are always read-only.
+    pub wav: bool,
+    /// Whether the streaming audio track of discs that use audio streaming is shown as
+    /// `stream.adp` in the root directory. Mounts with this set are always read-only.
+    pub dtk: bool,
+    /// Whether the root directory gets a `.meta` directory with virtual files describing the
+    /// disc, such as the boot DOL as an ELF file. Mounts with this set are always read-only.
+    pub meta: bool,
+    /// Directory with cover art named by game ID (`GAMEID.png`), shown as `.meta/cover.png`.
+    pub covers: Option<PathBuf>,
+    /// Whether covers missing from [`Options::covers`] are downloaded from `GameTDB` and saved
+    /// there, or in the user's cache directory if no cover directory is given.
+    pub online: bool,
+    /// Time to report for every entry's timestamps. If unset, the apploader's build date is used,
+    /// or the UNIX epoch if it doesn't have a valid one.
+    pub mtime: Option<SystemTime>,
+    /// Whether the mount is meant to be exported over NFS. This keeps inode numbers and
+    /// generations stable across remounts of the same image, supports looking up inodes from
+    /// NFS file handles, and lets the kernel cache entries for longer. Mounts with this set are
+    /// always read-only.
+    pub export: bool,
+    /// Whether all file data is read when the image is opened, and the files with data that
+    /// can't be read are listed in `.gcnfuse/damage.txt`. Mounts with this set are always
+    /// read-only.
+    pub damage_report: bool,
+    /// Whether the order FST files are read in is learned, kept across mounts, and used to read
+    /// the file likely read next in the background.
+    pub prefetch: bool,
+    /// How much the kernel may read ahead of reads of a file, in bytes. It can only be lowered
+    /// from what the kernel offers here.
+    pub max_readahead: Option<u32>,
+    /// Block size reported for files, in bytes, which sizes reads of tools like `cp`. By default
+    /// the disc's 32 KiB sectors.
+    pub block_size: Option<u32>,
+    /// Whether files of compressed images report the blocks their share of the image's file takes
+    /// up rather than their size, so `du` shows how much of the image they take up. Files that
+    /// aren't stored in the image as they're shown report their size.
+    pub compressed_blocks: bool,
+    /// How problems found in the disc's FST are handled.
+    pub strictness: Strictness,
+    /// Order directory entries are listed in. Files added by the overlay follow the disc's in FST
+    /// order.
+    pub sort: Sort,
+}
+
+impl Options {
+    /// Returns the name normalized per [`Options::normalization`], or unchanged if no
+    /// normalization was requested.
+    #[must_use]
+    pub fn normalize<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        self.normalization
+            .map_or(Cow::Borrowed(name), |form| Cow::Owned(form.apply(name)))
+    }
+
+    /// Returns whether files are shown differently than they are stored, or virtual files are
+    /// added, which makes the mount read-only.
+    #[must_use]
+    pub const fn unpacks_files(&self) -> bool {
+        self.expand_archives
+            || self.decompress
+            || self.wav
+            || self.dtk
+            || self.meta
+            || self.export
+            || self.damage_report
+    }
+}
@@ -0,0 +1,808 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: u32 = 0x45_4F46;
+const BPS_MAGIC: &[u8] = b"BPS1";
+const VCDIFF_MAGIC: &[u8] = &[0xD6, 0xC3, 0xC4, 0x00];
+const VCD_SOURCE: u8 = 0x01;
+const VCD_TARGET: u8 = 0x02;
+const VCD_ADLER32: u8 = 0x04;
+
+/// Where the bytes of a range of the patched image come from.
+#[derive(Copy, Clone, Debug)]
+enum Source {
+    /// The original image, starting at the given offset.
+    Original(u64),
+    /// The patch file, starting at the given offset.
+    Patch(usize),
+    /// A single repeated byte.
+    Fill(u8),
+    /// The patched image itself, starting at the given offset. The offset is always before the
+    /// range, but the two may overlap, in which case the bytes between them repeat.
+    Target(u64),
+}
+
+impl Source {
+    /// Returns the source for the bytes starting `offset` bytes into this one.
+    const fn at(self, offset: u64) -> Self {
+        match self {
+            Self::Original(start) => Self::Original(start + offset),
+            // Offsets into the patch are always within the patch's size, which is a usize
+            #[allow(clippy::cast_possible_truncation)]
+            Self::Patch(start) => Self::Patch(start + offset as usize),
+            Self::Fill(byte) => Self::Fill(byte),
+            Self::Target(start) => Self::Target(start + offset),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Segment {
+    len: u64,
+    source: Source,
+}
+
+fn invalid(format: &str) -> Error {
+    Error::Patch(format!("the {format} patch is truncated or corrupt"))
+}
+
+/// Cursor over the bytes of a patch.
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+    format: &'static str,
+}
+
+impl<'a> Cursor<'a> {
+    const fn new(data: &'a [u8], position: usize, format: &'static str) -> Self {
+        Self {
+            data,
+            position,
+            format,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| invalid(self.format))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn be(&mut self, len: usize) -> Result<u32, Error> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | u32::from(byte)))
+    }
+
+    /// Reads a BPS variable length number, least significant group first.
+    fn bps_number(&mut self) -> Result<u64, Error> {
+        let mut value: u64 = 0;
+        let mut shift: u64 = 1;
+        loop {
+            let byte = self.byte()?;
+            value = u64::from(byte & 0x7F)
+                .checked_mul(shift)
+                .and_then(|group| value.checked_add(group))
+                .ok_or_else(|| invalid(self.format))?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or_else(|| invalid(self.format))?;
+            value = value
+                .checked_add(shift)
+                .ok_or_else(|| invalid(self.format))?;
+        }
+    }
+
+    /// Reads a VCDIFF variable length number, most significant group first.
+    fn vcdiff_number(&mut self) -> Result<u64, Error> {
+        let mut value: u64 = 0;
+        loop {
+            let byte = self.byte()?;
+            if value.leading_zeros() < 7 {
+                return Err(invalid(self.format));
+            }
+            value = (value << 7) | u64::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn vcdiff_len(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.vcdiff_number()?).map_err(|_| invalid(self.format))
+    }
+}
+
+/// Description of the patched image as ranges of the original image, the patch and itself.
+struct Map {
+    segments: BTreeMap<u64, Segment>,
+    size: u64,
+    format: &'static str,
+}
+
+impl Map {
+    const fn new(format: &'static str) -> Self {
+        Self {
+            segments: BTreeMap::new(),
+            size: 0,
+            format,
+        }
+    }
+
+    /// Appends `len` bytes from `source` to the end of the patched image, failing if the image
+    /// would be too large.
+    fn push(&mut self, len: u64, source: Source) -> Result<(), Error> {
+        if len != 0 {
+            let size = self
+                .size
+                .checked_add(len)
+                .ok_or_else(|| invalid(self.format))?;
+            self.segments.insert(self.size, Segment { len, source });
+            self.size = size;
+        }
+        Ok(())
+    }
+
+    /// Makes sure a segment starts at `position`, splitting the one containing it if needed.
+    fn split(&mut self, position: u64) {
+        let Some((&start, &segment)) = self.segments.range(..position).next_back() else {
+            return;
+        };
+        let offset = position - start;
+        if offset < segment.len {
+            self.segments.insert(
+                start,
+                Segment {
+                    len: offset,
+                    source: segment.source,
+                },
+            );
+            self.segments.insert(
+                position,
+                Segment {
+                    len: segment.len - offset,
+                    source: segment.source.at(offset),
+                },
+            );
+        }
+    }
+
+    /// Grows or shrinks the image to `size`, filling any new space with zeroes.
+    fn resize(&mut self, size: u64) -> Result<(), Error> {
+        if size > self.size {
+            self.push(size - self.size, Source::Fill(0))?;
+        } else {
+            self.split(size);
+            self.segments.split_off(&size);
+            self.size = size;
+        }
+        Ok(())
+    }
+
+    /// Replaces `len` bytes at `position` with bytes from `source`, growing the image if needed.
+    fn overwrite(&mut self, position: u64, len: u64, source: Source) -> Result<(), Error> {
+        if len == 0 {
+            return Ok(());
+        }
+        let end = position
+            .checked_add(len)
+            .ok_or_else(|| invalid(self.format))?;
+        if end > self.size {
+            self.resize(end)?;
+        }
+        self.split(position);
+        self.split(end);
+        let replaced: Vec<u64> = self
+            .segments
+            .range(position..end)
+            .map(|(&start, _)| start)
+            .collect();
+        for start in replaced {
+            self.segments.remove(&start);
+        }
+        self.segments.insert(position, Segment { len, source });
+        Ok(())
+    }
+}
+
+fn parse_ips(patch: &[u8], original_size: u64) -> Result<Map, Error> {
+    let mut cursor = Cursor::new(patch, IPS_MAGIC.len(), "IPS");
+    let mut map = Map::new("IPS");
+    map.push(original_size, Source::Original(0))?;
+    loop {
+        let offset = cursor.be(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = u64::from(offset);
+        let len = cursor.be(2)?;
+        if len == 0 {
+            let len = cursor.be(2)?;
+            let byte = cursor.byte()?;
+            map.overwrite(offset, len.into(), Source::Fill(byte))?;
+        } else {
+            let start = cursor.position;
+            cursor.take(len as usize)?;
+            map.overwrite(offset, len.into(), Source::Patch(start))?;
+        }
+    }
+    // Some patchers append the size to truncate the image to after the end marker
+    if let Ok(size) = cursor.be(3) {
+        map.resize(size.into())?;
+    }
+    Ok(map)
+}
+
+fn parse_bps(patch: &[u8], original_size: u64) -> Result<Map, Error> {
+    const FOOTER_SIZE: usize = 12;
+    let format = "BPS";
+    let actions_end = patch
+        .len()
+        .checked_sub(FOOTER_SIZE)
+        .ok_or_else(|| invalid(format))?;
+    let (body, footer) = patch.split_at(actions_end);
+    let crc = u32::from_le_bytes([footer[8], footer[9], footer[10], footer[11]]);
+    if crc32(body, &footer[..8]) != crc {
+        return Err(Error::Patch(
+            "the BPS patch's checksum doesn't match".into(),
+        ));
+    }
+
+    let mut cursor = Cursor::new(body, BPS_MAGIC.len(), format);
+    let source_size = cursor.bps_number()?;
+    if source_size != original_size {
+        return Err(Error::Patch(format!(
+            "the BPS patch is for a {source_size} byte image, but the image is {original_size} \
+             bytes"
+        )));
+    }
+    let target_size = cursor.bps_number()?;
+    let metadata_size = usize::try_from(cursor.bps_number()?).map_err(|_| invalid(format))?;
+    cursor.take(metadata_size)?;
+
+    let mut map = Map::new(format);
+    let mut source_offset: u64 = 0;
+    let mut target_offset: u64 = 0;
+    let relative = |offset: u64, cursor: &mut Cursor| -> Result<u64, Error> {
+        let delta = cursor.bps_number()?;
+        let offset = if delta & 1 == 0 {
+            offset.checked_add(delta >> 1)
+        } else {
+            offset.checked_sub(delta >> 1)
+        };
+        offset.ok_or_else(|| invalid(format))
+    };
+    // Reads of the original image have to be within it
+    let original = |offset: u64, len: u64| {
+        offset
+            .checked_add(len)
+            .filter(|&end| end <= original_size)
+            .map(|_| Source::Original(offset))
+            .ok_or_else(|| invalid(format))
+    };
+    while cursor.position < body.len() {
+        let action = cursor.bps_number()?;
+        let len = (action >> 2) + 1;
+        let source = match action & 3 {
+            0 => original(map.size, len)?,
+            1 => {
+                let start = cursor.position;
+                cursor.take(usize::try_from(len).map_err(|_| invalid(format))?)?;
+                Source::Patch(start)
+            }
+            2 => {
+                source_offset = relative(source_offset, &mut cursor)?;
+                let source = original(source_offset, len)?;
+                source_offset += len;
+                source
+            }
+            _ => {
+                target_offset = relative(target_offset, &mut cursor)?;
+                if target_offset >= map.size {
+                    return Err(invalid(format));
+                }
+                let source = Source::Target(target_offset);
+                target_offset = target_offset
+                    .checked_add(len)
+                    .ok_or_else(|| invalid(format))?;
+                source
+            }
+        };
+        map.push(len, source)?;
+    }
+    if map.size != target_size {
+        return Err(invalid(format));
+    }
+    Ok(map)
+}
+
+/// CRC-32 (as used by zip and BPS) of `data` followed by `rest`.
+fn crc32(data: &[u8], rest: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data.iter().chain(rest) {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Instruction {
+    Noop,
+    Add,
+    Run,
+    Copy(u8),
+}
+
+/// Returns the VCDIFF default code table, as pairs of (instruction, size).
+fn vcdiff_code_table() -> Vec<[(Instruction, u8); 2]> {
+    use Instruction::Add;
+    use Instruction::Copy;
+    use Instruction::Noop;
+    use Instruction::Run;
+
+    let mut table = vec![[(Run, 0), (Noop, 0)]];
+    table.extend((0..18).map(|size| [(Add, size), (Noop, 0)]));
+    for mode in 0..9 {
+        table.push([(Copy(mode), 0), (Noop, 0)]);
+        table.extend((4..19).map(|size| [(Copy(mode), size), (Noop, 0)]));
+    }
+    for mode in 0..6 {
+        for add in 1..5 {
+            table.extend((4..7).map(|size| [(Add, add), (Copy(mode), size)]));
+        }
+    }
+    for mode in 6..9 {
+        table.extend((1..5).map(|add| [(Add, add), (Copy(mode), 4)]));
+    }
+    table.extend((0..9).map(|mode| [(Copy(mode), 4), (Add, 1)]));
+    table
+}
+
+/// The VCDIFF address cache, with the default 4 near and 3 same slots.
+struct AddressCache {
+    near: [u64; 4],
+    next: usize,
+    same: [u64; 3 * 256],
+}
+
+impl AddressCache {
+    const fn new() -> Self {
+        Self {
+            near: [0; 4],
+            next: 0,
+            same: [0; 3 * 256],
+        }
+    }
+
+    fn decode(&mut self, here: u64, mode: u8, addresses: &mut Cursor) -> Result<u64, Error> {
+        let address = match mode {
+            0 => addresses.vcdiff_number()?,
+            1 => here
+                .checked_sub(addresses.vcdiff_number()?)
+                .ok_or_else(|| invalid(addresses.format))?,
+            2..=5 => self.near[usize::from(mode - 2)]
+                .checked_add(addresses.vcdiff_number()?)
+                .ok_or_else(|| invalid(addresses.format))?,
+            _ => self.same[usize::from(mode - 6) * 256 + usize::from(addresses.byte()?)],
+        };
+        if address >= here {
+            return Err(invalid(addresses.format));
+        }
+        self.near[self.next] = address;
+        self.next = (self.next + 1) % self.near.len();
+        // The remainder is always less than the table's size
+        #[allow(clippy::cast_possible_truncation)]
+        let slot = (address % self.same.len() as u64) as usize;
+        self.same[slot] = address;
+        Ok(address)
+    }
+}
+
+/// The part of the original or patched image a VCDIFF window copies from.
+struct SourceSegment {
+    len: u64,
+    position: u64,
+    from_target: bool,
+}
+
+/// Decodes the instructions of a VCDIFF window, appending the target window to `map`.
+fn decode_window(
+    map: &mut Map,
+    table: &[[(Instruction, u8); 2]],
+    source: &SourceSegment,
+    data: &mut Cursor,
+    instructions: &mut Cursor,
+    addresses: &mut Cursor,
+) -> Result<(), Error> {
+    let format = data.format;
+    let window_start = map.size;
+    let mut cache = AddressCache::new();
+    while instructions.position < instructions.data.len() {
+        let entry = table[usize::from(instructions.byte()?)];
+        for (instruction, size) in entry {
+            if instruction == Instruction::Noop {
+                continue;
+            }
+            let len = if size == 0 {
+                instructions.vcdiff_number()?
+            } else {
+                size.into()
+            };
+            match instruction {
+                Instruction::Noop => {}
+                Instruction::Add => {
+                    let start = data.position;
+                    data.take(usize::try_from(len).map_err(|_| invalid(format))?)?;
+                    map.push(len, Source::Patch(start))?;
+                }
+                Instruction::Run => map.push(len, Source::Fill(data.byte()?))?,
+                Instruction::Copy(mode) => {
+                    let here = source.len + map.size - window_start;
+                    let address = cache.decode(here, mode, addresses)?;
+                    // Copies can start in the source segment and run into the target window
+                    let from_source = len.min(source.len.saturating_sub(address));
+                    if from_source != 0 {
+                        let start = source.position + address;
+                        let from = if source.from_target {
+                            Source::Target(start)
+                        } else {
+                            Source::Original(start)
+                        };
+                        map.push(from_source, from)?;
+                    }
+                    if len > from_source {
+                        let target = address + from_source - source.len;
+                        map.push(len - from_source, Source::Target(window_start + target))?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_vcdiff(patch: &[u8], original_size: u64) -> Result<Map, Error> {
+    let format = "xdelta";
+    let mut cursor = Cursor::new(patch, VCDIFF_MAGIC.len(), format);
+    let indicator = cursor.byte()?;
+    if indicator & 0x02 != 0 {
+        return Err(Error::Patch(
+            "xdelta patches with custom code tables aren't supported".into(),
+        ));
+    }
+    if indicator & 0x01 != 0 {
+        // Secondary compressor ID, only a problem if a window actually uses it
+        cursor.byte()?;
+    }
+    if indicator & 0x04 != 0 {
+        // xdelta3 application header, holding the original file names
+        let len = cursor.vcdiff_len()?;
+        cursor.take(len)?;
+    }
+
+    let table = vcdiff_code_table();
+    let mut map = Map::new(format);
+    while cursor.position < patch.len() {
+        let window = cursor.byte()?;
+        let (source_len, source_position) = if window & (VCD_SOURCE | VCD_TARGET) != 0 {
+            (cursor.vcdiff_number()?, cursor.vcdiff_number()?)
+        } else {
+            (0, 0)
+        };
+        let from_target = window & VCD_TARGET != 0;
+        let source_limit = if from_target { map.size } else { original_size };
+        if source_position
+            .checked_add(source_len)
+            .is_none_or(|end| end > source_limit)
+        {
+            return Err(invalid(format));
+        }
+
+        let _delta_len = cursor.vcdiff_number()?;
+        let target_len = cursor.vcdiff_number()?;
+        if cursor.byte()? != 0 {
+            return Err(Error::Patch(
+                "xdelta patches with secondary compression aren't supported, recreate the patch \
+                 with -S none"
+                    .into(),
+            ));
+        }
+        let data_len = cursor.vcdiff_len()?;
+        let instructions_len = cursor.vcdiff_len()?;
+        let addresses_len = cursor.vcdiff_len()?;
+        if window & VCD_ADLER32 != 0 {
+            // xdelta3 Adler-32 checksum of the target window
+            cursor.take(4)?;
+        }
+        let data_start = cursor.position;
+        cursor.take(data_len)?;
+        let mut data = Cursor::new(&patch[..cursor.position], data_start, format);
+        let instructions_start = cursor.position;
+        cursor.take(instructions_len)?;
+        let mut instructions = Cursor::new(&patch[..cursor.position], instructions_start, format);
+        let addresses_start = cursor.position;
+        cursor.take(addresses_len)?;
+        let mut addresses = Cursor::new(&patch[..cursor.position], addresses_start, format);
+
+        let window_start = map.size;
+        let source = SourceSegment {
+            len: source_len,
+            position: source_position,
+            from_target,
+        };
+        decode_window(
+            &mut map,
+            &table,
+            &source,
+            &mut data,
+            &mut instructions,
+            &mut addresses,
+        )?;
+        if map.size - window_start != target_len {
+            return Err(invalid(format));
+        }
+    }
+    Ok(map)
+}
+
+/// A disc image with an IPS, BPS or xdelta (VCDIFF) patch applied on the fly.
+///
+/// The patch is only parsed into a map of where each range of the patched image comes from, so
+/// reads of unpatched regions go straight to the original image.
+pub struct Patched<T> {
+    inner: T,
+    patch: Vec<u8>,
+    map: Map,
+    position: u64,
+}
+
+impl<T: Read + Seek> Patched<T> {
+    /// Applies `patch` to the image `inner`, whose size is `inner_size`.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Patch`] if the patch's format isn't recognised, the patch is corrupt, or it is
+    /// for a different image.
+    pub fn new(inner: T, inner_size: u64, patch: Vec<u8>) -> Result<Self, Error> {
+        let map = if patch.starts_with(IPS_MAGIC) {
+            parse_ips(&patch, inner_size)?
+        } else if patch.starts_with(BPS_MAGIC) {
+            parse_bps(&patch, inner_size)?
+        } else if patch.starts_with(VCDIFF_MAGIC) {
+            parse_vcdiff(&patch, inner_size)?
+        } else {
+            return Err(Error::Patch(
+                "unrecognised patch format, expected IPS, BPS or xdelta".into(),
+            ));
+        };
+        Ok(Self {
+            inner,
+            patch,
+            map,
+            position: 0,
+        })
+    }
+
+    /// Returns the size of the patched image.
+    pub const fn size(&self) -> u64 {
+        self.map.size
+    }
+
+    /// Returns the image the patch is applied to.
+    pub const fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the patch, as it was read.
+    pub fn patch(&self) -> &[u8] {
+        &self.patch
+    }
+}
+
+impl<T: Read + Seek> Read for Patched<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.map.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let mut position = self.position;
+        let mut len = (buf.len() as u64).min(self.map.size - position);
+        // Follow references to earlier parts of the patched image until reaching actual data
+        let source = loop {
+            let Some((&start, segment)) = self.map.segments.range(..=position).next_back() else {
+                unreachable!("segments cover the whole patched image");
+            };
+            let offset = position - start;
+            len = len.min(segment.len - offset);
+            match segment.source {
+                Source::Target(from) => {
+                    let distance = start - from;
+                    let offset = offset % distance;
+                    len = len.min(distance - offset);
+                    position = from + offset;
+                }
+                source => break source.at(offset),
+            }
+        };
+
+        // len is at most buf.len()
+        #[allow(clippy::cast_possible_truncation)]
+        let buf = &mut buf[..len as usize];
+        let read = match source {
+            Source::Original(offset) => {
+                self.inner.seek(SeekFrom::Start(offset))?;
+                self.inner.read(buf)?
+            }
+            Source::Patch(offset) => {
+                buf.copy_from_slice(&self.patch[offset..offset + buf.len()]);
+                buf.len()
+            }
+            Source::Fill(byte) => {
+                buf.fill(byte);
+                buf.len()
+            }
+            Source::Target(_) => unreachable!("target references are resolved above"),
+        };
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: Read + Seek> Seek for Patched<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.map.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &[u8] = b"0123456789abcdef";
+
+    fn apply(patch: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let inner = io::Cursor::new(ORIGINAL.to_vec());
+        let mut patched = Patched::new(inner, ORIGINAL.len() as u64, patch)?;
+        let mut contents = vec![];
+        patched.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Encodes a BPS variable length number.
+    fn bps_number(mut value: u64) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            let group = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | group);
+                return bytes;
+            }
+            bytes.push(group);
+            value -= 1;
+        }
+    }
+
+    /// Returns a BPS patch from the original to a `target_size` byte image made by `actions`.
+    fn bps(target_size: u64, actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend(bps_number(ORIGINAL.len() as u64));
+        patch.extend(bps_number(target_size));
+        patch.extend(bps_number(0));
+        patch.extend(actions);
+        patch.extend([0; 8]);
+        let crc = crc32(&patch, &[]);
+        patch.extend(crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn ips() {
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend([0, 0, 2, 0, 3]);
+        patch.extend(b"abc");
+        // Filled, running past the end
+        patch.extend([0, 0, 14, 0, 0, 0, 4, b'z']);
+        patch.extend(b"EOF");
+        // Truncated afterwards
+        patch.extend([0, 0, 17]);
+        assert_eq!(apply(patch).unwrap(), b"01abc56789abcdzzz");
+    }
+
+    #[test]
+    fn bps_actions() {
+        let mut actions = vec![];
+        // Source read
+        actions.extend(bps_number(3 << 2));
+        // Target read
+        actions.extend(bps_number((3 << 2) | 1));
+        actions.extend(b"wxyz");
+        // Source copy, 10 bytes on
+        actions.extend(bps_number((2 << 2) | 2));
+        actions.extend(bps_number(10 << 1));
+        // Target copy overlapping itself, 9 bytes on
+        actions.extend(bps_number((5 << 2) | 3));
+        actions.extend(bps_number(9 << 1));
+        assert_eq!(apply(bps(17, &actions)).unwrap(), b"0123wxyzabcbcbcbc");
+    }
+
+    #[test]
+    fn bps_checksum() {
+        let mut patch = bps(4, &bps_number(3 << 2));
+        *patch.last_mut().unwrap() ^= 1;
+        assert!(apply(patch).is_err());
+    }
+
+    #[test]
+    fn bps_past_original() {
+        // Source reads past the end of the original
+        let len = 1 << 40;
+        assert!(apply(bps(len, &bps_number((len - 1) << 2))).is_err());
+        // Source copies past the end of the original
+        let mut actions = bps_number(((len - 1) << 2) | 2);
+        actions.extend(bps_number(0));
+        assert!(apply(bps(len, &actions)).is_err());
+        // Source copies that would overflow
+        let mut actions = bps_number(2);
+        actions.extend(bps_number(u64::MAX - 1));
+        assert!(apply(bps(1, &actions)).is_err());
+    }
+
+    #[test]
+    fn vcdiff() {
+        let data = b"XYZ!";
+        // Add 3, run of 5, copy 6 from the source, copy 4 from the target
+        let instructions = [4, 0, 5, 22, 20];
+        let addresses = [4, 16];
+        let mut patch = VCDIFF_MAGIC.to_vec();
+        patch.push(0);
+        patch.extend([VCD_SOURCE, 16, 0, 0, 18, 0]);
+        patch.extend([4, 5, 2]);
+        patch.extend(data);
+        patch.extend(instructions);
+        patch.extend(addresses);
+        assert_eq!(apply(patch).unwrap(), b"XYZ!!!!!456789XYZ!");
+    }
+
+    #[test]
+    fn vcdiff_overflow() {
+        // A run of a byte, then one of 2^64 - 1 bytes
+        let instructions = [
+            0, 1, 0, 0x81, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F,
+        ];
+        let mut patch = VCDIFF_MAGIC.to_vec();
+        patch.push(0);
+        patch.extend([0, 0, 2, 0]);
+        patch.extend([2, 13, 0]);
+        patch.extend(b"ab");
+        patch.extend(instructions);
+        assert!(apply(patch).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use rvz::Rvz;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+#[cfg(windows)]
+use std::os::windows::fs::FileExt;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::mpsc;
+use std::thread;
+
+static THREADS: OnceLock<usize> = OnceLock::new();
+
+/// Sets how many threads decompress RVZ chunks, shared by every RVZ image open in the process.
+///
+/// With one, chunks are decompressed on the thread reading them. Without a count set, there's one
+/// thread per core. Only has an effect before any RVZ image is opened.
+pub fn set_decompression_threads(threads: usize) {
+    // Once the threads are started, as many stay as there were
+    let _ = THREADS.set(threads.max(1));
+}
+
+/// Returns how many threads decompress RVZ chunks.
+#[must_use]
+pub fn decompression_threads() -> usize {
+    *THREADS.get_or_init(|| thread::available_parallelism().map_or(1, usize::from))
+}
+
+/// A file read at its own position, so it can be shared by readers on different threads without
+/// them moving each other.
+struct PositionedFile {
+    file: Arc<File>,
+    position: u64,
+}
+
+impl Read for PositionedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        let len = self.file.read_at(buf, self.position)?;
+        // This moves the file's position too, but no reads of it go by that
+        #[cfg(windows)]
+        let len = self.file.seek_read(buf, self.position)?;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for PositionedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => self
+                .position
+                .checked_add_signed(offset)
+                .ok_or(io::ErrorKind::InvalidInput)?,
+            SeekFrom::End(offset) => self
+                .file
+                .metadata()?
+                .len()
+                .checked_add_signed(offset)
+                .ok_or(io::ErrorKind::InvalidInput)?,
+        };
+        Ok(self.position)
+    }
+}
+
+/// Work for the decompression threads.
+type Job = Box<dyn FnOnce() + Send>;
+
+static WORKERS: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+
+/// Returns the queue of the process's decompression threads, starting them the first time.
+fn workers() -> &'static mpsc::Sender<Job> {
+    WORKERS.get_or_init(|| {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..decompression_threads() {
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || {
+                loop {
+                    // The lock is only held while waiting, not while working
+                    let Ok(job) = receiver
+                        .lock()
+                        .map_err(drop)
+                        .and_then(|jobs| jobs.recv().map_err(drop))
+                    else {
+                        return;
+                    };
+                    job();
+                }
+            });
+        }
+        jobs
+    })
+}
+
+/// The chunks of an RVZ file decompressed concurrently, by threads shared with every other RVZ
+/// file open in the process.
+///
+/// Each thread reading the file has it open on its own, with its own cache of decompressed
+/// groups, so reads spanning several chunks decompress them all at once.
+pub struct Pool {
+    file: Arc<File>,
+    /// Readers of the file not in use by a thread, made as more threads read it at once.
+    readers: Arc<Mutex<Vec<Rvz<PositionedFile>>>>,
+    chunk_size: u64,
+}
+
+impl Pool {
+    /// Prepares reading the RVZ file `file` on the decompression threads.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Io`] if the file can't be opened again, and [`Error::Rvz`] if its headers can't
+    /// be parsed.
+    pub fn new(file: &File) -> Result<Self, Error> {
+        let file = Arc::new(file.try_clone()?);
+        let rvz = Rvz::new(PositionedFile {
+            file: Arc::clone(&file),
+            position: 0,
+        })?;
+        Ok(Self {
+            file,
+            chunk_size: rvz.metadata.disc.chunk_size.into(),
+            readers: Arc::new(Mutex::new(vec![rvz])),
+        })
+    }
+
+    /// Returns whether a read of `len` bytes at `offset` spans more than one chunk, and so is
+    /// worth spreading across the threads.
+    pub const fn spans_chunks(&self, offset: u64, len: usize) -> bool {
+        self.chunk_size != 0
+            && offset / self.chunk_size != (offset + len as u64 - 1) / self.chunk_size
+    }
+
+    /// Fills `buf` with the disc at `offset`, a chunk per job.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if any chunk can't be read, or the threads are gone.
+    pub fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let gone = || io::Error::other("decompression threads are gone");
+        let (done, results) = mpsc::channel::<(u64, io::Result<Vec<u8>>)>();
+        let end = offset + buf.len() as u64;
+        let mut start = offset;
+        let mut pieces = 0;
+        while start < end {
+            let piece_end = ((start / self.chunk_size + 1) * self.chunk_size).min(end);
+            #[allow(clippy::cast_possible_truncation)] // At most a chunk
+            let len = (piece_end - start) as usize;
+            let file = Arc::clone(&self.file);
+            let readers = Arc::clone(&self.readers);
+            let done = done.clone();
+            workers()
+                .send(Box::new(move || {
+                    let result = read_piece(file, &readers, start, len);
+                    // Nobody waits for the piece anymore if the read already failed
+                    let _ = done.send((start, result));
+                }))
+                .map_err(|_| gone())?;
+            start = piece_end;
+            pieces += 1;
+        }
+        drop(done);
+        for _ in 0..pieces {
+            let (start, piece) = results.recv().map_err(|_| gone())?;
+            let piece = piece?;
+            #[allow(clippy::cast_possible_truncation)] // Within buf
+            let start = (start - offset) as usize;
+            buf[start..start + piece.len()].copy_from_slice(&piece);
+        }
+        Ok(())
+    }
+}
+
+/// Reads `len` bytes of the disc at `offset` with one of `readers`, or a new reader of `file` if
+/// they're all in use, turning a panic on a damaged chunk into an error.
+fn read_piece(
+    file: Arc<File>,
+    readers: &Mutex<Vec<Rvz<PositionedFile>>>,
+    offset: u64,
+    len: usize,
+) -> io::Result<Vec<u8>> {
+    let poisoned = || io::Error::other("a decompression thread panicked");
+    let reader = readers.lock().map_err(|_| poisoned())?.pop();
+    let mut rvz = match reader {
+        Some(rvz) => rvz,
+        None => Rvz::new(PositionedFile { file, position: 0 })
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?,
+    };
+    let mut piece = vec![0; len];
+    rvz.seek(SeekFrom::Start(offset))?;
+    panic::catch_unwind(AssertUnwindSafe(|| rvz.read_exact(&mut piece))).unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "damaged compressed chunk",
+        ))
+    })?;
+    // A reader that failed may be left anywhere, so only ones that worked are used again
+    readers.lock().map_err(|_| poisoned())?.push(rvz);
+    Ok(piece)
+}
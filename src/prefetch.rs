@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::cache;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often learned patterns are saved while they change, so they survive the mount being
+/// killed rather than unmounted.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Returns the directory the learned read orders of games are kept in, following the XDG base
+/// directory spec.
+#[must_use]
+pub fn patterns_dir() -> Option<PathBuf> {
+    Some(cache::xdg_cache_dir()?.join("patterns"))
+}
+
+/// Which FST files of a disc were read right after which, to predict the next one.
+///
+/// Games load their files in much the same order every time, so what's learned is kept in
+/// [`patterns_dir`] by game ID and disc number, and picked up again by later mounts. They're
+/// saved as they change every [`SAVE_INTERVAL`], and once more when unmounting.
+#[derive(Debug, Default)]
+pub struct Patterns {
+    /// Where the patterns are kept, if anywhere.
+    path: Option<PathBuf>,
+    /// How many times each file was read right after a file, by FST index.
+    successors: HashMap<u32, HashMap<u32, u32>>,
+    /// The file read last, by FST index.
+    last: Option<u32>,
+    /// Whether anything was learned since the patterns were last saved.
+    changed: bool,
+    /// When the patterns were last saved, if they were.
+    saved: Option<Instant>,
+}
+
+impl Patterns {
+    /// Returns the patterns learned for disc `disc` of the game with ID `game_id` before, or none
+    /// if there aren't any or they can't be read.
+    pub fn load(game_id: &str, disc: u8) -> Self {
+        let Some(dir) = patterns_dir() else {
+            return Self::default();
+        };
+        if let Err(err) = fs::create_dir_all(&dir) {
+            eprintln!("can't make {}: {err}", dir.display());
+            return Self::default();
+        }
+        let path = dir.join(format!("{game_id}-{disc}"));
+        let mut successors: HashMap<u32, HashMap<u32, u32>> = HashMap::new();
+        // Lines are `from to count`, and anything else is skipped
+        for line in fs::read_to_string(&path).unwrap_or_default().lines() {
+            let mut fields = line.split(' ').map(str::parse::<u32>);
+            if let (Some(Ok(from)), Some(Ok(to)), Some(Ok(count))) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                successors.entry(from).or_default().insert(to, count);
+            }
+        }
+        Self {
+            path: Some(path),
+            successors,
+            ..Self::default()
+        }
+    }
+
+    /// Notes that `file` is being read. If it wasn't the file read last, returns the file most
+    /// often read after it, if any.
+    pub fn record(&mut self, file: u32) -> Option<u32> {
+        let last = self.last.replace(file);
+        if last == Some(file) {
+            return None;
+        }
+        if let Some(last) = last {
+            let count = self
+                .successors
+                .entry(last)
+                .or_default()
+                .entry(file)
+                .or_default();
+            *count = count.saturating_add(1);
+            self.changed = true;
+        }
+        let successors = self.successors.get(&file)?;
+        // The lowest index wins ties, so predictions don't depend on the map's order
+        successors
+            .iter()
+            .max_by_key(|&(&next, &count)| (count, u32::MAX - next))
+            .map(|(&next, _)| next)
+    }
+
+    /// Saves the patterns if anything was learned since they were last saved, and that was long
+    /// enough ago.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if they can't be written.
+    pub fn save_if_due(&mut self) -> io::Result<()> {
+        if self
+            .saved
+            .is_some_and(|saved| saved.elapsed() < SAVE_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    /// Saves the patterns where they were loaded from, if anything was learned since they were
+    /// last saved.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if they can't be written.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.changed {
+            return Ok(());
+        }
+        // Failing to save isn't retried until it's due again
+        self.saved = Some(Instant::now());
+        let mut lines: Vec<_> = self
+            .successors
+            .iter()
+            .flat_map(|(&from, successors)| {
+                successors
+                    .iter()
+                    .map(move |(&to, &count)| (from, to, count))
+            })
+            .collect();
+        lines.sort_unstable();
+        let contents = lines
+            .into_iter()
+            .fold(String::new(), |mut contents, (from, to, count)| {
+                let _ = writeln!(contents, "{from} {to} {count}");
+                contents
+            });
+        // Write it under another name first, so other mounts never see partial patterns
+        let partial = path.with_extension(format!("{}.partial", process::id()));
+        fs::write(&partial, contents)?;
+        fs::rename(&partial, path)?;
+        self.changed = false;
+        Ok(())
+    }
+}
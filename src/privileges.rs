@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+/// How much room the strings of a password database entry get at first.
+const INITIAL_BUFFER_SIZE: usize = 1024;
+
+/// A user to run as once the filesystem is mounted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct User {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+/// Returns whether the process runs as root.
+#[must_use]
+pub fn is_root() -> bool {
+    // SAFETY: geteuid can't fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+impl User {
+    /// Looks up a user by name, or by ID if `name` is a number.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if there's no such user or the user database can't be read.
+    pub fn lookup(name: &str) -> io::Result<Self> {
+        let c_name = CString::new(name)?;
+        let uid = name.parse::<libc::uid_t>().ok();
+        let mut buffer = vec![0; INITIAL_BUFFER_SIZE];
+        loop {
+            let mut entry = MaybeUninit::<libc::passwd>::uninit();
+            let mut found = ptr::null_mut();
+            // SAFETY: the entry and buffer outlive the call, which only writes within them
+            let result = unsafe {
+                match uid {
+                    Some(uid) => libc::getpwuid_r(
+                        uid,
+                        entry.as_mut_ptr(),
+                        buffer.as_mut_ptr(),
+                        buffer.len(),
+                        &raw mut found,
+                    ),
+                    None => libc::getpwnam_r(
+                        c_name.as_ptr(),
+                        entry.as_mut_ptr(),
+                        buffer.as_mut_ptr(),
+                        buffer.len(),
+                        &raw mut found,
+                    ),
+                }
+            };
+            if result == libc::ERANGE {
+                buffer.resize(buffer.len() * 2, 0);
+                continue;
+            }
+            if result != 0 {
+                return Err(io::Error::from_raw_os_error(result));
+            }
+            if found.is_null() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no user named {name}"),
+                ));
+            }
+            // SAFETY: the entry was filled in, as the call found the user
+            let entry = unsafe { entry.assume_init() };
+            return Ok(Self {
+                uid: entry.pw_uid,
+                gid: entry.pw_gid,
+            });
+        }
+    }
+
+    /// Switches the process to this user and their primary group, giving up every other group.
+    /// This can't be undone unless the user is root.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the process can't switch users, such as when it doesn't run as root.
+    pub fn switch_to(self) -> io::Result<()> {
+        let check = |result: libc::c_int| {
+            if result == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        };
+        // SAFETY: the group list outlives the call, and the others take no pointers. Groups go
+        // first, as they can't be changed once the user isn't root.
+        unsafe {
+            check(libc::setgroups(1, &raw const self.gid))?;
+            check(libc::setgid(self.gid))?;
+            check(libc::setuid(self.uid))?;
+        }
+        // Make sure root can't be regained
+        // SAFETY: setuid takes no pointers
+        if self.uid != 0 && unsafe { libc::setuid(0) } == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "root privileges could still be regained after switching users",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::cache::Cache;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+#[cfg(any(unix, feature = "winfsp"))]
+use std::iter;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::TryRecvError;
+
+/// How much is read at once when reads are sequential, enough to cover a whole RVZ chunk.
+const READ_AHEAD_SIZE: u64 = 1 << 20;
+
+/// A region of the image read in the background.
+pub struct Region {
+    pub start: u64,
+    pub data: Vec<u8>,
+    /// Whether the region is kept for good, rather than cached until there's no room for it.
+    pub keep: bool,
+}
+
+/// A reader that turns sequential small reads into fewer large ones.
+///
+/// The kernel splits reads of mounted files into pieces of at most 128 KiB, and each of them
+/// would otherwise seek and decompress on its own. Once a read starts where the last one ended, a
+/// larger block is read and the following reads are served from it. Other reads go straight to
+/// the image.
+pub struct ReadAhead<T> {
+    inner: T,
+    position: u64,
+    /// Offset in the image where the last read ended.
+    last_end: u64,
+    buffer: Vec<u8>,
+    /// Offset in the image of the start of the buffer.
+    buffer_start: u64,
+    /// Regions of the image still being read in the background, by offset.
+    warming: Option<Receiver<Region>>,
+    /// Regions of the image read in the background, by offset, kept for good.
+    warm: Vec<(u64, Vec<u8>)>,
+    /// Other regions of the image read in the background, by offset.
+    prefetched: Cache<u64>,
+}
+
+impl<T: Read + Seek> ReadAhead<T> {
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            position: 0,
+            last_end: u64::MAX,
+            buffer: vec![],
+            buffer_start: 0,
+            warming: None,
+            warm: vec![],
+            prefetched: Cache::new(),
+        }
+    }
+
+    /// Serves reads from the regions received from `regions` once they arrive, as they're read
+    /// in the background.
+    pub fn warm_up(&mut self, regions: Receiver<Region>) {
+        self.warming = Some(regions);
+    }
+
+    /// Returns the `len` bytes of the image at `start` from what's been read ahead, reading ahead
+    /// first if reads are sequential, so they can be answered without copying them. Returns `None`
+    /// if they aren't all there, and should be read instead.
+    #[cfg(any(unix, feature = "winfsp"))]
+    pub fn borrow(&mut self, start: u64, len: usize) -> Option<&[u8]> {
+        self.receive_warm();
+        if self.find(start, len).is_none()
+            && start == self.last_end
+            && (len as u64) < READ_AHEAD_SIZE
+        {
+            self.buffer_start = start;
+            let filled = self
+                .inner
+                .seek(SeekFrom::Start(start))
+                .and_then(|_| self.fill_buffer());
+            // Reading instead only fails if the damage is in what was asked for
+            if filled.is_err() {
+                self.buffer.clear();
+            }
+        }
+        self.find(start, len)?;
+        self.position = start + len as u64;
+        self.last_end = self.position;
+        self.find(start, len)
+    }
+
+    /// Returns the `len` bytes of the image at `start` if a warm region or the buffer has them
+    /// all.
+    #[cfg(any(unix, feature = "winfsp"))]
+    fn find(&self, start: u64, len: usize) -> Option<&[u8]> {
+        let warm = self
+            .warm
+            .iter()
+            .map(|(region, data)| (region, data.as_slice()));
+        let buffered = (&self.buffer_start, self.buffer.as_slice());
+        warm.chain(self.prefetched.iter())
+            .chain(iter::once(buffered))
+            .find_map(|(region, data)| {
+                let offset = usize::try_from(start.checked_sub(*region)?).ok()?;
+                data.get(offset..offset.checked_add(len)?)
+            })
+    }
+
+    /// Takes in the regions read in the background so far.
+    fn receive_warm(&mut self) {
+        if let Some(regions) = &self.warming {
+            loop {
+                match regions.try_recv() {
+                    Ok(region) if region.keep => self.warm.push((region.start, region.data)),
+                    Ok(region) => {
+                        // The same file may be asked for again before it's read
+                        if !self.prefetched.promote(&region.start) {
+                            self.prefetched.insert(region.start, region.data);
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.warming = None;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies as much as possible at the current position from the warm regions into `buf`.
+    fn copy_warm(&mut self, buf: &mut [u8]) -> usize {
+        self.receive_warm();
+        let warm = self
+            .warm
+            .iter()
+            .map(|(start, data)| (start, data.as_slice()));
+        for (start, data) in warm.chain(self.prefetched.iter()) {
+            let Some(offset) = self.position.checked_sub(*start) else {
+                continue;
+            };
+            let Some(available) = usize::try_from(offset)
+                .ok()
+                .and_then(|offset| data.get(offset..))
+            else {
+                continue;
+            };
+            let len = available.len().min(buf.len());
+            buf[..len].copy_from_slice(&available[..len]);
+            if len != 0 {
+                return len;
+            }
+        }
+        0
+    }
+
+    /// Reads up to [`READ_AHEAD_SIZE`] bytes at the current position into the buffer. The reads
+    /// are as large as possible, so a compressed image can decompress their chunks concurrently.
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        #[allow(clippy::cast_possible_truncation)] // A megabyte
+        self.buffer.resize(READ_AHEAD_SIZE as usize, 0);
+        let mut filled = 0;
+        while filled < self.buffer.len() {
+            match self.inner.read(&mut self.buffer[filled..]) {
+                Ok(0) => break,
+                Ok(len) => filled += len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.buffer.truncate(filled);
+        Ok(())
+    }
+
+    /// Copies as much as possible at the current position from the buffer into `buf`.
+    fn copy_buffered(&self, buf: &mut [u8]) -> usize {
+        let Some(start) = self.position.checked_sub(self.buffer_start) else {
+            return 0;
+        };
+        let Ok(start) = usize::try_from(start) else {
+            return 0;
+        };
+        let available = self.buffer.get(start..).unwrap_or_default();
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        len
+    }
+}
+
+impl<T: Read + Seek> Read for ReadAhead<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut len = self.copy_warm(buf);
+        if len == 0 {
+            len = self.copy_buffered(buf);
+        }
+        if len == 0 && !buf.is_empty() {
+            self.inner.seek(SeekFrom::Start(self.position))?;
+            if self.position == self.last_end && (buf.len() as u64) < READ_AHEAD_SIZE {
+                self.buffer_start = self.position;
+                let filled = self.fill_buffer();
+                if filled.is_ok() {
+                    len = self.copy_buffered(buf);
+                } else {
+                    // The damage could be past what was asked for, so only fail if it isn't
+                    self.buffer.clear();
+                    self.inner.seek(SeekFrom::Start(self.position))?;
+                    len = self.inner.read(buf)?;
+                }
+            } else {
+                len = self.inner.read(buf)?;
+            }
+        }
+        self.position += len as u64;
+        self.last_end = self.position;
+        Ok(len)
+    }
+}
+
+impl<T: Read + Seek> Seek for ReadAhead<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => self
+                .position
+                .checked_add_signed(offset)
+                .ok_or(io::ErrorKind::InvalidInput)?,
+            SeekFrom::End(_) => self.inner.seek(pos)?,
+        };
+        Ok(self.position)
+    }
+}
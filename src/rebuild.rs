@@ -0,0 +1,445 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::dol::Dol;
+use crate::error::Error;
+use crate::layout;
+use crate::layout::FileInfo;
+use crate::layout::LayoutOptions;
+use crate::layout::Placement;
+use crate::layout::SystemArea;
+use crate::options::Options;
+use crate::tree::FileData;
+use crate::tree::Index;
+use crate::tree::Inode;
+use crate::tree::Kind;
+use crate::tree::Tree;
+use gcn_disk::Disc;
+use gcn_disk::Entry;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Where the data for a file in the rebuilt image comes from.
+enum Source {
+    Disc(u32),
+    Host(PathBuf),
+}
+
+/// Returns where the data of every file in `tree` comes from, along with its size, in FST order.
+fn sources(tree: &Tree, disc: &Disc) -> Result<Vec<(FileInfo, Source)>, Error> {
+    let fs = &disc.filesystem;
+    let mut sources = vec![];
+    for inode in layout::files(tree) {
+        let original = tree
+            .is_from_disc(inode)
+            .then(|| &fs.entries[Index::from(inode).as_usize()]);
+        let original_offset = match original {
+            Some(Entry::File(file)) => Some(file.offset),
+            _ => None,
+        };
+        let (size, source) = match tree.get(inode).map(|node| &node.kind) {
+            Some(Kind::File(FileData::Disc(index))) => {
+                let Entry::File(file) = &fs.entries[index.as_usize()] else {
+                    unreachable!("disc file nodes always point to FST file entries");
+                };
+                (file.size, Source::Disc(file.offset))
+            }
+            Some(Kind::File(FileData::Host(path))) => {
+                let size = u32::try_from(path.metadata()?.len())
+                    .map_err(|_| Error::Layout(format!("{} is too large", path.display())))?;
+                (size, Source::Host(path.clone()))
+            }
+            _ => unreachable!("only existing files are laid out"),
+        };
+        let info = FileInfo {
+            inode,
+            size,
+            original_offset,
+        };
+        sources.push((info, source));
+    }
+    Ok(sources)
+}
+
+/// Reads the system files of the disc in `io`.
+fn system_area<T: Read + Seek>(
+    io: &mut T,
+    disc: &Disc,
+    apploader_size: u32,
+    dol_size: u32,
+) -> io::Result<SystemArea> {
+    let mut read = |offset: u64, size: usize| -> io::Result<Vec<u8>> {
+        let mut data = vec![0; size];
+        io.seek(SeekFrom::Start(offset))?;
+        io.read_exact(&mut data)?;
+        Ok(data)
+    };
+    Ok(SystemArea {
+        header: read(0, layout::HEADER_SIZE)?,
+        bi2: read(layout::HEADER_SIZE as u64, layout::BI2_SIZE)?,
+        apploader: read(layout::APPLOADER_OFFSET, apploader_size as usize)?,
+        dol: read(disc.header.executable_offset.into(), dol_size as usize)?,
+    })
+}
+
+/// Lays out a new image from scratch with the system files of the original, copying every file
+/// from either the disc in `io` or the host.
+fn repack<T: Read + Seek, W: Write + Seek>(
+    io: &mut T,
+    system: &SystemArea,
+    tree: &Tree,
+    files: Vec<(FileInfo, Source)>,
+    layout_options: &LayoutOptions,
+    out: &mut W,
+) -> Result<(), Error> {
+    let mut infos: Vec<FileInfo> = files.iter().map(|(info, _)| *info).collect();
+    layout::order_files(tree, &mut infos, layout_options.order);
+    let sources: HashMap<Inode, (u32, Source)> = files
+        .into_iter()
+        .map(|(info, source)| (info.inode, (info.size, source)))
+        .collect();
+    layout::write_image(
+        out,
+        system,
+        tree,
+        &infos,
+        layout_options,
+        &mut |inode, out| {
+            match &sources[&inode] {
+                (size, Source::Disc(offset)) => {
+                    io.seek(SeekFrom::Start((*offset).into()))?;
+                    io::copy(&mut (&mut *io).take((*size).into()), out)?;
+                }
+                (_, Source::Host(path)) => {
+                    io::copy(&mut File::open(path)?, out)?;
+                }
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Builds a new image from the disc in `io` with the changes in [`Options::overlay`] applied,
+/// writing it to `out`.
+///
+/// By default the new image starts as a copy of the original one, so the header, apploader, DOL
+/// and anything outside of the FST stay untouched. Files that still fit where the original file
+/// was are rewritten in place, and files that grew or are new are appended to the end of the
+/// image, aligned to [`LayoutOptions::alignment`] and in [`LayoutOptions::order`]. The FST is
+/// rebuilt, and moved to the end too if it no longer fits in its original location.
+///
+/// With [`LayoutOptions::repack`], the image is instead laid out from scratch with
+/// [`layout::write_image`], keeping only the system files of the original.
+///
+/// Only the paths and contents of the overlay's files go into the image, not their times or the
+/// order the overlay lists them in, so the same disc and overlay always rebuild to the same bytes.
+///
+/// # Errors
+///
+/// [`Error::Overlay`] if the overlay can't be read, [`Error::Layout`] if the layout options are
+/// invalid or the new FST can't be built, [`Error::Fst`] if the disc's FST has problems and
+/// [`Options::strictness`] is strict, and [`Error::Io`] for errors reading the disc or writing
+/// the new image.
+pub fn rebuild<T: Read + Seek, W: Write + Seek>(
+    io: &mut T,
+    disc: &mut Disc,
+    disc_size: u64,
+    options: &Options,
+    layout_options: &LayoutOptions,
+    out: &mut W,
+) -> Result<(), Error> {
+    layout_options.validate()?;
+    let mut tree = Tree::new(io, disc, options)?;
+    if let Some(overlay) = &options.overlay {
+        tree.overlay(Inode(1), overlay, options)
+            .map_err(Error::Overlay)?;
+    }
+    let files = sources(&tree, disc)?;
+
+    let header = &disc.header;
+    let dol = Dol::read(io, header.executable_offset.into())?;
+    let apploader_size = layout::apploader_size(io)?;
+
+    if layout_options.repack {
+        let system = system_area(io, disc, apploader_size, dol.size())?;
+        return repack(io, &system, &tree, files, layout_options, out);
+    }
+
+    io.seek(SeekFrom::Start(0))?;
+    io::copy(&mut io.take(disc_size), out)?;
+
+    // Rewritten data must never spill into anything the original image uses
+    let used = layout::used_extents(disc, apploader_size, dol.size());
+    let end_of_used = used.iter().map(|&(_, end)| end).max().unwrap_or(0);
+    let alignment = layout_options.alignment;
+    let appended_start = layout::align(disc_size.max(end_of_used), alignment);
+    // Space available at `offset` before the next thing the original image uses, or the first
+    // thing appended to it
+    let capacity = |offset: u64| {
+        used.iter()
+            .map(|&(start, _)| start)
+            .filter(|&start| start > offset)
+            .min()
+            .unwrap_or(appended_start)
+            .saturating_sub(offset)
+    };
+
+    let mut end = appended_start;
+    let mut append = |size: u64| -> Result<u32, Error> {
+        let offset = end;
+        end = layout::align(end + size, alignment);
+        u32::try_from(offset).map_err(|_| Error::Layout("the image grew past 4 GiB".into()))
+    };
+
+    let mut placements = HashMap::new();
+    let mut appended = vec![];
+    let mut paths = HashMap::new();
+    for (info, source) in &files {
+        let offset = match (source, info.original_offset) {
+            (Source::Disc(offset), _) => *offset,
+            (Source::Host(path), Some(offset))
+                if u64::from(info.size) <= capacity(offset.into()) =>
+            {
+                out.seek(SeekFrom::Start(offset.into()))?;
+                io::copy(&mut File::open(path)?, out)?;
+                offset
+            }
+            (Source::Host(path), _) => {
+                appended.push(FileInfo {
+                    original_offset: None,
+                    ..*info
+                });
+                paths.insert(info.inode, path);
+                continue;
+            }
+        };
+        let size = info.size;
+        placements.insert(info.inode, Placement { offset, size });
+    }
+    layout::order_files(&tree, &mut appended, layout_options.order);
+    for info in appended {
+        let offset = append(info.size.into())?;
+        out.seek(SeekFrom::Start(offset.into()))?;
+        io::copy(&mut File::open(paths[&info.inode])?, out)?;
+        let size = info.size;
+        placements.insert(info.inode, Placement { offset, size });
+    }
+
+    let fst = layout::build_fst(&tree, &placements)?;
+    let fst_size = fst.len() as u64;
+    let fst_offset = if fst_size <= capacity(header.fst_offset.into()) {
+        header.fst_offset
+    } else {
+        append(fst_size)?
+    };
+    out.seek(SeekFrom::Start(fst_offset.into()))?;
+    out.write_all(&fst)?;
+
+    let fst_size =
+        u32::try_from(fst_size).map_err(|_| Error::Layout("the FST is too large".into()))?;
+    layout::write_fst_fields(out, fst_offset, fst_size, fst_size.max(header.fst_max_size))?;
+
+    // Make sure the image includes any padding after the last appended file
+    let end = if end == appended_start {
+        disc_size
+    } else {
+        end
+    };
+    layout::pad_image(out, end, layout_options.padding)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lenient;
+    use crate::mkiso;
+    use crate::options::Strictness;
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    /// Returns the files of the image with their offsets and contents, by path.
+    fn files(image: &[u8]) -> BTreeMap<String, (u64, Vec<u8>)> {
+        let mut io = io::Cursor::new(image);
+        let mut disc = lenient::read_disc(&mut io, Strictness::Strict).unwrap();
+        layout::contents(&mut io, &mut disc)
+            .unwrap()
+            .into_iter()
+            .filter_map(|(path, (offset, size))| {
+                let path = path.strip_prefix("files/")?.to_string();
+                let start = usize::try_from(offset).unwrap();
+                let end = start + usize::try_from(size).unwrap();
+                Some((path, (offset, image[start..end].to_vec())))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let image = mkiso::tests::image(
+            "rebuild",
+            &[
+                ("a.txt", b"aaaa"),
+                ("grow.txt", b"gg"),
+                ("dir/gone.bin", b"x"),
+                ("dir/same.bin", b"ssss"),
+            ],
+        );
+        let original = files(&image);
+        let contents = |path: &str| original[path].1.as_slice();
+        assert_eq!(
+            original.keys().collect::<Vec<_>>(),
+            ["a.txt", "dir/gone.bin", "dir/same.bin", "grow.txt"]
+        );
+        assert_eq!(
+            [
+                contents("a.txt"),
+                contents("dir/same.bin"),
+                contents("grow.txt")
+            ],
+            [&b"aaaa"[..], b"ssss", b"gg"]
+        );
+
+        let overlay = env::temp_dir().join(format!(".gcnfuse-rebuild-overlay-{}", process::id()));
+        fs::create_dir_all(overlay.join("dir")).unwrap();
+        for (path, contents) in [
+            ("a.txt", &b"AB"[..]),
+            ("grow.txt", b"grown bigger"),
+            ("new.txt", b"new"),
+            ("dir/.wh.gone.bin", b""),
+        ] {
+            fs::write(overlay.join(path), contents).unwrap();
+        }
+        let options = Options {
+            overlay: Some(overlay.clone()),
+            ..Options::default()
+        };
+        let rebuilt = [false, true].map(|repack| {
+            let mut io = io::Cursor::new(&image);
+            let mut disc = lenient::read_disc(&mut io, Strictness::Strict)?;
+            let layout_options = LayoutOptions {
+                alignment: 4,
+                repack,
+                ..LayoutOptions::default()
+            };
+            let mut out = io::Cursor::new(vec![]);
+            let size = image.len() as u64;
+            rebuild(
+                &mut io,
+                &mut disc,
+                size,
+                &options,
+                &layout_options,
+                &mut out,
+            )?;
+            Ok::<_, Error>(out.into_inner())
+        });
+        let _ = fs::remove_dir_all(&overlay);
+
+        for (repack, rebuilt) in [false, true].into_iter().zip(rebuilt) {
+            let rebuilt = rebuilt.unwrap();
+            // The header is kept, but for where the FST went
+            assert_eq!(rebuilt[..0x424], image[..0x424], "{repack}");
+            let files = files(&rebuilt);
+            let contents: Vec<_> = files
+                .iter()
+                .map(|(path, (_, data))| (path.as_str(), data.as_slice()))
+                .collect();
+            assert_eq!(
+                contents,
+                [
+                    ("a.txt", &b"AB"[..]),
+                    ("dir/same.bin", b"ssss"),
+                    ("grow.txt", b"grown bigger"),
+                    ("new.txt", b"new"),
+                ],
+                "{repack}"
+            );
+            if !repack {
+                // Files that fit stay where they were, and the rest go after the original image
+                assert_eq!(files["a.txt"].0, original["a.txt"].0);
+                assert_eq!(files["dir/same.bin"].0, original["dir/same.bin"].0);
+                assert!(files["grow.txt"].0 >= image.len() as u64);
+                assert!(files["new.txt"].0 >= image.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        let image = mkiso::tests::image(
+            "rebuild-reproducible",
+            &[("a.txt", b"aaaa"), ("b/c.bin", b"cc"), ("b/d.bin", b"d")],
+        );
+        let changes: &[(&str, &[u8])] = &[
+            ("a.txt", b"much bigger"),
+            ("b/c.bin", b"C"),
+            ("b/e.bin", b"eeee"),
+            ("f/g.txt", b"g"),
+            ("b/.wh.d.bin", b""),
+        ];
+        let base = env::temp_dir().join(format!(".gcnfuse-rebuild-reproducible-{}", process::id()));
+        // Written in opposite orders, with different times, so the host lists them differently
+        let overlays = [false, true].map(|reversed| {
+            let overlay = base.join(format!("{reversed}"));
+            let mut order = changes.to_vec();
+            if reversed {
+                order.reverse();
+            }
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(u64::from(reversed) << 30);
+            for (path, contents) in order {
+                let path = overlay.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, contents).unwrap();
+                fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(modified)
+                    .unwrap();
+            }
+            overlay
+        });
+        let rebuilt = [false, true].map(|repack| {
+            overlays.clone().map(|overlay| {
+                let mut io = io::Cursor::new(&image);
+                let mut disc = lenient::read_disc(&mut io, Strictness::Strict)?;
+                let options = Options {
+                    overlay: Some(overlay),
+                    ..Options::default()
+                };
+                let layout_options = LayoutOptions {
+                    repack,
+                    ..LayoutOptions::default()
+                };
+                let mut out = io::Cursor::new(vec![]);
+                let size = image.len() as u64;
+                rebuild(
+                    &mut io,
+                    &mut disc,
+                    size,
+                    &options,
+                    &layout_options,
+                    &mut out,
+                )?;
+                Ok::<_, Error>(out.into_inner())
+            })
+        });
+        let _ = fs::remove_dir_all(&base);
+
+        for (repack, [first, second]) in [false, true].into_iter().zip(rebuilt) {
+            let (first, second) = (first.unwrap(), second.unwrap());
+            assert!(first == second, "{repack}");
+        }
+    }
+}
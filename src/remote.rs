@@ -0,0 +1,551 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::cache::Cache;
+use crate::interrupt;
+#[cfg(feature = "remote")]
+use crate::s3;
+use std::collections::HashMap;
+use std::io;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::process::Child;
+use std::process::ChildStdin;
+use std::process::ChildStdout;
+use std::process::Command;
+use std::process::Stdio;
+#[cfg(feature = "remote")]
+use std::time::Duration;
+#[cfg(feature = "remote")]
+use std::time::SystemTime;
+
+/// Size of the windows remote images are fetched and cached in.
+const WINDOW_SIZE: u64 = 1 << 20;
+
+/// Something that can read arbitrary ranges of a remote image.
+pub trait RangeRead {
+    /// Reads the `len` bytes at `offset`, all of which exist.
+    fn read_range(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>>;
+}
+
+/// An image read from a remote server in windows, keeping the last few in memory so small reads
+/// don't each become a request.
+pub struct Remote {
+    reader: Box<dyn RangeRead + Send>,
+    size: u64,
+    position: u64,
+    /// Recently read windows by index.
+    windows: Cache<u64>,
+}
+
+impl Remote {
+    pub(crate) fn new(reader: Box<dyn RangeRead + Send>, size: u64) -> Self {
+        Self {
+            reader,
+            size,
+            position: 0,
+            windows: Cache::new(),
+        }
+    }
+
+    /// Returns the size of the image.
+    #[must_use]
+    pub const fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the window with the given index, reading it if it isn't cached.
+    fn window(&mut self, index: u64) -> io::Result<&[u8]> {
+        if !self.windows.promote(&index) {
+            let offset = index * WINDOW_SIZE;
+            let len = WINDOW_SIZE.min(self.size - offset);
+            // Reads spanning many windows stop once nobody waits for them
+            interrupt::check()?;
+            let window = self.reader.read_range(offset, len)?;
+            self.windows.insert(index, window);
+        }
+        Ok(self.windows.most_recent())
+    }
+}
+
+impl Read for Remote {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size {
+            return Ok(0);
+        }
+        let position = self.position;
+        let window = self.window(position / WINDOW_SIZE)?;
+        // Less than a window
+        #[allow(clippy::cast_possible_truncation)]
+        let start = (position % WINDOW_SIZE) as usize;
+        let len = buf.len().min(window.len() - start);
+        buf[..len].copy_from_slice(&window[start..start + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for Remote {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or(io::ErrorKind::InvalidInput)?;
+        Ok(self.position)
+    }
+}
+
+/// SFTP packet types, from version 3 of the protocol.
+mod sftp {
+    pub const INIT: u8 = 1;
+    pub const VERSION: u8 = 2;
+    pub const OPEN: u8 = 3;
+    pub const READ: u8 = 5;
+    pub const FSTAT: u8 = 8;
+    pub const STATUS: u8 = 101;
+    pub const HANDLE: u8 = 102;
+    pub const DATA: u8 = 103;
+    pub const ATTRS: u8 = 105;
+
+    pub const OPEN_READ: u32 = 1;
+    pub const ATTR_SIZE: u32 = 1;
+}
+
+/// Largest read requested at once, which every SFTP server supports.
+const SFTP_READ_SIZE: u32 = 32 * 1024;
+
+/// Reads images over SFTP, through a session of the system's `ssh` so its configuration, keys
+/// and agent all work as usual.
+struct Sftp<W = ChildStdin, R = ChildStdout> {
+    /// The `ssh` session, which servers in tests run without.
+    child: Option<Child>,
+    input: W,
+    output: BufReader<R>,
+    handle: Vec<u8>,
+    next_id: u32,
+}
+
+/// Appends an SFTP string, prefixed by its length.
+fn put_string(packet: &mut Vec<u8>, string: &[u8]) {
+    // Strings sent are paths and handles
+    #[allow(clippy::cast_possible_truncation)]
+    packet.extend_from_slice(&(string.len() as u32).to_be_bytes());
+    packet.extend_from_slice(string);
+}
+
+/// Takes `len` bytes from the front of a packet.
+fn take<'a>(packet: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if packet.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "truncated SFTP packet",
+        ));
+    }
+    let (taken, rest) = packet.split_at(len);
+    *packet = rest;
+    Ok(taken)
+}
+
+fn take_u32(packet: &mut &[u8]) -> io::Result<u32> {
+    Ok(u32::from_be_bytes(take(packet, 4)?.try_into().unwrap()))
+}
+
+fn take_string<'a>(packet: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let len = take_u32(packet)? as usize;
+    take(packet, len)
+}
+
+/// Returns the body of a response of type `received`, failing if it's a status, which is only
+/// sent for errors here, or not of type `kind`.
+fn response(received: u8, body: Vec<u8>, kind: u8) -> io::Result<Vec<u8>> {
+    if received == sftp::STATUS {
+        let mut body = &body[..];
+        let code = take_u32(&mut body)?;
+        let message = take_string(&mut body).unwrap_or_default();
+        let message = String::from_utf8_lossy(message);
+        let kind = if code == 2 {
+            io::ErrorKind::NotFound
+        } else {
+            io::ErrorKind::Other
+        };
+        return Err(io::Error::new(
+            kind,
+            format!("SFTP error {code}: {message}"),
+        ));
+    }
+    if received != kind {
+        return Err(unexpected());
+    }
+    Ok(body)
+}
+
+fn unexpected() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "unexpected SFTP response")
+}
+
+impl<W: Write, R: Read> Sftp<W, R> {
+    /// Sends a request of the given type, returning its ID.
+    fn send(&mut self, kind: u8, body: &[u8]) -> io::Result<u32> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        // Requests are small
+        #[allow(clippy::cast_possible_truncation)]
+        let len = (body.len() + 5) as u32;
+        self.input.write_all(&len.to_be_bytes())?;
+        self.input.write_all(&[kind])?;
+        self.input.write_all(&id.to_be_bytes())?;
+        self.input.write_all(body)?;
+        Ok(id)
+    }
+
+    /// Receives the next response, returning its type, ID and the rest of it.
+    fn receive(&mut self) -> io::Result<(u8, u32, Vec<u8>)> {
+        let mut len = [0; 4];
+        self.output.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len);
+        if !(5..=1 << 24).contains(&len) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed SFTP packet",
+            ));
+        }
+        let mut packet = vec![0; len as usize];
+        self.output.read_exact(&mut packet)?;
+        let id = u32::from_be_bytes(packet[1..5].try_into().unwrap());
+        Ok((packet[0], id, packet.split_off(5)))
+    }
+
+    /// Receives the response to the request with the given ID, failing if it's a status,
+    /// which is only sent for errors here.
+    fn expect(&mut self, id: u32, kind: u8) -> io::Result<Vec<u8>> {
+        let (received, received_id, body) = self.receive()?;
+        if received_id != id {
+            return Err(unexpected());
+        }
+        response(received, body, kind)
+    }
+}
+
+impl<W: Write, R: Read> RangeRead for Sftp<W, R> {
+    fn read_range(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(usize::try_from(len).unwrap_or_default());
+        while (data.len() as u64) < len {
+            // All the reads for the rest of the range are sent before waiting for any of them
+            let start = offset + data.len() as u64;
+            let end = offset + len;
+            let mut requests = vec![];
+            for chunk in (start..end).step_by(SFTP_READ_SIZE as usize) {
+                let mut body = vec![];
+                put_string(&mut body, &self.handle);
+                body.extend_from_slice(&chunk.to_be_bytes());
+                // At most a read
+                #[allow(clippy::cast_possible_truncation)]
+                let size = u64::from(SFTP_READ_SIZE).min(end - chunk) as u32;
+                body.extend_from_slice(&size.to_be_bytes());
+                requests.push((self.send(sftp::READ, &body)?, size));
+            }
+            self.input.flush()?;
+            // Every response is received before any is looked at, so failed reads don't leave
+            // theirs to be taken for the responses to later requests. Servers may also answer
+            // them in any order
+            let mut responses = HashMap::with_capacity(requests.len());
+            for _ in 0..requests.len() {
+                let (received, id, body) = self.receive()?;
+                responses.insert(id, (received, body));
+            }
+            // Servers can return less than asked for, and the rest is read again after
+            let mut short = false;
+            for (id, size) in requests {
+                let (received, body) = responses.remove(&id).ok_or_else(unexpected)?;
+                let body = response(received, body, sftp::DATA)?;
+                let chunk = take_string(&mut &body[..])?;
+                if !short {
+                    data.extend_from_slice(chunk);
+                }
+                short |= chunk.len() < size as usize;
+            }
+            if data.len() as u64 == start - offset {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl<W, R> Drop for Sftp<W, R> {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Opens the image at the given `sftp://[user@]host[:port]/path` URL, with `/~/` starting paths
+/// relative to the home directory.
+///
+/// # Errors
+///
+/// [`io::Error`] if the SSH session can't be started, or the image can't be opened.
+pub fn sftp(url: &str) -> io::Result<Remote> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid URL {url}"));
+    let rest = url.strip_prefix("sftp://").ok_or_else(invalid)?;
+    let (authority, path) = rest.split_at(rest.find('/').ok_or_else(invalid)?);
+    let path = path.strip_prefix("/~/").unwrap_or(path);
+    let path = crate::http::percent_decode(path);
+    let (destination, port) = match authority.rsplit_once(':') {
+        Some((destination, port)) if !port.contains(']') => (destination, Some(port)),
+        _ => (authority, None),
+    };
+
+    let mut command = Command::new("ssh");
+    if let Some(port) = port {
+        command.args(["-p", port]);
+    }
+    let mut child = command
+        .args(["-s", "--", destination, "sftp"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut sftp = Sftp {
+        input: child.stdin.take().unwrap(),
+        output: BufReader::new(child.stdout.take().unwrap()),
+        child: Some(child),
+        handle: vec![],
+        next_id: 0,
+    };
+
+    // The version exchange is the only exchange without an ID, so it's done by hand
+    sftp.input
+        .write_all(&[0, 0, 0, 5, sftp::INIT, 0, 0, 0, 3])?;
+    sftp.input.flush()?;
+    let mut len = [0; 4];
+    sftp.output.read_exact(&mut len)?;
+    let mut version = vec![0; u32::from_be_bytes(len).min(1 << 16) as usize];
+    sftp.output.read_exact(&mut version)?;
+    if version.first() != Some(&sftp::VERSION) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an SFTP server",
+        ));
+    }
+
+    let mut body = vec![];
+    put_string(&mut body, path.as_bytes());
+    body.extend_from_slice(&sftp::OPEN_READ.to_be_bytes());
+    body.extend_from_slice(&0u32.to_be_bytes());
+    let id = sftp.send(sftp::OPEN, &body)?;
+    sftp.input.flush()?;
+    let body = sftp.expect(id, sftp::HANDLE)?;
+    sftp.handle = take_string(&mut &body[..])?.to_vec();
+
+    let mut body = vec![];
+    put_string(&mut body, &sftp.handle);
+    let id = sftp.send(sftp::FSTAT, &body)?;
+    sftp.input.flush()?;
+    let attrs = sftp.expect(id, sftp::ATTRS)?;
+    let mut attrs = &attrs[..];
+    if take_u32(&mut attrs)? & sftp::ATTR_SIZE == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{url} didn't report the image's size"),
+        ));
+    }
+    let size = u64::from_be_bytes(take(&mut attrs, 8)?.try_into().unwrap());
+    Ok(Remote::new(Box::new(sftp), size))
+}
+
+/// Reads images from an HTTP(S) server with `Range` requests.
+#[cfg(feature = "remote")]
+struct Http {
+    agent: ureq::Agent,
+    url: String,
+    /// Credentials to sign requests with, for S3 buckets that aren't public.
+    credentials: Option<s3::Credentials>,
+}
+
+#[cfg(feature = "remote")]
+impl Http {
+    /// Requests the given range, returning the response body and the `Content-Range` header.
+    fn get(&self, start: u64, end: u64) -> io::Result<(Vec<u8>, String)> {
+        let range = format!("bytes={start}-{end}");
+        let mut request = self.agent.get(&self.url).header("Range", &range);
+        if let Some(credentials) = &self.credentials {
+            for (name, value) in credentials.sign(&self.url, &range, SystemTime::now()) {
+                request = request.header(name, value);
+            }
+        }
+        let mut response = request.call().map_err(io::Error::other)?;
+        if response.status() != 206 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} doesn't support range requests", self.url),
+            ));
+        }
+        let range = response
+            .headers()
+            .get("content-range")
+            .and_then(|range| range.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let body = response
+            .body_mut()
+            .with_config()
+            // The limit is exclusive, and a body can be a whole window
+            .limit(WINDOW_SIZE + 1)
+            .read_to_vec()
+            .map_err(io::Error::other)?;
+        Ok((body, range))
+    }
+}
+
+#[cfg(feature = "remote")]
+impl RangeRead for Http {
+    fn read_range(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let (data, _) = self.get(offset, offset + len - 1)?;
+        if data.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(data)
+    }
+}
+
+/// Opens the image at `url` with `Http`, signing requests with any `credentials`.
+#[cfg(feature = "remote")]
+fn open_http(url: String, credentials: Option<s3::Credentials>) -> io::Result<Remote> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(30)))
+        .build()
+        .into();
+    let http = Http {
+        agent,
+        url,
+        credentials,
+    };
+    // The total size is only given in the Content-Range of a range request, like `bytes 0-0/1234`
+    let (_, range) = http.get(0, 0)?;
+    let size = range
+        .rsplit_once('/')
+        .and_then(|(_, size)| size.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} didn't report the image's size", http.url),
+            )
+        })?;
+    Ok(Remote::new(Box::new(http), size))
+}
+
+/// Opens the image at the given `http://` or `https://` URL. The server has to support range
+/// requests.
+///
+/// # Errors
+///
+/// [`io::Error`] if the server can't be reached, or doesn't support range requests.
+#[cfg(feature = "remote")]
+pub fn http(url: &str) -> io::Result<Remote> {
+    open_http(url.to_string(), None)
+}
+
+/// Opens the image at the given `s3://bucket/key` URL, signing requests with the credentials
+/// in the usual AWS environment variables if they're set.
+///
+/// # Errors
+///
+/// [`io::Error`] if the object can't be reached.
+#[cfg(feature = "remote")]
+pub fn s3(url: &str) -> io::Result<Remote> {
+    let (bucket, key) = url
+        .strip_prefix("s3://")
+        .and_then(|rest| rest.split_once('/'))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid URL {url}")))?;
+    open_http(s3::object_url(bucket, key), s3::Credentials::from_env())
+}
+
+/// HTTP(S) images are only supported with the `remote` feature.
+#[cfg(not(feature = "remote"))]
+pub fn http(_url: &str) -> io::Result<Remote> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "gcnfuse was built without the remote feature",
+    ))
+}
+
+/// S3 images are only supported with the `remote` feature.
+#[cfg(not(feature = "remote"))]
+pub fn s3(url: &str) -> io::Result<Remote> {
+    http(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Byte of the fake server's image at `offset`.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn byte_at(offset: u64) -> u8 {
+        (offset % 251) as u8
+    }
+
+    /// Answers the reads of `input` on `output` like an SFTP server would, but with a status for
+    /// the read numbered `failing`.
+    fn serve(mut input: impl Read, mut output: impl Write, failing: usize) {
+        let mut reads = 0;
+        let mut len = [0; 4];
+        while input.read_exact(&mut len).is_ok() {
+            let mut packet = vec![0; u32::from_be_bytes(len) as usize];
+            input.read_exact(&mut packet).unwrap();
+            let mut body = &packet[5..];
+            take_string(&mut body).unwrap();
+            let offset = u64::from_be_bytes(take(&mut body, 8).unwrap().try_into().unwrap());
+            let size = take_u32(&mut body).unwrap();
+            let mut response = vec![];
+            if reads == failing {
+                response.push(sftp::STATUS);
+                response.extend_from_slice(&packet[1..5]);
+                response.extend_from_slice(&4u32.to_be_bytes());
+                put_string(&mut response, b"failure");
+            } else {
+                response.push(sftp::DATA);
+                response.extend_from_slice(&packet[1..5]);
+                let data: Vec<u8> = (offset..offset + u64::from(size)).map(byte_at).collect();
+                put_string(&mut response, &data);
+            }
+            reads += 1;
+            #[allow(clippy::cast_possible_truncation)]
+            output
+                .write_all(&(response.len() as u32).to_be_bytes())
+                .unwrap();
+            output.write_all(&response).unwrap();
+        }
+    }
+
+    #[test]
+    fn failed_pipelined_read() {
+        let (requests, server_input) = io::pipe().unwrap();
+        let (client_output, responses) = io::pipe().unwrap();
+        let server = thread::spawn(move || serve(requests, responses, 1));
+        let mut sftp = Sftp {
+            child: None,
+            input: server_input,
+            output: BufReader::new(client_output),
+            handle: b"image".to_vec(),
+            next_id: 0,
+        };
+        let len = 3 * u64::from(SFTP_READ_SIZE);
+        let failed = sftp.read_range(0, len).unwrap_err();
+        assert_eq!(failed.kind(), io::ErrorKind::Other);
+        // The responses to the other reads of the window were taken, so the next reads get theirs
+        let offset = 5 * u64::from(SFTP_READ_SIZE);
+        let data = sftp.read_range(offset, len).unwrap();
+        drop(sftp);
+        server.join().unwrap();
+        assert!(data == (offset..offset + len).map(byte_at).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::interrupt;
+use crate::watch::Changed;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+/// A reader that retries failed reads, waiting twice as long before each retry as before the
+/// last, so brief hiccups of network or USB storage don't fail reads of the image.
+pub struct Retrying<T> {
+    inner: T,
+    retries: u32,
+    delay: Duration,
+}
+
+impl<T: Read + Seek> Retrying<T> {
+    /// Wraps `inner`, retrying each failed read up to `retries` times with `delay` before the
+    /// first retry.
+    pub const fn new(inner: T, retries: u32, delay: Duration) -> Self {
+        Self {
+            inner,
+            retries,
+            delay,
+        }
+    }
+
+    /// Returns the reader being retried.
+    pub const fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Returns whether an error could go away by trying again, rather than coming from a bad request,
+/// one nobody waits for anymore or an image that [`Changed`].
+fn transient(err: &io::Error) -> bool {
+    !matches!(
+        err.kind(),
+        io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported | io::ErrorKind::NotFound
+    ) && err.raw_os_error() != Some(libc::ECANCELED)
+        && !Changed::is(err)
+}
+
+impl<T: Read + Seek> Read for Retrying<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.inner.stream_position()?;
+        let mut delay = self.delay;
+        let mut retries = 0;
+        loop {
+            match self.inner.read(buf) {
+                Err(err) if retries < self.retries && transient(&err) => {
+                    eprintln!("read at {position:#x} failed, retrying in {delay:?}: {err}");
+                    // Nobody waits for the read anymore if the process asking for it was killed
+                    interrupt::sleep(delay)?;
+                    delay = delay.saturating_mul(2);
+                    retries += 1;
+                    // A failed read can leave the position anywhere
+                    self.inner.seek(SeekFrom::Start(position))?;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<T: Read + Seek> Seek for Retrying<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader failing every read with the error `fail` makes, counting them.
+    struct Failing<F> {
+        fail: F,
+        reads: u32,
+    }
+
+    impl<F: Fn() -> io::Error> Read for Failing<F> {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            Err((self.fail)())
+        }
+    }
+
+    impl<F> Seek for Failing<F> {
+        fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    /// Returns how many times a read failing with the errors `fail` makes is tried.
+    fn tries(fail: impl Fn() -> io::Error) -> u32 {
+        let mut retrying = Retrying::new(Failing { fail, reads: 0 }, 2, Duration::ZERO);
+        assert!(retrying.read(&mut [0; 4]).is_err());
+        retrying.inner.reads
+    }
+
+    #[test]
+    fn changed_not_retried() {
+        assert_eq!(tries(|| io::Error::other(Changed)), 1);
+        // Real stale handles, as from NFS, can go away
+        assert_eq!(tries(|| io::ErrorKind::StaleNetworkFileHandle.into()), 3);
+        assert_eq!(tries(|| io::ErrorKind::NotFound.into()), 1);
+    }
+}
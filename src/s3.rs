@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::http;
+use hmac::Hmac;
+use hmac::Mac;
+use sha2::Digest;
+use sha2::Sha256;
+use std::env;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+/// SHA-256 of an empty body, which is what every request sent has.
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Credentials to sign S3 requests with, from the usual AWS environment variables.
+pub struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    region: String,
+}
+
+/// Returns the region to use, from `AWS_REGION` or `AWS_DEFAULT_REGION`.
+fn region() -> String {
+    env::var("AWS_REGION")
+        .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+        .unwrap_or_else(|_| String::from("us-east-1"))
+}
+
+/// Returns the URL of the object with the given key in `bucket`, using the S3-compatible server
+/// in `AWS_ENDPOINT_URL_S3` or `AWS_ENDPOINT_URL` if set, and AWS otherwise.
+#[must_use]
+pub fn object_url(bucket: &str, key: &str) -> String {
+    let key = http::percent_encode(key);
+    let endpoint = env::var("AWS_ENDPOINT_URL_S3").or_else(|_| env::var("AWS_ENDPOINT_URL"));
+    // Other servers rarely support virtual hosted buckets, so they get the bucket in the path
+    endpoint.map_or_else(
+        |_| format!("https://{bucket}.s3.{}.amazonaws.com/{key}", region()),
+        |endpoint| format!("{}/{bucket}/{key}", endpoint.trim_end_matches('/')),
+    )
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+impl Credentials {
+    /// Reads credentials from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, with an optional
+    /// `AWS_SESSION_TOKEN`. Returns `None` if they aren't set, for public buckets.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            access_key: env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_key: env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            region: region(),
+        })
+    }
+
+    /// Returns the headers signing a GET request for `range` of the object at `url`, with
+    /// Signature Version 4.
+    #[must_use]
+    pub fn sign(&self, url: &str, range: &str, time: SystemTime) -> Vec<(&'static str, String)> {
+        let (year, month, day, hours, minutes, seconds, _) = http::civil(time);
+        let date = format!("{year:04}{month:02}{day:02}");
+        let timestamp = format!("{date}T{hours:02}{minutes:02}{seconds:02}Z");
+        let after_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let (host, path) =
+            after_scheme.split_at(after_scheme.find('/').unwrap_or(after_scheme.len()));
+        let path = if path.is_empty() { "/" } else { path };
+
+        let mut headers = vec![
+            ("host", host.to_string()),
+            ("range", range.to_string()),
+            ("x-amz-content-sha256", EMPTY_SHA256.to_string()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers =
+            headers
+                .iter()
+                .fold(String::new(), |mut canonical, (name, value)| {
+                    let _ = writeln!(canonical, "{name}:{}", value.trim());
+                    canonical
+                });
+        let canonical_request =
+            format!("GET\n{path}\n\n{canonical_headers}\n{signed_headers}\n{EMPTY_SHA256}");
+
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        let key = hmac(&key, &self.region);
+        let key = hmac(&key, "s3");
+        let key = hmac(&key, "aws4_request");
+        let signature = hex(&hmac(&key, &string_to_sign));
+
+        // Host and range are already sent
+        headers.drain(..2);
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+                 Signature={signature}",
+                self.access_key
+            ),
+        ));
+        headers
+    }
+}
@@ -0,0 +1,385 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::ffi::CString;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::ptr;
+
+// Landlock definitions from linux/landlock.h, which libc doesn't have
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+const ACCESS_REMOVE_DIR: u64 = 1 << 4;
+const ACCESS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_MAKE_DIR: u64 = 1 << 7;
+const ACCESS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_REFER: u64 = 1 << 13;
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+
+/// Access rights that only apply to files, the only ones a rule for a file can allow.
+const FILE_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE;
+const READ_ACCESS: u64 = ACCESS_READ_FILE | ACCESS_READ_DIR;
+const WRITE_ACCESS: u64 = READ_ACCESS
+    | ACCESS_WRITE_FILE
+    | ACCESS_REMOVE_DIR
+    | ACCESS_REMOVE_FILE
+    | ACCESS_MAKE_DIR
+    | ACCESS_MAKE_REG
+    | ACCESS_REFER
+    | ACCESS_TRUNCATE;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// System calls the filesystem makes while answering requests, reading the image and files on
+/// the host. Any other fails with `EPERM`.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_lseek,
+    libc::SYS_close,
+    libc::SYS_openat,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_getdents64,
+    libc::SYS_readlinkat,
+    libc::SYS_faccessat,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat2,
+    libc::SYS_ftruncate,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_getxattr,
+    libc::SYS_lgetxattr,
+    libc::SYS_fgetxattr,
+    libc::SYS_listxattr,
+    libc::SYS_llistxattr,
+    libc::SYS_flistxattr,
+    libc::SYS_utimensat,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_futex,
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_getrandom,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_ppoll,
+    libc::SYS_pipe2,
+    libc::SYS_dup3,
+    libc::SYS_wait4,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_restart_syscall,
+    // x86-64 still has the older calls the ones above replaced, which std uses for some things
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_access,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_mkdir,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_rmdir,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_rename,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_renameat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+];
+
+/// System calls needed to read images from remote servers.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const NETWORK_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_socket,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_shutdown,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+];
+
+/// Directories read when connecting to servers, for name resolution and certificates.
+const NETWORK_PATHS: &[&str] = &["/etc", "/usr", "/lib", "/lib64"];
+
+/// What the process still needs once the filesystem is mounted, with everything else denied by
+/// [`Sandbox::apply`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Files and directories that can be read, such as an overlay or a cover.
+    pub readable: Vec<PathBuf>,
+    /// Directories whose contents can be changed, such as a writable overlay.
+    pub writable: Vec<PathBuf>,
+    /// Whether the image is read from a remote server, which needs network access.
+    pub network: bool,
+}
+
+/// Converts the result of a system call returning -1 on errors into an [`io::Result`].
+fn check(result: libc::c_long) -> io::Result<libc::c_long> {
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(result)
+}
+
+/// Adds a rule to the Landlock ruleset allowing `access` beneath `path`, or to `path` itself if
+/// it's a file.
+fn add_rule(ruleset: &OwnedFd, path: &Path, access: u64) -> io::Result<()> {
+    let path_name = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is a valid C string, and the result is checked before being used as a fd
+    let fd =
+        check(unsafe { libc::open(path_name.as_ptr(), libc::O_PATH | libc::O_CLOEXEC).into() })?;
+    // SAFETY: the fd was just opened and nothing else owns it
+    #[allow(clippy::cast_possible_truncation)]
+    let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+    let access = if path.is_dir() {
+        access
+    } else {
+        access & FILE_ACCESS
+    };
+    let attr = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: fd.as_raw_fd(),
+    };
+    // SAFETY: the attribute matches the kernel's layout and outlives the call
+    check(unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            ptr::from_ref(&attr),
+            0,
+        )
+    })?;
+    Ok(())
+}
+
+/// Returns the file access rights the running kernel's version of Landlock handles, or `None`
+/// if it doesn't support Landlock.
+fn landlock_access() -> io::Result<Option<u64>> {
+    // SAFETY: asking for the ABI version takes no attribute
+    let version = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            ptr::null::<RulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    match check(version) {
+        Ok(1) => Ok(Some((1 << 13) - 1)),
+        Ok(2) => Ok(Some((1 << 14) - 1)),
+        Ok(_) => Ok(Some((1 << 15) - 1)),
+        Err(err) if matches!(err.raw_os_error(), Some(libc::ENOSYS | libc::EOPNOTSUPP)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Restricts the files the process can open with Landlock. Returns whether the kernel supports
+/// it.
+fn restrict_files(sandbox: &Sandbox) -> io::Result<bool> {
+    let Some(handled) = landlock_access()? else {
+        return Ok(false);
+    };
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    // SAFETY: the attribute matches the kernel's layout and outlives the call
+    let fd = check(unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            ptr::from_ref(&attr),
+            size_of::<RulesetAttr>(),
+            0,
+        )
+    })?;
+    // SAFETY: the fd was just created and nothing else owns it
+    #[allow(clippy::cast_possible_truncation)]
+    let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+    for path in &sandbox.readable {
+        add_rule(&ruleset, path, READ_ACCESS & handled)?;
+    }
+    for path in &sandbox.writable {
+        add_rule(&ruleset, path, WRITE_ACCESS & handled)?;
+    }
+    if sandbox.network {
+        for path in NETWORK_PATHS
+            .iter()
+            .map(Path::new)
+            .filter(|path| path.exists())
+        {
+            add_rule(&ruleset, path, (READ_ACCESS | ACCESS_EXECUTE) & handled)?;
+        }
+    }
+    // SAFETY: restricting the process only takes the ruleset
+    check(unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) })?;
+    Ok(true)
+}
+
+/// Returns a BPF statement.
+const fn statement(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+/// Restricts the system calls the process can make with seccomp.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn restrict_syscalls(sandbox: &Sandbox) -> io::Result<()> {
+    // BPF opcodes fit in 16 bits
+    #[allow(clippy::cast_possible_truncation)]
+    const LOAD: u16 = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+    #[allow(clippy::cast_possible_truncation)]
+    const JUMP_IF_EQUAL: u16 = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+    #[allow(clippy::cast_possible_truncation)]
+    const RETURN: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
+    // Offsets of the fields of seccomp_data
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    let mut syscalls = SYSCALLS.to_vec();
+    if sandbox.network {
+        syscalls.extend_from_slice(NETWORK_SYSCALLS);
+    }
+    let mut filter = vec![
+        statement(LOAD, ARCH),
+        libc::sock_filter {
+            code: JUMP_IF_EQUAL,
+            jt: 1,
+            jf: 0,
+            k: AUDIT_ARCH,
+        },
+        statement(RETURN, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(LOAD, NR),
+    ];
+    for syscall in syscalls {
+        // System call numbers are small and positive
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        filter.push(libc::sock_filter {
+            code: JUMP_IF_EQUAL,
+            jt: 0,
+            jf: 1,
+            k: syscall as u32,
+        });
+        filter.push(statement(RETURN, libc::SECCOMP_RET_ALLOW));
+    }
+    // EPERM is small and positive
+    #[allow(clippy::cast_sign_loss)]
+    filter.push(statement(
+        RETURN,
+        libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+    ));
+    let program = libc::sock_fprog {
+        // Far fewer instructions than the limit of 4096
+        #[allow(clippy::cast_possible_truncation)]
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    // SAFETY: the program points to the filter, which outlives the call
+    check(unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            ptr::from_ref(&program),
+        )
+    })?;
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn restrict_syscalls(_sandbox: &Sandbox) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "system calls can only be restricted on x86-64 and AArch64",
+    ))
+}
+
+impl Sandbox {
+    /// Restricts the process to what it needs to keep answering an already mounted filesystem:
+    /// Landlock only lets it open the paths given, and seccomp only lets it make the system calls
+    /// used to read them and the image and to answer the kernel. Kernels without Landlock only
+    /// get the system calls restricted, with a warning.
+    ///
+    /// This can't be undone, and applies to every thread of the process.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the process can't be restricted.
+    pub fn apply(&self) -> io::Result<()> {
+        // SAFETY: setting no_new_privs takes no pointers
+        check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) }.into())?;
+        if !restrict_files(self)? {
+            eprintln!("Landlock isn't available, only restricting system calls");
+        }
+        restrict_syscalls(self)
+    }
+}
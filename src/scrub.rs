@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::dol::Dol;
+use crate::error::Error;
+use crate::layout;
+use gcn_disk::Disc;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+/// Returns the ranges of the first `disc_size` bytes of the image that a scrubbed image keeps,
+/// sorted and merged, as `(start, end)` pairs.
+fn kept<T: Read + Seek>(io: &mut T, disc: &Disc, disc_size: u64) -> Result<Vec<(u64, u64)>, Error> {
+    let dol = Dol::read(io, disc.header.executable_offset.into())?;
+    let apploader_size = layout::apploader_size(io)?;
+    let mut used = layout::used_extents(disc, apploader_size, dol.size());
+    // Streamed audio isn't one of the FST's files, but follows them, as mounts expect
+    if disc.header.audio_streaming != 0 {
+        let after_files = used.iter().map(|&(_, end)| end).max().unwrap_or(0);
+        used.push((after_files, disc_size));
+    }
+    used.sort_unstable();
+    let mut kept: Vec<(u64, u64)> = vec![];
+    for (start, end) in used {
+        let (start, end) = (start.min(disc_size), end.min(disc_size));
+        match kept.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ if start < end => kept.push((start, end)),
+            _ => {}
+        }
+    }
+    Ok(kept)
+}
+
+/// Writes the `disc_size` bytes of the image in `io` to `out` with everything that isn't the
+/// header, bi2.bin, apploader, DOL, FST or one of the FST's files zeroed, returning how many
+/// bytes were zeroed.
+///
+/// The padding between files on retail discs is pseudo-random, so it barely compresses, while
+/// the zeroes it's replaced with take no space at all once compressed. Nothing the game reads is
+/// touched, though the image no longer matches the original dump. Discs that stream audio keep
+/// everything after their last file, where the audio is.
+///
+/// # Errors
+///
+/// [`Error::Io`] for errors reading the image or writing the new one.
+pub fn scrub<T: Read + Seek, W: Write>(
+    io: &mut T,
+    disc: &Disc,
+    disc_size: u64,
+    out: &mut W,
+) -> Result<u64, Error> {
+    let mut zeroed = 0;
+    let mut position = 0;
+    for (start, end) in kept(io, disc, disc_size)?
+        .into_iter()
+        .chain([(disc_size, disc_size)])
+    {
+        zeroed += io::copy(&mut io::repeat(0).take(start - position), out)?;
+        io.seek(SeekFrom::Start(start))?;
+        let copied = io::copy(&mut io.by_ref().take(end - start), out)?;
+        if copied != end - start {
+            return Err(
+                io::Error::new(io::ErrorKind::UnexpectedEof, "the image ended early").into(),
+            );
+        }
+        position = end;
+    }
+    Ok(zeroed)
+}
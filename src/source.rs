@@ -0,0 +1,264 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+#[cfg(target_os = "linux")]
+use crate::advice::Access;
+use crate::remote;
+use crate::remote::Remote;
+use crate::retry::Retrying;
+use crate::watch::Watch;
+use sha1::Digest;
+use sha1::Sha1;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+#[cfg(unix)]
+use std::os::fd::AsFd;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(windows)]
+use std::os::windows::io::AsHandle;
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// Where the bytes of an image come from, a local file or a remote server, optionally retrying
+/// failed reads. Reads of local files fail once they're changed by something else.
+pub enum Source {
+    File(Box<Local>),
+    Remote(Box<Remote>),
+    Retrying(Box<Retrying<Self>>),
+}
+
+/// A local image file, watched for changes, and with the kernel told how it's read.
+pub struct Local {
+    file: File,
+    watch: Watch,
+    /// Where the next read starts.
+    position: u64,
+    /// For block devices, the size they're taken to be, which reads and seeks from the end
+    /// are bounded by.
+    device_size: Option<u64>,
+    #[cfg(target_os = "linux")]
+    access: Access,
+}
+
+/// Size of a full disc. Block devices holding a dump, like a partition, are usually
+/// larger than the disc on them.
+const DISC_SIZE: u64 = 1_459_978_240;
+
+/// Creates a temporary file that's deleted as soon as it's closed.
+fn temporary_file() -> io::Result<File> {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+    let path = env::temp_dir().join(format!(".gcnfuse-{}-{nanos}", process::id()));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
+
+impl Source {
+    /// Opens the image at `path`, which can also be an `http://`, `https://`, `s3://` or `sftp://`
+    /// URL, or `-` for standard input.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the file or URL can't be opened.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        match path.to_str() {
+            Some("-") => Self::stdin(),
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+                Ok(Self::Remote(Box::new(remote::http(url)?)))
+            }
+            Some(url) if url.starts_with("s3://") => Ok(Self::Remote(Box::new(remote::s3(url)?))),
+            Some(url) if url.starts_with("sftp://") => {
+                Ok(Self::Remote(Box::new(remote::sftp(url)?)))
+            }
+            _ => Self::file(File::open(path)?, path),
+        }
+    }
+
+    /// Retries failed reads up to `retries` times, with `delay` before the first retry and twice
+    /// as long before each one after.
+    #[must_use]
+    pub fn retrying(self, retries: u32, delay: Duration) -> Self {
+        Self::Retrying(Box::new(Retrying::new(self, retries, delay)))
+    }
+
+    /// Reads the image from `file`, opened from `path`, watching for changes to it.
+    fn file(mut file: File, path: &Path) -> io::Result<Self> {
+        let watch = Watch::start(&file, path)?;
+        let position = file.stream_position()?;
+        #[cfg(unix)]
+        let device = file.metadata()?.file_type().is_block_device();
+        // Drives are only opened by their device paths there, which aren't files to watch
+        #[cfg(windows)]
+        let device = false;
+        let device_size = if device {
+            // Devices have no length, but can be seeked to their end
+            let size = file.seek(SeekFrom::End(0))?;
+            file.seek(SeekFrom::Start(position))?;
+            Some(size.min(DISC_SIZE))
+        } else {
+            None
+        };
+        Ok(Self::File(Box::new(Local {
+            file,
+            watch,
+            position,
+            device_size,
+            #[cfg(target_os = "linux")]
+            access: Access::new(),
+        })))
+    }
+
+    /// Opens the image on standard input. Images can't be read without seeking, so unless it's
+    /// redirected from a file, the input is first copied to a temporary file.
+    fn stdin() -> io::Result<Self> {
+        #[cfg(unix)]
+        let stdin = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+        #[cfg(windows)]
+        let stdin = File::from(io::stdin().as_handle().try_clone_to_owned()?);
+        if stdin.metadata()?.is_file() {
+            return Self::file(stdin, Path::new("standard input"));
+        }
+        let mut file = temporary_file()?;
+        io::copy(&mut io::stdin().lock(), &mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+        Self::file(file, Path::new("standard input"))
+    }
+
+    /// Returns the file read from, if the image is a local file.
+    #[must_use]
+    pub const fn as_file(&self) -> Option<&File> {
+        match self {
+            Self::File(local) => Some(&local.file),
+            _ => None,
+        }
+    }
+
+    /// Returns a hash of which file the image is and when it was last changed, its device, inode,
+    /// size and modification time, if it's a local file. Block devices have none, as writing
+    /// them doesn't change their modification time.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the file's metadata can't be read.
+    pub fn identity(&self) -> io::Result<Option<[u8; 20]>> {
+        let local = match self {
+            Self::File(local) if local.device_size.is_none() => local,
+            Self::File(_) | Self::Remote(_) => return Ok(None),
+            Self::Retrying(retrying) => return retrying.get_ref().identity(),
+        };
+        let metadata = local.file.metadata()?;
+        let mut sha1 = Sha1::new();
+        #[cfg(unix)]
+        {
+            sha1.update(metadata.dev().to_be_bytes());
+            sha1.update(metadata.ino().to_be_bytes());
+        }
+        sha1.update(metadata.len().to_be_bytes());
+        let modified = metadata.modified()?.duration_since(SystemTime::UNIX_EPOCH);
+        sha1.update(modified.unwrap_or_default().as_nanos().to_be_bytes());
+        Ok(Some(sha1.finalize().into()))
+    }
+
+    /// Returns what tells whether the image was changed, if it's a local file.
+    #[must_use]
+    pub fn watch(&self) -> Option<&Watch> {
+        match self {
+            Self::File(local) => Some(&local.watch),
+            Self::Remote(_) => None,
+            Self::Retrying(retrying) => retrying.get_ref().watch(),
+        }
+    }
+
+    /// Returns the size of the image. For block devices, anything past the end of a full disc
+    /// isn't considered part of it.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the size can't be determined.
+    pub fn size(&self) -> io::Result<u64> {
+        match self {
+            Self::File(local) => match local.device_size {
+                Some(size) => Ok(size),
+                None => Ok(local.file.metadata()?.len()),
+            },
+            Self::Remote(remote) => Ok(remote.size()),
+            Self::Retrying(retrying) => retrying.get_ref().size(),
+        }
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(local) => local.read(buf),
+            Self::Remote(remote) => remote.read(buf),
+            Self::Retrying(retrying) => retrying.read(buf),
+        }
+    }
+}
+
+impl Seek for Source {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(local) => local.seek(pos),
+            Self::Remote(remote) => remote.seek(pos),
+            Self::Retrying(retrying) => retrying.seek(pos),
+        }
+    }
+}
+
+impl Read for Local {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.watch.check()?;
+        let buf = match self.device_size {
+            Some(size) => {
+                let left = size.saturating_sub(self.position);
+                let len = usize::try_from(left).map_or(buf.len(), |left| left.min(buf.len()));
+                &mut buf[..len]
+            }
+            None => buf,
+        };
+        let len = self.file.read(buf)?;
+        #[cfg(target_os = "linux")]
+        self.access
+            .record(&self.file, self.position, self.position + len as u64);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for Local {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        // The end of a device is where the disc on it ends, not where the device does
+        let pos = match (pos, self.device_size) {
+            (SeekFrom::End(offset), Some(size)) => {
+                SeekFrom::Start(size.checked_add_signed(offset).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "invalid seek to a negative or overflowing position",
+                    )
+                })?)
+            }
+            (pos, _) => pos,
+        };
+        self.position = self.file.seek(pos)?;
+        Ok(self.position)
+    }
+}
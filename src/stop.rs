@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::io;
+#[cfg(unix)]
+use std::mem::MaybeUninit;
+use std::process;
+#[cfg(unix)]
+use std::ptr;
+#[cfg(windows)]
+use std::sync::Condvar;
+#[cfg(windows)]
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+#[cfg(windows)]
+use windows_sys::Win32::Foundation::FALSE;
+#[cfg(windows)]
+use windows_sys::Win32::Foundation::TRUE;
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::CTRL_BREAK_EVENT;
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::CTRL_C_EVENT;
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+#[cfg(windows)]
+use windows_sys::core::BOOL;
+
+/// Signals asking the process to stop, as sent by service managers and Ctrl-C.
+#[cfg(unix)]
+const SIGNALS: [libc::c_int; 2] = [libc::SIGTERM, libc::SIGINT];
+
+/// How long stopping may take before the process exits regardless.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether Ctrl-C or Ctrl-Break was pressed, set by the console's handler.
+#[cfg(windows)]
+static STOPPING: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+/// Returns the set of [`SIGNALS`].
+#[cfg(unix)]
+fn signal_set() -> libc::sigset_t {
+    let mut set = MaybeUninit::uninit();
+    // SAFETY: the set is initialized by sigemptyset before signals are added to it
+    unsafe {
+        libc::sigemptyset(set.as_mut_ptr());
+        for signal in SIGNALS {
+            libc::sigaddset(set.as_mut_ptr(), signal);
+        }
+        set.assume_init()
+    }
+}
+
+/// Leaves the signals asking the process to stop to [`on_stop`].
+///
+/// They're blocked in this thread and the threads it starts from then on, so they don't end the
+/// process with its mounts left behind, unusable.
+///
+/// # Errors
+///
+/// [`io::Error`] if the signals can't be blocked.
+#[cfg(unix)]
+pub fn block_stop_signals() -> io::Result<()> {
+    let set = signal_set();
+    // SAFETY: the set outlives the call, and the old set isn't asked for
+    match unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &raw const set, ptr::null_mut()) } {
+        0 => Ok(()),
+        err => Err(io::Error::from_raw_os_error(err)),
+    }
+}
+
+/// Calls `stop` on a thread of its own once a signal blocked by [`block_stop_signals`] arrives.
+///
+/// `stop` should have the process unmount and exit. If it's still running a while later, such as
+/// when it isn't allowed to unmount, it exits regardless.
+#[cfg(unix)]
+pub fn on_stop(stop: impl FnOnce() + Send + 'static) {
+    thread::spawn(move || {
+        let set = signal_set();
+        let mut signal = 0;
+        // SAFETY: the set and signal outlive the call
+        if unsafe { libc::sigwait(&raw const set, &raw mut signal) } != 0 {
+            return;
+        }
+        stop();
+        thread::sleep(STOP_TIMEOUT);
+        eprintln!("can't unmount, exiting with the mount left behind");
+        process::exit(1);
+    });
+}
+
+/// Tells [`on_stop`] Ctrl-C or Ctrl-Break was pressed, for the console's other events to end the
+/// process as usual. `WinFsp` removes the drives of a process that ends.
+#[cfg(windows)]
+unsafe extern "system" fn handle_console_event(event: u32) -> BOOL {
+    if event != CTRL_C_EVENT && event != CTRL_BREAK_EVENT {
+        return FALSE;
+    }
+    let (stopping, changed) = &STOPPING;
+    *stopping
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = true;
+    changed.notify_all();
+    TRUE
+}
+
+/// Leaves Ctrl-C and Ctrl-Break to [`on_stop`], rather than having them end the process at once.
+///
+/// # Errors
+///
+/// [`io::Error`] if the console's handler can't be set.
+#[cfg(windows)]
+pub fn block_stop_signals() -> io::Result<()> {
+    // SAFETY: the handler is a function for as long as the process runs
+    if unsafe { SetConsoleCtrlHandler(Some(handle_console_event), TRUE) } == FALSE {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Calls `stop` on a thread of its own once Ctrl-C or Ctrl-Break is pressed, after
+/// [`block_stop_signals`].
+///
+/// `stop` should have the process unmount and exit. If it's still running a while later, it
+/// exits regardless.
+#[cfg(windows)]
+pub fn on_stop(stop: impl FnOnce() + Send + 'static) {
+    thread::spawn(move || {
+        let (stopping, changed) = &STOPPING;
+        let stopping = stopping
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        drop(
+            changed
+                .wait_while(stopping, |stopping| !*stopping)
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        );
+        stop();
+        thread::sleep(STOP_TIMEOUT);
+        eprintln!("can't unmount, exiting with the mount left behind");
+        process::exit(1);
+    });
+}
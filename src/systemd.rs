@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::env;
+use std::io;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::fd::RawFd;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::SocketAddr;
+use std::os::unix::net::UnixDatagram;
+use std::os::unix::net::UnixListener;
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// The first file descriptor passed by socket activation, after standard input, output and error.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Whether the passed socket was already returned by [`activated_listener`].
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Tells the service manager that started the process, if any, about a change of its `state`,
+/// such as `READY=1` once it's mounted.
+///
+/// # Errors
+///
+/// [`io::Error`] if the service manager can't be told.
+pub fn notify(state: &str) -> io::Result<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let address = match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        _ => SocketAddr::from_pathname(&path)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+/// Returns the socket passed by the service manager that started the process, if it was started
+/// for connections to one. It's only passed once, so later calls return `None`.
+///
+/// # Errors
+///
+/// [`io::Error`] if more than the one socket was passed.
+pub fn activated_listener() -> io::Result<Option<UnixListener>> {
+    // The variables are inherited by children, which they aren't meant for
+    let for_process = env::var("LISTEN_PID").ok() == Some(process::id().to_string());
+    let count = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse().ok());
+    match count {
+        Some(1) if for_process && !TAKEN.swap(true, Ordering::Relaxed) => {}
+        Some(count) if count > 1 && for_process => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{count} sockets were passed, rather than one"),
+            ));
+        }
+        _ => return Ok(None),
+    }
+    // Passed descriptors are inherited by children unless told otherwise, and this fails if it
+    // wasn't passed after all
+    // SAFETY: setting flags takes no pointers
+    if unsafe { libc::fcntl(LISTEN_FDS_START, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the service manager passed the descriptor for this process to own
+    let fd = unsafe { OwnedFd::from_raw_fd(LISTEN_FDS_START) };
+    Ok(Some(UnixListener::from(fd)))
+}
@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use gcn_disk::disc::Header;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Returns the six character game ID of the disc, like `GALE01`.
+#[must_use]
+pub fn game_id(header: &Header) -> String {
+    format!(
+        "{}{}{}{}",
+        header.console_id, header.game_code, header.country_code, header.maker_code
+    )
+}
+
+/// Game titles by game ID, as found in the `wiitdb.txt` that `GameTDB` publishes.
+#[derive(Clone, Debug, Default)]
+pub struct TitleDatabase {
+    titles: HashMap<String, String>,
+}
+
+impl TitleDatabase {
+    /// Loads a title database in the `wiitdb.txt` format, where each line maps an ID to a title
+    /// like `GALE01 = Super Smash Bros. Melee`. Lines that don't look like that are ignored.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the file can't be read.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read(path)?;
+        let titles = String::from_utf8_lossy(&contents)
+            .lines()
+            .filter_map(|line| {
+                let (id, title) = line.split_once('=')?;
+                let (id, title) = (id.trim(), title.trim());
+                let valid = (4..=6).contains(&id.len())
+                    && id.bytes().all(|byte| byte.is_ascii_alphanumeric())
+                    && !title.is_empty();
+                valid.then(|| (id.to_ascii_uppercase(), title.to_string()))
+            })
+            .collect();
+        Ok(Self { titles })
+    }
+
+    /// Returns the title of the game with the given ID, falling back to the four character ID
+    /// shared by all of a game's publishers.
+    #[must_use]
+    pub fn title(&self, game_id: &str) -> Option<&str> {
+        self.titles
+            .get(game_id)
+            .or_else(|| self.titles.get(game_id.get(..4)?))
+            .map(String::as_str)
+    }
+}
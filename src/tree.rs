@@ -0,0 +1,625 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::archive::Member;
+use crate::audio::Codec;
+use crate::error::Error;
+use crate::fst;
+use crate::options::Options;
+use gcn_disk::DirectoryEntry;
+use gcn_disk::Disc;
+use gcn_disk::Entry;
+use gcn_disk::Fst;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Inode(pub u64);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Index(pub u32);
+
+impl From<u32> for Index {
+    fn from(val: u32) -> Self {
+        Self(val)
+    }
+}
+
+impl From<Index> for u32 {
+    fn from(val: Index) -> Self {
+        val.0
+    }
+}
+
+impl Index {
+    /// Returns the index as a `usize`, for indexing into the FST entries.
+    #[must_use]
+    pub fn as_usize(self) -> usize {
+        // This crate, like gcn_disk, doesn't support 16-bit platforms
+        usize::try_from(self.0).unwrap()
+    }
+}
+
+impl From<u64> for Inode {
+    fn from(val: u64) -> Self {
+        Self(val)
+    }
+}
+
+impl From<Inode> for u64 {
+    fn from(val: Inode) -> Self {
+        val.0
+    }
+}
+
+impl From<Inode> for Index {
+    fn from(val: Inode) -> Self {
+        // FST can only have u32 worth of inodes/entries, so this cast is guaranteed to work
+        #[allow(clippy::cast_possible_truncation)]
+        ((val.0 - 1) as u32).into()
+    }
+}
+
+impl From<Index> for Inode {
+    fn from(val: Index) -> Self {
+        // FST can only have u32 worth of inodes/entries, so this cast is guaranteed to work
+        #[allow(clippy::cast_possible_truncation)]
+        (u64::from(val.0) + 1).into()
+    }
+}
+
+/// Where the contents of a file come from.
+#[derive(Clone, Debug)]
+pub enum FileData {
+    /// A file on the disc, described by the FST entry at this index.
+    Disc(Index),
+    /// A file on the host, usually from the overlay directory.
+    Host(PathBuf),
+    /// `size` bytes starting at `offset` in the contents of another file, such as a file inside
+    /// an archive.
+    Slice {
+        source: Inode,
+        offset: u64,
+        size: u64,
+    },
+    /// Yaz0 or Yay0 compressed `data`, which decompresses to `size` bytes.
+    Compressed { data: Box<Self>, size: u64 },
+    /// The audio in the file `source`, converted to a WAV file of `size` bytes.
+    Wav {
+        source: Inode,
+        codec: Codec,
+        size: u64,
+    },
+    /// `size` bytes at `offset` in the disc image, for data outside of any FST file.
+    Image { offset: u64, size: u64 },
+    /// The boot DOL converted to an ELF file of `size` bytes.
+    Elf { size: u64 },
+    /// Contents made up when the image was opened, such as a report about it.
+    Generated(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+pub enum Kind {
+    File(FileData),
+    /// A directory, holding the inodes of its direct children in listing order.
+    Directory(Vec<Inode>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub parent: Inode,
+    pub kind: Kind,
+}
+
+/// Prefix of overlay files marking that the disc entry with the rest of the name was deleted.
+pub const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// Name of the overlay file marking that a directory hides all of the disc's entries in it.
+pub const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+/// The namespace exposed through FUSE.
+///
+/// The first nodes mirror the FST one to one, so an FST entry at index `i` is always inode
+/// `i + 1`. Nodes that don't come from the disc (such as overlay files) are appended after them.
+pub struct Tree {
+    nodes: Vec<Node>,
+    /// The number of nodes that mirror FST entries.
+    fst_len: usize,
+}
+
+/// Returns an iterator over the indices of the direct children of the given directory.
+fn fst_children<'a>(fs: &'a Fst, directory: &DirectoryEntry) -> impl Iterator<Item = Index> + 'a {
+    let mut index = directory.index + 1;
+    let end_index = directory.end_index;
+    std::iter::from_fn(move || {
+        if index >= end_index {
+            return None;
+        }
+        let current = index;
+        index = match &fs.entries[usize::try_from(current).unwrap()] {
+            Entry::File(_) => current + 1,
+            Entry::Directory(directory) => directory.end_index,
+        };
+        Some(current.into())
+    })
+}
+
+/// Returns `name` with a `~N` suffix inserted before its extension, if it has one.
+pub fn disambiguate(name: &str, n: u32) -> String {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{}~{n}{}", &name[..dot], &name[dot..]),
+        _ => format!("{name}~{n}"),
+    }
+}
+
+/// Reads the names of all FST entries, renaming entries that share a name with an earlier
+/// sibling (e.g. the second `name.ext` becomes `name~2.ext`).
+///
+/// Names that can't be read or can't be listed are replaced with `entry-N`, `N` being the
+/// entry's index, and added to `problems`, as are names starting past `string_table_end`, if
+/// known.
+fn read_names<T: Read + Seek>(
+    io: &mut T,
+    fs: &Fst,
+    image_size: u64,
+    string_table_end: Option<u64>,
+    options: &Options,
+    problems: &mut Vec<String>,
+) -> Result<Vec<String>, Error> {
+    let mut names = vec![String::new(); fs.entries.len()];
+    for (index, entry) in fs.entries.iter().enumerate().skip(1) {
+        let offset = match entry {
+            Entry::File(file) => file.filename_offset,
+            Entry::Directory(directory) => directory.filename_offset,
+        };
+        let placeholder = format!("entry-{index}");
+        // gcn_disk can't read a name starting at the end of the image
+        if u64::from(fs.string_table_offset) + u64::from(offset) >= image_size {
+            problems.push(format!(
+                "name of FST entry {index} is past the end of the image"
+            ));
+            names[index] = placeholder;
+            continue;
+        }
+        if string_table_end
+            .is_some_and(|end| u64::from(fs.string_table_offset) + u64::from(offset) >= end)
+        {
+            problems.push(format!(
+                "name of FST entry {index} is past the end of the FST"
+            ));
+        }
+        let name = match fs.get_entry_filename(io, entry) {
+            Ok(name) => name,
+            Err(gcn_disk::Error::Io(err)) => return Err(err.into()),
+            Err(err) => {
+                problems.push(format!("name of FST entry {index} can't be read: {err}"));
+                names[index] = placeholder;
+                continue;
+            }
+        };
+        names[index] = match fst::name_problem(&name) {
+            None => name,
+            Some(problem) => {
+                problems.push(format!("name \"{name}\" of FST entry {index} {problem}"));
+                placeholder
+            }
+        };
+    }
+
+    for entry in &fs.entries {
+        let Entry::Directory(directory) = entry else {
+            continue;
+        };
+        let children: Vec<usize> = fst_children(fs, directory).map(Index::as_usize).collect();
+        // Compare names the same way lookup does, so anything lookup would confuse is renamed
+        let mut taken: HashSet<String> = children
+            .iter()
+            .map(|&index| options.normalize(&names[index]).into_owned())
+            .collect();
+        let mut seen = HashSet::new();
+        for index in children {
+            let key = options.normalize(&names[index]).into_owned();
+            if seen.insert(key) {
+                continue;
+            }
+            let mut n = 2;
+            let renamed = loop {
+                let candidate = disambiguate(&names[index], n);
+                if taken.insert(options.normalize(&candidate).into_owned()) {
+                    break candidate;
+                }
+                n += 1;
+            };
+            eprintln!(
+                "duplicate name \"{}\" at FST index {index}, exposing it as \"{renamed}\"",
+                names[index]
+            );
+            names[index] = renamed;
+        }
+    }
+    Ok(names)
+}
+
+impl Tree {
+    /// Builds the tree mirroring the given disc's FST.
+    ///
+    /// Problems found in the FST are handled as [`Options::strictness`] asks, which can mean
+    /// fixing entries in the FST, see [`fst::check`].
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Io`] if reading the image fails, and [`Error::Fst`] if there are problems with
+    /// the FST and [`Strictness::Strict`](crate::Strictness::Strict) was asked for.
+    pub fn new<T: Read + Seek>(
+        io: &mut T,
+        disc: &mut Disc,
+        options: &Options,
+    ) -> Result<Self, Error> {
+        let image_size = io.seek(SeekFrom::End(0))?;
+        // Some discs, like Datel's, leave the size of the FST unset
+        let string_table_end = (disc.header.fst_size != 0)
+            .then(|| u64::from(disc.header.fst_offset) + u64::from(disc.header.fst_size));
+        let fs = &mut disc.filesystem;
+        let mut problems = fst::check(fs, image_size, options.strictness);
+        let names = read_names(io, fs, image_size, string_table_end, options, &mut problems)?;
+        fst::report(problems, options.strictness)?;
+        let nodes = fs
+            .entries
+            .iter()
+            .zip(names)
+            .map(|(entry, name)| match entry {
+                Entry::File(file) => Node {
+                    name,
+                    // Files don't record their parent, it's filled in below
+                    parent: Inode(1),
+                    kind: Kind::File(FileData::Disc(file.index.into())),
+                },
+                Entry::Directory(directory) => Node {
+                    name,
+                    parent: Index(directory.parent_index).into(),
+                    kind: Kind::Directory(fst_children(fs, directory).map(Inode::from).collect()),
+                },
+            })
+            .collect();
+        let mut tree = Self {
+            fst_len: fs.entries.len(),
+            nodes,
+        };
+        for index in 0..tree.nodes.len() {
+            if let Kind::Directory(children) = &tree.nodes[index].kind {
+                let parent = Inode(u64::try_from(index).unwrap() + 1);
+                for child in children.clone() {
+                    tree.node_mut(child).parent = parent;
+                }
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Returns a tree with just an empty root directory, such as when building a new image from
+    /// a host directory with [`Tree::overlay`].
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            nodes: vec![Node {
+                name: String::new(),
+                parent: Inode(1),
+                kind: Kind::Directory(vec![]),
+            }],
+            fst_len: 0,
+        }
+    }
+
+    /// Returns the node with the given inode, if there is one.
+    #[must_use]
+    pub fn get(&self, inode: Inode) -> Option<&Node> {
+        let index = usize::try_from(inode.0.checked_sub(1)?).ok()?;
+        self.nodes.get(index)
+    }
+
+    /// Returns the node with the given inode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such node.
+    pub fn node_mut(&mut self, inode: Inode) -> &mut Node {
+        &mut self.nodes[usize::try_from(inode.0 - 1).unwrap()]
+    }
+
+    /// Returns the inodes of the direct children of `inode`, or `None` if it isn't a directory.
+    #[must_use]
+    pub fn children(&self, inode: Inode) -> Option<&[Inode]> {
+        match &self.get(inode)?.kind {
+            Kind::Directory(children) => Some(children),
+            Kind::File(_) => None,
+        }
+    }
+
+    /// Finds the child of `parent` called `name`, comparing names as described by
+    /// [`Options::normalize`].
+    #[must_use]
+    pub fn lookup(&self, parent: Inode, name: &str, options: &Options) -> Option<Inode> {
+        let name = options.normalize(name);
+        self.children(parent)?
+            .iter()
+            .copied()
+            .find(|&child| options.normalize(&self.get(child).unwrap().name) == name)
+    }
+
+    /// Returns whether the node has a counterpart in the disc's FST.
+    ///
+    /// This stays true for disc files that have been replaced by overlay files.
+    #[must_use]
+    pub fn is_from_disc(&self, inode: Inode) -> bool {
+        usize::try_from(inode.0).is_ok_and(|inode| inode <= self.fst_len)
+    }
+
+    /// Returns the path of the node relative to the root of the tree.
+    #[must_use]
+    pub fn path(&self, inode: Inode) -> PathBuf {
+        let mut names = vec![];
+        let mut current = inode;
+        while current != Inode(1) {
+            let node = self.get(current).unwrap();
+            names.push(node.name.as_str());
+            current = node.parent;
+        }
+        names.iter().rev().collect()
+    }
+
+    /// Detaches the node from its parent, so it can no longer be found.
+    pub fn remove(&mut self, inode: Inode) {
+        let parent = self.get(inode).unwrap().parent;
+        if let Kind::Directory(children) = &mut self.node_mut(parent).kind {
+            children.retain(|&child| child != inode);
+        }
+    }
+
+    /// Adds a new node as the last child of `parent`, returning its inode.
+    pub fn add(&mut self, parent: Inode, name: String, kind: Kind) -> Inode {
+        self.nodes.push(Node { name, parent, kind });
+        let inode = Inode(u64::try_from(self.nodes.len()).unwrap());
+        if let Kind::Directory(children) = &mut self.node_mut(parent).kind {
+            children.push(inode);
+        }
+        inode
+    }
+
+    /// Adds the members of the archive whose contents are those of `source` under the directory
+    /// `parent`, returning the inodes of the files added.
+    ///
+    /// Members sharing a name with an earlier sibling, compared as [`Tree::lookup`] does, are
+    /// renamed like duplicate FST entries. Nested directories are filled in from a queue rather
+    /// than by recursing, like archives are parsed.
+    pub fn add_archive(
+        &mut self,
+        parent: Inode,
+        source: Inode,
+        members: Vec<Member>,
+        options: &Options,
+    ) -> Vec<Inode> {
+        let mut files = vec![];
+        let mut pending = vec![(parent, members)];
+        while let Some((parent, members)) = pending.pop() {
+            let mut taken = HashSet::new();
+            for member in members {
+                let (name, kind, members) = match member {
+                    Member::File { name, offset, size } => {
+                        let data = FileData::Slice {
+                            source,
+                            offset,
+                            size,
+                        };
+                        (name, Kind::File(data), None)
+                    }
+                    Member::Directory { name, members } => {
+                        (name, Kind::Directory(vec![]), Some(members))
+                    }
+                };
+                let mut unique = name.clone();
+                let mut n = 2;
+                while !taken.insert(options.normalize(&unique).into_owned()) {
+                    unique = disambiguate(&name, n);
+                    n += 1;
+                }
+                let inode = self.add(parent, unique, kind);
+                match members {
+                    Some(members) => pending.push((inode, members)),
+                    None => files.push(inode),
+                }
+            }
+        }
+        files
+    }
+
+    /// Merges the host directory `dir` on top of the directory `parent`.
+    ///
+    /// Host files replace disc files with the same path, host directories are merged with disc
+    /// directories with the same path, and anything else is added as a new entry. Whiteout files
+    /// (`.wh.name`) hide the disc entry `name`, and an opaque marker (`.wh..wh..opq`) hides every
+    /// disc entry in its directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns any IO error encountered while traversing `dir`.
+    pub fn overlay(&mut self, parent: Inode, dir: &Path, options: &Options) -> io::Result<()> {
+        let mut entries = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                eprintln!(
+                    "skipping overlay entry with a non UTF-8 name: {}",
+                    path.display()
+                );
+                continue;
+            };
+            entries.push((name, entry));
+        }
+        // Keep the order new entries show up in stable across mounts
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // Deal with deletions first, so they can't hide entries added by the overlay itself
+        for (name, _) in &entries {
+            if name == OPAQUE_MARKER {
+                for child in self.children(parent).unwrap_or_default().to_vec() {
+                    self.remove(child);
+                }
+            } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX)
+                && let Some(child) = self.lookup(parent, hidden, options)
+            {
+                self.remove(child);
+            }
+        }
+
+        for (name, entry) in entries {
+            if name.starts_with(WHITEOUT_PREFIX) {
+                continue;
+            }
+            let path = entry.path();
+            let is_dir = entry.file_type()?.is_dir();
+            match self.lookup(parent, &name, options) {
+                Some(existing) => match (&mut self.node_mut(existing).kind, is_dir) {
+                    (Kind::File(data), false) => *data = FileData::Host(path),
+                    (Kind::Directory(_), true) => self.overlay(existing, &path, options)?,
+                    // The overlay replaced a file with a directory or the other way around
+                    _ => {
+                        self.remove(existing);
+                        self.add_host(parent, name, path, is_dir, options)?;
+                    }
+                },
+                None => self.add_host(parent, name, path, is_dir, options)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the host file or directory at `path` under `parent`, along with its contents.
+    fn add_host(
+        &mut self,
+        parent: Inode,
+        name: String,
+        path: PathBuf,
+        is_dir: bool,
+        options: &Options,
+    ) -> io::Result<()> {
+        if is_dir {
+            let inode = self.add(parent, name, Kind::Directory(vec![]));
+            self.overlay(inode, &path, options)
+        } else {
+            self.add(parent, name, Kind::File(FileData::Host(path)));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Normalization;
+    use std::env;
+    use std::process;
+
+    /// Returns the names of the children of `inode`, in listing order.
+    fn names(tree: &Tree, inode: Inode) -> Vec<&str> {
+        tree.children(inode)
+            .unwrap()
+            .iter()
+            .map(|&child| tree.get(child).unwrap().name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn overlay_merges_and_hides() {
+        let options = Options::default();
+        let mut tree = Tree::empty();
+        let disc = |index| Kind::File(FileData::Disc(Index(index)));
+        tree.add(Inode(1), "a.txt".into(), disc(1));
+        tree.add(Inode(1), "keep.txt".into(), disc(2));
+        tree.add(Inode(1), "gone.txt".into(), disc(3));
+        let data = tree.add(Inode(1), "data".into(), Kind::Directory(vec![]));
+        tree.add(data, "old.bin".into(), disc(5));
+        let wiped = tree.add(Inode(1), "wiped".into(), Kind::Directory(vec![]));
+        tree.add(wiped, "x.bin".into(), disc(7));
+
+        let dir = env::temp_dir().join(format!(".gcnfuse-overlay-{}", process::id()));
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::create_dir_all(dir.join("wiped")).unwrap();
+        for file in [
+            "a.txt",
+            "new.txt",
+            ".wh.gone.txt",
+            "data/new.bin",
+            "wiped/.wh..wh..opq",
+            "wiped/y.bin",
+        ] {
+            fs::write(dir.join(file), file).unwrap();
+        }
+        let merged = tree.overlay(Inode(1), &dir, &options);
+        let _ = fs::remove_dir_all(&dir);
+        merged.unwrap();
+
+        assert_eq!(
+            names(&tree, Inode(1)),
+            ["a.txt", "keep.txt", "data", "wiped", "new.txt"]
+        );
+        let kind = |name| {
+            &tree
+                .get(tree.lookup(Inode(1), name, &options).unwrap())
+                .unwrap()
+                .kind
+        };
+        assert!(
+            matches!(kind("a.txt"), Kind::File(FileData::Host(path)) if path == &dir.join("a.txt"))
+        );
+        assert!(matches!(
+            kind("keep.txt"),
+            Kind::File(FileData::Disc(Index(2)))
+        ));
+        assert!(tree.lookup(Inode(1), "gone.txt", &options).is_none());
+        assert_eq!(names(&tree, data), ["old.bin", "new.bin"]);
+        assert_eq!(names(&tree, wiped), ["y.bin"]);
+    }
+
+    #[test]
+    fn archive_members_renamed_like_lookup_compares() {
+        let options = Options {
+            normalization: Some(Normalization::Nfc),
+            ..Options::default()
+        };
+        let file = |name: &str, offset| Member::File {
+            name: name.into(),
+            offset,
+            size: 1,
+        };
+        let members = vec![
+            file("caf\u{e9}.bin", 0),
+            file("cafe\u{301}.bin", 1),
+            Member::Directory {
+                name: "d".into(),
+                members: vec![file("x", 2), file("x", 3)],
+            },
+        ];
+        let mut tree = Tree::empty();
+        let files = tree.add_archive(Inode(1), Inode(1), members, &options);
+        assert_eq!(files.len(), 4);
+        assert_eq!(
+            names(&tree, Inode(1)),
+            ["caf\u{e9}.bin", "cafe\u{301}~2.bin", "d"]
+        );
+        let d = tree.lookup(Inode(1), "d", &options).unwrap();
+        assert_eq!(names(&tree, d), ["x", "x~2"]);
+        let x2 = tree.lookup(d, "x~2", &options).unwrap();
+        assert!(matches!(
+            tree.get(x2).unwrap().kind,
+            Kind::File(FileData::Slice { offset: 3, .. })
+        ));
+    }
+}
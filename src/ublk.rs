@@ -0,0 +1,494 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::error::Error;
+use crate::stop::on_stop;
+use io_uring::IoUring;
+use io_uring::cqueue;
+use io_uring::opcode;
+use io_uring::squeue;
+use io_uring::types::Fd;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// ublk definitions from linux/ublk_cmd.h, which libc doesn't have
+const CMD_ADD_DEV: u32 = ioctl_encode(0x04, 32);
+const CMD_DEL_DEV: u32 = ioctl_encode(0x05, 32);
+const CMD_START_DEV: u32 = ioctl_encode(0x06, 32);
+const CMD_STOP_DEV: u32 = ioctl_encode(0x07, 32);
+const CMD_SET_PARAMS: u32 = ioctl_encode(0x08, 32);
+const IO_FETCH_REQ: u32 = ioctl_encode(0x20, 16);
+const IO_COMMIT_AND_FETCH_REQ: u32 = ioctl_encode(0x21, 16);
+
+const F_CMD_IOCTL_ENCODE: u64 = 1 << 6;
+const PARAM_TYPE_BASIC: u32 = 1 << 0;
+const ATTR_READ_ONLY: u32 = 1 << 0;
+
+const IO_OP_READ: u8 = 0;
+const IO_OP_FLUSH: u8 = 2;
+
+const IO_RES_OK: i32 = 0;
+const IO_RES_ABORT: i32 = -libc::ENODEV;
+
+/// Returns the `_IOWR('u', nr, size)` number the driver takes commands as.
+const fn ioctl_encode(nr: u32, size: u32) -> u32 {
+    (3 << 30) | (size << 16) | ((b'u' as u32) << 8) | nr
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct DevInfo {
+    nr_hw_queues: u16,
+    queue_depth: u16,
+    state: u16,
+    pad0: u16,
+    max_io_buf_bytes: u32,
+    dev_id: u32,
+    ublksrv_pid: i32,
+    pad1: u32,
+    flags: u64,
+    ublksrv_flags: u64,
+    owner_uid: u32,
+    owner_gid: u32,
+    reserved1: u64,
+    reserved2: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    len: u32,
+    types: u32,
+    attrs: u32,
+    logical_bs_shift: u8,
+    physical_bs_shift: u8,
+    io_opt_shift: u8,
+    io_min_shift: u8,
+    max_sectors: u32,
+    chunk_sectors: u32,
+    dev_sectors: u64,
+    virt_boundary_mask: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IoDesc {
+    op_flags: u32,
+    nr_sectors: u32,
+    start_sector: u64,
+    addr: u64,
+}
+
+/// ID the driver picks a free device number for when adding one with.
+const ANY_DEVICE: u32 = u32::MAX;
+
+/// Requests queued at once, and the buffers answering them.
+const QUEUE_DEPTH: u16 = 32;
+
+/// Largest request, and the size of each of their buffers.
+const IO_BUF_BYTES: u32 = 512 << 10;
+
+/// Tag the wakeup asking the queue to stop completes with.
+const STOP_TAG: u64 = u64::MAX;
+
+/// How long the character device of a new device has to appear.
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns the 80 bytes of a control command for device `id`, its buffer at `addr` of `len`
+/// bytes, and `data`.
+fn control_command(id: u32, addr: u64, len: u16, data: u64) -> [u8; 80] {
+    let mut command = [0; 80];
+    command[0..4].copy_from_slice(&id.to_ne_bytes());
+    // Control commands aren't for any one queue
+    command[4..6].copy_from_slice(&u16::MAX.to_ne_bytes());
+    command[6..8].copy_from_slice(&len.to_ne_bytes());
+    command[8..16].copy_from_slice(&addr.to_ne_bytes());
+    command[16..24].copy_from_slice(&data.to_ne_bytes());
+    command
+}
+
+/// Returns the 16 bytes of an I/O command for `tag` on the first queue, with `result` and the
+/// buffer at `addr`.
+fn io_command(tag: u16, result: i32, addr: u64) -> [u8; 16] {
+    let mut command = [0; 16];
+    command[2..4].copy_from_slice(&tag.to_ne_bytes());
+    command[4..8].copy_from_slice(&result.to_ne_bytes());
+    command[8..16].copy_from_slice(&addr.to_ne_bytes());
+    command
+}
+
+/// `/dev/ublk-control`, which devices are added, started, stopped and deleted through.
+struct Control {
+    file: File,
+    ring: IoUring<squeue::Entry128, cqueue::Entry>,
+}
+
+impl Control {
+    fn open() -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/ublk-control")
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("can't open /dev/ublk-control, which needs the ublk_drv module: {err}"),
+                )
+            })?;
+        let ring = IoUring::builder().build(4)?;
+        Ok(Self { file, ring })
+    }
+
+    /// Sends `command`, returning the driver's result.
+    fn send(&mut self, op: u32, command: [u8; 80]) -> io::Result<i32> {
+        let entry = opcode::UringCmd80::new(Fd(self.file.as_raw_fd()), op)
+            .cmd(command)
+            .build();
+        // SAFETY: the buffers the command points to outlive the wait for it below
+        unsafe { self.ring.submission().push(&entry) }
+            .map_err(|_| io::Error::other("the control ring is full"))?;
+        self.ring.submit_and_wait(1)?;
+        let result = self
+            .ring
+            .completion()
+            .next()
+            .ok_or_else(|| io::Error::other("the control command didn't complete"))?
+            .result();
+        if result < 0 {
+            return Err(io::Error::from_raw_os_error(-result));
+        }
+        Ok(result)
+    }
+
+    /// Adds a device with a single queue, returning its ID.
+    fn add(&mut self) -> io::Result<u32> {
+        let mut info = DevInfo {
+            nr_hw_queues: 1,
+            queue_depth: QUEUE_DEPTH,
+            max_io_buf_bytes: IO_BUF_BYTES,
+            dev_id: ANY_DEVICE,
+            flags: F_CMD_IOCTL_ENCODE,
+            ..DevInfo::default()
+        };
+        let addr = (&raw mut info) as u64;
+        #[allow(clippy::cast_possible_truncation)]
+        let len = size_of::<DevInfo>() as u16;
+        self.send(CMD_ADD_DEV, control_command(ANY_DEVICE, addr, len, 0))?;
+        Ok(info.dev_id)
+    }
+
+    /// Makes device `id` a read-only disk of `size` bytes.
+    fn set_params(&mut self, id: u32, size: u64) -> io::Result<()> {
+        #[allow(clippy::cast_possible_truncation)]
+        let len = size_of::<Params>() as u16;
+        let params = Params {
+            len: len.into(),
+            types: PARAM_TYPE_BASIC,
+            attrs: ATTR_READ_ONLY,
+            logical_bs_shift: 9,
+            physical_bs_shift: 12,
+            // Reads are best aligned to the discs' 32 KiB sectors
+            io_opt_shift: 15,
+            io_min_shift: 9,
+            max_sectors: IO_BUF_BYTES >> 9,
+            dev_sectors: size.div_ceil(512),
+            ..Params::default()
+        };
+        let addr = (&raw const params) as u64;
+        self.send(CMD_SET_PARAMS, control_command(id, addr, len, 0))?;
+        Ok(())
+    }
+
+    fn start(&mut self, id: u32) -> io::Result<()> {
+        let pid = u64::from(std::process::id());
+        self.send(CMD_START_DEV, control_command(id, 0, 0, pid))?;
+        Ok(())
+    }
+
+    fn stop(&mut self, id: u32) -> io::Result<()> {
+        self.send(CMD_STOP_DEV, control_command(id, 0, 0, 0))?;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u32) -> io::Result<()> {
+        self.send(CMD_DEL_DEV, control_command(id, 0, 0, 0))?;
+        Ok(())
+    }
+}
+
+/// The request descriptors of a queue, which the driver fills in as requests arrive.
+struct Descriptors {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Descriptors {
+    /// Maps the descriptors of the first queue of the device opened as `device`.
+    fn map(device: &File) -> io::Result<Self> {
+        // SAFETY: sysconf has no preconditions
+        let page_size = usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) })
+            .map_err(|_| io::Error::last_os_error())?;
+        let len = (usize::from(QUEUE_DEPTH) * size_of::<IoDesc>()).next_multiple_of(page_size);
+        // SAFETY: a new mapping is asked for, of the device's own memory
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                device.as_raw_fd(),
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { addr, len })
+    }
+
+    /// Returns the descriptor of the request `tag` was fetched for.
+    fn get(&self, tag: u16) -> IoDesc {
+        // SAFETY: tags are below the queue depth the mapping holds descriptors for, the mapping is
+        // page aligned, and the driver only changes a descriptor before completing the fetch,
+        // which taking the completion off the ring orders this after
+        unsafe { self.addr.cast::<IoDesc>().add(usize::from(tag)).read() }
+    }
+}
+
+impl Drop for Descriptors {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is no longer used
+        unsafe { libc::munmap(self.addr, self.len) };
+    }
+}
+
+/// Opens the character device of device `id`, waiting for it to appear.
+fn open_device(id: u32) -> io::Result<File> {
+    let path = PathBuf::from(format!("/dev/ublkc{id}"));
+    let mut waited = Duration::ZERO;
+    loop {
+        match OpenOptions::new().read(true).write(true).open(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound && waited < DEVICE_TIMEOUT => {
+                thread::sleep(Duration::from_millis(10));
+                waited += Duration::from_millis(10);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Fills `buf` with the image's bytes at `offset`, zeroes past its `size` bytes, or returns the
+/// error to answer with.
+fn read_image<T: Read + Seek>(image: &mut T, size: u64, offset: u64, buf: &mut [u8]) -> i32 {
+    let available = usize::try_from(size.saturating_sub(offset)).unwrap_or(usize::MAX);
+    let (data, past_end) = buf.split_at_mut(buf.len().min(available));
+    past_end.fill(0);
+    let result = image
+        .seek(SeekFrom::Start(offset))
+        .and_then(|_| image.read_exact(data));
+    if let Err(err) = result {
+        eprintln!("can't read the image at {offset:#x}: {err}");
+        return -libc::EIO;
+    }
+    // Never more than the largest request
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let len = buf.len() as i32;
+    len
+}
+
+/// Buffers the kernel writes to while a queue's requests are pending, which have to outlive the
+/// thread answering them.
+struct Buffers {
+    requests: Vec<Vec<u8>>,
+    woken: [u8; 8],
+}
+
+impl Buffers {
+    fn new() -> Self {
+        Self {
+            requests: vec![vec![0; IO_BUF_BYTES as usize]; QUEUE_DEPTH.into()],
+            woken: [0; 8],
+        }
+    }
+}
+
+/// Answers the requests for device `id`'s only queue from `image`, telling `ready` once it's
+/// waiting for them, until `wake` is written to or the device stops.
+fn serve_queue<T: Read + Seek>(
+    id: u32,
+    image: &mut T,
+    size: u64,
+    buffers: &mut Buffers,
+    wake: &OwnedFd,
+    ready: &mpsc::Sender<()>,
+) -> io::Result<()> {
+    let device = open_device(id)?;
+    let descriptors = Descriptors::map(&device)?;
+    let mut ring = IoUring::new((u32::from(QUEUE_DEPTH) + 1).next_power_of_two())?;
+    let fd = Fd(device.as_raw_fd());
+    let fetch = |op: u32, tag: u16, result: i32, buf: &mut Vec<u8>| {
+        opcode::UringCmd16::new(fd, op)
+            .cmd(io_command(tag, result, buf.as_mut_ptr() as u64))
+            .build()
+            .user_data(tag.into())
+    };
+    let wakeup = opcode::Read::new(Fd(wake.as_raw_fd()), buffers.woken.as_mut_ptr(), 8)
+        .build()
+        .user_data(STOP_TAG);
+    // SAFETY: the buffers outlive the thread, whose exit cancels what's pending, and aren't
+    // touched again until their request arrives
+    unsafe {
+        let mut submission = ring.submission();
+        for (tag, buf) in (0..QUEUE_DEPTH).zip(&mut buffers.requests) {
+            submission
+                .push(&fetch(IO_FETCH_REQ, tag, 0, buf))
+                .map_err(|_| io::Error::other("the queue's ring is full"))?;
+        }
+        submission
+            .push(&wakeup)
+            .map_err(|_| io::Error::other("the queue's ring is full"))?;
+    }
+    ring.submit()?;
+    let _ = ready.send(());
+    let mut fetching = QUEUE_DEPTH;
+    while fetching > 0 {
+        ring.submit_and_wait(1)?;
+        let completed: Vec<_> = ring.completion().collect();
+        for entry in completed {
+            if entry.user_data() == STOP_TAG {
+                return Ok(());
+            }
+            // Only the queue's tags were submitted besides the wakeup
+            #[allow(clippy::cast_possible_truncation)]
+            let tag = entry.user_data() as u16;
+            if entry.result() != IO_RES_OK {
+                // Aborted as the device stops, after which the tag isn't fetched again
+                if entry.result() != IO_RES_ABORT {
+                    eprintln!(
+                        "can't fetch a request: {}",
+                        io::Error::from_raw_os_error(-entry.result())
+                    );
+                }
+                fetching -= 1;
+                continue;
+            }
+            let desc = descriptors.get(tag);
+            let buf = &mut buffers.requests[usize::from(tag)];
+            // The driver never asks for more than the buffer, as its largest request
+            let len = (desc.nr_sectors as usize * 512).min(buf.len());
+            // The operation is the low byte, and the rest flags
+            #[allow(clippy::cast_possible_truncation)]
+            let result = match desc.op_flags as u8 {
+                IO_OP_READ => read_image(image, size, desc.start_sector * 512, &mut buf[..len]),
+                IO_OP_FLUSH => 0,
+                _ => -libc::EROFS,
+            };
+            // SAFETY: as above
+            unsafe {
+                ring.submission()
+                    .push(&fetch(IO_COMMIT_AND_FETCH_REQ, tag, result, buf))
+                    .map_err(|_| io::Error::other("the queue's ring is full"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes to the event file `wake`.
+fn wake_up(wake: &OwnedFd) {
+    let value = 1u64.to_ne_bytes();
+    // SAFETY: the value outlives the call
+    unsafe { libc::write(wake.as_raw_fd(), value.as_ptr().cast(), value.len()) };
+}
+
+/// Serves the `size` bytes of the disc image read from `image` as a read-only ublk block device,
+/// calling `started` with its path, such as `/dev/ublkb0`, once it can be opened.
+///
+/// The device is local to the machine and needs root and Linux's `ublk_drv` module. This returns
+/// once a signal blocked by [`crate::block_stop_signals`] arrives, with the device removed.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the device can't be added or served, or `started` fails.
+pub fn serve_ublk<T: Read + Seek + Send>(
+    mut image: T,
+    size: u64,
+    started: impl FnOnce(&Path) -> io::Result<()>,
+) -> Result<(), Error> {
+    let mut control = Control::open()?;
+    let id = control.add()?;
+    // SAFETY: eventfd has no preconditions, and the descriptor it returns is owned from then on
+    let wake = match unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) } {
+        -1 => Err(io::Error::last_os_error()),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+    };
+    let result = wake.and_then(|wake| {
+        control.set_params(id, size)?;
+        let (stop, stopped) = mpsc::channel();
+        let mut buffers = Buffers::new();
+        thread::scope(|scope| {
+            let (ready, is_ready) = mpsc::channel();
+            let queue = {
+                let (buffers, wake, stop) = (&mut buffers, &wake, stop.clone());
+                scope.spawn(move || {
+                    let result = serve_queue(id, &mut image, size, buffers, wake, &ready);
+                    let _ = stop.send(());
+                    result
+                })
+            };
+            // The queue has to be waiting for requests before the device starts
+            let result = is_ready
+                .recv()
+                .map_err(|_| io::Error::other("the queue stopped"))
+                .and_then(|()| control.start(id))
+                .and_then(|()| {
+                    on_stop(move || {
+                        let _ = stop.send(());
+                    });
+                    started(&PathBuf::from(format!("/dev/ublkb{id}")))
+                });
+            if result.is_ok() {
+                let _ = stopped.recv();
+            }
+            // Stopped while the queue still answers, so what's in flight completes
+            let stop_result = control.stop(id);
+            wake_up(&wake);
+            let queue_result = queue
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("the queue panicked")));
+            // The queue failing is what stops everything else
+            queue_result.and(result).and(stop_result)
+        })
+    });
+    let delete_result = control.delete(id);
+    result?;
+    delete_result?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts() {
+        assert_eq!(size_of::<DevInfo>(), 64);
+        assert_eq!(size_of::<Params>(), 40);
+        assert_eq!(size_of::<IoDesc>(), 24);
+        assert_eq!(CMD_ADD_DEV, 0xc020_7504);
+        assert_eq!(IO_COMMIT_AND_FETCH_REQ, 0xc010_7521);
+    }
+}
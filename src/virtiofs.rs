@@ -0,0 +1,491 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::attr::FileAttr;
+use crate::attr::FileType;
+use crate::error::Error;
+use crate::fuse;
+use crate::fuse::GcnFuse;
+use crate::tree::Inode;
+use fuse_backend_rs::api::filesystem::Context;
+use fuse_backend_rs::api::filesystem::DirEntry;
+use fuse_backend_rs::api::filesystem::Entry;
+use fuse_backend_rs::api::filesystem::FileSystem;
+use fuse_backend_rs::api::filesystem::GetxattrReply;
+use fuse_backend_rs::api::filesystem::ListxattrReply;
+use fuse_backend_rs::api::filesystem::OpenOptions;
+use fuse_backend_rs::api::filesystem::ZeroCopyWriter;
+use fuse_backend_rs::api::server::Server;
+use fuse_backend_rs::transport::Reader;
+use fuse_backend_rs::transport::VirtioFsWriter;
+use std::ffi::CStr;
+use std::ffi::OsStr;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use std::time::SystemTime;
+use vhost::vhost_user::message::VhostUserProtocolFeatures;
+use vhost::vhost_user::message::VhostUserVirtioFeatures;
+use vhost_user_backend::VhostUserBackendMut;
+use vhost_user_backend::VhostUserDaemon;
+use vhost_user_backend::VringRwLock;
+use vhost_user_backend::VringT;
+use virtio_queue::QueueOwnedT;
+use vm_memory::GuestAddressSpace;
+use vm_memory::GuestMemoryAtomic;
+use vm_memory::GuestMemoryMmap;
+use vmm_sys_util::epoll::EventSet;
+use vmm_sys_util::event::EventConsumer;
+use vmm_sys_util::event::EventFlag;
+use vmm_sys_util::event::EventNotifier;
+use vmm_sys_util::event::new_event_consumer_and_notifier;
+
+// Feature bits from linux/virtio_config.h and linux/virtio_ring.h
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+const VIRTIO_RING_F_INDIRECT_DESC: u64 = 1 << 28;
+const VIRTIO_RING_F_EVENT_IDX: u64 = 1 << 29;
+
+/// Queues of the device, the high priority one and a single one for requests.
+const QUEUES: usize = 2;
+
+/// Most requests a queue holds.
+const QUEUE_SIZE: usize = 1024;
+
+/// Returns the stat structure virtio-fs sends for `attr`.
+fn stat(attr: &FileAttr) -> libc::stat64 {
+    // SAFETY: stat64 is plain data, for which zeroes are valid
+    let mut stat: libc::stat64 = unsafe { mem::zeroed() };
+    let kind = match attr.kind {
+        FileType::Directory => libc::S_IFDIR,
+        FileType::Symlink => libc::S_IFLNK,
+        _ => libc::S_IFREG,
+    };
+    stat.st_ino = attr.ino;
+    stat.st_mode = kind | libc::mode_t::from(attr.perm);
+    stat.st_nlink = attr.nlink.into();
+    stat.st_uid = attr.uid;
+    stat.st_gid = attr.gid;
+    stat.st_size = attr.size.try_into().unwrap_or(i64::MAX);
+    stat.st_blocks = attr.blocks.try_into().unwrap_or(i64::MAX);
+    stat.st_blksize = attr.blksize.into();
+    for (time, secs, nsecs) in [
+        (attr.atime, &mut stat.st_atime, &mut stat.st_atime_nsec),
+        (attr.mtime, &mut stat.st_mtime, &mut stat.st_mtime_nsec),
+        (attr.ctime, &mut stat.st_ctime, &mut stat.st_ctime_nsec),
+    ] {
+        let since_epoch = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        *secs = since_epoch.as_secs().try_into().unwrap_or(i64::MAX);
+        *nsecs = since_epoch.subsec_nanos().into();
+    }
+    stat
+}
+
+/// The filesystem answering virtio-fs requests, the same one FUSE mounts.
+struct Virtiofs<T: Read + Seek> {
+    fuse: Mutex<GcnFuse<T>>,
+}
+
+impl<T: Read + Seek> Virtiofs<T> {
+    fn lock(&self) -> MutexGuard<'_, GcnFuse<T>> {
+        // A request that panicked left nothing that matters, as with the other frontends
+        self.fuse
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Returns the entry `lookup` answers with for the given inode.
+    fn entry(fuse: &GcnFuse<T>, inode: Inode) -> io::Result<Entry> {
+        let attr = fuse
+            .get_attr(inode)
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::ENOENT))?;
+        Ok(Entry {
+            inode: inode.into(),
+            generation: fuse.generation(),
+            attr: stat(&attr),
+            attr_flags: 0,
+            attr_timeout: fuse.ttl(),
+            entry_timeout: fuse.ttl(),
+        })
+    }
+}
+
+impl<T: Read + Seek> FileSystem for Virtiofs<T> {
+    type Inode = u64;
+    type Handle = u64;
+
+    fn destroy(&self) {
+        fuser::Filesystem::destroy(&mut *self.lock());
+    }
+
+    fn lookup(&self, _ctx: &Context, parent: u64, name: &CStr) -> io::Result<Entry> {
+        let fuse = self.lock();
+        match fuse.find(parent.into(), OsStr::from_bytes(name.to_bytes())) {
+            Ok(Some(inode)) => Self::entry(&fuse, inode),
+            // Inode 0 lets the guest remember the name is missing, as for FUSE
+            Ok(None) => Ok(Entry {
+                inode: 0,
+                generation: 0,
+                // SAFETY: stat64 is plain data, for which zeroes are valid
+                attr: unsafe { mem::zeroed() },
+                attr_flags: 0,
+                attr_timeout: fuse.ttl(),
+                entry_timeout: fuse.ttl(),
+            }),
+            Err(err) => Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+
+    fn getattr(
+        &self,
+        _ctx: &Context,
+        inode: u64,
+        _handle: Option<u64>,
+    ) -> io::Result<(libc::stat64, Duration)> {
+        let fuse = self.lock();
+        let attr = fuse
+            .get_attr(inode.into())
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::ENOENT))?;
+        Ok((stat(&attr), fuse.ttl()))
+    }
+
+    fn open(
+        &self,
+        _ctx: &Context,
+        inode: u64,
+        flags: u32,
+        _fuse_flags: u32,
+    ) -> io::Result<(Option<u64>, OpenOptions, Option<u32>)> {
+        if self.lock().get_attr(inode.into()).is_none() {
+            return Err(io::Error::from_raw_os_error(libc::ENOENT));
+        }
+        // Guests only ever get the disc as it is
+        #[allow(clippy::cast_possible_wrap)] // Open flags are an int to begin with
+        if fuse::opens_for_writing(flags as i32) {
+            return Err(io::Error::from_raw_os_error(libc::EROFS));
+        }
+        Ok((None, OpenOptions::empty(), None))
+    }
+
+    fn read(
+        &self,
+        _ctx: &Context,
+        inode: u64,
+        _handle: u64,
+        w: &mut dyn ZeroCopyWriter,
+        size: u32,
+        offset: u64,
+        _lock_owner: Option<u64>,
+        _flags: u32,
+    ) -> io::Result<usize> {
+        let offset =
+            i64::try_from(offset).map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))?;
+        self.lock()
+            .read_contents(inode.into(), offset, size)
+            .map_err(io::Error::from_raw_os_error)
+            .and_then(|data| {
+                w.write_all(&data)?;
+                Ok(data.len())
+            })
+    }
+
+    fn release(
+        &self,
+        _ctx: &Context,
+        _inode: u64,
+        _flags: u32,
+        _handle: u64,
+        _flush: bool,
+        _flock_release: bool,
+        _lock_owner: Option<u64>,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn opendir(
+        &self,
+        _ctx: &Context,
+        inode: u64,
+        _flags: u32,
+    ) -> io::Result<(Option<u64>, OpenOptions)> {
+        let fh = self
+            .lock()
+            .open_dir(inode.into())
+            .map_err(io::Error::from_raw_os_error)?;
+        Ok((Some(fh), OpenOptions::empty()))
+    }
+
+    fn readdir(
+        &self,
+        _ctx: &Context,
+        inode: u64,
+        handle: u64,
+        _size: u32,
+        offset: u64,
+        add_entry: &mut dyn FnMut(DirEntry) -> io::Result<usize>,
+    ) -> io::Result<()> {
+        let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+        self.lock()
+            .listed(inode.into(), handle)
+            .map_err(io::Error::from_raw_os_error)
+            .and_then(|listing| {
+                for (i, (inode, type_, name)) in listing.iter().enumerate().skip(offset) {
+                    let entry = DirEntry {
+                        ino: (*inode).into(),
+                        offset: i as u64 + 1,
+                        type_: match type_ {
+                            FileType::Directory => libc::DT_DIR,
+                            _ => libc::DT_REG,
+                        }
+                        .into(),
+                        name: name.as_bytes(),
+                    };
+                    // Nothing is added once the reply is full
+                    if add_entry(entry)? == 0 {
+                        break;
+                    }
+                }
+                Ok(())
+            })
+    }
+
+    fn releasedir(&self, _ctx: &Context, _inode: u64, _flags: u32, handle: u64) -> io::Result<()> {
+        self.lock().close_dir(handle);
+        Ok(())
+    }
+
+    fn getxattr(
+        &self,
+        _ctx: &Context,
+        inode: u64,
+        name: &CStr,
+        size: u32,
+    ) -> io::Result<GetxattrReply> {
+        let value = self
+            .lock()
+            .xattr(inode.into(), OsStr::from_bytes(name.to_bytes()))?
+            .ok_or_else(|| io::Error::from_raw_os_error(fuse::ENOATTR))?;
+        xattr_reply(
+            value.into_bytes(),
+            size,
+            GetxattrReply::Value,
+            GetxattrReply::Count,
+        )
+    }
+
+    fn listxattr(&self, _ctx: &Context, inode: u64, size: u32) -> io::Result<ListxattrReply> {
+        let mut names = vec![];
+        let list = self.lock().xattr_names(inode.into());
+        for name in list {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        xattr_reply(names, size, ListxattrReply::Names, ListxattrReply::Count)
+    }
+}
+
+/// Returns the reply with an extended attribute value or list, or just its size if `size` is 0.
+fn xattr_reply<R>(
+    data: Vec<u8>,
+    size: u32,
+    data_reply: impl FnOnce(Vec<u8>) -> R,
+    size_reply: impl FnOnce(u32) -> R,
+) -> io::Result<R> {
+    // Attributes are tiny
+    #[allow(clippy::cast_possible_truncation)]
+    let len = data.len() as u32;
+    if size == 0 {
+        Ok(size_reply(len))
+    } else if len <= size {
+        Ok(data_reply(data))
+    } else {
+        Err(io::Error::from_raw_os_error(libc::ERANGE))
+    }
+}
+
+/// The vhost-user device, which takes the guest's requests off its queues.
+struct Backend<T: Read + Seek + Send> {
+    server: Arc<Server<Virtiofs<T>>>,
+    memory: Option<GuestMemoryAtomic<GuestMemoryMmap>>,
+    event_idx: bool,
+}
+
+impl<T: Read + Seek + Send> Backend<T> {
+    /// Answers the requests waiting on `vring`.
+    fn process_queue(&self, vring: &VringRwLock) -> io::Result<()> {
+        let memory = self
+            .memory
+            .as_ref()
+            .ok_or_else(|| io::Error::other("the guest's memory isn't mapped yet"))?
+            .memory();
+        loop {
+            if self.event_idx {
+                vring.disable_notification().map_err(io::Error::other)?;
+            }
+            let chains: Vec<_> = vring
+                .get_mut()
+                .get_queue_mut()
+                .iter(memory.clone())
+                .map_err(io::Error::other)?
+                .collect();
+            for chain in chains {
+                let head = chain.head_index();
+                let reader = Reader::from_descriptor_chain(&*memory, chain.clone())
+                    .map_err(io::Error::other)?;
+                let writer = VirtioFsWriter::new(&*memory, chain).map_err(io::Error::other)?;
+                // The guest is told of failures in the reply, so these only come from bad requests
+                let used = self
+                    .server
+                    .handle_message(reader, writer.into(), None, None)
+                    .unwrap_or_else(|err| {
+                        eprintln!("can't answer a request: {err}");
+                        0
+                    });
+                let used = u32::try_from(used).unwrap_or(u32::MAX);
+                vring.add_used(head, used).map_err(io::Error::other)?;
+                if !self.event_idx || vring.needs_notification().map_err(io::Error::other)? {
+                    vring.signal_used_queue()?;
+                }
+            }
+            // Requests may have arrived since notifications were last asked for
+            if !self.event_idx || !vring.enable_notification().map_err(io::Error::other)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<T: Read + Seek + Send> VhostUserBackendMut for Backend<T> {
+    type Bitmap = ();
+    type Vring = VringRwLock;
+
+    fn num_queues(&self) -> usize {
+        QUEUES
+    }
+
+    fn max_queue_size(&self) -> usize {
+        QUEUE_SIZE
+    }
+
+    fn features(&self) -> u64 {
+        VIRTIO_F_VERSION_1
+            | VIRTIO_RING_F_INDIRECT_DESC
+            | VIRTIO_RING_F_EVENT_IDX
+            | VhostUserVirtioFeatures::PROTOCOL_FEATURES.bits()
+    }
+
+    fn protocol_features(&self) -> VhostUserProtocolFeatures {
+        VhostUserProtocolFeatures::MQ | VhostUserProtocolFeatures::REPLY_ACK
+    }
+
+    fn set_event_idx(&mut self, enabled: bool) {
+        self.event_idx = enabled;
+    }
+
+    fn update_memory(&mut self, memory: GuestMemoryAtomic<GuestMemoryMmap>) -> io::Result<()> {
+        self.memory = Some(memory);
+        Ok(())
+    }
+
+    fn exit_event(&self, _thread_index: usize) -> Option<(EventConsumer, EventNotifier)> {
+        new_event_consumer_and_notifier(EventFlag::NONBLOCK).ok()
+    }
+
+    fn handle_event(
+        &mut self,
+        device_event: u16,
+        evset: EventSet,
+        vrings: &[VringRwLock],
+        _thread_id: usize,
+    ) -> io::Result<()> {
+        if evset != EventSet::IN {
+            return Err(io::Error::other(format!("unexpected event {evset:?}")));
+        }
+        let vring = vrings
+            .get(usize::from(device_event))
+            .ok_or_else(|| io::Error::other(format!("no queue {device_event}")))?;
+        self.process_queue(vring)
+    }
+}
+
+/// Serves the filesystem read-only to a virtual machine over vhost-user-fs, listening on the
+/// Unix socket at `socket` for QEMU or cloud-hypervisor to connect to.
+///
+/// The guest mounts it with `mount -t virtiofs TAG DIR`, the tag being the one the hypervisor's
+/// device is given. This returns once the hypervisor disconnects.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the socket can't be listened on, or the connection fails.
+pub fn serve_virtiofs<T: Read + Seek + Send + 'static>(
+    fuse: GcnFuse<T>,
+    socket: &Path,
+) -> Result<(), Error> {
+    let backend = Backend {
+        server: Arc::new(Server::new(Virtiofs {
+            fuse: Mutex::new(fuse),
+        })),
+        memory: None,
+        event_idx: false,
+    };
+    let mut daemon = VhostUserDaemon::new(
+        "gcnfuse".to_string(),
+        Arc::new(Mutex::new(backend)),
+        GuestMemoryAtomic::new(GuestMemoryMmap::new()),
+    )
+    .map_err(|err| io::Error::other(err.to_string()))?;
+    daemon
+        .serve(socket)
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let time = SystemTime::UNIX_EPOCH + Duration::new(1_000_000_000, 500);
+        let attr = FileAttr {
+            ino: 5,
+            size: 17,
+            blocks: 1,
+            atime: time,
+            mtime: time,
+            ctime: time,
+            crtime: time,
+            kind: FileType::Directory,
+            perm: 0o555,
+            nlink: 2,
+            uid: 1000,
+            gid: 100,
+            rdev: 0,
+            blksize: 2048,
+            flags: 0,
+        };
+        let stat = stat(&attr);
+        assert_eq!(stat.st_ino, 5);
+        assert_eq!(stat.st_mode, libc::S_IFDIR | 0o555);
+        assert_eq!(stat.st_size, 17);
+        assert_eq!(stat.st_blksize, 2048);
+        assert_eq!((stat.st_uid, stat.st_gid), (1000, 100));
+        assert_eq!((stat.st_mtime, stat.st_mtime_nsec), (1_000_000_000, 500));
+    }
+
+    #[test]
+    fn xattr_replies() {
+        let reply = |size| xattr_reply(b"crc".to_vec(), size, Ok, Err);
+        assert_eq!(reply(0).unwrap(), Err(3));
+        assert_eq!(reply(3).unwrap(), Ok(b"crc".to_vec()));
+        assert_eq!(reply(2).unwrap_err().raw_os_error(), Some(libc::ERANGE));
+    }
+}
@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// How often the image's size and modification time are checked.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether an image file was changed after it was opened, which is checked in the background for
+/// as long as a copy of this is kept.
+#[derive(Clone, Debug)]
+pub struct Watch {
+    changed: Arc<AtomicBool>,
+}
+
+impl Watch {
+    /// Takes a shared lock on `file`, the image at `path`, so programs that lock it to write it
+    /// wait until it's closed, and starts checking whether it's changed anyway.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] if the file's metadata can't be read or it can't be duplicated to check it.
+    pub fn start(file: &File, path: &Path) -> io::Result<Self> {
+        if let Err(err) = file.try_lock_shared() {
+            eprintln!(
+                "{} is locked for writing by another program, reading it anyway: {err}",
+                path.display(),
+            );
+        }
+        let changed = Arc::new(AtomicBool::new(false));
+        let watched = Arc::downgrade(&changed);
+        let file = file.try_clone()?;
+        let opened = stamp(&file)?;
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            while let Some(changed) = wait(&watched) {
+                if stamp(&file).ok() != Some(opened) {
+                    eprintln!(
+                        "{} was changed while it was being read, failing all further reads of it",
+                        path.display()
+                    );
+                    changed.store(true, Ordering::Relaxed);
+                    return;
+                }
+            }
+        });
+        Ok(Self { changed })
+    }
+
+    /// Fails if the image was changed, as what's read from it might not match what was read
+    /// before.
+    ///
+    /// # Errors
+    ///
+    /// [`io::Error`] holding [`Changed`] if the image was changed.
+    pub fn check(&self) -> io::Result<()> {
+        if self.changed.load(Ordering::Relaxed) {
+            return Err(io::Error::other(Changed));
+        }
+        Ok(())
+    }
+}
+
+/// What reads of an image that was changed fail with, inside an [`io::Error`], so retrying reads
+/// give up on them at once. It isn't an OS error, so filesystems answer it with `EIO`.
+#[derive(Debug)]
+pub struct Changed;
+
+impl Changed {
+    /// Returns whether `err` is for an image that was changed.
+    pub fn is(err: &io::Error) -> bool {
+        err.get_ref().is_some_and(|inner| inner.is::<Self>())
+    }
+}
+
+impl fmt::Display for Changed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the image was changed while it was being read")
+    }
+}
+
+impl error::Error for Changed {}
+
+/// Waits until the image is due to be checked again, returning its flag, or `None` if nothing
+/// reads it anymore.
+fn wait(watched: &Weak<AtomicBool>) -> Option<Arc<AtomicBool>> {
+    thread::sleep(WATCH_INTERVAL);
+    watched.upgrade()
+}
+
+/// Returns what tells whether `file` changed, its size and modification time.
+fn stamp(file: &File) -> io::Result<(u64, SystemTime)> {
+    let metadata = file.metadata()?;
+    Ok((metadata.len(), metadata.modified()?))
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::attr::FileType;
+use crate::error::Error;
+use crate::fuse::GcnFuse;
+use crate::http;
+use crate::http::Body;
+use crate::http::Request;
+use crate::http::Response;
+use crate::tree::Inode;
+use std::fmt::Write;
+use std::io::Read;
+use std::io::Seek;
+use std::sync::Mutex;
+
+/// Methods that would change the filesystem, which is always read-only over `WebDAV`.
+const WRITE_METHODS: &[&str] = &[
+    "PUT",
+    "DELETE",
+    "MKCOL",
+    "COPY",
+    "MOVE",
+    "PROPPATCH",
+    "LOCK",
+    "UNLOCK",
+];
+
+/// Appends the `<response>` element describing the given entry to a PROPFIND reply.
+fn write_entry<T: Read + Seek>(
+    xml: &mut String,
+    fuse: &GcnFuse<T>,
+    href: &str,
+    name: &str,
+    inode: Inode,
+) {
+    let Some(attr) = fuse.get_attr(inode) else {
+        return;
+    };
+    let resource_type = if attr.kind == FileType::Directory {
+        "<D:collection/>"
+    } else {
+        ""
+    };
+    let _ = write!(
+        xml,
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop>\
+         <D:displayname>{}</D:displayname>\
+         <D:resourcetype>{resource_type}</D:resourcetype>\
+         <D:getcontentlength>{}</D:getcontentlength>\
+         <D:getcontenttype>application/octet-stream</D:getcontenttype>\
+         <D:getlastmodified>{}</D:getlastmodified>\
+         <D:creationdate>{}</D:creationdate>\
+         </D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+        http::escape(&http::percent_encode(href)),
+        http::escape(name),
+        attr.size,
+        http::http_date(attr.mtime),
+        http::rfc3339_date(attr.crtime),
+    );
+}
+
+/// Answers a PROPFIND request for the entry at the request's path, and its children unless the
+/// `Depth` header is 0. Every property is always returned, whatever the request asked for.
+fn propfind<T: Read + Seek>(request: &Request, fuse: &Mutex<GcnFuse<T>>) -> Response {
+    let fuse = http::lock(fuse);
+    let Some(inode) = fuse.resolve(&request.path) else {
+        return Response::new(404);
+    };
+    let entries = fuse.entries(inode);
+    let mut href = request.path.clone();
+    if entries.is_some() && !href.ends_with('/') {
+        href.push('/');
+    }
+
+    let mut xml =
+        String::from(r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#);
+    let name = href
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    write_entry(&mut xml, &fuse, &href, name, inode);
+    // Infinite depth is refused by most servers, and listing one level is a valid reply to it
+    if request.header("depth") != Some("0") {
+        for (name, child) in entries.unwrap_or_default() {
+            let mut child_href = format!("{href}{name}");
+            if fuse.entries(child).is_some() {
+                child_href.push('/');
+            }
+            write_entry(&mut xml, &fuse, &child_href, &name, child);
+        }
+    }
+    drop(fuse);
+    xml.push_str("</D:multistatus>");
+    Response::new(207).body(
+        r#"application/xml; charset="utf-8""#,
+        Body::Bytes(xml.into_bytes()),
+    )
+}
+
+fn handle<T: Read + Seek>(request: &Request, fuse: &Mutex<GcnFuse<T>>) -> Response {
+    const ALLOW: &str = "OPTIONS, GET, HEAD, PROPFIND";
+    match request.method.as_str() {
+        "OPTIONS" => Response::new(200)
+            .header("DAV", "1")
+            .header("Allow", ALLOW)
+            .header("MS-Author-Via", "DAV"),
+        "PROPFIND" => propfind(request, fuse),
+        "GET" | "HEAD" => {
+            let Some(inode) = http::lock(fuse).resolve(&request.path) else {
+                return Response::new(404);
+            };
+            http::file_response(fuse, request, inode).header("Allow", ALLOW)
+        }
+        method if WRITE_METHODS.contains(&method) => Response::new(403),
+        _ => Response::new(501),
+    }
+}
+
+/// Serves the filesystem read-only over `WebDAV` on `listen`, an address like `0.0.0.0:8080`.
+/// This never returns unless the address can't be listened on.
+///
+/// # Errors
+///
+/// [`Error::Io`] if the address can't be listened on.
+pub fn serve_webdav<T: Read + Seek + Send>(fuse: GcnFuse<T>, listen: &str) -> Result<(), Error> {
+    http::serve(fuse, listen, handle)?;
+    Ok(())
+}
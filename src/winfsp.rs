@@ -0,0 +1,309 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later OR GPL-2.0-or-later OR MPL-2.0
+// SPDX-FileCopyrightText: 2026 Gabriel Marcano <gabemarcano@yahoo.com>
+
+use crate::attr::FileAttr;
+use crate::attr::FileType;
+use crate::error::Error;
+use crate::fuse::GcnFuse;
+use crate::stop::on_stop;
+use crate::tree::Inode;
+use std::ffi::c_void;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::mpsc;
+use std::time::SystemTime;
+use windows_sys::Win32::Foundation::STATUS_END_OF_FILE;
+use windows_sys::Win32::Foundation::STATUS_FILE_IS_A_DIRECTORY;
+use windows_sys::Win32::Foundation::STATUS_INVALID_PARAMETER;
+use windows_sys::Win32::Foundation::STATUS_IO_DEVICE_ERROR;
+use windows_sys::Win32::Foundation::STATUS_NOT_A_DIRECTORY;
+use windows_sys::Win32::Foundation::STATUS_OBJECT_NAME_INVALID;
+use windows_sys::Win32::Foundation::STATUS_OBJECT_NAME_NOT_FOUND;
+use windows_sys::Win32::Storage::FileSystem::FILE_ATTRIBUTE_DIRECTORY;
+use windows_sys::Win32::Storage::FileSystem::FILE_ATTRIBUTE_READONLY;
+use winfsp::FspError;
+use winfsp::U16CStr;
+use winfsp::filesystem::DirBuffer;
+use winfsp::filesystem::DirInfo;
+use winfsp::filesystem::DirMarker;
+use winfsp::filesystem::FileInfo;
+use winfsp::filesystem::FileSecurity;
+use winfsp::filesystem::FileSystemContext;
+use winfsp::filesystem::OpenFileInfo;
+use winfsp::filesystem::VolumeInfo;
+use winfsp::filesystem::WideNameInfo;
+use winfsp::host::FileSystemHost;
+use winfsp::host::VolumeParams;
+
+/// FILETIME of the Unix epoch, in the 100 nanosecond units since 1601 it counts.
+const UNIX_EPOCH_FILETIME: u64 = 116_444_736_000_000_000;
+
+/// Returns `time` as a FILETIME.
+fn filetime(time: SystemTime) -> u64 {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    u64::try_from(since_epoch.as_nanos() / 100)
+        .unwrap_or(u64::MAX)
+        .saturating_add(UNIX_EPOCH_FILETIME)
+}
+
+/// Returns the file attributes Windows is told `attr` has.
+const fn attributes(attr: &FileAttr) -> u32 {
+    match attr.kind {
+        FileType::Directory => FILE_ATTRIBUTE_DIRECTORY,
+        _ => FILE_ATTRIBUTE_READONLY,
+    }
+}
+
+/// Fills in what `WinFsp` is told about a file from `attr`.
+fn fill_file_info(attr: &FileAttr, info: &mut FileInfo) {
+    info.file_attributes = attributes(attr);
+    info.allocation_size = attr.blocks * 512;
+    info.file_size = attr.size;
+    info.creation_time = filetime(attr.crtime);
+    info.last_access_time = filetime(attr.atime);
+    info.last_write_time = filetime(attr.mtime);
+    info.change_time = filetime(attr.ctime);
+    info.index_number = attr.ino;
+}
+
+/// Returns the NTSTATUS to fail with for an errno returned by the filesystem.
+const fn status(errno: c_int) -> FspError {
+    FspError::NTSTATUS(match errno {
+        libc::ENOENT => STATUS_OBJECT_NAME_NOT_FOUND,
+        libc::ENOTDIR => STATUS_NOT_A_DIRECTORY,
+        libc::EISDIR => STATUS_FILE_IS_A_DIRECTORY,
+        libc::EINVAL => STATUS_INVALID_PARAMETER,
+        _ => STATUS_IO_DEVICE_ERROR,
+    })
+}
+
+/// A file or directory opened through the drive.
+struct Opened {
+    inode: Inode,
+    /// The entries of a directory, taken when it's first read through this handle.
+    listing: DirBuffer,
+}
+
+/// The filesystem answering `WinFsp`, the same one FUSE mounts.
+struct Drive<T: Read + Seek> {
+    fuse: Mutex<GcnFuse<T>>,
+    label: String,
+    /// How many bytes the disc's files take up together, shown as the drive's size.
+    size: u64,
+}
+
+impl<T: Read + Seek> Drive<T> {
+    fn lock(&self) -> MutexGuard<'_, GcnFuse<T>> {
+        // A request that panicked left nothing that matters, as with the other frontends
+        self.fuse
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Returns the inode and attributes of the file at `file_name`, a `\` separated path from the
+    /// root directory.
+    fn resolve(fuse: &GcnFuse<T>, file_name: &U16CStr) -> winfsp::Result<(Inode, FileAttr)> {
+        // Names on the disc are always valid strings, so a name that isn't can't match anything
+        let path = file_name
+            .to_string()
+            .map_err(|_| FspError::NTSTATUS(STATUS_OBJECT_NAME_INVALID))?;
+        fuse.resolve(&path.replace('\\', "/"))
+            .and_then(|inode| Some((inode, fuse.get_attr(inode)?)))
+            .ok_or(FspError::NTSTATUS(STATUS_OBJECT_NAME_NOT_FOUND))
+    }
+}
+
+/// Returns how many bytes the files under `inode` take up together, or the size of `inode` if
+/// it's a file.
+fn used<T: Read + Seek>(fuse: &GcnFuse<T>, inode: Inode) -> u64 {
+    fuse.entries(inode).map_or_else(
+        || fuse.get_attr(inode).map_or(0, |attr| attr.size),
+        |entries| entries.iter().map(|&(_, child)| used(fuse, child)).sum(),
+    )
+}
+
+impl<T: Read + Seek> FileSystemContext for Drive<T> {
+    type FileContext = Opened;
+
+    fn get_security_by_name(
+        &self,
+        file_name: &U16CStr,
+        _security_descriptor: Option<&mut [c_void]>,
+        _reparse_point_resolver: impl FnOnce(&U16CStr) -> Option<FileSecurity>,
+    ) -> winfsp::Result<FileSecurity> {
+        let (_, attr) = Self::resolve(&self.lock(), file_name)?;
+        // Without a security descriptor, everyone may open everything, and nothing can be changed
+        // on a read-only volume anyway
+        Ok(FileSecurity {
+            reparse: false,
+            sz_security_descriptor: 0,
+            attributes: attributes(&attr),
+        })
+    }
+
+    fn open(
+        &self,
+        file_name: &U16CStr,
+        _create_options: u32,
+        _granted_access: u32,
+        file_info: &mut OpenFileInfo,
+    ) -> winfsp::Result<Opened> {
+        let (inode, attr) = Self::resolve(&self.lock(), file_name)?;
+        fill_file_info(&attr, file_info.as_mut());
+        Ok(Opened {
+            inode,
+            listing: DirBuffer::new(),
+        })
+    }
+
+    fn close(&self, _context: Opened) {}
+
+    fn get_file_info(&self, context: &Opened, file_info: &mut FileInfo) -> winfsp::Result<()> {
+        let attr = self
+            .lock()
+            .get_attr(context.inode)
+            .ok_or(FspError::NTSTATUS(STATUS_OBJECT_NAME_NOT_FOUND))?;
+        fill_file_info(&attr, file_info);
+        Ok(())
+    }
+
+    fn read(&self, context: &Opened, buffer: &mut [u8], offset: u64) -> winfsp::Result<u32> {
+        let offset =
+            i64::try_from(offset).map_err(|_| FspError::NTSTATUS(STATUS_INVALID_PARAMETER))?;
+        let size = u32::try_from(buffer.len()).unwrap_or(u32::MAX);
+        self.lock()
+            .read_contents(context.inode, offset, size)
+            .map_err(status)
+            .and_then(|data| {
+                // Reads at or past the end must fail, rather than read nothing
+                if data.is_empty() {
+                    return Err(FspError::NTSTATUS(STATUS_END_OF_FILE));
+                }
+                buffer[..data.len()].copy_from_slice(&data);
+                // At most the buffer's size, which fits
+                #[allow(clippy::cast_possible_truncation)]
+                Ok(data.len() as u32)
+            })
+    }
+
+    fn read_directory(
+        &self,
+        context: &Opened,
+        _pattern: Option<&U16CStr>,
+        marker: DirMarker,
+        buffer: &mut [u8],
+    ) -> winfsp::Result<u32> {
+        // Entries are only taken on the first read, later ones pick up after the marker
+        if let Ok(lock) = context.listing.acquire(marker.is_none(), None) {
+            let fuse = self.lock();
+            let entries: Vec<_> = fuse
+                .listing(context.inode)
+                .map_err(status)?
+                .into_iter()
+                // The root directory has no . and .. on Windows
+                .filter(|(_, _, name)| {
+                    context.inode != Inode(1) || !matches!(name.as_str(), "." | "..")
+                })
+                .filter_map(|(inode, _, name)| Some((fuse.get_attr(inode)?, name)))
+                .collect();
+            drop(fuse);
+            for (attr, name) in entries {
+                let mut info = DirInfo::<255>::new();
+                fill_file_info(&attr, info.file_info_mut());
+                info.set_name(name)?;
+                lock.write(&mut info)?;
+            }
+        }
+        Ok(context.listing.read(marker, buffer))
+    }
+
+    fn get_volume_info(&self, volume_info: &mut VolumeInfo) -> winfsp::Result<()> {
+        volume_info.total_size = self.size;
+        volume_info.free_size = 0;
+        volume_info.set_volume_label(&self.label);
+        Ok(())
+    }
+}
+
+/// Mounts the filesystem read-only with `WinFsp` at `mount`, a drive letter such as `G:` or an
+/// empty directory, with `label` as the volume's name.
+///
+/// This returns once Ctrl-C or Ctrl-Break is pressed after [`crate::block_stop_signals`], with
+/// the drive removed. `WinFsp` must be installed.
+///
+/// # Errors
+///
+/// [`Error::Io`] if `WinFsp` can't be loaded or the drive can't be mounted.
+pub fn mount_winfsp<T: Read + Seek + Send>(
+    fuse: GcnFuse<T>,
+    mount: &Path,
+    label: &str,
+) -> Result<(), Error> {
+    winfsp::winfsp_init().map_err(|_| io::Error::other("can't load WinFsp, is it installed?"))?;
+    let size = used(&fuse, Inode(1));
+    let drive = Drive {
+        fuse: Mutex::new(fuse),
+        label: label.to_string(),
+        size,
+    };
+    let mut params = VolumeParams::new();
+    params
+        .filesystem_name("gcnfuse")
+        .sector_size(2048)
+        .sectors_per_allocation_unit(1)
+        .max_component_length(255)
+        .case_sensitive_search(true)
+        .case_preserved_names(true)
+        .unicode_on_disk(true)
+        .read_only_volume(true)
+        // Nothing on the disc changes, so WinFsp can keep what it's told for good
+        .file_info_timeout(u32::MAX);
+    let mut host: FileSystemHost<Drive<T>> =
+        FileSystemHost::new(params, drive).map_err(io::Error::from)?;
+    host.mount(mount).map_err(io::Error::from)?;
+    let (stop, stopped) = mpsc::channel();
+    on_stop(move || {
+        let _ = stop.send(());
+    });
+    let started = host.start().map_err(io::Error::from);
+    if started.is_ok() {
+        let _ = stopped.recv();
+        host.stop();
+    }
+    host.unmount();
+    Ok(started?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn filetime_counts_from_1601() {
+        assert_eq!(filetime(SystemTime::UNIX_EPOCH), UNIX_EPOCH_FILETIME);
+        assert_eq!(
+            filetime(SystemTime::UNIX_EPOCH + Duration::from_secs(1)),
+            UNIX_EPOCH_FILETIME + 10_000_000
+        );
+    }
+
+    #[test]
+    fn errnos_map_to_statuses() {
+        assert!(matches!(
+            status(libc::ENOENT),
+            FspError::NTSTATUS(STATUS_OBJECT_NAME_NOT_FOUND)
+        ));
+        assert!(matches!(
+            status(libc::EIO),
+            FspError::NTSTATUS(STATUS_IO_DEVICE_ERROR)
+        ));
+    }
+}